@@ -7,9 +7,14 @@ pub enum AssetType {
     Texture,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ModelType {
     Wavefront,
+    Ply,
+    Stl,
+    Collada,
+    //.rmdl, already imported and written out by import-model
+    Cooked,
 }
 
 impl fmt::Display for ModelType {
@@ -26,6 +31,10 @@ pub const SUB_TYPE_ATTRIBUTE: &str = "sub_type";
 pub const MODEL_TYPE: &str = "model";
 pub const TEXTURE_TYPE: &str = "texture";
 pub const WAVEFRONT_TYPE: &str = "wavefront";
+pub const PLY_TYPE: &str = "ply";
+pub const STL_TYPE: &str = "stl";
+pub const COLLADA_TYPE: &str = "collada";
+pub const COOKED_TYPE: &str = "cooked";
 
 pub struct Asset {
     pub location: Option<String>,
@@ -44,6 +53,26 @@ impl AssetType {
                     AssetType::Model(ModelType::Wavefront)
                 );
             }
+            if sub_type == PLY_TYPE {
+                return Ok(
+                    AssetType::Model(ModelType::Ply)
+                );
+            }
+            if sub_type == STL_TYPE {
+                return Ok(
+                    AssetType::Model(ModelType::Stl)
+                );
+            }
+            if sub_type == COLLADA_TYPE {
+                return Ok(
+                    AssetType::Model(ModelType::Collada)
+                );
+            }
+            if sub_type == COOKED_TYPE {
+                return Ok(
+                    AssetType::Model(ModelType::Cooked)
+                );
+            }
             return Err("no subtype found for asset type: 'model'".to_string());
         }
         if tpe == TEXTURE_TYPE {
@@ -1,6 +1,14 @@
 pub const REL_MODEL_PATH: &str = "models";
 pub const REL_TEXTURE_PATH: &str = "textures";
 pub const REL_WAVEFRONT_MODELS_PATH: &str = "wavefront";
+pub const REL_PLY_MODELS_PATH: &str = "ply";
+pub const REL_STL_MODELS_PATH: &str = "stl";
+pub const REL_COLLADA_MODELS_PATH: &str = "collada";
+pub const REL_COOKED_MODELS_PATH: &str = "cooked";
 
 pub const WAVEFRONT_EXTENSION: &str = "obj";
+pub const PLY_EXTENSION: &str = "ply";
+pub const STL_EXTENSION: &str = "stl";
+pub const COLLADA_EXTENSION: &str = "dae";
+pub const COOKED_EXTENSION: &str = "rmdl";
 pub const TEXTURE_EXTENSION: &str = "png";
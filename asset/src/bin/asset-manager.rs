@@ -12,8 +12,10 @@ use asset::{
     asset::{
         path_defs::{
             REL_MODEL_PATH, REL_TEXTURE_PATH, 
-            REL_WAVEFRONT_MODELS_PATH, 
-            TEXTURE_EXTENSION, WAVEFRONT_EXTENSION,
+            REL_WAVEFRONT_MODELS_PATH, REL_PLY_MODELS_PATH, REL_STL_MODELS_PATH,
+            REL_COLLADA_MODELS_PATH, REL_COOKED_MODELS_PATH,
+            TEXTURE_EXTENSION, WAVEFRONT_EXTENSION, PLY_EXTENSION, STL_EXTENSION,
+            COLLADA_EXTENSION, COOKED_EXTENSION,
         }, Asset, AssetType, ModelType
     }, source::{local_file::LocalFile, AssetSource}
 };
@@ -46,33 +48,58 @@ struct Args {
     manifest_file: String,
 }
 
+//globs every file with extension under models_path/rel_path as a model of model_type
+fn parse_models_dir(
+    models_path: &Path, rel_path: &str, extension: &str, model_type: ModelType,
+) -> Result<Vec<Asset>, String> {
+    let mut to_ret: Vec<Asset> = vec!();
+    let type_path = models_path.join(rel_path);
+    if !type_path.exists() {
+        return Ok(to_ret);
+    }
+    let type_glob = type_path.join(format!("*.{extension}"));
+    for entry in glob(type_glob.to_str().unwrap()).unwrap() {
+        //probably a cleaner if let syntax here...
+        match entry {
+            Ok(path) => {
+                let name: &str = path.file_stem().unwrap().to_str().unwrap();
+                to_ret.push(
+                    Asset{
+                        location: Some(path.display().to_string()),
+                        asset_type: AssetType::Model(model_type.clone()),
+                        name: name.to_string(),
+                        id: name.to_string(),
+                    },
+                );
+            },
+            Err(err) => {
+                return Err(format!("{rel_path} glob() failed with: {err}"));
+            },
+        }
+    }
+    Ok(to_ret)
+}
+
 fn parse_assets_dir(assets_path: &Path) -> Result<Vec<Asset>, String> {
     let models_path = assets_path.join(REL_MODEL_PATH);
     let mut to_ret: Vec<Asset> = vec!();
     if models_path.exists() {
-        let wavefront_path = models_path.join(REL_WAVEFRONT_MODELS_PATH);
-        if wavefront_path.exists() {
-            let wavefront_glob = wavefront_path.join(format!("*.{WAVEFRONT_EXTENSION}"));
-            for entry in glob(wavefront_glob.to_str().unwrap()).unwrap() {
-                //probably a cleaner if let syntax here...
-                match entry {
-                    Ok(path) => {
-                        let name: &str = path.file_stem().unwrap().to_str().unwrap();
-                        to_ret.push(
-                            Asset{
-                                location: Some(path.display().to_string()),
-                                asset_type: AssetType::Model(ModelType::Wavefront),
-                                name: name.to_string(),
-                                id: name.to_string(),
-                            },
-                        );
-                    },
-                    Err(err) => {
-                        return Err(format!("wavefront glob() failed with: {err}"));
-                    },
-                }
-            }
-        }
+        to_ret.append(&mut parse_models_dir(
+            &models_path, REL_WAVEFRONT_MODELS_PATH, WAVEFRONT_EXTENSION,
+            ModelType::Wavefront,
+        )?);
+        to_ret.append(&mut parse_models_dir(
+            &models_path, REL_PLY_MODELS_PATH, PLY_EXTENSION, ModelType::Ply,
+        )?);
+        to_ret.append(&mut parse_models_dir(
+            &models_path, REL_STL_MODELS_PATH, STL_EXTENSION, ModelType::Stl,
+        )?);
+        to_ret.append(&mut parse_models_dir(
+            &models_path, REL_COLLADA_MODELS_PATH, COLLADA_EXTENSION, ModelType::Collada,
+        )?);
+        to_ret.append(&mut parse_models_dir(
+            &models_path, REL_COOKED_MODELS_PATH, COOKED_EXTENSION, ModelType::Cooked,
+        )?);
     }
     /*
     //for now textures are referenced via their models
@@ -0,0 +1,59 @@
+/* Wavefront import on generated grids, a few hundred thousand to a few million lines.
+ * Compares a single thread against a pool per core, the output is identical either way.
+ * cargo bench -p renderer --bench wavefront
+ */
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use renderer::importer::{
+    wavefront::Wavefront,
+    ImportSettings, Importer, MemoryResolver,
+};
+
+//size x size quads, per vertex positions, uvs and normals like a scan exported from blender
+fn grid_obj(size: usize) -> String {
+    let mut obj = String::from("o grid\n");
+    for y in 0..=size {
+        for x in 0..=size {
+            let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+            let z = (u * 20.0).sin() * (v * 20.0).cos();
+            obj.push_str(&format!("v {u:.6} {v:.6} {z:.6}\nvt {u:.6} {v:.6}\n"));
+            obj.push_str(&format!("vn {:.6} {:.6} 1.000000\n", -z * 0.1, z * 0.1));
+        }
+    }
+    for y in 0..size {
+        for x in 0..size {
+            let i = y * (size + 1) + x + 1;
+            let j = i + size + 1;
+            let (k, l) = (i + 1, j + 1);
+            obj.push_str(&format!("f {i}/{i}/{i} {k}/{k}/{k} {l}/{l}/{l} {j}/{j}/{j}\n"));
+        }
+    }
+    obj
+}
+
+fn wavefront_import(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wavefront_import");
+    group.sample_size(10);
+    for size in [256, 768] {
+        let obj = grid_obj(size);
+        group.throughput(Throughput::Bytes(obj.len() as u64));
+        for (label, threads) in [("single", Some(1)), ("parallel", None)] {
+            let settings = ImportSettings{
+                threads,
+                ..Default::default()
+            };
+            group.bench_with_input(BenchmarkId::new(label, size), &obj, |b, obj| {
+                b.iter(|| {
+                    Wavefront::new(None)
+                        .import_bytes(obj.as_bytes(), "grid", &MemoryResolver::new(), &settings)
+                        .unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, wavefront_import);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// vertices packed by model::quantize, the normalized formats arrive in [0, 1] or [-1, 1]
+// and Dequantize takes them back to model space, see quantize.rs for the layout
+// glslc quantized.vert -o vert.spv
+
+layout (push_constant) uniform Dequantize {
+    vec4 position_offset;
+    vec4 position_scale;
+    // xy offset, zw scale
+    vec4 uv_offset_scale;
+} dq;
+
+layout (location = 0) in vec4 q_pos;
+layout (location = 1) in vec2 q_uv;
+layout (location = 2) in vec2 q_norm;
+
+layout (location = 0) out vec2 o_uv;
+layout (location = 1) out vec3 o_norm;
+
+// same as octahedral_decode
+vec3 octahedral_decode(vec2 e) {
+    vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+    float t = max(-n.z, 0.0);
+    n.x += n.x >= 0.0 ? -t : t;
+    n.y += n.y >= 0.0 ? -t : t;
+    return normalize(n);
+}
+
+void main() {
+    o_uv = dq.uv_offset_scale.xy + q_uv * dq.uv_offset_scale.zw;
+    o_norm = octahedral_decode(q_norm);
+    gl_Position = dq.position_offset + q_pos * dq.position_scale;
+}
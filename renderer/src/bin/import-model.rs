@@ -1,28 +1,100 @@
 /*
  * I should probably add a backup as this gets more "serious"
  */
-use std::fs::{
-    read_to_string, File, OpenOptions,
-};
-
 use clap::Parser;
 
 use renderer::{
-    importer::{
-        wavefront::Wavefront,
-        Importer,
+    exporter::registry::ExporterRegistry,
+    importer::{registry::ImporterRegistry, ImportSettings},
+    model::{
+        conversion::Conversion,
+        block_compression::compress_textures,
+        lod::{generate_lods, LodSettings, LodTarget},
+        meshlet::{generate_meshlets, MeshletSettings},
+        mipmap::{generate_mips, MipFilter, MipSettings},
+        optimize::{optimize, OptimizeSettings},
+        quantize::{generate_quantized, PositionEncoding, QuantizeSettings},
+        texture_format::TextureFormat,
+        validate::{repair, validate, ValidateSettings},
+        Model,
     },
-    model::Model,
 };
 
-const WAVEFRONT: &str = "wavefront";
-
-/// CLI tool to parse model files exported by blender
+/// CLI tool to parse model files exported by blender and convert them to other formats,
+/// or cook them to .rmdl for the renderer to load directly
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short, long, default_value = WAVEFRONT)]
-    parser: String,
+    /// Registered importer name, eg wavefront, ply, stl, collada,
+    /// picked from each in_file's extension when not given
+    #[arg(short, long)]
+    parser: Option<String>,
+
+    /// Registered exporter name, eg cooked, wavefront, gltf,
+    /// picked from each out_file's extension when not given
+    #[arg(short, long)]
+    exporter: Option<String>,
+
+    /// Convert from blender's z up, OpenGL uv conventions to the renderer's
+    #[arg(short, long)]
+    blender: bool,
+
+    /// Uniform scale applied on import, eg 0.01 for a model authored in centimeters
+    #[arg(short, long)]
+    scale: Option<f32>,
+
+    /// Reorder triangles and vertices for the GPU before exporting and print the
+    /// cache statistics, cooking always does this
+    #[arg(long)]
+    optimize: bool,
+
+    /// Also sort triangles to reduce overdraw when optimizing
+    #[arg(long)]
+    overdraw: bool,
+
+    /// Report degenerate triangles, duplicate, NaN and unused vertices, inconsistent
+    /// winding and non manifold edges in each imported model
+    #[arg(long)]
+    validate: bool,
+
+    /// Weld duplicate vertices, remove degenerate triangles and unused vertices and
+    /// unify the winding before anything else
+    #[arg(long)]
+    repair: bool,
+
+    /// Generate simplified levels of detail keeping these fractions of the triangles,
+    /// eg 0.5,0.25,0.125, cooked models store them
+    #[arg(long, value_delimiter = ',')]
+    lods: Vec<f32>,
+
+    /// Split the triangles into meshlets of at most 64 vertices and 124 triangles with
+    /// culling bounds, cooked models store them
+    #[arg(long)]
+    meshlets: bool,
+
+    /// Generate mip chains for every texture with this filter, box, kaiser or lanczos,
+    /// cooked models store them
+    #[arg(long)]
+    mips: Option<String>,
+
+    /// Keep the alpha tested coverage of mask materials' base color in every mip
+    #[arg(long)]
+    alpha_coverage: bool,
+
+    /// Block compress textures, bc1, bc3, bc5 or bc7, after any --mips so every level is
+    /// compressed, base color and emissive as srgb. bc5 is only used for normal maps, the
+    /// other slots get bc7, and bc1 base colors that are blended or masked get bc3
+    #[arg(long)]
+    compress: Option<String>,
+
+    /// Quantize the vertices for the GPU last and print the error it introduced, cooked
+    /// models store the quantized copy and the renderer draws it
+    #[arg(short, long)]
+    quantize: bool,
+
+    /// Quantize positions as f16 rather than normalized against the bounds
+    #[arg(long)]
+    half_positions: bool,
 
     #[arg(short, long)]
     in_files: Vec<String>,
@@ -31,37 +103,94 @@ struct Args {
     out_files: Vec<String>,
 }
 
-fn make_importer(parser: &str) -> Result<impl Importer, String> {
-    match parser.trim() {
-        WAVEFRONT => {
-            Ok(Wavefront::new(None))
-        },
-        &_ => {
-            Err(format!("Unrecognized file_type passed: '{parser}', exiting"))
-        },
-    }
-}
-
 //NOTE, only handles a single model per input...for now
 fn main() {
     let args = Args::parse();
     if args.out_files.len() != args.in_files.len() {
         panic!("in_files count must be equal to out_files count");
     }
-    let parser = make_importer(&args.parser).unwrap();
-    let models: Vec<Model> = args.in_files.iter().map(|f| {
-        //this is really dumb...but im new to rust so w/e, ill develop better patterns
-        //not a critical loop anyway
-        let lines: Vec<String> = read_to_string(f)
-            .expect(format!("Could not open in_file: '{f}' for reading.").as_str())
-            .lines().map(|l| l.to_string()).collect();
-        parser.generate_model(lines.iter()).unwrap()
+    let importers = ImporterRegistry::default();
+    let exporters = ExporterRegistry::default();
+    let mut conversion = if args.blender {
+        Conversion::blender_to_vulkan()
+    } else {
+        Conversion::IDENTITY
+    };
+    conversion.scale *= args.scale.unwrap_or(1.0);
+    let settings = ImportSettings{
+        conversion,
+        ..Default::default()
+    };
+    let mut models: Vec<Model> = args.in_files.iter().map(|f| {
+        let parser = match &args.parser {
+            Some(parser) => importers.get_by_name(parser),
+            None => importers.get_by_location(f),
+        }.unwrap();
+        parser.generate_model_with_settings(f, &settings).unwrap()
     }).collect();
-    for (i, m) in models.iter().enumerate() {
-        let out_file_name = &args.out_files[i];
-        let out_file = OpenOptions::new().create(true).write(true).open(out_file_name);
-        //m.write_to_disk(out_file).unwrap();
-        //println!("mesh: {:?}", m);
-        //write!("{}", m);
+    for (m, out_file) in models.iter_mut().zip(args.out_files.iter()) {
+        if args.validate {
+            println!("{out_file}: {}", validate(m, &ValidateSettings::default()));
+        }
+        if args.repair {
+            println!("{out_file}: {}", repair(m, &ValidateSettings::default()).unwrap());
+        }
+        if !args.lods.is_empty() {
+            let settings = LodSettings{
+                targets: args.lods.iter().map(|r| LodTarget::Ratio(*r)).collect(),
+            };
+            generate_lods(m, &settings).unwrap();
+            //a level stops short of its ratio when nothing more can collapse
+            let triangles = m.indeces.len() / 3;
+            for (i, (lod, ratio)) in m.lods.iter().zip(args.lods.iter()).enumerate() {
+                let kept = lod.indeces.len() / 3;
+                println!(
+                    "{out_file}: lod {} {kept} triangles, {:.3} of the model for {ratio}, error {}",
+                    i + 1, kept as f32 / triangles.max(1) as f32, lod.error,
+                );
+            }
+        }
+        if args.meshlets {
+            generate_meshlets(m, &MeshletSettings::default()).unwrap();
+            println!("{out_file}: {} meshlets", m.meshlets.meshlets.len());
+        }
+        if let Some(filter) = &args.mips {
+            let settings = MipSettings{
+                filter: MipFilter::from_name(filter)
+                    .unwrap_or_else(|| panic!("unknown mip filter: {filter}")),
+                alpha_coverage: args.alpha_coverage,
+            };
+            println!("{out_file}: {} mip chains", generate_mips(m, &settings));
+        }
+        if let Some(format) = &args.compress {
+            let format = TextureFormat::from_name(format)
+                .unwrap_or_else(|| panic!("unknown texture compression: {format}"));
+            println!("{out_file}: {} textures compressed", compress_textures(m, format));
+        }
+        if args.optimize {
+            let settings = OptimizeSettings{
+                overdraw: args.overdraw,
+                ..Default::default()
+            };
+            println!("{out_file}: {}", optimize(m, &settings).unwrap());
+        }
+        if args.quantize {
+            let settings = QuantizeSettings{
+                position: if args.half_positions {
+                    PositionEncoding::Half
+                } else {
+                    PositionEncoding::Normalized
+                },
+            };
+            generate_quantized(m, &settings).unwrap();
+            if let Some(quantized) = &m.quantized {
+                println!("{out_file}:\n{}", quantized.report);
+            }
+        }
+        let exporter = match &args.exporter {
+            Some(exporter) => exporters.get_by_name(exporter),
+            None => exporters.get_by_location(out_file),
+        }.unwrap();
+        exporter.write_model(m, out_file).unwrap();
     }
 }
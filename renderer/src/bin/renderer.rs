@@ -4,6 +4,7 @@ use renderer::{
     window::Window,
     vulkan::Vulkan,
     model::model_manager::ModelManager,
+    importer::registry::ImporterRegistry,
 };
 
 use asset::source::{
@@ -26,7 +27,9 @@ fn get_asset_source(manifest: &str) -> Result<impl AssetSource, String> {
 fn main() -> Result<(), String> {
     let args = Args::parse();
     let asset_source = get_asset_source(&args.assets_manifest)?;
-    let mut model_manager = ModelManager::new(Box::new(asset_source));
+    let mut model_manager = ModelManager::new(
+        Box::new(asset_source), ImporterRegistry::default(),
+    );
     //TODO these shouldn't be called (Window|Vulkan)::new()
     let window = Window::new(1920, 1080, None).unwrap();
     let vulkan = Vulkan::new(&window).unwrap();
@@ -0,0 +1,46 @@
+/* Writes the .rmdl cooked format, see model::cooked for the layout.
+ * Textures are stored in the file, nothing goes to the sink.
+ * Cooking optimizes the triangle and vertex order, see model::optimize.
+ */
+
+use std::io::Write;
+
+use super::{
+    Exporter, Sink,
+};
+
+use crate::{
+    model::{
+        cooked::write_optimized,
+        optimize::OptimizeSettings,
+        Model,
+    },
+    RenderResult,
+};
+
+#[derive(Default)]
+pub struct Cooked {
+    settings: OptimizeSettings,
+}
+
+impl Cooked {
+    pub fn new() -> Self {
+        Self{
+            settings: OptimizeSettings::default(),
+        }
+    }
+
+    pub fn with_settings(settings: OptimizeSettings) -> Self {
+        Self{
+            settings,
+        }
+    }
+}
+
+impl Exporter for Cooked {
+    fn export(
+        &self, model: &Model, _name: &str, out: &mut dyn Write, _sink: &mut dyn Sink,
+    ) -> RenderResult<()> {
+        write_optimized(model, out, &self.settings).map(|_| ())
+    }
+}
@@ -0,0 +1,387 @@
+/* glTF 2.0 writer, a .gltf json file plus a single .bin buffer next to it.
+ * Every attribute gets its own tightly packed buffer view, the indeces share one view
+ * with an accessor per SubMesh, each SubMesh becomes a primitive of a single mesh.
+ * Materials are glTF's own model, they're written as they are, each texture slot with
+ * its uv set and a sampler, samplers and images are shared where they're the same.
+ * Model uvs follow the OBJ convention with v going up from the bottom of the image,
+ * glTF's v goes down from the top so it's flipped on the way out.
+ */
+
+use std::io::Write;
+
+use serde_json::{json, Value};
+
+use super::{
+    submeshes, texture_reference, Exporter, MeshAttributes, Sink,
+};
+
+use crate::{
+    model::{
+        material::{AlphaMode, SamplerSettings, TextureFilter, TextureSlotKind, TextureWrap},
+        Model,
+    },
+    RenderResult,
+};
+
+pub const GLTF_TYPE: &str = "gltf";
+pub const GLTF_EXTENSION: &str = "gltf";
+
+const GLTF_VERSION: &str = "2.0";
+const GLTF_GENERATOR: &str = "renderer import-model";
+//from the spec, https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_MODE_POINTS: u32 = 0;
+const GLTF_MODE_TRIANGLES: u32 = 4;
+const GLTF_NEAREST: u32 = 9728;
+const GLTF_LINEAR: u32 = 9729;
+//NEAREST_MIPMAP_NEAREST, then LINEAR_, NEAREST_MIPMAP_LINEAR and LINEAR_MIPMAP_LINEAR
+const GLTF_MIPMAP_FILTERS: u32 = 9984;
+const GLTF_CLAMP_TO_EDGE: u32 = 33071;
+const GLTF_MIRRORED_REPEAT: u32 = 33648;
+const GLTF_REPEAT: u32 = 10497;
+
+#[derive(Default)]
+pub struct Gltf {}
+
+impl Gltf {
+    pub fn new() -> Self {
+        Self{}
+    }
+}
+
+fn gltf_sampler(sampler: &SamplerSettings) -> Value {
+    let filter = |filter: TextureFilter| match filter {
+        TextureFilter::Nearest => GLTF_NEAREST,
+        TextureFilter::Linear => GLTF_LINEAR,
+    };
+    let linear = |filter: TextureFilter| (filter == TextureFilter::Linear) as u32;
+    let wrap = |wrap: TextureWrap| match wrap {
+        TextureWrap::Repeat => GLTF_REPEAT,
+        TextureWrap::MirroredRepeat => GLTF_MIRRORED_REPEAT,
+        TextureWrap::ClampToEdge => GLTF_CLAMP_TO_EDGE,
+    };
+    json!({
+        "magFilter": filter(sampler.mag_filter),
+        "minFilter": GLTF_MIPMAP_FILTERS + linear(sampler.min_filter)
+            + 2 * linear(sampler.mipmap_filter),
+        "wrapS": wrap(sampler.wrap_u),
+        "wrapT": wrap(sampler.wrap_v),
+    })
+}
+
+//the index of value in values, added when it isn't there yet
+fn shared(values: &mut Vec<Value>, value: Value) -> usize {
+    values.iter().position(|v| *v == value).unwrap_or_else(|| {
+        values.push(value);
+        values.len() - 1
+    })
+}
+
+//collects the .bin along with the views and accessors pointing into it
+struct BufferBuilder {
+    data: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl BufferBuilder {
+    fn new() -> Self {
+        Self{
+            data: vec![],
+            views: vec![],
+            accessors: vec![],
+        }
+    }
+
+    //views have to start 4 byte aligned
+    fn add_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.data.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.data.extend_from_slice(bytes);
+        self.views.len() - 1
+    }
+
+    fn add_accessor(&mut self, accessor: Value) -> usize {
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    //a float attribute with N components per vertex, returns the accessor
+    fn add_attribute<const N: usize>(
+        &mut self, values: &[[f32; N]], with_bounds: bool,
+    ) -> usize {
+        let bytes: Vec<u8> = values.iter().flatten().flat_map(|f| f.to_le_bytes()).collect();
+        let view = self.add_view(&bytes, GLTF_ARRAY_BUFFER);
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": GLTF_FLOAT,
+            "count": values.len(),
+            "type": match N {
+                2 => "VEC2",
+                3 => "VEC3",
+                _ => "VEC4",
+            },
+        });
+        //required for POSITION
+        if with_bounds && !values.is_empty() {
+            let mut min = [f32::MAX; N];
+            let mut max = [f32::MIN; N];
+            for value in values {
+                for c in 0..N {
+                    min[c] = min[c].min(value[c]);
+                    max[c] = max[c].max(value[c]);
+                }
+            }
+            accessor["min"] = json!(min.to_vec());
+            accessor["max"] = json!(max.to_vec());
+        }
+        self.add_accessor(accessor)
+    }
+}
+
+impl Exporter for Gltf {
+    fn export(
+        &self, model: &Model, name: &str, out: &mut dyn Write, sink: &mut dyn Sink,
+    ) -> RenderResult<()> {
+        let attributes = MeshAttributes::new(&model.mesh);
+        let mut buffer = BufferBuilder::new();
+        let mut primitive_attributes = json!({
+            "POSITION": buffer.add_attribute(&attributes.positions, true),
+        });
+        if let Some(normals) = &attributes.normals {
+            primitive_attributes["NORMAL"] = json!(buffer.add_attribute(normals, false));
+        }
+        if let Some(tangents) = &attributes.tangents {
+            primitive_attributes["TANGENT"] = json!(buffer.add_attribute(tangents, false));
+        }
+        if let Some(uvs) = &attributes.uvs {
+            let flipped: Vec<[f32; 2]> = uvs.iter().map(|[u, v]| [*u, 1.0 - v]).collect();
+            primitive_attributes["TEXCOORD_0"] = json!(buffer.add_attribute(&flipped, false));
+        }
+        if let Some(colors) = &attributes.colors {
+            primitive_attributes["COLOR_0"] = json!(buffer.add_attribute(colors, false));
+        }
+        let mut primitives: Vec<Value> = vec![];
+        if model.indeces.is_empty() {
+            //nothing to index, a point cloud
+            primitives.push(json!({
+                "attributes": primitive_attributes,
+                "mode": GLTF_MODE_POINTS,
+            }));
+        } else {
+            let bytes: Vec<u8> = model.indeces.iter().flat_map(|i| i.to_le_bytes()).collect();
+            let view = buffer.add_view(&bytes, GLTF_ELEMENT_ARRAY_BUFFER);
+            for submesh in submeshes(model) {
+                if submesh.first_index + submesh.index_count > model.indeces.len() {
+                    return Err(format!(
+                        "submesh '{}' is out of range of the indeces", submesh.name,
+                    ));
+                }
+                let indeces = buffer.add_accessor(json!({
+                    "bufferView": view,
+                    "byteOffset": submesh.first_index * 4,
+                    "componentType": GLTF_UNSIGNED_INT,
+                    "count": submesh.index_count,
+                    "type": "SCALAR",
+                }));
+                let mut primitive = json!({
+                    "attributes": primitive_attributes,
+                    "indices": indeces,
+                    "mode": GLTF_MODE_TRIANGLES,
+                });
+                if let Some(material) = submesh.material {
+                    primitive["material"] = json!(material);
+                }
+                primitives.push(primitive);
+            }
+        }
+        let mut materials: Vec<Value> = vec![];
+        let (mut images, mut samplers, mut textures) = (vec![], vec![], vec![]);
+        for (i, material) in model.materials.iter().enumerate() {
+            let mut gltf_material = json!({
+                "name": material.name,
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": material.base_color,
+                    "metallicFactor": material.metallic,
+                    "roughnessFactor": material.roughness,
+                },
+                "emissiveFactor": material.emissive,
+                "alphaMode": match material.alpha_mode {
+                    AlphaMode::Opaque => "OPAQUE",
+                    AlphaMode::Mask => "MASK",
+                    AlphaMode::Blend => "BLEND",
+                },
+                "doubleSided": material.double_sided,
+            });
+            if material.alpha_mode == AlphaMode::Mask {
+                gltf_material["alphaCutoff"] = json!(material.alpha_cutoff);
+            }
+            for (kind, slot) in material.textures() {
+                let uri = texture_reference(slot, kind, name, i, sink)?;
+                let source = shared(&mut images, json!({ "uri": uri }));
+                let sampler = shared(&mut samplers, gltf_sampler(&slot.sampler));
+                let index = shared(&mut textures, json!({ "source": source, "sampler": sampler }));
+                let mut info = json!({ "index": index, "texCoord": slot.uv_set });
+                match kind {
+                    TextureSlotKind::BaseColor => {
+                        gltf_material["pbrMetallicRoughness"]["baseColorTexture"] = info;
+                    },
+                    TextureSlotKind::MetallicRoughness => {
+                        gltf_material["pbrMetallicRoughness"]["metallicRoughnessTexture"] = info;
+                    },
+                    TextureSlotKind::Normal => {
+                        info["scale"] = json!(slot.scale);
+                        gltf_material["normalTexture"] = info;
+                    },
+                    TextureSlotKind::Occlusion => {
+                        info["strength"] = json!(slot.scale);
+                        gltf_material["occlusionTexture"] = info;
+                    },
+                    TextureSlotKind::Emissive => gltf_material["emissiveTexture"] = info,
+                }
+            }
+            materials.push(gltf_material);
+        }
+        let bin_file = format!("{name}.bin");
+        let mut gltf = json!({
+            "asset": { "version": GLTF_VERSION, "generator": GLTF_GENERATOR },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "name": model.name, "mesh": 0 }],
+            "meshes": [{ "name": model.name, "primitives": primitives }],
+            "buffers": [{ "uri": bin_file, "byteLength": buffer.data.len() }],
+            "bufferViews": buffer.views,
+            "accessors": buffer.accessors,
+        });
+        //empty arrays aren't allowed
+        if !materials.is_empty() {
+            gltf["materials"] = json!(materials);
+        }
+        if !textures.is_empty() {
+            gltf["textures"] = json!(textures);
+            gltf["samplers"] = json!(samplers);
+            gltf["images"] = json!(images);
+        }
+        sink.write(&bin_file, &buffer.data)?;
+        serde_json::to_writer_pretty(out, &gltf).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{
+        exporter::{
+            gltf::Gltf,
+            Exporter, MemorySink,
+        },
+        importer::{
+            collada::Collada,
+            Importer,
+        },
+        model::{
+            material::TextureSlotKind,
+            Model,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    //reads a float or u32 accessor back out of the buffer
+    fn read_accessor(gltf: &Value, bin: &[u8], accessor: usize) -> Vec<Vec<f32>> {
+        let accessor = &gltf["accessors"][accessor];
+        let view = &gltf["bufferViews"][accessor["bufferView"].as_u64().unwrap() as usize];
+        let components = match accessor["type"].as_str().unwrap() {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            _ => 4,
+        };
+        let offset = view["byteOffset"].as_u64().unwrap() as usize
+            + accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let float = accessor["componentType"].as_u64().unwrap() == 5126;
+        (0..accessor["count"].as_u64().unwrap() as usize).map(|i| {
+            (0..components).map(|c| {
+                let at = offset + (i * components + c) * 4;
+                let bytes: [u8; 4] = bin[at..at + 4].try_into().unwrap();
+                if float { f32::from_le_bytes(bytes) } else { u32::from_le_bytes(bytes) as f32 }
+            }).collect()
+        }).collect()
+    }
+
+    fn export(model: &Model) -> (Value, MemorySink) {
+        let mut out = vec![];
+        let mut sink = MemorySink::new();
+        Gltf::new().export(model, "scene", &mut out, &mut sink).unwrap();
+        (serde_json::from_slice(&out).unwrap(), sink)
+    }
+
+    #[test]
+    fn gltf_export_collada_scene() {
+        let model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        let (gltf, sink) = export(&model);
+        assert_eq!(gltf["asset"]["version"], "2.0");
+        let bin = sink.get("scene.bin").expect("bin written");
+        assert_eq!(gltf["buffers"][0]["byteLength"].as_u64().unwrap() as usize, bin.len());
+        let primitives = gltf["meshes"][0]["primitives"].as_array().unwrap();
+        assert_eq!(primitives.len(), model.submeshes.len(), "a primitive per submesh");
+        let vertices = model.get_vertices().unwrap();
+        for (primitive, submesh) in primitives.iter().zip(model.submeshes.iter()) {
+            let attribute = |name: &str| {
+                read_accessor(&gltf, bin, primitive["attributes"][name].as_u64().unwrap() as usize)
+            };
+            let (positions, normals, uvs) =
+                (attribute("POSITION"), attribute("NORMAL"), attribute("TEXCOORD_0"));
+            assert_eq!(positions.len(), vertices.len(), "position count");
+            let indeces =
+                read_accessor(&gltf, bin, primitive["indices"].as_u64().unwrap() as usize);
+            let expected =
+                &model.indeces[submesh.first_index..submesh.first_index + submesh.index_count];
+            assert_eq!(indeces.len(), expected.len(), "{} index count", submesh.name);
+            for (i, e) in indeces.iter().zip(expected.iter()) {
+                assert_eq!(i[0] as u32, *e, "{} index", submesh.name);
+                let v = &vertices[*e as usize];
+                let i = i[0] as usize;
+                assert_eq!(positions[i], vec![v.pos.x, v.pos.y, v.pos.z]);
+                assert_eq!(normals[i], vec![v.norm.x, v.norm.y, v.norm.z]);
+                assert_eq!(uvs[i], vec![v.uv.x, 1.0 - v.uv.y], "flipped v");
+            }
+            assert_eq!(
+                primitive["material"].as_u64().map(|m| m as usize), submesh.material,
+                "{} material", submesh.name,
+            );
+        }
+        //the textured material references its own copy of the texture, the flat one is
+        //only factors
+        assert_eq!(gltf["materials"].as_array().unwrap().len(), model.materials.len());
+        assert_eq!(gltf["images"].as_array().unwrap().len(), 1);
+        let base_color = model.materials[0].texture(TextureSlotKind::BaseColor).unwrap();
+        assert_eq!(gltf["images"][0]["uri"], "scene_0.png");
+        let png = image::load_from_memory(sink.get("scene_0.png").unwrap()).unwrap();
+        assert_eq!(png.to_rgba8(), base_color.texture.image);
+        let textured = &gltf["materials"][0]["pbrMetallicRoughness"];
+        assert_eq!(textured["baseColorTexture"]["index"], 0);
+        assert_eq!(gltf["samplers"][0]["wrapS"], 10497, "repeat");
+        assert_eq!(gltf["samplers"][0]["minFilter"], 9987, "linear mipmap linear");
+        let flat = &gltf["materials"][1];
+        assert!(flat["pbrMetallicRoughness"]["baseColorTexture"].is_null());
+        let factor = &flat["pbrMetallicRoughness"]["baseColorFactor"];
+        assert!((factor[0].as_f64().unwrap() - 0.8).abs() < 1e-6, "{factor}");
+        assert_eq!(flat["alphaMode"], "OPAQUE");
+        let min = &gltf["accessors"][0]["min"];
+        assert!(min.is_array(), "position bounds");
+    }
+}
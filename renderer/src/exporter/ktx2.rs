@@ -0,0 +1,224 @@
+/* Writes KTX2 textures, see importer::ktx2 for the layout. Levels are written smallest
+ * first, each aligned to its format's block size, and the data format descriptor is the
+ * basic one, the color model, transfer function and where each channel is in a block.
+ * There's no key/value data and no supercompression.
+ */
+
+use std::io::Write;
+
+use crate::{
+    importer::ktx2::{KTX2_HEADER_SIZE, KTX2_IDENTIFIER, KTX2_LEVEL_INDEX_SIZE},
+    model::texture_format::{EncodedTexture, TextureFormat},
+    RenderResult,
+};
+
+const KHR_DF_VERSION: u16 = 2;
+const KHR_DF_MODEL_RGBSDA: u8 = 1;
+const KHR_DF_MODEL_BC1A: u8 = 128;
+const KHR_DF_MODEL_BC3: u8 = 130;
+const KHR_DF_MODEL_BC5: u8 = 132;
+const KHR_DF_MODEL_BC7: u8 = 134;
+const KHR_DF_PRIMARIES_BT709: u8 = 1;
+const KHR_DF_TRANSFER_LINEAR: u8 = 1;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+const KHR_DF_CHANNEL_ALPHA: u8 = 15;
+//set on alpha samples of srgb formats, alpha is never srgb
+const KHR_DF_SAMPLE_DATATYPE_LINEAR: u8 = 0x10;
+const KHR_DF_SAMPLE_DATATYPE_SIGNED: u8 = 0x40;
+const KHR_DF_SAMPLE_DATATYPE_FLOAT: u8 = 0x80;
+
+//the basic data format descriptor, with its total size in front
+fn data_format_descriptor(format: TextureFormat) -> Vec<u8> {
+    //channel, bit offset and bit length of each sample
+    let (model, samples): (u8, &[(u8, u16, u8)]) = match format {
+        TextureFormat::Rgba8 | TextureFormat::Rgba8Srgb => (KHR_DF_MODEL_RGBSDA, &[
+            (0, 0, 8), (1, 8, 8), (2, 16, 8), (KHR_DF_CHANNEL_ALPHA, 24, 8),
+        ]),
+        //BC1A's channel 1 is color with punch through alpha
+        TextureFormat::Bc1 | TextureFormat::Bc1Srgb => (KHR_DF_MODEL_BC1A, &[(1, 0, 64)]),
+        TextureFormat::Bc3 | TextureFormat::Bc3Srgb => (KHR_DF_MODEL_BC3, &[
+            (KHR_DF_CHANNEL_ALPHA, 0, 64), (0, 64, 64),
+        ]),
+        TextureFormat::Bc5 => (KHR_DF_MODEL_BC5, &[(0, 0, 64), (1, 64, 64)]),
+        TextureFormat::Bc7 | TextureFormat::Bc7Srgb => (KHR_DF_MODEL_BC7, &[(0, 0, 128)]),
+        TextureFormat::Rgba16F => (KHR_DF_MODEL_RGBSDA, &[
+            (0, 0, 16), (1, 16, 16), (2, 32, 16), (KHR_DF_CHANNEL_ALPHA, 48, 16),
+        ]),
+        TextureFormat::Rgba32F => (KHR_DF_MODEL_RGBSDA, &[
+            (0, 0, 32), (1, 32, 32), (2, 64, 32), (KHR_DF_CHANNEL_ALPHA, 96, 32),
+        ]),
+    };
+    let block_size = 24 + 16 * samples.len();
+    let extent = format.block_extent() as u8 - 1;
+    let mut dfd = vec![];
+    dfd.extend((4 + block_size as u32).to_le_bytes());
+    //vendor and descriptor type, both 0 for Khronos' basic descriptor
+    dfd.extend(0u32.to_le_bytes());
+    dfd.extend(KHR_DF_VERSION.to_le_bytes());
+    dfd.extend((block_size as u16).to_le_bytes());
+    let transfer = if format.is_srgb() { KHR_DF_TRANSFER_SRGB } else { KHR_DF_TRANSFER_LINEAR };
+    dfd.extend([model, KHR_DF_PRIMARIES_BT709, transfer, 0]);
+    dfd.extend([extent, extent, 0, 0]);
+    dfd.extend([format.block_bytes() as u8, 0, 0, 0, 0, 0, 0, 0]);
+    //the values that map to 0 and 1, -1 and 1 as 32 bit floats for the float formats
+    let (lower, upper) = if format.is_float() {
+        ((-1.0f32).to_bits(), 1.0f32.to_bits())
+    } else if format.is_compressed() {
+        (0, u32::MAX)
+    } else {
+        (0, u8::MAX as u32)
+    };
+    for &(channel, offset, length) in samples {
+        let linear = format.is_srgb() && channel == KHR_DF_CHANNEL_ALPHA;
+        let qualifiers = if format.is_float() {
+            KHR_DF_SAMPLE_DATATYPE_FLOAT | KHR_DF_SAMPLE_DATATYPE_SIGNED
+        } else if linear {
+            KHR_DF_SAMPLE_DATATYPE_LINEAR
+        } else {
+            0
+        };
+        dfd.extend(offset.to_le_bytes());
+        dfd.extend([length - 1, channel | qualifiers, 0, 0, 0, 0]);
+        dfd.extend(lower.to_le_bytes());
+        dfd.extend(upper.to_le_bytes());
+    }
+    dfd
+}
+
+pub fn write_ktx2(texture: &EncodedTexture, out: &mut dyn Write) -> RenderResult<()> {
+    texture.check()?;
+    let dfd = data_format_descriptor(texture.format);
+    let count = texture.levels.len();
+    let dfd_offset = KTX2_HEADER_SIZE + count * KTX2_LEVEL_INDEX_SIZE;
+    //every block size is already a multiple of 4
+    let alignment = texture.format.block_bytes();
+    let mut offsets = vec![0; count];
+    let mut end = dfd_offset + dfd.len();
+    for level in (0..count).rev() {
+        offsets[level] = end.next_multiple_of(alignment);
+        end = offsets[level] + texture.levels[level].len();
+    }
+
+    let mut data = Vec::with_capacity(end);
+    data.extend_from_slice(KTX2_IDENTIFIER);
+    let layers = if texture.layers > 1 { texture.layers } else { 0 };
+    let header = [
+        texture.format.vk_format().as_raw() as u32,
+        //type size, 1 for block compressed and 8 bit formats
+        texture.format.component_bytes() as u32,
+        texture.width,
+        texture.height,
+        0,
+        layers,
+        texture.faces,
+        count as u32,
+        0,
+        dfd_offset as u32,
+        dfd.len() as u32,
+        0,
+        0,
+    ];
+    header.iter().for_each(|v| data.extend(v.to_le_bytes()));
+    //no supercompression global data
+    data.extend([0; 16]);
+    for (offset, level) in offsets.iter().zip(texture.levels.iter()) {
+        let length = level.len() as u64;
+        for v in [*offset as u64, length, length] {
+            data.extend(v.to_le_bytes());
+        }
+    }
+    data.extend(dfd);
+    for level in (0..count).rev() {
+        data.resize(offsets[level], 0);
+        data.extend_from_slice(&texture.levels[level]);
+    }
+    out.write_all(&data).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::Rgba;
+
+    use crate::exporter::ktx2::write_ktx2;
+    use crate::importer::ktx2::read_ktx2;
+    use crate::model::{
+        block_compression::compress_texture,
+        material::{load_texture, Texture, TextureImage},
+        texture_format::{EncodedTexture, TextureFormat},
+    };
+
+    fn ktx2(texture: &EncodedTexture) -> Vec<u8> {
+        let mut data = vec![];
+        write_ktx2(texture, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn ktx2_round_trip() {
+        //a bc7 cubemap with its mips, each face its own bytes
+        let cubemap = EncodedTexture{
+            format: TextureFormat::Bc7Srgb,
+            width: 8,
+            height: 8,
+            layers: 1,
+            faces: 6,
+            levels: vec![
+                (0..6 * 64).map(|i| (i / 64) as u8).collect(),
+                (0..6 * 16).map(|i| (i / 16 + 10) as u8).collect(),
+                (0..6 * 16).map(|i| (i / 16 + 20) as u8).collect(),
+                (0..6 * 16).map(|i| (i / 16 + 30) as u8).collect(),
+            ],
+        };
+        let data = ktx2(&cubemap);
+        assert_eq!(&data[12..16], &146u32.to_le_bytes());
+        assert_eq!(read_ktx2(&data).unwrap(), cubemap);
+        //levels are smallest first and block aligned
+        let offset = |level: usize| {
+            let entry = 80 + 24 * level;
+            u64::from_le_bytes(data[entry..entry + 8].try_into().unwrap()) as usize
+        };
+        assert!(offset(3) < offset(2) && offset(1) < offset(0));
+        assert!((0..4).all(|level| offset(level) % 16 == 0));
+        assert_eq!(offset(0) + 6 * 64, data.len());
+
+        //an rgba8 array
+        let array = EncodedTexture{
+            format: TextureFormat::Rgba8,
+            width: 2,
+            height: 1,
+            layers: 3,
+            faces: 1,
+            levels: vec![(0..24).collect(), (100..112).collect()],
+        };
+        assert_eq!(read_ktx2(&ktx2(&array)).unwrap(), array);
+        //and a float one, its type size is a half's
+        let texels = [[-1.5, 0.0, 1.0, 1.0], [2.0, 1000.0, 0.25, 0.5]];
+        let half = EncodedTexture::from_floats(TextureFormat::Rgba16F, 2, 1, &texels);
+        let data = ktx2(&half);
+        assert_eq!(&data[16..20], &2u32.to_le_bytes());
+        assert_eq!(read_ktx2(&data).unwrap().decode_floats(0, 0, 0), texels);
+
+        //loaded as a texture, a single rgba8 image is a plain one, anything else stays
+        //encoded with its first level decoded
+        let image = TextureImage::from_fn(8, 4, |x, y| Rgba([x as u8 * 30, y as u8 * 60, 0, 255]));
+        let mut texture = Texture::new(image.clone());
+        texture.mips = vec![TextureImage::new(4, 2), TextureImage::new(2, 1)];
+        let data = ktx2(&EncodedTexture::from_texture(&texture, true));
+        assert_eq!(load_texture(&mut Cursor::new(data)).unwrap(), texture);
+        let bc1 = compress_texture(&texture, TextureFormat::Bc1);
+        let loaded = load_texture(&mut Cursor::new(ktx2(&bc1))).unwrap();
+        assert_eq!(loaded.level_count(), 3);
+        assert_eq!(loaded.bytes(), 16 + 8 + 8);
+        assert_eq!(loaded.image, bc1.decode(0, 0, 0));
+        assert_eq!(loaded.encoded, Some(bc1));
+
+        let mut truncated = ktx2(&array);
+        truncated.pop();
+        assert!(read_ktx2(&truncated).is_err());
+        let mut supercompressed = ktx2(&array);
+        supercompressed[44] = 2;
+        assert!(read_ktx2(&supercompressed).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+/* Exporters are the way back out, a Model into a file format other tools understand.
+ * The main file goes to any Write, companion files (.mtl, .bin, textures) go to a Sink
+ * under the same name the main file references them by. Textures are always written,
+ * an export never points back at the files the model was imported from.
+ */
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, ImageFormat, Rgba32FImage};
+
+use crate::{
+    importer::{ImportSource, Resolver},
+    model::{
+        material::{TextureSlot, TextureSlotKind},
+        Mesh, Model, SubMesh, Vector2, Vector4, DEFAULT_MODEL_NAME,
+    },
+    RenderResult,
+};
+
+pub mod wavefront;
+pub mod gltf;
+pub mod cooked;
+pub mod ktx2;
+pub mod registry;
+
+//where an exporter puts its companion files
+pub trait Sink {
+    fn write(&mut self, name: &str, data: &[u8]) -> RenderResult<()>;
+}
+
+//writes names relative to a directory, absolute names are used as is
+pub struct FileSink {
+    root: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(root: &Path) -> Self {
+        Self{
+            root: root.to_path_buf(),
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&mut self, name: &str, data: &[u8]) -> RenderResult<()> {
+        let path = self.root.join(name);
+        std::fs::write(&path, data).map_err(|e| {
+            format!("could not open '{}' for writing: {e}", path.to_string_lossy())
+        })
+    }
+}
+
+//keeps everything in memory, doubles as a Resolver so exports can be imported straight back
+#[derive(Default)]
+pub struct MemorySink {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(name).map(|f| f.as_slice())
+    }
+}
+
+impl Sink for MemorySink {
+    fn write(&mut self, name: &str, data: &[u8]) -> RenderResult<()> {
+        self.files.insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+impl Resolver for MemorySink {
+    fn resolve(&self, name: &str) -> RenderResult<Box<dyn ImportSource>> {
+        let data = self.files.get(name).ok_or(format!("no file named '{name}' to resolve"))?;
+        Ok(Box::new(Cursor::new(data.clone())))
+    }
+}
+
+pub trait Exporter {
+    //name is the base for any companion file names
+    fn export(
+        &self, model: &Model, name: &str, out: &mut dyn Write, sink: &mut dyn Sink,
+    ) -> RenderResult<()>;
+
+    //companion files are written next to loc, named after its file stem
+    fn write_model(&self, model: &Model, loc: &str) -> RenderResult<()> {
+        let path = Path::new(loc);
+        let file = File::create(path)
+            .map_err(|e| format!("could not open '{loc}' for writing: {e}"))?;
+        let name = path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(DEFAULT_MODEL_NAME);
+        let mut sink = FileSink::new(path.parent().unwrap_or(Path::new(".")));
+        let mut out = BufWriter::new(file);
+        self.export(model, name, &mut out, &mut sink)?;
+        out.flush().map_err(|e| e.to_string())
+    }
+}
+
+//the attributes a Mesh variant has, flattened out for writers, None if the variant
+//doesn't carry it
+pub(crate) struct MeshAttributes {
+    pub positions: Vec<[f32; 3]>,
+    pub uvs: Option<Vec<[f32; 2]>>,
+    pub normals: Option<Vec<[f32; 3]>>,
+    pub colors: Option<Vec<[f32; 4]>>,
+    pub tangents: Option<Vec<[f32; 4]>>,
+}
+
+impl MeshAttributes {
+    pub fn new(mesh: &Mesh) -> Self {
+        let mut attributes = Self{
+            positions: vec![],
+            uvs: None,
+            normals: None,
+            colors: None,
+            tangents: None,
+        };
+        let xyz = |v: &Vector4<f32>| [v.x, v.y, v.z];
+        let xyzw = |v: &Vector4<f32>| [v.x, v.y, v.z, v.w];
+        let uv = |v: &Vector2<f32>| [v.x, v.y];
+        match mesh {
+            Mesh::PositionMesh(vertices) => {
+                attributes.positions = vertices.iter().map(|v| xyz(&v.0)).collect();
+            },
+            Mesh::TextureMesh(vertices) => {
+                attributes.positions = vertices.iter().map(|v| xyz(&v.pos)).collect();
+                attributes.uvs = Some(vertices.iter().map(|v| uv(&v.uv)).collect());
+            },
+            Mesh::NormalMesh(vertices) => {
+                attributes.positions = vertices.iter().map(|v| xyz(&v.pos)).collect();
+                attributes.uvs = Some(vertices.iter().map(|v| uv(&v.uv)).collect());
+                attributes.normals = Some(vertices.iter().map(|v| xyz(&v.norm)).collect());
+            },
+            Mesh::ColorMesh(vertices) => {
+                attributes.positions = vertices.iter().map(|v| xyz(&v.pos)).collect();
+                attributes.uvs = Some(vertices.iter().map(|v| uv(&v.uv)).collect());
+                attributes.normals = Some(vertices.iter().map(|v| xyz(&v.norm)).collect());
+                attributes.colors = Some(vertices.iter().map(|v| xyzw(&v.color)).collect());
+            },
+            Mesh::TangentMesh(vertices) => {
+                attributes.positions = vertices.iter().map(|v| xyz(&v.pos)).collect();
+                attributes.uvs = Some(vertices.iter().map(|v| uv(&v.uv)).collect());
+                attributes.normals = Some(vertices.iter().map(|v| xyz(&v.norm)).collect());
+                attributes.tangents = Some(vertices.iter().map(|v| xyzw(&v.tangent)).collect());
+            },
+        }
+        attributes
+    }
+}
+
+//a model without submeshes is drawn as one
+pub(crate) fn submeshes(model: &Model) -> Vec<SubMesh> {
+    if model.submeshes.is_empty() {
+        let mut submesh = SubMesh::new(&model.name, 0, model.indeces.len(), None);
+        submesh.bounds = model.bounds;
+        return vec!(submesh);
+    }
+    model.submeshes.clone()
+}
+
+//the texture written out next to the model and the name it's referenced by. Where it
+//was loaded from is relative to the original file and means nothing next to the export,
+//so every texture is written. <name>_<material index>.png for the base color, the other
+//slots get their name on the end. Float textures are written as .exr to stay HDR
+pub(crate) fn texture_reference(
+    slot: &TextureSlot, kind: TextureSlotKind, name: &str, index: usize, sink: &mut dyn Sink,
+) -> RenderResult<String> {
+    let float = slot.texture.encoded.as_ref().filter(|encoded| encoded.format.is_float());
+    let extension = if float.is_some() { "exr" } else { "png" };
+    let location = match kind {
+        TextureSlotKind::BaseColor => format!("{name}_{index}.{extension}"),
+        _ => format!("{name}_{index}_{}.{extension}", kind.name().replace(' ', "_")),
+    };
+    let mut data = Cursor::new(vec![]);
+    match float {
+        Some(encoded) => {
+            let texels = encoded.decode_floats(0, 0, 0).concat();
+            let image = Rgba32FImage::from_raw(encoded.width, encoded.height, texels)
+                .expect("checked texture level is too short");
+            DynamicImage::ImageRgba32F(image).write_to(&mut data, ImageFormat::OpenExr)
+        },
+        None => slot.texture.write_to(&mut data, ImageFormat::Png),
+    }.map_err(|e| e.to_string())?;
+    sink.write(&location, data.get_ref())?;
+    Ok(location)
+}
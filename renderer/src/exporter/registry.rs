@@ -0,0 +1,98 @@
+/* Lookup for exporters, same idea as the ImporterRegistry, by name or by the
+ * extension of the file being written.
+ */
+
+use std::{
+    collections::HashMap,
+    path::Path,
+};
+
+use asset::asset::{
+    path_defs::{COOKED_EXTENSION, WAVEFRONT_EXTENSION},
+    COOKED_TYPE, WAVEFRONT_TYPE,
+};
+
+use super::{
+    cooked::Cooked,
+    gltf::{Gltf, GLTF_EXTENSION, GLTF_TYPE},
+    wavefront::Wavefront,
+    Exporter,
+};
+
+use crate::RenderResult;
+
+pub struct ExporterRegistry {
+    exporters: HashMap<String, Box<dyn Exporter>>,
+    //extension -> exporter name
+    extensions: HashMap<String, String>,
+}
+
+impl ExporterRegistry {
+    //empty, use default() for one with the built in exporters
+    pub fn new() -> Self {
+        Self{
+            exporters: HashMap::new(),
+            extensions: HashMap::new(),
+        }
+    }
+
+    //replaces any exporter already registered under name, extensions are
+    //repointed at the new exporter, extensions are case insensitive
+    pub fn register(&mut self, name: &str, extensions: &[&str], exporter: Box<dyn Exporter>) {
+        self.exporters.insert(name.to_string(), exporter);
+        for extension in extensions {
+            self.extensions.insert(extension.to_lowercase(), name.to_string());
+        }
+    }
+
+    pub fn get_by_name(&self, name: &str) -> RenderResult<&dyn Exporter> {
+        self.exporters.get(name.trim())
+            .map(|e| e.as_ref())
+            .ok_or(format!("no exporter registered with name: '{name}'"))
+    }
+
+    pub fn get_by_extension(&self, extension: &str) -> RenderResult<&dyn Exporter> {
+        let name = self.extensions.get(&extension.to_lowercase())
+            .ok_or(format!("no exporter registered for extension: '{extension}'"))?;
+        self.get_by_name(name)
+    }
+
+    pub fn get_by_location(&self, loc: &str) -> RenderResult<&dyn Exporter> {
+        let extension = Path::new(loc).extension()
+            .and_then(|e| e.to_str())
+            .ok_or(format!("can't determine an exporter for '{loc}', it has no extension"))?;
+        self.get_by_extension(extension)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.exporters.keys().map(|n| n.as_str()).collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for ExporterRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(WAVEFRONT_TYPE, &[WAVEFRONT_EXTENSION], Box::new(Wavefront::new()));
+        registry.register(GLTF_TYPE, &[GLTF_EXTENSION], Box::new(Gltf::new()));
+        registry.register(COOKED_TYPE, &[COOKED_EXTENSION], Box::new(Cooked::new()));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exporter::registry::ExporterRegistry;
+
+    #[test]
+    fn registry_builtin_exporters() {
+        let registry = ExporterRegistry::default();
+        assert_eq!(registry.names(), vec!["cooked", "gltf", "wavefront"]);
+        assert!(registry.get_by_location("out/cube.obj").is_ok());
+        assert!(registry.get_by_location("out/cube.GLTF").is_ok());
+        assert!(registry.get_by_location("out/cube.rmdl").is_ok());
+        assert!(registry.get_by_location("out/cube.ply").is_err());
+        assert!(registry.get_by_location("out/cube").is_err());
+    }
+}
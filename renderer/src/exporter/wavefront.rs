@@ -0,0 +1,291 @@
+/* Wavefront OBJ/MTL writer.
+ * Every Model vertex is written as its own v/vt/vn triple, so faces index all three
+ * with the same number. Each SubMesh gets a "usemtl", named after its material when it
+ * has one so the importer can match it back up, and the materials go into <name>.mtl.
+ * OBJ has no standard way to store vertex colors or tangents, those are dropped.
+ * Materials are written as Phong parameters for older readers, roughness as Ns the way
+ * Blender does, along with the PBR extension's Pr and Pm so they read back exactly. The
+ * metallic roughness texture is one image, written as both map_Pr and map_Pm, mtl.rs
+ * reads it back as is. MTL has nowhere to put occlusion, alpha masking or double
+ * sidedness, those are dropped.
+ */
+
+use std::io::Write;
+
+use super::{
+    submeshes, texture_reference, Exporter, MeshAttributes, Sink,
+};
+
+use crate::{
+    model::{
+        material::{roughness_to_shininess, AlphaMode, TextureSlot, TextureSlotKind, TextureWrap},
+        Model,
+    },
+    RenderResult,
+};
+
+//the importer only accepts decimal points, never exponents
+const FLOAT_PRECISION: usize = 6;
+
+#[derive(Default)]
+pub struct Wavefront {}
+
+impl Wavefront {
+    pub fn new() -> Self {
+        Self{}
+    }
+
+    fn write_mat_file(
+        &self, model: &Model, name: &str, sink: &mut dyn Sink,
+    ) -> RenderResult<String> {
+        let mat_file = format!("{name}.mtl");
+        let mut mtl = String::new();
+        let p = FLOAT_PRECISION;
+        for (i, material) in model.materials.iter().enumerate() {
+            let [r, g, b, a] = material.base_color;
+            let [er, eg, eb] = material.emissive;
+            mtl.push_str(&format!("newmtl {}\n", material.name));
+            mtl.push_str(&format!("Kd {r:.p$} {g:.p$} {b:.p$}\n"));
+            mtl.push_str(&format!("Ke {er:.p$} {eg:.p$} {eb:.p$}\n"));
+            mtl.push_str(&format!("Ns {:.p$}\n", roughness_to_shininess(material.roughness)));
+            //blending is the only way MTL has to say it's see through
+            let opacity = if material.alpha_mode == AlphaMode::Blend { a } else { 1.0 };
+            mtl.push_str(&format!("d {opacity:.p$}\nillum 2\n"));
+            mtl.push_str(&format!("Pr {:.p$}\nPm {:.p$}\n", material.roughness, material.metallic));
+            for (kind, slot) in material.textures() {
+                let keywords: &[&str] = match kind {
+                    TextureSlotKind::BaseColor => &["map_Kd"],
+                    TextureSlotKind::MetallicRoughness => &["map_Pr", "map_Pm"],
+                    TextureSlotKind::Normal => &["map_Bump"],
+                    TextureSlotKind::Emissive => &["map_Ke"],
+                    TextureSlotKind::Occlusion => continue,
+                };
+                let texture = texture_reference(slot, kind, name, i, sink)?;
+                for keyword in keywords {
+                    mtl.push_str(&format!("{keyword}{} {texture}\n", map_options(kind, slot)));
+                }
+            }
+            mtl.push('\n');
+        }
+        sink.write(&mat_file, mtl.as_bytes())?;
+        Ok(mat_file)
+    }
+}
+
+//what of the slot MTL can say about it, with a space in front
+fn map_options(kind: TextureSlotKind, slot: &TextureSlot) -> String {
+    let mut options = String::new();
+    if slot.sampler.wrap_u == TextureWrap::ClampToEdge {
+        options.push_str(" -clamp on");
+    }
+    if kind == TextureSlotKind::Normal {
+        options.push_str(&format!(" -bm {:.1$}", slot.scale, FLOAT_PRECISION));
+    }
+    options
+}
+
+impl Exporter for Wavefront {
+    fn export(
+        &self, model: &Model, name: &str, out: &mut dyn Write, sink: &mut dyn Sink,
+    ) -> RenderResult<()> {
+        let attributes = MeshAttributes::new(&model.mesh);
+        let mut obj = String::new();
+        if !model.materials.is_empty() {
+            obj.push_str(&format!("mtllib {}\n", self.write_mat_file(model, name, sink)?));
+        }
+        obj.push_str(&format!("o {}\n", model.name));
+        let p = FLOAT_PRECISION;
+        for [x, y, z] in attributes.positions.iter() {
+            obj.push_str(&format!("v {x:.p$} {y:.p$} {z:.p$}\n"));
+        }
+        for [u, v] in attributes.uvs.iter().flatten() {
+            obj.push_str(&format!("vt {u:.p$} {v:.p$}\n"));
+        }
+        for [x, y, z] in attributes.normals.iter().flatten() {
+            obj.push_str(&format!("vn {x:.p$} {y:.p$} {z:.p$}\n"));
+        }
+        //normals are already split wherever they need to be
+        if attributes.normals.is_some() {
+            obj.push_str("s off\n");
+        }
+        let vertex = |i: u32| -> String {
+            let i = i + 1;
+            match (&attributes.uvs, &attributes.normals) {
+                (Some(_), Some(_)) => format!("{i}/{i}/{i}"),
+                (Some(_), None) => format!("{i}/{i}"),
+                (None, Some(_)) => format!("{i}//{i}"),
+                (None, None) => format!("{i}"),
+            }
+        };
+        for submesh in submeshes(model) {
+            let material = submesh.material.and_then(|m| model.materials.get(m));
+            match material {
+                Some(material) => obj.push_str(&format!("usemtl {}\n", material.name)),
+                None => obj.push_str(&format!("usemtl {}\n", submesh.name)),
+            }
+            let indeces = model.indeces.get(
+                submesh.first_index..submesh.first_index + submesh.index_count
+            ).ok_or(format!("submesh '{}' is out of range of the indeces", submesh.name))?;
+            for t in indeces.chunks_exact(3) {
+                obj.push_str(&format!("f {} {} {}\n", vertex(t[0]), vertex(t[1]), vertex(t[2])));
+            }
+        }
+        out.write_all(obj.as_bytes()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::read,
+        io::Cursor,
+        sync::Arc,
+    };
+
+    use crate::{
+        exporter::{
+            wavefront::Wavefront as WavefrontExporter,
+            Exporter, MemorySink,
+        },
+        importer::{
+            collada::Collada,
+            ply::Ply,
+            wavefront::Wavefront,
+            ImportSettings, Importer, MemoryResolver,
+        },
+        model::{
+            material::{load_texture, Material, Texture, TextureSlot, TextureSlotKind},
+            texture_format::{EncodedTexture, TextureFormat},
+            Model,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    //vertices get renumbered in the order faces first use them, so compare per corner
+    fn assert_same_model(a: &Model, b: &Model) {
+        assert_eq!(a.name, b.name, "name");
+        assert_eq!(a.indeces.len(), b.indeces.len(), "index length");
+        let (va, vb) = (a.get_vertices().unwrap(), b.get_vertices().unwrap());
+        assert_eq!(va.len(), vb.len(), "vertex length");
+        for (i, j) in a.indeces.iter().zip(b.indeces.iter()) {
+            let (x, y) = (&va[*i as usize], &vb[*j as usize]);
+            for (c, d) in [
+                (x.pos.x, y.pos.x), (x.pos.y, y.pos.y), (x.pos.z, y.pos.z),
+                (x.uv.x, y.uv.x), (x.uv.y, y.uv.y),
+                (x.norm.x, y.norm.x), (x.norm.y, y.norm.y), (x.norm.z, y.norm.z),
+            ] {
+                assert!((c - d).abs() < 1.0e-5, "vertex {c} != {d}");
+            }
+        }
+        assert_eq!(a.submeshes.len(), b.submeshes.len(), "submesh length");
+        for (x, y) in a.submeshes.iter().zip(b.submeshes.iter()) {
+            assert_eq!(x.first_index, y.first_index, "{} first index", x.name);
+            assert_eq!(x.index_count, y.index_count, "{} index count", x.name);
+            assert_eq!(x.material, y.material, "{} material", x.name);
+        }
+        assert_eq!(a.materials.len(), b.materials.len(), "material length");
+        for (x, y) in a.materials.iter().zip(b.materials.iter()) {
+            assert_eq!(x.name, y.name, "material name");
+            let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+            assert!(close(&x.base_color, &y.base_color), "{} base color", x.name);
+            assert!(close(&x.emissive, &y.emissive), "{} emissive", x.name);
+            let (xf, yf) = ([x.metallic, x.roughness], [y.metallic, y.roughness]);
+            assert!(close(&xf, &yf), "{} metallic roughness", x.name);
+            assert_eq!(x.alpha_mode, y.alpha_mode, "{} alpha mode", x.name);
+            for kind in TextureSlotKind::ALL {
+                let texture = |m: &Material| m.texture(kind).map(|slot| slot.texture.clone());
+                assert_eq!(texture(x), texture(y), "{} {} texture", x.name, kind.name());
+            }
+        }
+    }
+
+    fn round_trip(model: &Model) -> Model {
+        let mut obj = vec![];
+        let mut sink = MemorySink::new();
+        WavefrontExporter::new().export(model, "exported", &mut obj, &mut sink).unwrap();
+        //the exported .mtl and textures come out of the sink, nothing is read from where
+        //the original was
+        Wavefront::new(None)
+            .import_bytes(&obj, "unused", &sink, &ImportSettings::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn wavefront_export_round_trip() {
+        let obj = "mtllib tri.mtl\no tri\n\
+            v 0.0 0.0 0.0\nv 1.0 0.0 0.0\nv 0.0 1.0 0.0\nv 1.0 1.0 0.0\n\
+            vt 0.0 0.0\nvt 1.0 0.0\nvt 0.0 1.0\nvt 1.0 1.0\n\
+            vn 0.0 0.0 1.0\n\
+            usemtl red\nf 1/1/1 2/2/1 3/3/1\nusemtl blue\nf 2/2/1 4/4/1 3/3/1\n";
+        let texture = read(format!("{TEST_DIRECTORY}/collada_texture.png")).unwrap();
+        let mut resolver = MemoryResolver::new();
+        resolver.insert(
+            "tri.mtl", b"newmtl red\nmap_Kd red.png\nnewmtl blue\nmap_Kd blue.png\n".to_vec(),
+        );
+        resolver.insert("red.png", texture.clone());
+        resolver.insert("blue.png", texture);
+        let model = Wavefront::new(None)
+            .import_bytes(obj.as_bytes(), "unused", &resolver, &ImportSettings::default())
+            .unwrap();
+        assert_eq!(model.submeshes.len(), 2, "one submesh per usemtl");
+        assert_eq!(model.submeshes[1].material, Some(1));
+        assert_same_model(&model, &round_trip(&model));
+    }
+
+    #[test]
+    fn wavefront_export_other_importers() {
+        //collada's texture is found next to the .dae, the export has its own copy
+        let collada = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        let mut exported = round_trip(&collada);
+        exported.name = collada.name.clone();
+        assert_same_model(&collada, &exported);
+        let ply = Ply::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/ply_ascii_cube.ply"))
+            .unwrap();
+        let mut exported = round_trip(&ply);
+        exported.name = ply.name.clone();
+        assert_same_model(&ply, &exported);
+    }
+
+    #[test]
+    fn wavefront_export_writes_textures() {
+        //a texture is written out next to the model whether it came from a file or not,
+        //only.png is next to the original
+        let texture = read(format!("{TEST_DIRECTORY}/collada_texture.png")).unwrap();
+        let mut resolver = MemoryResolver::new();
+        resolver.insert("tri.mtl", b"newmtl only\nmap_Kd only.png\n".to_vec());
+        resolver.insert("only.png", texture);
+        let obj = "mtllib tri.mtl\nv 0.0 0.0 0.0\nv 1.0 0.0 0.0\nv 0.0 1.0 0.0\nf 1 2 3\n";
+        let model = Wavefront::new(None)
+            .import_bytes(obj.as_bytes(), "tri", &resolver, &ImportSettings::default())
+            .unwrap();
+        let base_color = TextureSlotKind::BaseColor;
+        let location = &model.materials[0].texture(base_color).unwrap().location;
+        assert_eq!(location.as_deref(), Some("only.png"));
+        let mut out = vec![];
+        let mut sink = MemorySink::new();
+        WavefrontExporter::new().export(&model, "tri", &mut out, &mut sink).unwrap();
+        let mtl = String::from_utf8(sink.get("tri.mtl").unwrap().to_vec()).unwrap();
+        assert!(mtl.contains("map_Kd tri_0.png") && !mtl.contains("only.png"), "{mtl}");
+        assert!(sink.get("tri_0.png").is_some(), "texture written");
+        let exported = Wavefront::new(None)
+            .import_bytes(&out, "tri", &sink, &ImportSettings::default())
+            .unwrap();
+        let texture = |m: &Model| m.materials[0].texture(base_color).unwrap().texture.clone();
+        assert_eq!(texture(&exported), texture(&model));
+
+        //float textures go out as .exr, HDR and all
+        let texels = [[12.5, 0.25, -1.0, 1.0], [0.0, 3000.0, 0.5, 1.0]];
+        let sky = EncodedTexture::from_floats(TextureFormat::Rgba32F, 2, 1, &texels);
+        let slot = TextureSlot::shared(Arc::new(Texture::from_encoded(sky)), None);
+        let mut model = model;
+        model.materials[0].set_texture(base_color, Some(slot));
+        let mut sink = MemorySink::new();
+        WavefrontExporter::new().export(&model, "tri", &mut vec![], &mut sink).unwrap();
+        let exr = load_texture(&mut Cursor::new(sink.get("tri_0.exr").unwrap())).unwrap();
+        assert_eq!(exr.encoded.unwrap().decode_floats(0, 0, 0), texels);
+    }
+}
@@ -0,0 +1,762 @@
+/* COLLADA (.dae) importer.
+ * Reads the whole document into a tiny element tree first, COLLADA is all
+ * "#id" references pointing forwards and backwards through the file so streaming
+ * it isn't worth the trouble.
+ * Supported:
+ *   <library_geometries>: <mesh> with <triangles>, <polylist> and <polygons>
+ *   <library_materials>/<library_effects>/<library_images>: profile_COMMON's phong,
+ *     blinn, lambert or constant colors and diffuse texture, converted from Phong with
+ *     Material::from_phong, shininess taken to be on MTL's 0 to 1000 scale
+ *   <library_visual_scenes>: node hierarchy with matrix/translate/rotate/scale
+ * Every instanced primitive becomes its own SubMesh, positions and normals are
+ * baked into model space using the node's world transform.
+ * Primitives without a NORMAL input get generated normals.
+ */
+
+use std::collections::HashMap;
+
+use xml::reader::{
+    EventReader,
+    XmlEvent,
+};
+
+use super::{
+    ImportSettings, ImportSource, Importer, Resolver,
+};
+
+use crate::{
+    model::{
+        bounds::compute_bounds,
+        conversion::apply_conversion,
+        material::{Material, Phong, TextureSlot, TextureSlotKind},
+        normals::generate_normals, IndexCoord, IndexVector, Mesh, Model,
+        NormalVector, NormalVertex, PositionVector, SubMesh, TextureVector,
+    },
+    RenderResult,
+};
+
+const FILE_URI_PREFIX: &str = "file://";
+
+//row major, m[row][col], COLLADA's <matrix> is written the same way
+type ColladaMatrix = [[f32; 4]; 4];
+
+const IDENTITY: ColladaMatrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+#[derive(Default)]
+pub struct Collada {}
+
+impl Collada {
+    pub fn new() -> Self {
+        Self{}
+    }
+}
+
+impl Importer for Collada {
+    //textures are pulled through the resolver using the <init_from> path
+    fn import(
+        &self, source: &mut dyn ImportSource, name: &str, resolver: &dyn Resolver,
+        settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let root = XmlElement::parse(source)?;
+        if root.name != "COLLADA" {
+            return Err(format!("not a COLLADA document, root element: '{}'", root.name));
+        }
+        let document = ColladaDocument::new(&root, resolver);
+        let mut builder = ModelBuilder::new(name);
+        let instances = document.geometry_instances()?;
+        if instances.is_empty() {
+            return Err("COLLADA document has no geometry".to_string());
+        }
+        for instance in instances.iter() {
+            builder.add_instance(&document, instance)?;
+        }
+        //a single primitive without normals regenerates the whole model
+        let missing_normals = builder.missing_normals;
+        let mut model = builder.build();
+        if settings.force_normals || missing_normals {
+            generate_normals(&mut model, settings.normal_mode, None)?;
+        }
+        compute_bounds(&mut model)?;
+        apply_conversion(&mut model, &settings.conversion)?;
+        Ok(model)
+    }
+}
+
+struct XmlElement {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn parse(reader: &mut dyn ImportSource) -> RenderResult<Self> {
+        let mut stack: Vec<XmlElement> = vec![];
+        for event in EventReader::new(reader) {
+            match event.map_err(|e| format!("error reading COLLADA document, '{e}'"))? {
+                XmlEvent::StartElement{name, attributes, ..} => {
+                    stack.push(XmlElement{
+                        name: name.local_name,
+                        attributes: attributes.into_iter()
+                            .map(|a| (a.name.local_name, a.value))
+                            .collect(),
+                        children: vec![],
+                        text: String::new(),
+                    });
+                },
+                XmlEvent::Characters(text) => {
+                    if let Some(current) = stack.last_mut() {
+                        current.text.push_str(&text);
+                    }
+                },
+                XmlEvent::EndElement{..} => {
+                    //panic impossible, xml-rs won't emit an unbalanced end
+                    let done = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(done),
+                        None => return Ok(done),
+                    }
+                },
+                _ => {},
+            }
+        }
+        Err("COLLADA document ended unexpectedly".to_string())
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|a| a.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn floats(&self) -> RenderResult<Vec<f32>> {
+        self.text.split_whitespace().map(|f| {
+            f.parse::<f32>().map_err(|_| format!("Unable to parse COLLADA float: '{f}'"))
+        }).collect()
+    }
+
+    fn ints(&self) -> RenderResult<Vec<usize>> {
+        self.text.split_whitespace().map(|i| {
+            i.parse::<usize>().map_err(|_| format!("Unable to parse COLLADA index: '{i}'"))
+        }).collect()
+    }
+}
+
+//"#some_id" -> "some_id"
+fn url_id(url: &str) -> &str {
+    url.strip_prefix('#').unwrap_or(url)
+}
+
+struct GeometryInstance<'a> {
+    geometry: &'a XmlElement,
+    transform: ColladaMatrix,
+    //<triangles material="symbol"> -> material id
+    materials: HashMap<String, String>,
+}
+
+struct ColladaDocument<'a> {
+    root: &'a XmlElement,
+    resolver: &'a dyn Resolver,
+}
+
+impl<'a> ColladaDocument<'a> {
+    fn new(root: &'a XmlElement, resolver: &'a dyn Resolver) -> Self {
+        Self{root, resolver}
+    }
+
+    fn library(&self, name: &str) -> Option<&'a XmlElement> {
+        self.root.child(name)
+    }
+
+    fn by_id(&self, library: &str, id: &str) -> Option<&'a XmlElement> {
+        self.library(library)?.children.iter().find(|c| c.attr("id") == Some(id))
+    }
+
+    //walks the visual scene, with no scene every geometry is instanced once as is
+    fn geometry_instances(&self) -> RenderResult<Vec<GeometryInstance<'a>>> {
+        let mut instances: Vec<GeometryInstance<'a>> = vec![];
+        let scene = self.root.child("scene")
+            .and_then(|s| s.child("instance_visual_scene"))
+            .and_then(|i| i.attr("url"))
+            .and_then(|url| self.by_id("library_visual_scenes", url_id(url)))
+            .or_else(|| self.library("library_visual_scenes")?.child("visual_scene"));
+        match scene {
+            Some(scene) => {
+                for node in scene.children("node") {
+                    self.walk_node(node, &IDENTITY, &mut instances)?;
+                }
+            },
+            None => {
+                if let Some(geometries) = self.library("library_geometries") {
+                    for geometry in geometries.children("geometry") {
+                        instances.push(GeometryInstance{
+                            geometry,
+                            transform: IDENTITY,
+                            materials: HashMap::new(),
+                        });
+                    }
+                }
+            },
+        }
+        Ok(instances)
+    }
+
+    fn walk_node(
+        &self, node: &'a XmlElement, parent: &ColladaMatrix,
+        instances: &mut Vec<GeometryInstance<'a>>,
+    ) -> RenderResult<()> {
+        let mut transform = *parent;
+        //transform elements are applied in document order
+        for c in node.children.iter() {
+            let local = match c.name.as_str() {
+                "matrix" => {
+                    let m = c.floats()?;
+                    if m.len() != 16 {
+                        return Err(format!("COLLADA <matrix> needs 16 values, got {}", m.len()));
+                    }
+                    let mut local = IDENTITY;
+                    for (r, row) in local.iter_mut().enumerate() {
+                        row.copy_from_slice(&m[r*4..r*4 + 4]);
+                    }
+                    local
+                },
+                "translate" => {
+                    let t = c.floats()?;
+                    if t.len() != 3 {
+                        return Err("COLLADA <translate> needs 3 values".to_string());
+                    }
+                    translation(t[0], t[1], t[2])
+                },
+                "rotate" => {
+                    let r = c.floats()?;
+                    if r.len() != 4 {
+                        return Err("COLLADA <rotate> needs 4 values".to_string());
+                    }
+                    rotation(r[0], r[1], r[2], r[3])
+                },
+                "scale" => {
+                    let s = c.floats()?;
+                    if s.len() != 3 {
+                        return Err("COLLADA <scale> needs 3 values".to_string());
+                    }
+                    scale(s[0], s[1], s[2])
+                },
+                _ => continue,
+            };
+            transform = multiply(&transform, &local);
+        }
+        for instance in node.children("instance_geometry") {
+            let url = instance.attr("url").ok_or("instance_geometry has no url".to_string())?;
+            let geometry = self.by_id("library_geometries", url_id(url))
+                .ok_or(format!("COLLADA geometry not found: '{url}'"))?;
+            let mut materials: HashMap<String, String> = HashMap::new();
+            if let Some(bind) = instance.child("bind_material") {
+                let instance_materials = bind.child("technique_common")
+                    .map(|t| t.children("instance_material").collect::<Vec<_>>())
+                    .unwrap_or_default();
+                for m in instance_materials {
+                    if let (Some(symbol), Some(target)) = (m.attr("symbol"), m.attr("target")) {
+                        materials.insert(symbol.to_string(), url_id(target).to_string());
+                    }
+                }
+            }
+            instances.push(GeometryInstance{geometry, transform, materials});
+        }
+        for child in node.children("node") {
+            self.walk_node(child, &transform, instances)?;
+        }
+        Ok(())
+    }
+
+    //material id -> its Phong parameters and diffuse texture location,
+    //None if there's no such material or it has no common profile
+    fn phong(&self, material_id: &str) -> Option<(Phong, Option<String>)> {
+        let material = self.by_id("library_materials", material_id)?;
+        let effect_url = material.child("instance_effect")?.attr("url")?;
+        let effect = self.by_id("library_effects", url_id(effect_url))?;
+        let profile = effect.child("profile_COMMON")?;
+        let technique = profile.child("technique")?.children.iter()
+            .find(|t| ["phong", "blinn", "lambert", "constant"].contains(&t.name.as_str()))?;
+        let color = |name: &str| -> Option<[f32; 3]> {
+            let values = technique.child(name)?.child("color")?.floats().ok()?;
+            (values.len() >= 3).then(|| [values[0], values[1], values[2]])
+        };
+        let float = |name: &str| -> Option<f32> {
+            technique.child(name)?.child("float")?.text.trim().parse().ok()
+        };
+        let defaults = Phong::default();
+        let phong = Phong{
+            diffuse: color("diffuse").unwrap_or(defaults.diffuse),
+            specular: color("specular").unwrap_or(defaults.specular),
+            shininess: float("shininess").unwrap_or(defaults.shininess),
+            emissive: color("emission").unwrap_or(defaults.emissive),
+            //A_ONE, the default opaque mode, 1 is opaque
+            opacity: float("transparency").unwrap_or(defaults.opacity),
+            ..defaults
+        };
+        let texture = technique.child("diffuse")
+            .and_then(|d| d.child("texture"))
+            .and_then(|t| t.attr("texture"))
+            .and_then(|t| self.texture_location(profile, t));
+        Some((phong, texture))
+    }
+
+    //a <texture texture="..."> reference -> the image's location
+    fn texture_location(&self, profile: &XmlElement, texture: &str) -> Option<String> {
+        //1.4.1: texture -> sampler2D newparam -> surface newparam -> image
+        //1.5: texture can reference the image directly
+        let mut image_id = texture.to_string();
+        let newparam = |sid: &str| profile.children("newparam").find(|p| p.attr("sid") == Some(sid));
+        if let Some(sampler) = newparam(&image_id).and_then(|p| p.child("sampler2D")) {
+            if let Some(source) = sampler.child("source") {
+                image_id = source.text.trim().to_string();
+            } else if let Some(instance) = sampler.child("instance_image") {
+                image_id = url_id(instance.attr("url")?).to_string();
+            }
+        }
+        if let Some(surface) = newparam(&image_id).and_then(|p| p.child("surface")) {
+            image_id = surface.child("init_from")?.text.trim().to_string();
+        }
+        let image = self.by_id("library_images", &image_id)?;
+        let init_from = image.child("init_from")?;
+        //1.5 nests the path in <ref>
+        let location = match init_from.child("ref") {
+            Some(r) => r.text.trim(),
+            None => init_from.text.trim(),
+        };
+        let location = location.strip_prefix(FILE_URI_PREFIX).unwrap_or(location);
+        Some(location.to_string())
+    }
+}
+
+struct ColladaSource {
+    values: Vec<f32>,
+    stride: usize,
+}
+
+impl ColladaSource {
+    fn new(source: &XmlElement) -> RenderResult<Self> {
+        let id = source.attr("id").unwrap_or("");
+        let values = source.child("float_array")
+            .ok_or(format!("COLLADA source '{id}' has no float_array"))?
+            .floats()?;
+        let stride = source.child("technique_common")
+            .and_then(|t| t.child("accessor"))
+            .and_then(|a| a.attr("stride"))
+            .map(|s| s.parse::<usize>())
+            .unwrap_or(Ok(1))
+            .map_err(|_| format!("Unable to parse COLLADA accessor stride for '{id}'"))?;
+        Ok(Self{values, stride})
+    }
+
+    fn get(&self, index: usize, component: usize) -> RenderResult<f32> {
+        self.values.get(index * self.stride + component).copied()
+            .ok_or(format!("COLLADA source index out of range: {index}"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Semantic {
+    Position,
+    Normal,
+    TexCoord,
+}
+
+struct PrimitiveInput<'a> {
+    semantic: Semantic,
+    offset: usize,
+    source: &'a ColladaSource,
+}
+
+//accumulates every instanced primitive into one Model
+struct ModelBuilder {
+    name: String,
+    vertices: Vec<NormalVertex>,
+    indeces: IndexVector,
+    submeshes: Vec<SubMesh>,
+    materials: Vec<Material>,
+    //material id -> index in materials, None if it isn't in the document
+    material_map: HashMap<String, Option<usize>>,
+    missing_normals: bool,
+}
+
+impl ModelBuilder {
+    fn new(name: &str) -> Self {
+        Self{
+            name: name.to_string(),
+            vertices: vec![],
+            indeces: vec![],
+            submeshes: vec![],
+            materials: vec![],
+            material_map: HashMap::new(),
+            missing_normals: false,
+        }
+    }
+
+    fn material_index(
+        &mut self, document: &ColladaDocument, material_id: &str,
+    ) -> RenderResult<Option<usize>> {
+        if let Some(index) = self.material_map.get(material_id) {
+            return Ok(*index);
+        }
+        let index = match document.phong(material_id) {
+            Some((phong, location)) => {
+                let mut material = Material::from_phong(material_id, &phong);
+                if let Some(location) = location {
+                    let texture = document.resolver.texture(&location)?;
+                    let slot = TextureSlot::shared(texture, Some(location));
+                    material.set_texture(TextureSlotKind::BaseColor, Some(slot));
+                }
+                self.materials.push(material);
+                Some(self.materials.len() - 1)
+            },
+            None => None,
+        };
+        self.material_map.insert(material_id.to_string(), index);
+        Ok(index)
+    }
+
+    fn add_instance(
+        &mut self, document: &ColladaDocument, instance: &GeometryInstance,
+    ) -> RenderResult<()> {
+        let geometry_id = instance.geometry.attr("id").unwrap_or("");
+        let mesh = match instance.geometry.child("mesh") {
+            Some(mesh) => mesh,
+            //splines and such, nothing to draw
+            None => return Ok(()),
+        };
+        let mut sources: HashMap<&str, ColladaSource> = HashMap::new();
+        for source in mesh.children("source") {
+            if let Some(id) = source.attr("id") {
+                sources.insert(id, ColladaSource::new(source)?);
+            }
+        }
+        //<vertices> remaps VERTEX to one or more real sources
+        let vertices_id = mesh.child("vertices").and_then(|v| v.attr("id"));
+        let vertex_inputs: Vec<(&str, &str)> = mesh.child("vertices")
+            .map(|v| v.children("input")
+                .filter_map(|i| Some((i.attr("semantic")?, url_id(i.attr("source")?))))
+                .collect())
+            .unwrap_or_default();
+        let normal_matrix = normal_matrix(&instance.transform);
+        for primitive in mesh.children.iter() {
+            let polygons: Vec<Vec<usize>> = match primitive.name.as_str() {
+                "triangles" => {
+                    let p = primitive.child("p").map(|p| p.ints()).unwrap_or(Ok(vec![]))?;
+                    vec![p]
+                },
+                "polylist" | "polygons" => {
+                    primitive.children("p").map(|p| p.ints()).collect::<RenderResult<_>>()?
+                },
+                _ => continue,
+            };
+            let mut inputs: Vec<PrimitiveInput> = vec![];
+            for input in primitive.children("input") {
+                let semantic = input.attr("semantic").unwrap_or("");
+                let source = url_id(input.attr("source").unwrap_or(""));
+                let offset: usize = input.attr("offset").unwrap_or("0").parse()
+                    .map_err(|_| format!("Unable to parse COLLADA input offset in '{geometry_id}'"))?;
+                //only the first uv set for now
+                if semantic == "TEXCOORD" && inputs.iter().any(|i| i.semantic == Semantic::TexCoord) {
+                    continue;
+                }
+                let mut resolved: Vec<(Semantic, &str)> = vec![];
+                match semantic {
+                    "VERTEX" if Some(source) == vertices_id => {
+                        for (s, src) in vertex_inputs.iter() {
+                            match *s {
+                                "POSITION" => resolved.push((Semantic::Position, src)),
+                                "NORMAL" => resolved.push((Semantic::Normal, src)),
+                                "TEXCOORD" => resolved.push((Semantic::TexCoord, src)),
+                                _ => {},
+                            }
+                        }
+                    },
+                    "VERTEX" | "POSITION" => resolved.push((Semantic::Position, source)),
+                    "NORMAL" => resolved.push((Semantic::Normal, source)),
+                    "TEXCOORD" => resolved.push((Semantic::TexCoord, source)),
+                    _ => {},
+                }
+                for (semantic, source) in resolved {
+                    let source = sources.get(source)
+                        .ok_or(format!("COLLADA source not found: '{source}'"))?;
+                    inputs.push(PrimitiveInput{semantic, offset, source});
+                }
+            }
+            if !inputs.iter().any(|i| i.semantic == Semantic::Position) {
+                return Err(format!("COLLADA primitive in '{geometry_id}' has no positions"));
+            }
+            if !inputs.iter().any(|i| i.semantic == Semantic::Normal) {
+                self.missing_normals = true;
+            }
+            //every input shares the stride of the <p> array, even the unused ones
+            let stride = primitive.children("input")
+                .filter_map(|i| i.attr("offset")?.parse::<usize>().ok())
+                .max()
+                .unwrap_or(0) + 1;
+            let vcount: Option<Vec<usize>> = match primitive.child("vcount") {
+                Some(v) => Some(v.ints()?),
+                None => None,
+            };
+            let material = match primitive.attr("material") {
+                Some(symbol) => {
+                    let material_id = instance.materials.get(symbol)
+                        .map(|m| m.as_str())
+                        .unwrap_or(symbol);
+                    self.material_index(document, material_id)?
+                },
+                None => None,
+            };
+            let first_index = self.indeces.len();
+            //deduplicate on the raw index tuple, per primitive
+            let mut vertex_map: HashMap<Vec<usize>, IndexCoord> = HashMap::new();
+            for p in polygons.iter() {
+                let faces: Vec<usize> = match (&vcount, primitive.name.as_str()) {
+                    (Some(vcount), "polylist") => vcount.clone(),
+                    (_, "polygons") => vec![p.len() / stride],
+                    _ => vec![3; p.len() / (stride * 3)],
+                };
+                let mut corner = 0;
+                for face_size in faces {
+                    let mut face: Vec<IndexCoord> = Vec::with_capacity(face_size);
+                    for _ in 0..face_size {
+                        let key = p.get(corner * stride..(corner + 1) * stride)
+                            .ok_or(format!("COLLADA <p> too short in '{geometry_id}'"))?
+                            .to_vec();
+                        corner += 1;
+                        if let Some(index) = vertex_map.get(&key) {
+                            face.push(*index);
+                            continue;
+                        }
+                        let vertex = self.make_vertex(
+                            &inputs, &key, &instance.transform, &normal_matrix,
+                        )?;
+                        self.vertices.push(vertex);
+                        let index = (self.vertices.len() - 1) as IndexCoord;
+                        vertex_map.insert(key, index);
+                        face.push(index);
+                    }
+                    //fan triangulation
+                    for i in 1..face.len().saturating_sub(1) {
+                        self.indeces.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                    }
+                }
+            }
+            let name = primitive.attr("material").unwrap_or(geometry_id);
+            self.submeshes.push(SubMesh::new(
+                name, first_index, self.indeces.len() - first_index, material,
+            ));
+        }
+        Ok(())
+    }
+
+    fn make_vertex(
+        &self, inputs: &[PrimitiveInput], key: &[usize],
+        transform: &ColladaMatrix, normal_matrix: &ColladaMatrix,
+    ) -> RenderResult<NormalVertex> {
+        let mut pos = [0.0; 3];
+        let mut norm = [0.0; 3];
+        let mut uv = [0.0; 2];
+        for input in inputs {
+            let index = key[input.offset];
+            match input.semantic {
+                Semantic::Position => {
+                    for (c, p) in pos.iter_mut().enumerate() {
+                        *p = input.source.get(index, c)?;
+                    }
+                },
+                Semantic::Normal => {
+                    for (c, n) in norm.iter_mut().enumerate() {
+                        *n = input.source.get(index, c)?;
+                    }
+                },
+                Semantic::TexCoord => {
+                    for (c, t) in uv.iter_mut().enumerate() {
+                        *t = input.source.get(index, c)?;
+                    }
+                },
+            }
+        }
+        let pos = transform_point(transform, &pos);
+        let norm = normalize(&transform_vector(normal_matrix, &norm));
+        Ok(NormalVertex::new(
+            PositionVector::new(pos[0], pos[1], pos[2], 1.0),
+            TextureVector::new(uv[0], uv[1]),
+            NormalVector::new(norm[0], norm[1], norm[2], 1.0),
+        ))
+    }
+
+    fn build(self) -> Model {
+        Model{
+            submeshes: self.submeshes,
+            materials: self.materials,
+            ..Model::new(&self.name, Mesh::NormalMesh(self.vertices), self.indeces)
+        }
+    }
+}
+
+fn multiply(a: &ColladaMatrix, b: &ColladaMatrix) -> ColladaMatrix {
+    let mut m = [[0.0; 4]; 4];
+    for (r, row) in m.iter_mut().enumerate() {
+        for (c, val) in row.iter_mut().enumerate() {
+            *val = (0..4).map(|k| a[r][k] * b[k][c]).sum();
+        }
+    }
+    m
+}
+
+fn translation(x: f32, y: f32, z: f32) -> ColladaMatrix {
+    let mut m = IDENTITY;
+    m[0][3] = x;
+    m[1][3] = y;
+    m[2][3] = z;
+    m
+}
+
+fn scale(x: f32, y: f32, z: f32) -> ColladaMatrix {
+    let mut m = IDENTITY;
+    m[0][0] = x;
+    m[1][1] = y;
+    m[2][2] = z;
+    m
+}
+
+//axis angle, angle is in degrees
+fn rotation(x: f32, y: f32, z: f32, angle: f32) -> ColladaMatrix {
+    let [x, y, z] = normalize(&[x, y, z]);
+    let (s, c) = angle.to_radians().sin_cos();
+    let t = 1.0 - c;
+    [
+        [t*x*x + c, t*x*y - s*z, t*x*z + s*y, 0.0],
+        [t*x*y + s*z, t*y*y + c, t*y*z - s*x, 0.0],
+        [t*x*z - s*y, t*y*z + s*x, t*z*z + c, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+//inverse transpose of the upper 3x3, the cofactor matrix is that up to a scale
+//and the normals get normalized anyway
+fn normal_matrix(m: &ColladaMatrix) -> ColladaMatrix {
+    let mut n = IDENTITY;
+    for (r, row) in n.iter_mut().take(3).enumerate() {
+        for (c, val) in row.iter_mut().take(3).enumerate() {
+            let (r1, r2) = ((r + 1) % 3, (r + 2) % 3);
+            let (c1, c2) = ((c + 1) % 3, (c + 2) % 3);
+            *val = m[r1][c1]*m[r2][c2] - m[r1][c2]*m[r2][c1];
+        }
+    }
+    n
+}
+
+fn transform_point(m: &ColladaMatrix, p: &[f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (r, o) in out.iter_mut().enumerate() {
+        *o = m[r][0]*p[0] + m[r][1]*p[1] + m[r][2]*p[2] + m[r][3];
+    }
+    out
+}
+
+fn transform_vector(m: &ColladaMatrix, v: &[f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (r, o) in out.iter_mut().enumerate() {
+        *o = m[r][0]*v[0] + m[r][1]*v[1] + m[r][2]*v[2];
+    }
+    out
+}
+
+fn normalize(a: &[f32; 3]) -> [f32; 3] {
+    let len = (a[0]*a[0] + a[1]*a[1] + a[2]*a[2]).sqrt();
+    if len > 0.0 {
+        [a[0] / len, a[1] / len, a[2] / len]
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        importer::{
+            collada::Collada,
+            Importer,
+        },
+        model::{
+            material::TextureSlotKind,
+            Mesh,
+            Vector2,
+            Vector4,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    fn assert_close(a: &Vector4<f32>, b: &Vector4<f32>, msg: &str) {
+        let close = (a.x - b.x).abs() < 1.0e-5 && (a.y - b.y).abs() < 1.0e-5
+            && (a.z - b.z).abs() < 1.0e-5 && (a.w - b.w).abs() < 1.0e-5;
+        assert!(close, "{msg}: {a:?} != {b:?}");
+    }
+
+    #[test]
+    fn collada_submeshes_and_materials() {
+        let model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        assert_eq!(model.name, "collada_scene");
+        //quad polylist + triangle, instanced by two nodes
+        assert_eq!(model.submeshes.len(), 4, "submesh count");
+        assert_eq!(model.indeces.len(), 2*(6 + 3), "index length");
+        //both materials, in the order they're first used
+        assert_eq!(model.materials.len(), 2, "material count");
+        assert_eq!(model.submeshes[0].material, Some(0));
+        assert_eq!(model.submeshes[1].material, Some(1));
+        assert_eq!(model.submeshes[2].material, Some(0));
+        assert_eq!(model.submeshes[0].first_index, 0);
+        assert_eq!(model.submeshes[0].index_count, 6);
+        assert_eq!(model.submeshes[1].first_index, 6);
+        assert_eq!(model.submeshes[1].index_count, 3);
+        let texture = model.materials[0].texture(TextureSlotKind::BaseColor).unwrap();
+        assert_eq!(texture.texture.width(), 2);
+        let flat = &model.materials[1];
+        assert_eq!(flat.name, "flat-material");
+        assert_eq!(flat.base_color, [0.8, 0.1, 0.1, 1.0]);
+        assert_eq!(flat.textures().count(), 0);
+    }
+
+    #[test]
+    fn collada_node_transforms() {
+        let model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        let vertices = match &model.mesh {
+            Mesh::NormalMesh(vertices) => vertices,
+            _ => panic!("expected a NormalMesh"),
+        };
+        //first node is translated by (0, 0, 5)
+        assert_close(&vertices[0].pos, &Vector4::new(-1.0, -1.0, 5.0, 1.0), "translated");
+        assert_close(&vertices[0].norm, &Vector4::new(0.0, 0.0, 1.0, 1.0), "translated normal");
+        assert_eq!(vertices[2].uv, Vector2::new(1.0, 1.0));
+        //second node is a child of the first, rotated 90 degrees about y
+        let rotated = &vertices[model.indeces[model.submeshes[2].first_index] as usize];
+        assert_close(&rotated.pos, &Vector4::new(0.0, -1.0, 6.0, 1.0), "rotated");
+        assert_close(&rotated.norm, &Vector4::new(1.0, 0.0, 0.0, 1.0), "rotated normal");
+    }
+
+    #[test]
+    #[should_panic(expected = "COLLADA source not found: 'missing-normals'")]
+    fn collada_missing_source() {
+        let _ = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_bad_source.dae"))
+            .unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+/* Loads .rmdl files written by import-model, see model::cooked for the format.
+ * Everything was done when the model was cooked, normals and conversions included,
+ * only a conversion in the import settings is applied on top.
+ */
+
+use super::{
+    ImportSettings, ImportSource, Importer, Resolver,
+};
+
+use crate::{
+    model::{
+        conversion::apply_conversion,
+        cooked::read_model,
+        Model,
+    },
+    RenderResult,
+};
+
+#[derive(Default)]
+pub struct Cooked {}
+
+impl Cooked {
+    pub fn new() -> Self {
+        Self{}
+    }
+}
+
+impl Importer for Cooked {
+    fn import(
+        &self, source: &mut dyn ImportSource, _name: &str, _resolver: &dyn Resolver,
+        settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let mut data = vec![];
+        source.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        let mut model = read_model(&data)?;
+        apply_conversion(&mut model, &settings.conversion)?;
+        Ok(model)
+    }
+}
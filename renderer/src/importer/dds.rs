@@ -0,0 +1,231 @@
+/* Reads DDS textures, DirectX's container, the other common way textures come block
+ * compressed. Everything is little endian.
+ *
+ *    0  [u8; 4]  magic, "DDS "
+ *    4  u32      header size, 124
+ *   12  u32      height, u32 width
+ *   28  u32      mip count, 0 is 1
+ *   76  pixel format, u32 size, u32 flags, [u8; 4] fourCC, u32 rgb bit count,
+ *                u32 r, g, b and a masks
+ *  108  u32      caps, u32 caps2, a cubemap has all 6 faces flagged in caps2
+ *  128  only when fourCC is "DX10", u32 dxgi format, u32 resource dimension,
+ *                u32 misc flags (4 for a cubemap), u32 array size, u32 misc flags 2
+ *
+ * The data is each layer in turn, each layer its faces in turn and each face its whole
+ * mip chain, it's reordered into EncodedTexture's levels.
+ * Formats are the fourCCs DXT1, DXT5, ATI2 and BC5U, 32 bit rgba and bgra, and the
+ * dxgi formats of the same, plus half and float rgba, dxgi 10 and 2, and the legacy
+ * fourCCs for them, D3DFMT 113 and 116.
+ */
+
+use crate::{
+    model::{
+        mipmap::mip_level_count,
+        texture_format::{EncodedTexture, TextureFormat},
+    },
+    RenderResult,
+};
+
+pub const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+const DDS_HEADER_END: usize = 128;
+const DDS_DX10_HEADER_END: usize = 148;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xfc00;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+const DDS_DIMENSION_TEXTURE3D: u32 = 4;
+
+//dxgi formats, the bgra ones are swizzled to rgba
+const DXGI_FORMATS: [(u32, TextureFormat, bool); 13] = [
+    (28, TextureFormat::Rgba8, false),
+    (29, TextureFormat::Rgba8Srgb, false),
+    (87, TextureFormat::Rgba8, true),
+    (91, TextureFormat::Rgba8Srgb, true),
+    (71, TextureFormat::Bc1, false),
+    (72, TextureFormat::Bc1Srgb, false),
+    (77, TextureFormat::Bc3, false),
+    (78, TextureFormat::Bc3Srgb, false),
+    (83, TextureFormat::Bc5, false),
+    (98, TextureFormat::Bc7, false),
+    (99, TextureFormat::Bc7Srgb, false),
+    (10, TextureFormat::Rgba16F, false),
+    (2, TextureFormat::Rgba32F, false),
+];
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+//the format, whether it's bgra, and where the data starts
+fn dds_format(data: &[u8]) -> RenderResult<(TextureFormat, bool, usize)> {
+    let flags = u32_at(data, 80);
+    let four_cc = &data[84..88];
+    if flags & DDPF_FOURCC != 0 {
+        let format = match four_cc {
+            b"DXT1" => TextureFormat::Bc1,
+            b"DXT5" => TextureFormat::Bc3,
+            b"ATI2" | b"BC5U" => TextureFormat::Bc5,
+            //D3DFMT_A16B16G16R16F and D3DFMT_A32B32G32R32F, written as a number
+            [113, 0, 0, 0] => TextureFormat::Rgba16F,
+            [116, 0, 0, 0] => TextureFormat::Rgba32F,
+            b"DX10" => {
+                if data.len() < DDS_DX10_HEADER_END {
+                    return Err("dds file is truncated in its dx10 header".to_string());
+                }
+                let dxgi = u32_at(data, 128);
+                if u32_at(data, 132) == DDS_DIMENSION_TEXTURE3D {
+                    return Err("dds texture is 3d".to_string());
+                }
+                let (_, format, bgra) = DXGI_FORMATS.into_iter().find(|f| f.0 == dxgi)
+                    .ok_or(format!("dds texture has an unsupported dxgi format: {dxgi}"))?;
+                return Ok((format, bgra, DDS_DX10_HEADER_END));
+            },
+            _ => {
+                let name = String::from_utf8_lossy(four_cc);
+                return Err(format!("dds texture has an unsupported fourCC: '{name}'"));
+            },
+        };
+        return Ok((format, false, DDS_HEADER_END));
+    }
+    let bits = u32_at(data, 88);
+    let masks = [u32_at(data, 92), u32_at(data, 96), u32_at(data, 100)];
+    let bgra = match (flags & DDPF_RGB != 0 && bits == 32, masks) {
+        (true, [0xff, 0xff00, 0xff0000]) => false,
+        (true, [0xff0000, 0xff00, 0xff]) => true,
+        _ => return Err(format!("dds texture has an unsupported {bits} bit pixel format")),
+    };
+    Ok((TextureFormat::Rgba8, bgra, DDS_HEADER_END))
+}
+
+pub fn read_dds(data: &[u8]) -> RenderResult<EncodedTexture> {
+    if data.len() < DDS_HEADER_END || !data.starts_with(DDS_MAGIC) {
+        return Err("not a dds file".to_string());
+    }
+    let (format, bgra, start) = dds_format(data)?;
+    let dx10 = start == DDS_DX10_HEADER_END;
+    let caps2 = u32_at(data, 112);
+    let cubemap = caps2 & DDSCAPS2_CUBEMAP != 0
+        || (dx10 && u32_at(data, 136) & DDS_RESOURCE_MISC_TEXTURECUBE != 0);
+    if cubemap && !dx10 && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+        return Err("dds cubemap doesn't have all 6 faces".to_string());
+    }
+    let mut texture = EncodedTexture{
+        format,
+        width: u32_at(data, 16),
+        height: u32_at(data, 12),
+        layers: if dx10 { u32_at(data, 140).max(1) } else { 1 },
+        faces: if cubemap { 6 } else { 1 },
+        levels: vec![],
+    };
+    if texture.width == 0 || texture.height == 0 {
+        return Err(format!("dds texture is {} x {}", texture.width, texture.height));
+    }
+    let count = u32_at(data, 28).max(1);
+    if count > mip_level_count(texture.width, texture.height) {
+        return Err(format!("dds texture has {count} levels, too many for {} x {}",
+            texture.width, texture.height));
+    }
+    let count = count as usize;
+    texture.levels = vec![vec![]; count];
+    let mut offset = start;
+    //runs out of data long before it runs out of a bad layer count
+    for _ in 0..texture.layers as u64 * texture.faces as u64 {
+        for level in 0..count {
+            let size = texture.image_bytes(level);
+            let image = offset.checked_add(size).and_then(|end| data.get(offset..end))
+                .ok_or("dds file is truncated".to_string())?;
+            texture.levels[level].extend_from_slice(image);
+            offset += size;
+        }
+    }
+    if bgra {
+        for texel in texture.levels.iter_mut().flat_map(|level| level.chunks_exact_mut(4)) {
+            texel.swap(0, 2);
+        }
+    }
+    texture.check().map_err(|e| format!("bad dds texture: {e}"))?;
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::importer::dds::read_dds;
+    use crate::model::texture_format::TextureFormat;
+
+    //a dds header, the pixel format is a fourCC or 32 bit bgra
+    fn header(width: u32, height: u32, mips: u32, four_cc: Option<&[u8; 4]>) -> Vec<u8> {
+        let mut data = vec![0u8; 128];
+        let mut put = |offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        put(4, 124);
+        put(12, height);
+        put(16, width);
+        put(28, mips);
+        put(76, 32);
+        match four_cc {
+            Some(_) => put(80, 0x4),
+            None => {
+                put(80, 0x41);
+                put(88, 32);
+                put(92, 0xff0000);
+                put(96, 0xff00);
+                put(100, 0xff);
+                put(104, 0xff000000);
+            },
+        }
+        data[..4].copy_from_slice(b"DDS ");
+        if let Some(four_cc) = four_cc {
+            data[84..88].copy_from_slice(four_cc);
+        }
+        data
+    }
+
+    #[test]
+    fn dds_layouts() {
+        //bgra, 2 levels
+        let mut data = header(2, 1, 2, None);
+        data.extend([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let texture = read_dds(&data).unwrap();
+        assert_eq!(texture.format, TextureFormat::Rgba8);
+        assert_eq!(texture.levels, vec![vec![3, 2, 1, 4, 7, 6, 5, 8], vec![11, 10, 9, 12]]);
+        assert!(read_dds(&data[..data.len() - 1]).is_err());
+
+        //a dx10 bc1 cubemap, every face is its whole chain, 8 x 8 is 4 blocks then 1
+        let mut data = header(8, 8, 2, Some(b"DX10"));
+        for value in [71u32, 3, 0x4, 1, 0] {
+            data.extend(value.to_le_bytes());
+        }
+        for face in 0..6u8 {
+            data.extend([face; 32]);
+            data.extend([face + 10; 8]);
+        }
+        let texture = read_dds(&data).unwrap();
+        assert_eq!(texture.format, TextureFormat::Bc1);
+        assert_eq!((texture.faces, texture.layers, texture.level_count()), (6, 1, 2));
+        assert_eq!(texture.image(0, 0, 2), &[2; 32]);
+        assert_eq!(texture.image(1, 0, 5), &[15; 8]);
+
+        //dxt5 as a fourCC, as an array of 2 through dx10, and things that aren't read
+        let mut data = header(4, 4, 1, Some(b"DXT5"));
+        data.extend([0; 16]);
+        assert_eq!(read_dds(&data).unwrap().format, TextureFormat::Bc3);
+        let mut data = header(4, 4, 0, Some(b"DX10"));
+        for value in [98u32, 3, 0, 2, 0] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend([0; 32]);
+        let texture = read_dds(&data).unwrap();
+        assert_eq!((texture.format, texture.layers), (TextureFormat::Bc7, 2));
+        //half floats, by their legacy number
+        let mut data = header(1, 1, 1, Some(&[113, 0, 0, 0]));
+        data.extend([0, 0x3c, 0, 0x40, 0, 0, 0, 0x3c]);
+        let texture = read_dds(&data).unwrap();
+        assert_eq!(texture.decode_floats(0, 0, 0), vec![[1.0, 2.0, 0.0, 1.0]]);
+        assert!(read_dds(&header(4, 4, 1, Some(b"DXT3"))).is_err());
+        assert!(read_dds(&header(4, 4, 4, Some(b"DXT1"))).is_err());
+        assert!(read_dds(b"DDS ").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+/* Reads KTX2 textures, Khronos' container for textures in a Vulkan format, with their
+ * mip levels, array layers and cubemap faces. Everything is little endian.
+ *
+ * header, 80 bytes
+ *    0  [u8; 12]  identifier, KTX2_IDENTIFIER
+ *   12  u32       vkFormat
+ *   16  u32       type size
+ *   20  u32       width, u32 height, u32 depth
+ *   32  u32       layer count, 0 when it isn't an array
+ *   36  u32       face count, 1 or 6
+ *   40  u32       level count, 0 asks the loader to make the mips, read as 1
+ *   44  u32       supercompression scheme
+ *   48  u32       dfd offset, u32 dfd length, u32 key/value offset, u32 key/value length
+ *   64  u64       supercompression global data offset, u64 its length
+ * level index    per level, largest first, u64 offset, u64 length, u64 uncompressed length
+ *
+ * Each level is every layer in turn, each layer its faces in turn. The data format
+ * descriptor is written by exporter::ktx2 but the vkFormat says all that's needed here.
+ * Supercompressed files (Basis Universal, zstd, zlib) and 3d textures aren't read.
+ */
+
+use ash::vk;
+
+use crate::{
+    model::{
+        mipmap::mip_level_count,
+        texture_format::{EncodedTexture, TextureFormat},
+    },
+    RenderResult,
+};
+
+pub const KTX2_IDENTIFIER: &[u8; 12] = b"\xabKTX 20\xbb\r\n\x1a\n";
+pub const KTX2_HEADER_SIZE: usize = 80;
+pub const KTX2_LEVEL_INDEX_SIZE: usize = 24;
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+pub fn read_ktx2(data: &[u8]) -> RenderResult<EncodedTexture> {
+    if data.len() < KTX2_HEADER_SIZE || !data.starts_with(KTX2_IDENTIFIER) {
+        return Err("not a ktx2 file".to_string());
+    }
+    let vk_format = u32_at(data, 12);
+    let format = TextureFormat::from_vk_format(vk::Format::from_raw(vk_format as i32))
+        .ok_or(format!("ktx2 texture has an unsupported vkFormat: {vk_format}"))?;
+    let (width, height, depth) = (u32_at(data, 20), u32_at(data, 24), u32_at(data, 28));
+    if depth > 1 {
+        return Err(format!("ktx2 texture is 3d, {width} x {height} x {depth}"));
+    }
+    let supercompression = u32_at(data, 44);
+    if supercompression != 0 {
+        return Err(format!("ktx2 texture is supercompressed, scheme {supercompression}"));
+    }
+    let mut texture = EncodedTexture{
+        format,
+        width,
+        //1d textures have no height
+        height: height.max(1),
+        layers: u32_at(data, 32).max(1),
+        faces: u32_at(data, 36),
+        levels: vec![],
+    };
+    let count = u32_at(data, 40).max(1);
+    if count > mip_level_count(texture.width, texture.height) {
+        return Err(format!("ktx2 texture has {count} levels, too many for {width} x {height}"));
+    }
+    let index_end = KTX2_HEADER_SIZE + count as usize * KTX2_LEVEL_INDEX_SIZE;
+    if data.len() < index_end {
+        return Err("ktx2 file is truncated in its level index".to_string());
+    }
+    for level in 0..count as usize {
+        let entry = KTX2_HEADER_SIZE + level * KTX2_LEVEL_INDEX_SIZE;
+        let (offset, length) = (u64_at(data, entry), u64_at(data, entry + 8));
+        let expected = texture.level_bytes(level);
+        if length != expected as u64 {
+            return Err(format!("ktx2 level {level} is {length} bytes, expected {expected}"));
+        }
+        let level_data = usize::try_from(offset).ok()
+            .and_then(|offset| data.get(offset..offset.checked_add(expected)?))
+            .ok_or(format!("ktx2 level {level} is past the end of the file"))?;
+        texture.levels.push(level_data.to_vec());
+    }
+    texture.check().map_err(|e| format!("bad ktx2 texture: {e}"))?;
+    Ok(texture)
+}
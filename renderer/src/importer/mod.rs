@@ -1,8 +1,192 @@
-use crate::model::Model;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rayon::ThreadPoolBuilder;
+
+use crate::model::{
+    conversion::Conversion,
+    material::{load_texture, SharedTexture},
+    normals::NormalMode,
+    texture_manager::TextureManager,
+    Model, DEFAULT_MODEL_NAME,
+};
 use crate::RenderResult;
 
 pub mod wavefront;
+pub mod mtl;
+pub mod ply;
+pub mod stl;
+pub mod collada;
+pub mod cooked;
+pub mod ktx2;
+pub mod dds;
+pub mod registry;
+
+//anything an importer can pull bytes out of, files, cursors over memory, archive entries
+pub trait ImportSource: Read + Seek {}
+
+impl<T: Read + Seek> ImportSource for T {}
+
+//models reference companion files (.mtl, textures) by the name written in the model,
+//a resolver turns that name into something readable
+//closures work too: |name: &str| -> RenderResult<Box<dyn ImportSource>> {...}
+pub trait Resolver {
+    fn resolve(&self, name: &str) -> RenderResult<Box<dyn ImportSource>>;
+
+    //decoded, resolvers backed by a TextureManager only decode a texture once
+    fn texture(&self, name: &str) -> RenderResult<SharedTexture> {
+        Ok(Arc::new(load_texture(self.resolve(name)?.as_mut())?))
+    }
+}
+
+impl<F> Resolver for F
+        where F: Fn(&str) -> RenderResult<Box<dyn ImportSource>> {
+    fn resolve(&self, name: &str) -> RenderResult<Box<dyn ImportSource>> {
+        self(name)
+    }
+}
+
+//resolves names relative to a directory, absolute names are used as is
+pub struct FileResolver {
+    root: PathBuf,
+    textures: Option<Arc<TextureManager>>,
+}
+
+impl FileResolver {
+    pub fn new(root: &Path) -> Self {
+        Self{
+            root: root.to_path_buf(),
+            textures: None,
+        }
+    }
+
+    //textures are shared through textures, keyed by their full path
+    pub fn with_textures(root: &Path, textures: Arc<TextureManager>) -> Self {
+        Self{
+            textures: Some(textures),
+            ..Self::new(root)
+        }
+    }
+}
+
+impl Resolver for FileResolver {
+    fn resolve(&self, name: &str) -> RenderResult<Box<dyn ImportSource>> {
+        let path = self.root.join(name);
+        let file = File::open(&path).map_err(|e| {
+            format!("could not open '{}' for reading: {e}", path.to_string_lossy())
+        })?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+
+    fn texture(&self, name: &str) -> RenderResult<SharedTexture> {
+        match &self.textures {
+            Some(textures) => textures.get_texture_by_path(&self.root.join(name)),
+            None => Ok(Arc::new(load_texture(self.resolve(name)?.as_mut())?)),
+        }
+    }
+}
+
+//name -> bytes, for tests and anything that's already been pulled out of an archive
+#[derive(Default)]
+pub struct MemoryResolver {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) {
+        self.files.insert(name.to_string(), data);
+    }
+}
+
+impl Resolver for MemoryResolver {
+    fn resolve(&self, name: &str) -> RenderResult<Box<dyn ImportSource>> {
+        let data = self.files.get(name).ok_or(format!("no file named '{name}' to resolve"))?;
+        Ok(Box::new(Cursor::new(data.clone())))
+    }
+}
+
+//per import options, an importer ignores anything that doesn't apply to its format
+#[derive(Clone, Debug, Default)]
+pub struct ImportSettings {
+    //used whenever the source has no normals, or always with force_normals
+    pub normal_mode: NormalMode,
+    //throw away the source's normals and generate them
+    pub force_normals: bool,
+    //axis remap, scale and uv flip applied to everything imported, recorded on the Model
+    pub conversion: Conversion,
+    //threads for importers that parse in parallel, None uses one per core
+    pub threads: Option<usize>,
+}
+
+//runs op on a pool of the given size, None runs it on rayon's global pool
+pub(crate) fn run_with_threads<T: Send>(
+    threads: Option<usize>, op: impl FnOnce() -> T + Send,
+) -> RenderResult<T> {
+    match threads {
+        Some(threads) => {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|e| format!("could not create a pool of {threads} threads: {e}"))?;
+            Ok(pool.install(op))
+        },
+        None => Ok(op()),
+    }
+}
+
+//the file stem is the fallback name
+fn import_file<I: Importer + ?Sized>(
+    importer: &I, loc: &str, resolver: &FileResolver, settings: &ImportSettings,
+) -> RenderResult<Model> {
+    let path = Path::new(loc);
+    let file = File::open(path)
+        .map_err(|e| format!("could not open '{loc}' for reading: {e}"))?;
+    let name = path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(DEFAULT_MODEL_NAME);
+    importer.import(&mut BufReader::new(file), name, resolver, settings)
+}
 
 pub trait Importer {
-    fn generate_model(&self, loc: &str) -> RenderResult<Model>;
+    //name is the model name to use when the source doesn't carry one of its own
+    fn import(
+        &self, source: &mut dyn ImportSource, name: &str, resolver: &dyn Resolver,
+        settings: &ImportSettings,
+    ) -> RenderResult<Model>;
+
+    fn generate_model(&self, loc: &str) -> RenderResult<Model> {
+        self.generate_model_with_settings(loc, &ImportSettings::default())
+    }
+
+    //companion files are resolved relative to loc's directory,
+    //the file stem is the fallback name
+    fn generate_model_with_settings(
+        &self, loc: &str, settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let root = Path::new(loc).parent().unwrap_or(Path::new("."));
+        import_file(self, loc, &FileResolver::new(root), settings)
+    }
+
+    //textures already held through textures, by this model or any other, are shared
+    fn generate_model_with_textures(
+        &self, loc: &str, settings: &ImportSettings, textures: &Arc<TextureManager>,
+    ) -> RenderResult<Model> {
+        let root = Path::new(loc).parent().unwrap_or(Path::new("."));
+        import_file(self, loc, &FileResolver::with_textures(root, textures.clone()), settings)
+    }
+
+    fn import_bytes(
+        &self, data: &[u8], name: &str, resolver: &dyn Resolver, settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        self.import(&mut Cursor::new(data), name, resolver, settings)
+    }
 }
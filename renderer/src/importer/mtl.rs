@@ -0,0 +1,287 @@
+/* Wavefront MTL material libraries, what an OBJ's "mtllib" points at.
+ * Each "newmtl" is read as Phong parameters (Kd, Ks, Ns, Ke, d/Tr, illum) plus the
+ * PBR extension's Pr and Pm, then converted with Material::from_phong.
+ * Texture maps:
+ *   map_Kd                  base color
+ *   map_Pr, map_Pm          roughness and metallic, grey images packed into the g and b
+ *                           channels of one metallic roughness texture
+ *   map_Ke                  emissive
+ *   norm, map_Bump, bump    normal map, -bm is its strength, Blender writes normal maps
+ *                           as map_Bump
+ * Of the map options only -clamp (clamp to edge instead of repeating) and -bm are used,
+ * the rest are skipped over.
+ */
+
+use image::Rgba;
+
+use super::Resolver;
+
+use crate::{
+    model::material::{
+        Material, Phong, TextureImage, TextureSlot, TextureSlotKind, TextureWrap,
+    },
+    RenderResult,
+};
+
+//options followed by a fixed number of arguments
+const MAP_OPTIONS: [(&str, usize); 10] = [
+    ("-blendu", 1), ("-blendv", 1), ("-boost", 1), ("-cc", 1), ("-clamp", 1),
+    ("-imfchan", 1), ("-texres", 1), ("-type", 1), ("-bm", 1), ("-mm", 2),
+];
+//options followed by 1 to 3 numbers
+const MAP_VECTOR_OPTIONS: [&str; 3] = ["-o", "-s", "-t"];
+
+//a map statement's file and the options that matter
+struct TextureMap {
+    path: String,
+    clamp: bool,
+    bump_scale: f32,
+}
+
+fn parse_map(value: &str) -> RenderResult<TextureMap> {
+    let mut map = TextureMap{
+        path: String::new(),
+        clamp: false,
+        bump_scale: 1.0,
+    };
+    let mut tokens = value.split_whitespace().peekable();
+    while let Some(token) = tokens.peek().copied() {
+        if let Some((option, count)) = MAP_OPTIONS.iter().find(|(o, _)| *o == token) {
+            tokens.next();
+            let arguments: Vec<&str> = tokens.by_ref().take(*count).collect();
+            if arguments.len() != *count {
+                return Err(format!("map option {option} is missing its arguments"));
+            }
+            match *option {
+                "-clamp" => map.clamp = arguments[0] == "on",
+                "-bm" => map.bump_scale = arguments[0].parse()
+                    .map_err(|_| format!("Unable to parse -bm {}", arguments[0]))?,
+                _ => {},
+            }
+        } else if MAP_VECTOR_OPTIONS.contains(&token) {
+            tokens.next();
+            for _ in 0..3 {
+                if tokens.next_if(|t| t.parse::<f32>().is_ok()).is_none() {
+                    break;
+                }
+            }
+        } else {
+            break;
+        }
+    }
+    //file names can have spaces
+    map.path = tokens.collect::<Vec<&str>>().join(" ");
+    if map.path.is_empty() {
+        return Err(format!("map '{value}' has no file"));
+    }
+    Ok(map)
+}
+
+//a color is 3 numbers, or 1 for all three
+fn parse_color(value: &str) -> Option<[f32; 3]> {
+    let values: Vec<f32> = value.split_whitespace()
+        .map(|v| v.parse::<f32>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match values[..] {
+        [v] => Some([v; 3]),
+        [r, g, b, ..] => Some([r, g, b]),
+        _ => None,
+    }
+}
+
+//one "newmtl" as written, the maps by keyword, only the first of each is kept
+struct MtlMaterial {
+    name: String,
+    phong: Phong,
+    maps: Vec<(&'static str, TextureMap)>,
+}
+
+impl MtlMaterial {
+    fn map(&self, keyword: &str) -> Option<&TextureMap> {
+        self.maps.iter().find(|(k, _)| *k == keyword).map(|(_, map)| map)
+    }
+}
+
+const MAP_KEYWORDS: [&str; 7] = [
+    "map_Kd", "map_Pr", "map_Pm", "map_Ke", "norm", "map_Bump", "bump",
+];
+
+fn load_map(
+    map: &TextureMap, material: &str, resolver: &dyn Resolver,
+) -> RenderResult<TextureSlot> {
+    let path = &map.path;
+    let texture = resolver.texture(path).map_err(|e| {
+        format!("Could not load texture '{path}' for material '{material}': {e}")
+    })?;
+    let mut slot = TextureSlot::shared(texture, Some(path.clone()));
+    if map.clamp {
+        slot.sampler.wrap_u = TextureWrap::ClampToEdge;
+        slot.sampler.wrap_v = TextureWrap::ClampToEdge;
+    }
+    Ok(slot)
+}
+
+//roughness from the g channel and metallic from the b channel of their images, which is
+//where a grey image has them too. When both are the same image it's already packed,
+//the way the exporter writes it, otherwise they're packed at the first one's size
+fn metallic_roughness(
+    roughness: Option<TextureSlot>, metallic: Option<TextureSlot>,
+) -> Option<TextureSlot> {
+    match (roughness, metallic) {
+        (None, None) => None,
+        (Some(r), Some(m)) if r.location.is_some() && r.location == m.location => Some(r),
+        (r, m) => Some(pack(r.as_ref(), m.as_ref())),
+    }
+}
+
+fn pack(roughness: Option<&TextureSlot>, metallic: Option<&TextureSlot>) -> TextureSlot {
+    let first = roughness.or(metallic).unwrap();
+    let (width, height) = first.texture.dimensions();
+    //nearest texel of an image at another size, white without one, so the factor stays
+    let channel = |slot: Option<&TextureSlot>, x: u32, y: u32, c: usize| -> u8 {
+        slot.map_or(u8::MAX, |slot| {
+            let (w, h) = slot.texture.dimensions();
+            let (sx, sy) = (x * w / width, y * h / height);
+            slot.texture.get_pixel(sx, sy)[c]
+        })
+    };
+    let texture = TextureImage::from_fn(width, height, |x, y| {
+        Rgba([0, channel(roughness, x, y, 1), channel(metallic, x, y, 2), u8::MAX])
+    });
+    let mut slot = TextureSlot::new(texture, None);
+    slot.sampler = first.sampler;
+    slot
+}
+
+pub(crate) fn parse_mat_file(
+    mat_file: &str, lines: &str, resolver: &dyn Resolver,
+) -> RenderResult<Vec<Material>> {
+    let mut parsed: Vec<MtlMaterial> = vec![];
+    for l in lines.lines() {
+        let to_check = l.trim();
+        let Some((keyword, value)) = to_check.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let value = value.trim();
+        if keyword == "newmtl" {
+            parsed.push(MtlMaterial{
+                name: value.to_string(),
+                phong: Phong::default(),
+                maps: vec![],
+            });
+            continue;
+        }
+        let known = ["Kd", "Ks", "Ke", "Ns", "d", "Tr", "illum", "Pr", "Pm"].contains(&keyword)
+            || MAP_KEYWORDS.contains(&keyword);
+        if !known {
+            continue;
+        }
+        let bad_line = || format!("Could not parse '{to_check}' in material file: {mat_file}");
+        let material = parsed.last_mut().ok_or_else(bad_line)?;
+        let number = || value.parse::<f32>().map_err(|_| bad_line());
+        let phong = &mut material.phong;
+        match keyword {
+            "Kd" => phong.diffuse = parse_color(value).ok_or_else(bad_line)?,
+            "Ks" => phong.specular = parse_color(value).ok_or_else(bad_line)?,
+            "Ke" => phong.emissive = parse_color(value).ok_or_else(bad_line)?,
+            "Ns" => phong.shininess = number()?,
+            "d" => phong.opacity = number()?,
+            "Tr" => phong.opacity = 1.0 - number()?,
+            "illum" => phong.illumination = value.parse().map_err(|_| bad_line())?,
+            "Pr" => phong.roughness = Some(number()?),
+            "Pm" => phong.metallic = Some(number()?),
+            map => {
+                let map = MAP_KEYWORDS.into_iter().find(|k| *k == map).unwrap();
+                if material.map(map).is_none() {
+                    let parsed = parse_map(value).map_err(|e| format!("{}, {e}", bad_line()))?;
+                    material.maps.push((map, parsed));
+                }
+            },
+        }
+    }
+
+    parsed.into_iter().map(|mtl| {
+        let mut material = Material::from_phong(&mtl.name, &mtl.phong);
+        let load = |keyword: &str| -> RenderResult<Option<TextureSlot>> {
+            mtl.map(keyword).map(|map| load_map(map, &mtl.name, resolver)).transpose()
+        };
+        material.set_texture(TextureSlotKind::BaseColor, load("map_Kd")?);
+        let packed = metallic_roughness(load("map_Pr")?, load("map_Pm")?);
+        material.set_texture(TextureSlotKind::MetallicRoughness, packed);
+        material.set_texture(TextureSlotKind::Emissive, load("map_Ke")?);
+        let normal = ["norm", "map_Bump", "bump"].into_iter().find_map(|k| mtl.map(k));
+        if let Some(map) = normal {
+            let mut slot = load_map(map, &mtl.name, resolver)?;
+            slot.scale = map.bump_scale;
+            material.set_texture(TextureSlotKind::Normal, Some(slot));
+        }
+        Ok(material)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read;
+
+    use crate::{
+        importer::{
+            mtl::{parse_map, parse_mat_file},
+            MemoryResolver,
+        },
+        model::material::{AlphaMode, TextureSlotKind, TextureWrap},
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    #[test]
+    fn mtl_phong_and_maps() {
+        let texture = read(format!("{TEST_DIRECTORY}/collada_texture.png")).unwrap();
+        let mut resolver = MemoryResolver::new();
+        for name in ["color.png", "rough.png", "metal.png", "my normal.png"] {
+            resolver.insert(name, texture.clone());
+        }
+        let mtl = "# comment\n\
+            newmtl plain\nKd 0.8 0.1 0.1\nNs 250.0\nd 0.5\n\n\
+            newmtl mapped\nKd 1.0\nPr 0.3\nPm 1.0\nillum 2\n\
+            map_Kd -clamp on -o 0.5 0.5 color.png\nmap_Kd ignored.png\n\
+            map_Pr rough.png\nmap_Pm metal.png\nmap_Bump -bm 0.5 my normal.png\n";
+        let materials = parse_mat_file("test.mtl", mtl, &resolver).unwrap();
+        assert_eq!(materials.len(), 2);
+
+        let plain = &materials[0];
+        assert_eq!(plain.name, "plain");
+        assert_eq!(plain.base_color, [0.8, 0.1, 0.1, 0.5]);
+        assert_eq!(plain.alpha_mode, AlphaMode::Blend);
+        assert!((plain.roughness - 0.5).abs() < 1e-6);
+        assert_eq!(plain.textures().count(), 0);
+
+        let mapped = &materials[1];
+        assert_eq!(mapped.base_color, [1.0; 4]);
+        assert_eq!((mapped.roughness, mapped.metallic), (0.3, 1.0));
+        let color = mapped.texture(TextureSlotKind::BaseColor).unwrap();
+        assert_eq!(color.location.as_deref(), Some("color.png"));
+        assert_eq!(color.sampler.wrap_u, TextureWrap::ClampToEdge);
+        let normal = mapped.texture(TextureSlotKind::Normal).unwrap();
+        assert_eq!((normal.location.as_deref(), normal.scale), (Some("my normal.png"), 0.5));
+        //two images packed into a new one
+        let packed = mapped.texture(TextureSlotKind::MetallicRoughness).unwrap();
+        assert_eq!(packed.location, None);
+        let original = &color.texture;
+        assert_eq!(packed.texture.dimensions(), original.dimensions());
+        let (p, o) = (packed.texture.get_pixel(1, 0), original.get_pixel(1, 0));
+        assert_eq!((p[1], p[2]), (o[1], o[2]));
+
+        //one image for both is already packed
+        let mtl = "newmtl both\nmap_Pr rough.png\nmap_Pm rough.png\n";
+        let both = parse_mat_file("test.mtl", mtl, &resolver).unwrap();
+        let slot = both[0].texture(TextureSlotKind::MetallicRoughness).unwrap();
+        assert_eq!(slot.location.as_deref(), Some("rough.png"));
+
+        assert!(parse_mat_file("test.mtl", "Kd 1 1 1\n", &resolver).is_err());
+        assert!(parse_mat_file("test.mtl", "newmtl a\nKd red\n", &resolver).is_err());
+        assert!(parse_mat_file("test.mtl", "newmtl a\nmap_Kd missing.png\n", &resolver).is_err());
+        assert!(parse_map("-clamp on").is_err());
+        assert!(parse_map("-mm 0").is_err());
+    }
+}
@@ -0,0 +1,632 @@
+/* Stanford PLY importer, handles ascii, binary_little_endian and binary_big_endian.
+ * Only the "vertex" and "face" elements are mapped into the Model, everything else
+ * is read (so binary offsets stay correct) and then dropped on the floor.
+ * Faces are list properties and get fan triangulated, so quads and ngons are fine
+ * as long as they're convex.
+ */
+
+use super::{
+    ImportSettings, ImportSource, Importer, Resolver,
+};
+
+use crate::{
+    model::{
+        bounds::compute_bounds,
+        conversion::apply_conversion,
+        normals::generate_normals,
+        ColorVector, ColorVertex, IndexCoord, IndexVector, Mesh, Model, NormalVector,
+        NormalVertex, PositionVector, PostionVertex, SubMesh, TextureVector, TextureVertex,
+    },
+    RenderResult,
+};
+
+const PLY_MAGIC: &str = "ply";
+const PLY_END_HEADER: &str = "end_header";
+const PLY_VERTEX_ELEMENT: &str = "vertex";
+const PLY_FACE_ELEMENT: &str = "face";
+
+//blender, meshlab and the stanford repository don't agree on these...
+const PLY_U_NAMES: [&str; 4] = ["u", "s", "texture_u", "texture_s"];
+const PLY_V_NAMES: [&str; 4] = ["v", "t", "texture_v", "texture_t"];
+const PLY_RED_NAMES: [&str; 2] = ["red", "diffuse_red"];
+const PLY_GREEN_NAMES: [&str; 2] = ["green", "diffuse_green"];
+const PLY_BLUE_NAMES: [&str; 2] = ["blue", "diffuse_blue"];
+const PLY_ALPHA_NAMES: [&str; 2] = ["alpha", "diffuse_alpha"];
+const PLY_FACE_INDEX_NAMES: [&str; 2] = ["vertex_indices", "vertex_index"];
+
+#[derive(Default)]
+pub struct Ply {}
+
+impl Ply {
+    pub fn new() -> Self {
+        Self{}
+    }
+
+    //the heavy lifting, split out from the trait so tests don't need to touch disk
+    fn generate_model_from_bytes(
+        &self, data: &[u8], name: &str, settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let (header, body_start) = PlyHeader::parse(data)?;
+        let mut body = PlyBody::new(&header.format, &data[body_start..])?;
+        let mut vertex_rows: Option<(&PlyElement, Vec<Vec<PlyValue>>)> = None;
+        let mut face_rows: Option<(&PlyElement, Vec<Vec<PlyValue>>)> = None;
+        for element in header.elements.iter() {
+            let rows = body.read_element(element)?;
+            match element.name.as_str() {
+                PLY_VERTEX_ELEMENT => vertex_rows = Some((element, rows)),
+                PLY_FACE_ELEMENT => face_rows = Some((element, rows)),
+                _ => {},
+            }
+        }
+        let (vertex_element, vertex_rows) = vertex_rows.ok_or(
+            format!("ply file has no '{PLY_VERTEX_ELEMENT}' element")
+        )?;
+        let mesh = Self::generate_mesh(vertex_element, &vertex_rows)?;
+        let indeces = match face_rows {
+            Some((face_element, face_rows)) => {
+                Self::generate_indeces(face_element, &face_rows, vertex_rows.len())?
+            },
+            None => vec!(),
+        };
+        let mut model = Model{
+            submeshes: vec!(SubMesh::new(name, 0, indeces.len(), None)),
+            ..Model::new(name, mesh, indeces)
+        };
+        if settings.force_normals || vertex_element.index_of(&["nx"]).is_none() {
+            generate_normals(&mut model, settings.normal_mode, None)?;
+        }
+        compute_bounds(&mut model)?;
+        apply_conversion(&mut model, &settings.conversion)?;
+        Ok(model)
+    }
+
+    //picks the smallest Mesh variant that holds everything the file has
+    fn generate_mesh(
+        element: &PlyElement, rows: &[Vec<PlyValue>],
+    ) -> RenderResult<Mesh> {
+        let x = element.required_index("x")?;
+        let y = element.required_index("y")?;
+        let z = element.required_index("z")?;
+        let norm = match (
+            element.index_of(&["nx"]), element.index_of(&["ny"]), element.index_of(&["nz"]),
+        ) {
+            (Some(nx), Some(ny), Some(nz)) => Some((nx, ny, nz)),
+            _ => None,
+        };
+        let uv = match (element.index_of(&PLY_U_NAMES), element.index_of(&PLY_V_NAMES)) {
+            (Some(u), Some(v)) => Some((u, v)),
+            _ => None,
+        };
+        let color = match (
+            element.index_of(&PLY_RED_NAMES),
+            element.index_of(&PLY_GREEN_NAMES),
+            element.index_of(&PLY_BLUE_NAMES),
+        ) {
+            (Some(r), Some(g), Some(b)) => Some((r, g, b, element.index_of(&PLY_ALPHA_NAMES))),
+            _ => None,
+        };
+        let pos_fn = |row: &[PlyValue]| -> RenderResult<PositionVector> {
+            Ok(PositionVector::new(
+                row[x].scalar()? as f32, row[y].scalar()? as f32, row[z].scalar()? as f32,
+                1.0,
+            ))
+        };
+        let uv_fn = |row: &[PlyValue]| -> RenderResult<TextureVector> {
+            match uv {
+                Some((u, v)) => Ok(TextureVector::new(
+                    row[u].scalar()? as f32, row[v].scalar()? as f32,
+                )),
+                None => Ok(TextureVector::new(0.0, 0.0)),
+            }
+        };
+        //keeping the homogenous 1.0 the wavefront importer uses
+        let norm_fn = |row: &[PlyValue]| -> RenderResult<NormalVector> {
+            match norm {
+                Some((nx, ny, nz)) => Ok(NormalVector::new(
+                    row[nx].scalar()? as f32, row[ny].scalar()? as f32,
+                    row[nz].scalar()? as f32, 1.0,
+                )),
+                None => Ok(NormalVector::new(0.0, 0.0, 0.0, 1.0)),
+            }
+        };
+        let channel_fn = |row: &[PlyValue], i: usize| -> RenderResult<f32> {
+            Ok(element.properties[i].normalize(row[i].scalar()?))
+        };
+        if let Some((r, g, b, a)) = color {
+            let vertices = rows.iter().map(|row| {
+                let alpha = match a {
+                    Some(a) => channel_fn(row, a)?,
+                    None => 1.0,
+                };
+                Ok(ColorVertex{
+                    pos: pos_fn(row)?,
+                    uv: uv_fn(row)?,
+                    norm: norm_fn(row)?,
+                    color: ColorVector::new(
+                        channel_fn(row, r)?, channel_fn(row, g)?, channel_fn(row, b)?, alpha,
+                    ),
+                })
+            }).collect::<RenderResult<Vec<ColorVertex>>>()?;
+            return Ok(Mesh::ColorMesh(vertices));
+        }
+        if norm.is_some() {
+            let vertices = rows.iter().map(|row| {
+                Ok(NormalVertex::new(pos_fn(row)?, uv_fn(row)?, norm_fn(row)?))
+            }).collect::<RenderResult<Vec<NormalVertex>>>()?;
+            return Ok(Mesh::NormalMesh(vertices));
+        }
+        if uv.is_some() {
+            let vertices = rows.iter().map(|row| {
+                Ok(TextureVertex{
+                    pos: pos_fn(row)?,
+                    uv: uv_fn(row)?,
+                })
+            }).collect::<RenderResult<Vec<TextureVertex>>>()?;
+            return Ok(Mesh::TextureMesh(vertices));
+        }
+        let vertices = rows.iter().map(|row| {
+            Ok(PostionVertex(pos_fn(row)?))
+        }).collect::<RenderResult<Vec<PostionVertex>>>()?;
+        Ok(Mesh::PositionMesh(vertices))
+    }
+
+    //fan triangulates every face, (0, i, i + 1)
+    fn generate_indeces(
+        element: &PlyElement, rows: &[Vec<PlyValue>], vertex_count: usize,
+    ) -> RenderResult<IndexVector> {
+        let list = element.index_of(&PLY_FACE_INDEX_NAMES).ok_or(
+            format!("ply '{PLY_FACE_ELEMENT}' element has no vertex index list")
+        )?;
+        let mut indeces: IndexVector = vec![];
+        for (f, row) in rows.iter().enumerate() {
+            let face = match &row[list] {
+                PlyValue::List(face) => face,
+                PlyValue::Scalar(_) => {
+                    return Err(format!("ply face index property must be a list, face: {f}"));
+                },
+            };
+            if face.len() < 3 {
+                return Err(format!("ply face: {f} has fewer than 3 vertices"));
+            }
+            let face = face.iter().map(|i| {
+                let index = *i as usize;
+                if *i < 0.0 || index >= vertex_count {
+                    return Err(format!("ply face: {f} references missing vertex: {i}"));
+                }
+                Ok(index as IndexCoord)
+            }).collect::<RenderResult<Vec<IndexCoord>>>()?;
+            for i in 1..face.len() - 1 {
+                indeces.push(face[0]);
+                indeces.push(face[i]);
+                indeces.push(face[i + 1]);
+            }
+        }
+        Ok(indeces)
+    }
+}
+
+impl Importer for Ply {
+    //ply has no notion of a name, always uses the one passed in
+    //and never references other files
+    fn import(
+        &self, source: &mut dyn ImportSource, name: &str, _resolver: &dyn Resolver,
+        settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let mut data = vec![];
+        source.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        self.generate_model_from_bytes(&data, name, settings)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+impl PlyFormat {
+    fn get(format: &str, version: &str) -> RenderResult<Self> {
+        if version != "1.0" {
+            return Err(format!("unsupported ply version: '{version}'"));
+        }
+        match format {
+            "ascii" => Ok(Self::Ascii),
+            "binary_little_endian" => Ok(Self::BinaryLittleEndian),
+            "binary_big_endian" => Ok(Self::BinaryBigEndian),
+            _ => Err(format!("unknown ply format: '{format}'")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlyScalar {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl PlyScalar {
+    //both the original names and the sized aliases show up in the wild
+    fn get(tpe: &str) -> RenderResult<Self> {
+        match tpe {
+            "char" | "int8" => Ok(Self::Char),
+            "uchar" | "uint8" => Ok(Self::UChar),
+            "short" | "int16" => Ok(Self::Short),
+            "ushort" | "uint16" => Ok(Self::UShort),
+            "int" | "int32" => Ok(Self::Int),
+            "uint" | "uint32" => Ok(Self::UInt),
+            "float" | "float32" => Ok(Self::Float),
+            "double" | "float64" => Ok(Self::Double),
+            _ => Err(format!("unknown ply property type: '{tpe}'")),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::Char | Self::UChar => 1,
+            Self::Short | Self::UShort => 2,
+            Self::Int | Self::UInt | Self::Float => 4,
+            Self::Double => 8,
+        }
+    }
+
+    //integer colors are stored as [0, max], floats are assumed to already be [0, 1]
+    fn max(&self) -> Option<f64> {
+        match self {
+            Self::Char => Some(i8::MAX as f64),
+            Self::UChar => Some(u8::MAX as f64),
+            Self::Short => Some(i16::MAX as f64),
+            Self::UShort => Some(u16::MAX as f64),
+            Self::Int => Some(i32::MAX as f64),
+            Self::UInt => Some(u32::MAX as f64),
+            Self::Float | Self::Double => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PlyPropertyType {
+    Scalar(PlyScalar),
+    //(count type, item type)
+    List(PlyScalar, PlyScalar),
+}
+
+#[derive(Debug)]
+struct PlyProperty {
+    name: String,
+    tpe: PlyPropertyType,
+}
+
+impl PlyProperty {
+    fn normalize(&self, val: f64) -> f32 {
+        match &self.tpe {
+            PlyPropertyType::Scalar(scalar) => {
+                match scalar.max() {
+                    Some(max) => (val / max) as f32,
+                    None => val as f32,
+                }
+            },
+            PlyPropertyType::List(..) => val as f32,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+impl PlyElement {
+    fn index_of(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|p| names.contains(&p.name.as_str()))
+    }
+
+    fn required_index(&self, name: &str) -> RenderResult<usize> {
+        self.index_of(&[name]).ok_or(
+            format!("ply element '{}' is missing property '{name}'", self.name)
+        )
+    }
+}
+
+#[derive(Debug)]
+struct PlyHeader {
+    format: PlyFormat,
+    elements: Vec<PlyElement>,
+}
+
+impl PlyHeader {
+    //returns the header and the offset of the first byte of element data
+    fn parse(data: &[u8]) -> RenderResult<(Self, usize)> {
+        let mut offset = 0;
+        let mut format: Option<PlyFormat> = None;
+        let mut elements: Vec<PlyElement> = vec![];
+        let mut first = true;
+        loop {
+            let end = data[offset..].iter().position(|b| *b == b'\n').ok_or(
+                format!("ply header is missing '{PLY_END_HEADER}'")
+            )?;
+            let line = std::str::from_utf8(&data[offset..offset + end])
+                .map_err(|e| format!("ply header is not valid ascii: {e}"))?
+                .trim();
+            offset += end + 1;
+            if first {
+                if line != PLY_MAGIC {
+                    return Err(format!("not a ply file, expected '{PLY_MAGIC}' got: '{line}'"));
+                }
+                first = false;
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                [] => {},
+                ["comment", ..] | ["obj_info", ..] => {},
+                ["format", fmt, version] => {
+                    format = Some(PlyFormat::get(fmt, version)?);
+                },
+                ["element", name, count] => {
+                    elements.push(PlyElement{
+                        name: name.to_string(),
+                        count: count.parse().map_err(|_| {
+                            format!("Unable to parse ply element count: {line}")
+                        })?,
+                        properties: vec![],
+                    });
+                },
+                ["property", "list", count_tpe, item_tpe, name] => {
+                    let element = elements.last_mut().ok_or(
+                        format!("ply property declared before any element: {line}")
+                    )?;
+                    element.properties.push(PlyProperty{
+                        name: name.to_string(),
+                        tpe: PlyPropertyType::List(
+                            PlyScalar::get(count_tpe)?, PlyScalar::get(item_tpe)?,
+                        ),
+                    });
+                },
+                ["property", tpe, name] => {
+                    let element = elements.last_mut().ok_or(
+                        format!("ply property declared before any element: {line}")
+                    )?;
+                    element.properties.push(PlyProperty{
+                        name: name.to_string(),
+                        tpe: PlyPropertyType::Scalar(PlyScalar::get(tpe)?),
+                    });
+                },
+                [PLY_END_HEADER] => break,
+                _ => {
+                    return Err(format!("Unable to parse ply header line: {line}"));
+                },
+            }
+        }
+        let format = format.ok_or("ply header has no format line".to_string())?;
+        Ok((Self{format, elements}, offset))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PlyValue {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+impl PlyValue {
+    fn scalar(&self) -> RenderResult<f64> {
+        match self {
+            Self::Scalar(val) => Ok(*val),
+            Self::List(_) => Err("expected a scalar ply property, got a list".to_string()),
+        }
+    }
+}
+
+enum PlyBody<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary{
+        data: &'a [u8],
+        offset: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> PlyBody<'a> {
+    fn new(format: &PlyFormat, data: &'a [u8]) -> RenderResult<Self> {
+        match format {
+            PlyFormat::Ascii => {
+                let text = std::str::from_utf8(data)
+                    .map_err(|e| format!("ascii ply body is not valid utf8: {e}"))?;
+                Ok(Self::Ascii(text.split_ascii_whitespace()))
+            },
+            PlyFormat::BinaryLittleEndian => {
+                Ok(Self::Binary{data, offset: 0, big_endian: false})
+            },
+            PlyFormat::BinaryBigEndian => {
+                Ok(Self::Binary{data, offset: 0, big_endian: true})
+            },
+        }
+    }
+
+    //every scalar type fits losslessly in an f64, keeps the rest of the parser simple
+    fn read_scalar(&mut self, tpe: PlyScalar) -> RenderResult<f64> {
+        match self {
+            Self::Ascii(tokens) => {
+                let token = tokens.next().ok_or("unexpected end of ascii ply data".to_string())?;
+                token.parse::<f64>()
+                    .map_err(|_| format!("Unable to parse ply value: '{token}'"))
+            },
+            Self::Binary{data, offset, big_endian} => {
+                let size = tpe.size();
+                if *offset + size > data.len() {
+                    return Err("unexpected end of binary ply data".to_string());
+                }
+                let bytes = &data[*offset..*offset + size];
+                *offset += size;
+                macro_rules! from_bytes {
+                    ($t:ty) => {{
+                        let raw: [u8; std::mem::size_of::<$t>()] = bytes.try_into().unwrap();
+                        if *big_endian {
+                            <$t>::from_be_bytes(raw) as f64
+                        } else {
+                            <$t>::from_le_bytes(raw) as f64
+                        }
+                    }};
+                }
+                Ok(match tpe {
+                    PlyScalar::Char => from_bytes!(i8),
+                    PlyScalar::UChar => from_bytes!(u8),
+                    PlyScalar::Short => from_bytes!(i16),
+                    PlyScalar::UShort => from_bytes!(u16),
+                    PlyScalar::Int => from_bytes!(i32),
+                    PlyScalar::UInt => from_bytes!(u32),
+                    PlyScalar::Float => from_bytes!(f32),
+                    PlyScalar::Double => from_bytes!(f64),
+                })
+            },
+        }
+    }
+
+    fn read_element(&mut self, element: &PlyElement) -> RenderResult<Vec<Vec<PlyValue>>> {
+        //the count is the header's word, rows only get space as they're read
+        let mut rows = vec![];
+        for _ in 0..element.count {
+            let mut row = Vec::with_capacity(element.properties.len());
+            for property in element.properties.iter() {
+                match property.tpe {
+                    PlyPropertyType::Scalar(tpe) => {
+                        row.push(PlyValue::Scalar(self.read_scalar(tpe)?));
+                    },
+                    PlyPropertyType::List(count_tpe, item_tpe) => {
+                        let count = self.read_scalar(count_tpe)? as usize;
+                        let items = (0..count)
+                            .map(|_| self.read_scalar(item_tpe))
+                            .collect::<RenderResult<Vec<f64>>>()?;
+                        row.push(PlyValue::List(items));
+                    },
+                }
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read;
+
+    use crate::{
+        importer::{
+            ply::Ply,
+            ImportSettings, Importer,
+        },
+        model::{
+            Mesh,
+            Vector2,
+            Vector4,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    fn read_test_file(file_name: &str) -> Vec<u8> {
+        read(format!("{TEST_DIRECTORY}/{file_name}"))
+            .unwrap_or_else(|_| panic!("Could not open 'testdata/{file_name}' for reading."))
+    }
+
+    #[test]
+    fn ply_ascii_cube() {
+        let model = Ply::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/ply_ascii_cube.ply"))
+            .unwrap();
+        assert_eq!(model.name, "ply_ascii_cube");
+        //6 quads, fan triangulated
+        assert_eq!(model.indeces.len(), 6*2*3, "index length");
+        assert_eq!(&model.indeces[0..6], &[0, 1, 2, 0, 2, 3]);
+        match model.mesh {
+            Mesh::NormalMesh(vertices) => {
+                assert_eq!(vertices.len(), 24, "vertex length");
+                assert_eq!(vertices[5].pos, Vector4::new(-1.0, -1.0, -1.0, 1.0));
+                assert_eq!(vertices[5].uv, Vector2::new(1.0, 0.0));
+                assert_eq!(vertices[5].norm, Vector4::new(0.0, 0.0, -1.0, 1.0));
+            },
+            _ => panic!("expected a NormalMesh"),
+        }
+    }
+
+    #[test]
+    //the binary files are the ascii cube re-encoded, they must produce identical models
+    fn ply_binary_matches_ascii() {
+        let ply = Ply::new();
+        let ascii = ply.generate_model_from_bytes(
+            &read_test_file("ply_ascii_cube.ply"), "cube", &ImportSettings::default(),
+        ).unwrap();
+        for file_name in ["ply_binary_le_cube.ply", "ply_binary_be_cube.ply"] {
+            let binary = ply.generate_model_from_bytes(
+                &read_test_file(file_name), "cube", &ImportSettings::default(),
+            ).unwrap();
+            assert_eq!(ascii.indeces, binary.indeces, "{file_name} indeces");
+            match (&ascii.mesh, &binary.mesh) {
+                (Mesh::NormalMesh(a), Mesh::NormalMesh(b)) => {
+                    assert_eq!(a.len(), b.len(), "{file_name} vertex length");
+                    for (a, b) in a.iter().zip(b.iter()) {
+                        assert_eq!(a.pos, b.pos, "{file_name} position");
+                        assert_eq!(a.uv, b.uv, "{file_name} uv");
+                        assert_eq!(a.norm, b.norm, "{file_name} normal");
+                    }
+                },
+                _ => panic!("expected two NormalMeshes for {file_name}"),
+            }
+        }
+    }
+
+    #[test]
+    fn ply_colors() {
+        let model = Ply::new().generate_model_from_bytes(
+            &read_test_file("ply_ascii_colors.ply"), "colors", &ImportSettings::default(),
+        ).unwrap();
+        assert_eq!(model.indeces, vec![0, 1, 2, 0, 2, 3, 0, 3, 4]);
+        match model.mesh {
+            Mesh::ColorMesh(vertices) => {
+                assert_eq!(vertices.len(), 5, "vertex length");
+                assert_eq!(vertices[0].color, Vector4::new(1.0, 0.0, 0.0, 1.0));
+                assert_eq!(vertices[4].color, Vector4::new(0.0, 0.0, 1.0, 0.0));
+                //no normals in the file, generated from the flat fan
+                assert_eq!(vertices[4].norm, Vector4::new(0.0, 0.0, 1.0, 1.0));
+            },
+            _ => panic!("expected a ColorMesh"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "references missing vertex: 7")]
+    fn ply_bad_indeces() {
+        let _ = Ply::new().generate_model_from_bytes(
+            &read_test_file("ply_bad_indeces.ply"), "bad", &ImportSettings::default(),
+        ).unwrap();
+    }
+
+    #[test]
+    fn ply_oversized_count() {
+        //a count no file could back, with one vertex's worth of data
+        for format in ["ascii", "binary_little_endian"] {
+            let mut data = format!(
+                "ply\nformat {format} 1.0\nelement vertex 99999999999999\n\
+                property float x\nproperty float y\nproperty float z\nend_header\n",
+            ).into_bytes();
+            match format {
+                "ascii" => data.extend_from_slice(b"0 0 0\n"),
+                _ => data.extend_from_slice(&[0; 12]),
+            }
+            let result = Ply::new().generate_model_from_bytes(
+                &data, "oversized", &ImportSettings::default(),
+            );
+            assert!(result.is_err(), "{format}");
+        }
+    }
+}
@@ -0,0 +1,152 @@
+/* Central lookup for importers, everything that needs to turn a file into a Model
+ * should go through here instead of matching on types itself.
+ * Importers are registered under a name, which for the built in ones is the same
+ * lowercased ModelType string the asset manifest stores as the model sub_type,
+ * and any number of file extensions.
+ * Downstream crates can register their own importers, or replace the built in ones,
+ * by registering under a new (or existing) name.
+ */
+
+use std::{
+    collections::HashMap,
+    path::Path,
+};
+
+use asset::asset::{
+    path_defs::{
+        COLLADA_EXTENSION, COOKED_EXTENSION, PLY_EXTENSION, STL_EXTENSION,
+        WAVEFRONT_EXTENSION,
+    },
+    ModelType, COLLADA_TYPE, COOKED_TYPE, PLY_TYPE, STL_TYPE, WAVEFRONT_TYPE,
+};
+
+use super::{
+    collada::Collada,
+    cooked::Cooked,
+    ply::Ply,
+    stl::Stl,
+    wavefront::Wavefront,
+    Importer,
+};
+
+use crate::RenderResult;
+
+pub struct ImporterRegistry {
+    importers: HashMap<String, Box<dyn Importer>>,
+    //extension -> importer name
+    extensions: HashMap<String, String>,
+}
+
+impl ImporterRegistry {
+    //empty, use default() for one with the built in importers
+    pub fn new() -> Self {
+        Self{
+            importers: HashMap::new(),
+            extensions: HashMap::new(),
+        }
+    }
+
+    //replaces any importer already registered under name, extensions are
+    //repointed at the new importer, extensions are case insensitive
+    pub fn register(&mut self, name: &str, extensions: &[&str], importer: Box<dyn Importer>) {
+        self.importers.insert(name.to_string(), importer);
+        for extension in extensions {
+            self.extensions.insert(extension.to_lowercase(), name.to_string());
+        }
+    }
+
+    pub fn get_by_name(&self, name: &str) -> RenderResult<&dyn Importer> {
+        self.importers.get(name.trim())
+            .map(|i| i.as_ref())
+            .ok_or(format!("no importer registered with name: '{name}'"))
+    }
+
+    pub fn get_by_type(&self, model_type: &ModelType) -> RenderResult<&dyn Importer> {
+        self.get_by_name(&format!("{model_type}").to_lowercase())
+    }
+
+    pub fn get_by_extension(&self, extension: &str) -> RenderResult<&dyn Importer> {
+        let name = self.extensions.get(&extension.to_lowercase())
+            .ok_or(format!("no importer registered for extension: '{extension}'"))?;
+        self.get_by_name(name)
+    }
+
+    pub fn get_by_location(&self, loc: &str) -> RenderResult<&dyn Importer> {
+        let extension = Path::new(loc).extension()
+            .and_then(|e| e.to_str())
+            .ok_or(format!("can't determine an importer for '{loc}', it has no extension"))?;
+        self.get_by_extension(extension)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.importers.keys().map(|n| n.as_str()).collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for ImporterRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            WAVEFRONT_TYPE, &[WAVEFRONT_EXTENSION], Box::new(Wavefront::new(None)),
+        );
+        registry.register(PLY_TYPE, &[PLY_EXTENSION], Box::new(Ply::new()));
+        registry.register(STL_TYPE, &[STL_EXTENSION], Box::new(Stl::new(None)));
+        registry.register(COLLADA_TYPE, &[COLLADA_EXTENSION], Box::new(Collada::new()));
+        registry.register(COOKED_TYPE, &[COOKED_EXTENSION], Box::new(Cooked::new()));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asset::asset::ModelType;
+
+    use crate::{
+        importer::{
+            registry::ImporterRegistry,
+            ImportSettings, ImportSource, Importer, MemoryResolver, Resolver,
+        },
+        model::Model,
+        RenderResult,
+    };
+
+    struct Custom;
+
+    impl Importer for Custom {
+        fn import(
+            &self, _source: &mut dyn ImportSource, name: &str, _resolver: &dyn Resolver,
+            _settings: &ImportSettings,
+        ) -> RenderResult<Model> {
+            Err(format!("custom importer called for {name}"))
+        }
+    }
+
+    #[test]
+    fn registry_builtin_lookups() {
+        let registry = ImporterRegistry::default();
+        assert_eq!(registry.names(), vec!["collada", "cooked", "ply", "stl", "wavefront"]);
+        for model_type in [
+            ModelType::Wavefront, ModelType::Ply, ModelType::Stl, ModelType::Collada,
+            ModelType::Cooked,
+        ] {
+            assert!(registry.get_by_type(&model_type).is_ok(), "{model_type}");
+        }
+        assert!(registry.get_by_location("models/cube.OBJ").is_ok());
+        assert!(registry.get_by_location("models/cube.dae").is_ok());
+        assert!(registry.get_by_location("models/cube.rmdl").is_ok());
+        assert!(registry.get_by_location("models/cube").is_err());
+        assert!(registry.get_by_extension("fbx").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "custom importer called for scan")]
+    fn registry_custom_importer() {
+        let mut registry = ImporterRegistry::default();
+        registry.register("custom", &["xyz"], Box::new(Custom));
+        let _ = registry.get_by_location("scan.xyz").unwrap()
+            .import_bytes(&[], "scan", &MemoryResolver::new(), &ImportSettings::default())
+            .unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+/* STL importer, ascii and binary.
+ * STL is raw triangle soup, every triangle carries its own three positions, so the
+ * positions get welded back together (within weld_epsilon) to produce an indexed mesh.
+ * The per-facet normals in the file are ignored, exporters get them wrong more often
+ * than not, they're always generated from the welded geometry using the import settings.
+ * There are no uvs in STL, everything is zeroed.
+ */
+
+use super::{
+    ImportSettings, ImportSource, Importer, Resolver,
+};
+
+use crate::{
+    model::{
+        bounds::compute_bounds,
+        conversion::apply_conversion,
+        normals::generate_normals,
+        weld::WeldGrid,
+        IndexCoord, Mesh, Model, PositionVector, PostionVertex, SubMesh,
+    },
+    RenderResult,
+};
+
+const STL_ASCII_MAGIC: &str = "solid";
+const STL_BINARY_HEADER_SIZE: usize = 80;
+//normal + 3 vertices + attribute byte count
+const STL_BINARY_TRIANGLE_SIZE: usize = 4*3*4 + 2;
+pub const DEFAULT_WELD_EPSILON: f32 = 1.0e-5;
+
+type StlPosition = [f32; 3];
+type StlTriangle = [StlPosition; 3];
+
+pub struct Stl {
+    weld_epsilon: f32,
+}
+
+impl Stl {
+    pub fn new(weld_epsilon: Option<f32>) -> Self {
+        Self{
+            weld_epsilon: weld_epsilon.unwrap_or(DEFAULT_WELD_EPSILON),
+        }
+    }
+
+    fn generate_model_from_bytes(
+        &self, data: &[u8], name: &str, settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let (solid_name, triangles) = if is_binary(data) {
+            (None, parse_binary(data)?)
+        } else {
+            parse_ascii(data)?
+        };
+        let (positions, triangles) = weld(&triangles, self.weld_epsilon);
+        let indeces: Vec<IndexCoord> = triangles.iter().flatten().copied().collect();
+        let name = solid_name.unwrap_or_else(|| name.to_string());
+        let mesh = Mesh::PositionMesh(positions.iter()
+            .map(|p| PostionVertex(PositionVector::new(p[0], p[1], p[2], 1.0)))
+            .collect());
+        let mut model = Model{
+            submeshes: vec!(SubMesh::new(&name, 0, indeces.len(), None)),
+            ..Model::new(&name, mesh, indeces)
+        };
+        generate_normals(&mut model, settings.normal_mode, None)?;
+        compute_bounds(&mut model)?;
+        apply_conversion(&mut model, &settings.conversion)?;
+        Ok(model)
+    }
+}
+
+impl Importer for Stl {
+    //stl never references other files
+    fn import(
+        &self, source: &mut dyn ImportSource, name: &str, _resolver: &dyn Resolver,
+        settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let mut data = vec![];
+        source.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        self.generate_model_from_bytes(&data, name, settings)
+    }
+}
+
+//plenty of binary exporters start the 80 byte header with "solid" too,
+//the size check is the only reliable test
+fn is_binary(data: &[u8]) -> bool {
+    if data.len() >= STL_BINARY_HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes(
+            data[STL_BINARY_HEADER_SIZE..STL_BINARY_HEADER_SIZE + 4].try_into().unwrap()
+        ) as usize;
+        if STL_BINARY_HEADER_SIZE + 4 + count * STL_BINARY_TRIANGLE_SIZE == data.len() {
+            return true;
+        }
+    }
+    !data.starts_with(STL_ASCII_MAGIC.as_bytes())
+}
+
+fn parse_binary(data: &[u8]) -> RenderResult<Vec<StlTriangle>> {
+    if data.len() < STL_BINARY_HEADER_SIZE + 4 {
+        return Err("binary stl file is too short".to_string());
+    }
+    let count = u32::from_le_bytes(
+        data[STL_BINARY_HEADER_SIZE..STL_BINARY_HEADER_SIZE + 4].try_into().unwrap()
+    ) as usize;
+    let body = &data[STL_BINARY_HEADER_SIZE + 4..];
+    if body.len() < count * STL_BINARY_TRIANGLE_SIZE {
+        return Err(format!(
+            "binary stl declares {count} triangles but only has {} bytes of data", body.len(),
+        ));
+    }
+    let read_f32 = |offset: usize| -> f32 {
+        f32::from_le_bytes(body[offset..offset + 4].try_into().unwrap())
+    };
+    Ok((0..count).map(|t| {
+        //skip the 12 byte facet normal
+        let base = t * STL_BINARY_TRIANGLE_SIZE + 12;
+        let mut triangle = [[0.0; 3]; 3];
+        for (v, vertex) in triangle.iter_mut().enumerate() {
+            for (c, coord) in vertex.iter_mut().enumerate() {
+                *coord = read_f32(base + v*12 + c*4);
+            }
+        }
+        triangle
+    }).collect())
+}
+
+//returns the name of the first solid, if it has one
+fn parse_ascii(data: &[u8]) -> RenderResult<(Option<String>, Vec<StlTriangle>)> {
+    let text = std::str::from_utf8(data)
+        .map_err(|e| format!("ascii stl file is not valid utf8: {e}"))?;
+    let mut name: Option<String> = None;
+    let mut triangles: Vec<StlTriangle> = vec![];
+    let mut current: Vec<StlPosition> = vec![];
+    for line in text.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] => {},
+            ["solid", solid_name @ ..] => {
+                if name.is_none() && !solid_name.is_empty() {
+                    name = Some(solid_name.join(" "));
+                }
+            },
+            ["vertex", x, y, z] => {
+                let parse = |c: &str| {
+                    c.parse::<f32>()
+                        .map_err(|_| format!("Unable to parse stl vertex: {}", line.trim()))
+                };
+                current.push([parse(x)?, parse(y)?, parse(z)?]);
+            },
+            ["endfacet"] => {
+                if current.len() != 3 {
+                    return Err(format!(
+                        "stl facet: {} has {} vertices, expected 3", triangles.len(), current.len(),
+                    ));
+                }
+                triangles.push([current[0], current[1], current[2]]);
+                current.clear();
+            },
+            ["facet", ..] | ["outer", "loop"] | ["endloop"] | ["endsolid", ..] => {},
+            _ => {
+                return Err(format!("Unable to parse stl line: {}", line.trim()));
+            },
+        }
+    }
+    Ok((name, triangles))
+}
+
+//a position welds to the first one within epsilon of it, epsilon of 0 is an exact match
+fn weld(
+    triangles: &[StlTriangle], epsilon: f32,
+) -> (Vec<StlPosition>, Vec<[IndexCoord; 3]>) {
+    let mut grid: WeldGrid<IndexCoord> = WeldGrid::new(epsilon);
+    let mut positions: Vec<StlPosition> = vec![];
+    let mut welded: Vec<[IndexCoord; 3]> = Vec::with_capacity(triangles.len());
+    let mut find_or_insert = |p: &StlPosition| -> IndexCoord {
+        if let Some(index) = grid.find(p, |_| true) {
+            return index;
+        }
+        positions.push(*p);
+        let index = (positions.len() - 1) as IndexCoord;
+        grid.insert(*p, index);
+        index
+    };
+    for triangle in triangles {
+        let indeces = [
+            find_or_insert(&triangle[0]),
+            find_or_insert(&triangle[1]),
+            find_or_insert(&triangle[2]),
+        ];
+        //welding can collapse slivers, they can't be drawn anyway
+        if indeces[0] != indeces[1] && indeces[1] != indeces[2] && indeces[0] != indeces[2] {
+            welded.push(indeces);
+        }
+    }
+    (positions, welded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read;
+
+    use crate::{
+        importer::{
+            stl::{
+                weld, Stl,
+            },
+            ImportSettings, Importer,
+        },
+        model::{
+            normals::NormalMode,
+            Mesh,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    fn read_test_file(file_name: &str) -> Vec<u8> {
+        read(format!("{TEST_DIRECTORY}/{file_name}"))
+            .unwrap_or_else(|_| panic!("Could not open 'testdata/{file_name}' for reading."))
+    }
+
+    #[test]
+    fn stl_ascii_cube_smooth() {
+        //a crease angle wide enough that the cube's edges smooth over
+        let settings = ImportSettings{
+            normal_mode: NormalMode::Smooth{ crease_angle: 180.0 },
+            ..Default::default()
+        };
+        let model = Stl::new(None).generate_model_with_settings(
+            &format!("{TEST_DIRECTORY}/stl_ascii_cube.stl"), &settings,
+        ).unwrap();
+        assert_eq!(model.name, "cube");
+        assert_eq!(model.indeces.len(), 12*3, "index length");
+        match model.mesh {
+            Mesh::NormalMesh(vertices) => {
+                //36 raw positions weld down to the 8 corners
+                assert_eq!(vertices.len(), 8, "vertex length");
+                //every corner normal has to be unit length and point out of the cube
+                for v in vertices.iter() {
+                    let n = &v.norm;
+                    let len = (n.x*n.x + n.y*n.y + n.z*n.z).sqrt();
+                    assert!((len - 1.0).abs() < 1.0e-5, "smooth normal length");
+                    assert!(n.x * v.pos.x > 0.0, "smooth normal x direction");
+                    assert!(n.y * v.pos.y > 0.0, "smooth normal y direction");
+                    assert!(n.z * v.pos.z > 0.0, "smooth normal z direction");
+                }
+            },
+            _ => panic!("expected a NormalMesh"),
+        }
+    }
+
+    #[test]
+    fn stl_binary_cube_facet() {
+        let settings = ImportSettings{
+            normal_mode: NormalMode::Flat,
+            ..Default::default()
+        };
+        let model = Stl::new(None)
+            .generate_model_from_bytes(&read_test_file("stl_binary_cube.stl"), "cube", &settings)
+            .unwrap();
+        assert_eq!(model.indeces.len(), 12*3, "index length");
+        match model.mesh {
+            Mesh::NormalMesh(vertices) => {
+                //coplanar triangles share, 4 per side
+                assert_eq!(vertices.len(), 6*4, "vertex length");
+                for v in vertices.iter() {
+                    let len = v.norm.x.abs() + v.norm.y.abs() + v.norm.z.abs();
+                    assert!((len - 1.0).abs() < 1.0e-6, "axis aligned facet normal");
+                }
+            },
+            _ => panic!("expected a NormalMesh"),
+        }
+    }
+
+    #[test]
+    fn stl_ascii_matches_binary() {
+        let stl = Stl::new(None);
+        let settings = ImportSettings::default();
+        let ascii = stl.generate_model_from_bytes(
+            &read_test_file("stl_ascii_cube.stl"), "cube", &settings,
+        ).unwrap();
+        let binary = stl.generate_model_from_bytes(
+            &read_test_file("stl_binary_cube.stl"), "cube", &settings,
+        ).unwrap();
+        assert_eq!(ascii.indeces, binary.indeces);
+    }
+
+    #[test]
+    fn stl_weld_epsilon() {
+        let triangles = [
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            [[1.000001, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 0.999999, 0.0]],
+        ];
+        let (positions, welded) = weld(&triangles, 1.0e-5);
+        assert_eq!(positions.len(), 4, "welded position count");
+        assert_eq!(welded, vec![[0, 1, 2], [1, 3, 2]]);
+        let (positions, _) = weld(&triangles, 0.0);
+        assert_eq!(positions.len(), 6, "exact position count");
+    }
+
+    #[test]
+    #[should_panic(expected = "has 2 vertices, expected 3")]
+    fn stl_bad_facet() {
+        let _ = Stl::new(None).generate_model_from_bytes(
+            &read_test_file("stl_bad_facet.stl"), "bad", &ImportSettings::default(),
+        ).unwrap();
+    }
+}
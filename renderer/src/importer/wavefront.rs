@@ -1,23 +1,24 @@
-use std::{
-    collections::HashMap, fs::read_to_string, path::{
-        Path, PathBuf
-    },
-};
+use std::collections::HashMap;
 
+use rayon::prelude::*;
 use regex::Regex;
 
-use super::Importer;
-
-const WAVEFRONT_MAT_EXTENSION: &str = "mtl";
+use super::{
+    mtl::parse_mat_file,
+    run_with_threads, ImportSettings, ImportSource, Importer, Resolver,
+};
 
 use crate::{
     model::{
+        bounds::compute_bounds,
+        conversion::apply_conversion,
         material::Material, 
+        normals::generate_normals,
         IndexCoord, 
         IndexVector, 
         DEFAULT_MODEL_NAME,
         Mesh, Model, NormalVector, 
-        NormalVertex, PositionVector, TextureVector,
+        NormalVertex, PositionVector, SubMesh, TextureVector,
     },
     RenderResult,
 };
@@ -29,13 +30,39 @@ pub struct Wavefront{
     name: String,
 }
 
-fn mat_file_from_obj_file(obj_file: &str) -> Option<PathBuf> {
-    let mut mat_file = PathBuf::from(obj_file);
-    mat_file.set_extension(WAVEFRONT_MAT_EXTENSION);
-    if !Path::new(&mat_file).exists() {
-        return None;
+//lines per parallel work item, small enough to spread a few thousand lines around,
+//big enough that scheduling doesn't cost more than parsing
+const PARSE_CHUNK_LINES: usize = 4096;
+
+//a face vertex's 1 based position, texture and normal indeces, 0 when not given
+type VertexKey = (u32, u32, u32);
+
+//the lines that have to stay in order with the faces
+enum FaceLine {
+    Face(Vec<VertexKey>),
+    UseMat(String),
+    Smoothing(u32),
+}
+
+fn read_source(source: &mut dyn ImportSource) -> RenderResult<String> {
+    let mut lines = String::new();
+    source.read_to_string(&mut lines).map_err(|e| e.to_string())?;
+    Ok(lines)
+}
+
+//parses chunks of lines in parallel, keeping their order
+//each chunk stops at its first error and chunks are checked in order,
+//so a file with several bad lines always reports the same one
+fn parse_lines<S, T, F>(vals: &[S], parse: F) -> RenderResult<Vec<T>>
+        where S: AsRef<str> + Sync, T: Send, F: Fn(&str) -> RenderResult<T> + Sync {
+    let chunks = vals.par_chunks(PARSE_CHUNK_LINES)
+        .map(|chunk| chunk.iter().map(|l| parse(l.as_ref())).collect::<RenderResult<Vec<T>>>())
+        .collect::<Vec<RenderResult<Vec<T>>>>();
+    let mut parsed = Vec::with_capacity(vals.len());
+    for chunk in chunks {
+        parsed.extend(chunk?);
     }
-    Some(mat_file)
+    Ok(parsed)
 }
 
 impl Wavefront {
@@ -48,11 +75,10 @@ impl Wavefront {
         }
     }
 
-    fn load_position_vector<'a, I>(&mut self, vals: I) -> Result<(), String>
-            where I: Iterator<Item = &'a String> {
+    fn load_position_vector<S: AsRef<str> + Sync>(&mut self, vals: &[S]) -> RenderResult<()> {
         let re = Regex::new(r"^v (?P<x>-?\d+\.\d+) (?P<y>-?\d+\.\d+) (?P<z>-?\d+\.\d+)$")
             .unwrap(); //should never panic as this pattern is hardcoded
-        self.pos = vals.map(|p| {
+        self.pos = parse_lines(vals, |p| {
             if let Some(cap) = re.captures(p.trim()) {
                 let (x, y, z) = (&cap["x"], &cap["y"], &cap["z"]);
                 Ok(PositionVector::new(
@@ -64,15 +90,14 @@ impl Wavefront {
             } else {
                 Err(format!("Unable to parse wavefront position vector: {}", p))
             }
-        }).collect::<Result<Vec<PositionVector>, String>>()?;
+        })?;
         Ok(())
     }
 
-    fn load_texture_vector<'a, I>(&mut self, vals: I) -> Result<(), String>
-            where I: Iterator<Item = &'a String> {
+    fn load_texture_vector<S: AsRef<str> + Sync>(&mut self, vals: &[S]) -> RenderResult<()> {
         let re = Regex::new(r"^vt (?P<u>-?\d+\.\d+) (?P<v>-?\d+\.\d+)$")
             .unwrap(); //should never panic as this pattern is hardcoded
-        self.uv = vals.map(|t| {
+        self.uv = parse_lines(vals, |t| {
             if let Some(cap) = re.captures(t.trim()) {
                 let (u, v) = (&cap["u"], &cap["v"]);
                 Ok(TextureVector::new(
@@ -81,15 +106,14 @@ impl Wavefront {
             } else {
                 Err(format!("Unable to parse wavefront texture vector: {}", t))
             }
-        }).collect::<Result<Vec<TextureVector>, String>>()?;
+        })?;
         Ok(())
     }
 
-    fn load_normal_vector<'a, I>(&mut self, vals: I) -> Result<(), String>
-            where I: Iterator<Item = &'a String> {
+    fn load_normal_vector<S: AsRef<str> + Sync>(&mut self, vals: &[S]) -> RenderResult<()> {
         let re = Regex::new(r"^vn (?P<x>-?\d+\.\d+) (?P<y>-?\d+\.\d+) (?P<z>-?\d+\.\d+)$")
             .unwrap(); //should never panic as this pattern is hardcoded
-        self.norm = vals.map(|n| {
+        self.norm = parse_lines(vals, |n| {
             if let Some(cap) = re.captures(n.trim()) {
                 let (x, y, z) = (&cap["x"], &cap["y"], &cap["z"]);
                 Ok(NormalVector::new(
@@ -101,90 +125,156 @@ impl Wavefront {
             } else {
                 Err(format!("Unable to parse wavefront normal vector: {}", n))
             }
-        }).collect::<Result<Vec<NormalVector>, String>>()?;
+        })?;
         Ok(())
     }
 
+    //"v", "v/vt", "v//vn" or "v/vt/vn", indeces are checked against what's been loaded
+    fn parse_face_vertex(&self, vert: &str, val: &str) -> RenderResult<VertexKey> {
+        let bad_index = || format!("Unable to parse wavefront index for {val}, exiting");
+        let mut parts = vert.split('/');
+        //obj indeces start at 1, 0 is left for a missing index
+        let mut lookup = |len: usize, required: bool| -> RenderResult<u32> {
+            match parts.next() {
+                Some("") if !required => Ok(0),
+                Some(part) if part.bytes().all(|b| b.is_ascii_digit()) && !part.is_empty() => {
+                    let i: usize = part.parse().map_err(|_| bad_index())?;
+                    if i == 0 || i > len {
+                        return Err(format!("wavefront index {vert} is out of range in {val}"));
+                    }
+                    i.try_into().map_err(|_| bad_index())
+                },
+                None if !required => Ok(0),
+                _ => Err(bad_index()),
+            }
+        };
+        let pos = lookup(self.pos.len(), true)?;
+        let tex = lookup(self.uv.len(), false)?;
+        //"v//" isn't valid, a second slash needs a normal after it
+        let norm = match vert.matches('/').count() {
+            2 => lookup(self.norm.len(), true)?,
+            _ => lookup(self.norm.len(), false)?,
+        };
+        if parts.next().is_some() {
+            return Err(bad_index());
+        }
+        Ok((pos, tex, norm))
+    }
+
+    fn parse_face_line(&self, val: &str) -> RenderResult<FaceLine> {
+        let mut tokens = val.split_whitespace();
+        match tokens.next() {
+            Some("usemtl") => Ok(FaceLine::UseMat(tokens.collect::<Vec<&str>>().join(" "))),
+            Some("s") => match tokens.next() {
+                Some("off") | None => Ok(FaceLine::Smoothing(0)),
+                Some(g) => g.parse().map(FaceLine::Smoothing).map_err(|_| {
+                    format!("Unable to parse wavefront smoothing group for {val}")
+                }),
+            },
+            Some("f") => {
+                let face = tokens
+                    .map(|vert| self.parse_face_vertex(vert, val))
+                    .collect::<RenderResult<Vec<VertexKey>>>()?;
+                if face.len() < 3 {
+                    return Err(format!("wavefront face {val} has fewer than 3 vertices"));
+                }
+                Ok(FaceLine::Face(face))
+            },
+            _ => Err(format!("Unable to parse wavefront index for {val}, exiting")),
+        }
+    }
+
     //Only supports model::Mesh(NormalMesh) for now
     //Once the model is generated, the memory will be all over the place
     //copy the model's data so it's cache friendly
     //this call isn't in the critical loop, but what it generates WILL be
     //not sure about the above anymore...will need to run it through a debugger
     //though may be good at some point to write a defragger for Model
-    fn generate_model<'a, I>(&self, vals: I) -> RenderResult<Model>
-            where I: Iterator<Item = &'a String> {
-        let vert_1 = r"(?P<vert_1>(?P<pos_1>\d+)/(?P<tex_1>\d+)/(?P<norm_1>\d+))";
-        let vert_2 = r"(?P<vert_2>(?P<pos_2>\d+)/(?P<tex_2>\d+)/(?P<norm_2>\d+))";
-        let vert_3 = r"(?P<vert_3>(?P<pos_3>\d+)/(?P<tex_3>\d+)/(?P<norm_3>\d+))";
-        let re = Regex::new(format!(r"^f {vert_1} {vert_2} {vert_3}$").as_str())
-            .unwrap(); //should never panic as this pattern is hardcoded
-        let mut face_map: HashMap<String, IndexCoord> = HashMap::new();
+    //faces are "v", "v/vt", "v//vn" or "v/vt/vn", anything over 3 vertices is fan triangulated
+    //"s" and "usemtl" lines come through interleaved with the faces, they apply to every
+    //face after them, every "usemtl" starts a new SubMesh named after the material
+    //a single vertex without a normal has the normals for the whole model generated
+    //lines are parsed in parallel, vertices are then numbered in the order faces first
+    //use them, one after the other, so the output doesn't depend on the thread count
+    fn generate_model<S: AsRef<str> + Sync>(
+        &self, vals: &[S], settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let face_lines = parse_lines(vals, |val| self.parse_face_line(val))?;
+        let mut face_map: HashMap<VertexKey, IndexCoord> = HashMap::new();
         let mut indeces: IndexVector = vec![];
         let mut vertices: Vec<NormalVertex> = vec![];
-        let mut update_fn = |cap: &regex::Captures, i: i32| {
-            if face_map.contains_key(&cap[format!("vert_{i}").as_str()]) {
-                //panic impossible
-                indeces.push(
-                    *(face_map.get(&cap[format!("vert_{i}").as_str()]).unwrap())
-                );
-            } else {
-                let p_index: usize = (&cap[format!("pos_{i}").as_str()])
-                    .parse().unwrap(); //panic impossible
-                let t_index: usize = (&cap[format!("tex_{i}").as_str()])
-                    .parse().unwrap(); //panic impossible
-                let n_index: usize = (&cap[format!("norm_{i}").as_str()])
-                    .parse().unwrap(); //panic impossible
-                vertices.push(NormalVertex::new(
-                    self.pos[p_index - 1],
-                    self.uv[t_index - 1],
-                    self.norm[n_index - 1],
-                ));
-                let index: IndexCoord = (vertices.len() - 1).try_into().unwrap();
-                face_map.insert(
-                    cap[format!("vert_{i}").as_str()].to_string(), index,
-                );
-                indeces.push(index);
+        //one per triangle, only used if the file has "s" lines, before the first one it's off
+        let mut groups: Vec<u32> = vec![];
+        let mut has_groups = false;
+        let mut group: u32 = 0;
+        let mut missing_normals = false;
+        let mut submeshes: Vec<SubMesh> = vec![];
+        let mut submesh_name = self.name.clone();
+        let mut submesh_start: usize = 0;
+        for line in face_lines {
+            let face = match line {
+                FaceLine::UseMat(name) => {
+                    if indeces.len() > submesh_start {
+                        submeshes.push(SubMesh::new(
+                            &submesh_name, submesh_start, indeces.len() - submesh_start, None,
+                        ));
+                    }
+                    submesh_name = name;
+                    submesh_start = indeces.len();
+                    continue;
+                },
+                FaceLine::Smoothing(g) => {
+                    has_groups = true;
+                    group = g;
+                    continue;
+                },
+                FaceLine::Face(face) => face,
+            };
+            let face: Vec<IndexCoord> = face.into_iter().map(|key| {
+                *face_map.entry(key).or_insert_with(|| {
+                    let (p_index, t_index, n_index) = key;
+                    let uv = match t_index {
+                        0 => TextureVector::new(0.0, 0.0),
+                        t => self.uv[t as usize - 1],
+                    };
+                    let norm = match n_index {
+                        0 => {
+                            missing_normals = true;
+                            NormalVector::new(0.0, 0.0, 0.0, 1.0)
+                        },
+                        n => self.norm[n as usize - 1],
+                    };
+                    vertices.push(NormalVertex::new(self.pos[p_index as usize - 1], uv, norm));
+                    (vertices.len() - 1).try_into().unwrap()
+                })
+            }).collect();
+            for i in 1..face.len() - 1 {
+                indeces.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                groups.push(group);
             }
+        }
+        if indeces.len() > submesh_start || submeshes.is_empty() {
+            submeshes.push(SubMesh::new(
+                &submesh_name, submesh_start, indeces.len() - submesh_start, None,
+            ));
+        }
+        let mut model = Model{
+            submeshes,
+            ..Model::new(&self.name, Mesh::NormalMesh(vertices), indeces)
         };
-        for val in vals {
-            if let Some(cap) = re.captures(val.trim()) {
-                update_fn(&cap, 1);
-                update_fn(&cap, 2);
-                update_fn(&cap, 3);
-            } else {
-                return Err(format!("Unable to parse wavefront index for {val}, exiting"));
-            }
+        if settings.force_normals || missing_normals {
+            let groups = has_groups.then_some(groups.as_slice());
+            generate_normals(&mut model, settings.normal_mode, groups)?;
         }
-        Ok(Model{
-            mesh: Mesh::NormalMesh(vertices),
-            indeces,
-            name: self.name.clone(),
-            materials: vec!(),
-        })
+        Ok(model)
     }
 
-    //only supporting a single, optional(Result) material for now
-    //this sucks, but just need to keep moving on, I can clean up the model stuff later
-    //basically, wanted to have Model.materials == Vec<Material>
-    //where Material.texture == TextureImage, but can't get a Material out of the Vec
-    //without cloning, need to think about this some
-    fn parse_mat_file(&self, mat_file: &PathBuf) -> RenderResult<Material> {
-        let lines = read_to_string(mat_file).map_err(|e| e.to_string())?;
-        for l in lines.lines() {
-            let to_check: &str = l.trim();
-            //let to_ret: Material;
-            if to_check.starts_with("map_Kd ") {
-                if let Some((_, path)) = to_check.split_once(char::is_whitespace) {
-                    if let Ok(mat) = Material::new(&PathBuf::from(path)) {
-                        return Ok(mat);
-                    };
-                }
-            }
-
-        }
-        //don't fucking care, shut the fuck up and print it
-        let file_tex = mat_file.to_string_lossy();
-        Err(format!("Could not parse material file: {file_tex}"))
+    //see mtl.rs, every "newmtl" becomes a Material, textured or not
+    fn parse_mat_file(
+        &self, mat_file: &str, resolver: &dyn Resolver,
+    ) -> RenderResult<Vec<Material>> {
+        let lines = read_source(resolver.resolve(mat_file)?.as_mut())?;
+        parse_mat_file(mat_file, &lines, resolver)
     }
 
 }
@@ -195,6 +285,9 @@ enum WavefrontLineType {
     Normal,
     Face,
     Name,
+    MatLib,
+    Smoothing,
+    UseMat,
 }
 
 impl WavefrontLineType {
@@ -209,69 +302,124 @@ impl WavefrontLineType {
             return Some(Self::Face);
         } else if line.trim().starts_with("o ") {
             return Some(Self::Name);
+        } else if line.trim().starts_with("mtllib ") {
+            return Some(Self::MatLib);
+        } else if line.trim().starts_with("s ") {
+            return Some(Self::Smoothing);
+        } else if line.trim().starts_with("usemtl ") {
+            return Some(Self::UseMat);
         }
         None
     }
 }
 
-impl Importer for Wavefront {
-    //only doing textures for now
-    fn generate_model(&self, loc: &str) -> RenderResult<Model> {
-        let mut pos_vec: Vec<String> = vec![];
-        let mut text_vec: Vec<String> = vec![];
-        let mut norm_vec: Vec<String> = vec![];
-        let mut face_vec: Vec<String> = vec![];
-        let mut name_opt: Option<String> = None;
+//every line of a file sorted by what it feeds, in file order
+#[derive(Default)]
+struct WavefrontSections<'a> {
+    pos: Vec<&'a str>,
+    text: Vec<&'a str>,
+    norm: Vec<&'a str>,
+    //smoothing groups and materials apply to the faces that follow, keep them in order
+    face: Vec<&'a str>,
+    name: Option<String>,
+    mat_file: Option<String>,
+    uses_materials: bool,
+}
+
+impl<'a> WavefrontSections<'a> {
+    fn new(lines: &[&'a str], name_re: &Regex) -> Self {
+        let mut sections = Self::default();
+        for line in lines {
+            match WavefrontLineType::get(line) {
+                Some(WavefrontLineType::Position) => sections.pos.push(line),
+                Some(WavefrontLineType::Texture) => sections.text.push(line),
+                Some(WavefrontLineType::Normal) => sections.norm.push(line),
+                Some(WavefrontLineType::Face) | Some(WavefrontLineType::Smoothing) => {
+                    sections.face.push(line);
+                },
+                Some(WavefrontLineType::UseMat) => {
+                    sections.uses_materials = true;
+                    sections.face.push(line);
+                },
+                Some(WavefrontLineType::Name) => {
+                    if let Some(cap) = name_re.captures(line) {
+                        sections.name = Some(cap["name"].to_string());
+                    }
+                },
+                //only the first library
+                Some(WavefrontLineType::MatLib) if sections.mat_file.is_none() => {
+                    sections.mat_file = line.trim().split_once(char::is_whitespace)
+                        .map(|(_, mat_file)| mat_file.trim().to_string());
+                },
+                _ => {},
+            }
+        }
+        sections
+    }
+
+    //other comes after self in the file
+    fn append(&mut self, mut other: Self) {
+        self.pos.append(&mut other.pos);
+        self.text.append(&mut other.text);
+        self.norm.append(&mut other.norm);
+        self.face.append(&mut other.face);
+        //the last object name wins, the first library does
+        self.name = other.name.or(self.name.take());
+        self.mat_file = self.mat_file.take().or(other.mat_file);
+        self.uses_materials |= other.uses_materials;
+    }
+}
+
+impl Wavefront {
+    //everything but the material library, which needs the resolver
+    fn parse_source<'a>(
+        &self, source: &'a str, name: &str, settings: &ImportSettings,
+    ) -> RenderResult<(Model, WavefrontSections<'a>)> {
         let name_re = Regex::new(r"^o (?P<name>\w+)\s*$").map_err(|e| {
             format!(
                 "failed generating regex for a wavefront model parser, with error: {e}")
                 .to_string()
             }
         )?;
-        let lines = read_to_string(loc).map_err(|e| e.to_string())?;
-        lines.lines().for_each(|l| {
-            if let Some(line_type) = WavefrontLineType::get(l) {
-                let line = l.to_string();
-                match line_type {
-                    WavefrontLineType::Position => {
-                        pos_vec.push(line);
-                    },
-                    WavefrontLineType::Texture => {
-                        text_vec.push(line);
-                    },
-                    WavefrontLineType::Normal => {
-                        norm_vec.push(line);
-                    },
-                    WavefrontLineType::Face => {
-                        face_vec.push(line);
-                    },
-                    WavefrontLineType::Name => {
-                        if let Some(cap) = name_re.captures(&line) {
-                            name_opt = Some(cap["name"].to_string());
-                        }
-                    },
-                }
-            }
-        });
-        if name_opt.is_none() {
-            return Err(
-                String::from("Could not deternmine model name from passed iterator")
-            );
-        }
-        let mut wavefront = Self::new(name_opt);
-        if let Err(res) = wavefront.load_position_vector(pos_vec.iter()) {
-            return Err(res);
-        }
-        if let Err(res) = wavefront.load_normal_vector(norm_vec.iter()) {
-            return Err(res);
+        let lines: Vec<&str> = source.lines().collect();
+        let mut sections = WavefrontSections::default();
+        for chunk in lines.par_chunks(PARSE_CHUNK_LINES)
+                .map(|chunk| WavefrontSections::new(chunk, &name_re))
+                .collect::<Vec<WavefrontSections>>() {
+            sections.append(chunk);
         }
-        if let Err(res) = wavefront.load_texture_vector(text_vec.iter()) {
-            return Err(res);
-        }
-        let mut model = wavefront.generate_model(face_vec.iter())?;
-        if let Some(mat_file) = mat_file_from_obj_file(loc) {
-            model.materials = vec!(self.parse_mat_file(&mat_file)?);
+        //no object name, fall back to what the caller passed
+        let mut wavefront = Self::new(sections.name.clone().or_else(|| Some(name.to_string())));
+        wavefront.load_position_vector(&sections.pos)?;
+        wavefront.load_normal_vector(&sections.norm)?;
+        wavefront.load_texture_vector(&sections.text)?;
+        let model = wavefront.generate_model(&sections.face, settings)?;
+        Ok((model, sections))
+    }
+}
+
+impl Importer for Wavefront {
+    fn import(
+        &self, source: &mut dyn ImportSource, name: &str, resolver: &dyn Resolver,
+        settings: &ImportSettings,
+    ) -> RenderResult<Model> {
+        let source = read_source(source)?;
+        let (mut model, sections) = run_with_threads(settings.threads, || {
+            self.parse_source(&source, name, settings)
+        })??;
+        if let Some(mat_file) = sections.mat_file {
+            model.materials = self.parse_mat_file(&mat_file, resolver)?;
+            //without any "usemtl" everything uses the first material
+            for submesh in model.submeshes.iter_mut() {
+                submesh.material = if sections.uses_materials {
+                    model.materials.iter().position(|m| m.name == submesh.name)
+                } else {
+                    (!model.materials.is_empty()).then_some(0)
+                };
+            }
         }
+        compute_bounds(&mut model)?;
+        apply_conversion(&mut model, &settings.conversion)?;
         Ok(model)
     }
 }
@@ -281,11 +429,14 @@ mod tests {
     use std::fs::read_to_string;
 
     use crate::{
+        exporter::MeshAttributes,
         importer::{
             wavefront::Wavefront,
-            Importer,
+            ImportSettings, Importer, MemoryResolver,
         },
         model::{
+            normals::NormalMode,
+            primitives::{make_primitive, Primitive},
             Vector2,
             Vector4,
         },
@@ -302,7 +453,7 @@ mod tests {
         let mut wavefront = Wavefront::new(None);
         //let _ = wavefront.load_position_vector(data.lines()).unwrap();
         let to_parse: Vec<String> = data.lines().map(|l| l.to_string()).collect();
-        let _ = wavefront.load_position_vector(to_parse.iter()).unwrap();
+        let _ = wavefront.load_position_vector(&to_parse).unwrap();
         assert_eq!(wavefront.pos.len(), 64, "pos vector length");
         assert_eq!(wavefront.pos[0], Vector4::new(0.0, 3.080803, 3.080803, 1.0));
         assert_eq!(
@@ -318,7 +469,7 @@ mod tests {
                 .as_str());
         let mut wavefront = Wavefront::new(None);
         let to_parse: Vec<String> = data.lines().map(|l| l.to_string()).collect();
-        let _ = wavefront.load_texture_vector(to_parse.iter()).unwrap();
+        let _ = wavefront.load_texture_vector(&to_parse).unwrap();
         assert_eq!(wavefront.uv.len(), 130, "uv vector length");
         assert_eq!(wavefront.uv[22], Vector2::new(0.656250, 0.5));
         assert_eq!(wavefront.uv[86], Vector2::new(0.203178, 0.014612));
@@ -332,7 +483,7 @@ mod tests {
                     .as_str());
         let mut wavefront = Wavefront::new(None);
         let to_parse: Vec<String> = data.lines().map(|l| l.to_string()).collect();
-        let _ = wavefront.load_normal_vector(to_parse.iter()).unwrap();
+        let _ = wavefront.load_normal_vector(&to_parse).unwrap();
         assert_eq!(wavefront.norm.len(), 34, "norm vector length");
         assert_eq!(wavefront.norm[13], Vector4::new(-0.4714, -0.0, -0.8819, 1.0));
         assert_eq!(
@@ -364,18 +515,19 @@ mod tests {
         let mut wavefront = Wavefront::new(None);
 
         let to_pos_parse: Vec<String> = pos_data.lines().map(|l| l.to_string()).collect();
-        wavefront.load_position_vector(to_pos_parse.iter()).unwrap();
+        wavefront.load_position_vector(&to_pos_parse).unwrap();
 
         let to_tex_parse: Vec<String> = tex_data.lines().map(|l| l.to_string()).collect();
-        wavefront.load_texture_vector(to_tex_parse.iter()).unwrap();
+        wavefront.load_texture_vector(&to_tex_parse).unwrap();
 
         let to_norm_parse: Vec<String> = norm_data.lines()
             .map(|l| l.to_string()).collect();
-        wavefront.load_normal_vector(to_norm_parse.iter()).unwrap();
+        wavefront.load_normal_vector(&to_norm_parse).unwrap();
 
         let to_index_parse: Vec<String> = index_data.lines()
             .map(|l| l.to_string()).collect();
-        let model = wavefront.generate_model(to_index_parse.iter()).unwrap();
+        let model = wavefront.generate_model(&to_index_parse, &ImportSettings::default())
+            .unwrap();
 
         assert_eq!(model.indeces.len(), 124*3);
         //6/64/31 first occurrence is 91st, 192nd
@@ -392,7 +544,7 @@ mod tests {
                 .as_str());
         let mut wavefront = Wavefront::new(None);
         let to_parse: Vec<String> = data.lines().map(|l| l.to_string()).collect();
-        let _ = wavefront.load_position_vector(to_parse.iter()).unwrap();
+        let _ = wavefront.load_position_vector(&to_parse).unwrap();
     }
 
     #[test]
@@ -404,7 +556,7 @@ mod tests {
                 .as_str());
         let mut wavefront = Wavefront::new(None);
         let to_parse: Vec<String> = data.lines().map(|l| l.to_string()).collect();
-        let _ = wavefront.load_texture_vector(to_parse.iter()).unwrap();
+        let _ = wavefront.load_texture_vector(&to_parse).unwrap();
         assert_eq!(wavefront.uv.len(), 130, "uv vector length");
         assert_eq!(wavefront.uv[22], glm::Vector2::new(0.656250, 1.0));
         assert_eq!(wavefront.uv[86], glm::Vector2::new(0.485388, 0.203178));
@@ -419,11 +571,146 @@ mod tests {
                     .as_str());
         let mut wavefront = Wavefront::new(None);
         let to_parse: Vec<String> = data.lines().map(|l| l.to_string()).collect();
-        let _ = wavefront.load_normal_vector(to_parse.iter()).unwrap();
+        let _ = wavefront.load_normal_vector(&to_parse).unwrap();
         assert_eq!(wavefront.norm.len(), 34, "norm vector length");
         assert_eq!(wavefront.norm[13], glm::Vector4::new(-0.4714, -0.0, -0.8819, 1.0));
         assert_eq!(
             wavefront.norm[21], glm::Vector4::new(0.8819, -0.0, -0.4714, 1.0),
         );
     }
+
+    #[test]
+    //everything comes out of memory, the .mtl and texture through the resolver
+    fn wavefront_import_from_memory() {
+        let obj = "mtllib tri.mtl\n\
+            v 0.0 0.0 0.0\nv 1.0 0.0 0.0\nv 0.0 1.0 0.0\n\
+            vt 0.0 0.0\nvt 1.0 0.0\nvt 0.0 1.0\n\
+            vn 0.0 0.0 1.0\n\
+            f 1/1/1 2/2/1 3/3/1\n";
+        let texture = std::fs::read(format!("{TEST_DIRECTORY}/collada_texture.png")).unwrap();
+        let mut resolver = MemoryResolver::new();
+        resolver.insert("tri.mtl", b"newmtl tri\nmap_Kd textures/tri.png\n".to_vec());
+        resolver.insert("textures/tri.png", texture);
+        let model = Wavefront::new(None)
+            .import_bytes(obj.as_bytes(), "tri", &resolver, &ImportSettings::default())
+            .unwrap();
+        assert_eq!(model.name, "tri");
+        assert_eq!(model.indeces.len(), 3);
+        assert_eq!(model.materials.len(), 1);
+        assert_eq!(model.submeshes[0].material, Some(0));
+    }
+
+    #[test]
+    //no vn lines, a quad per side of a cube, one smoothing group per side
+    fn wavefront_generated_normals() {
+        let obj = "o cube\n\
+            v -1.0 -1.0 -1.0\nv 1.0 -1.0 -1.0\nv 1.0 1.0 -1.0\nv -1.0 1.0 -1.0\n\
+            v -1.0 -1.0 1.0\nv 1.0 -1.0 1.0\nv 1.0 1.0 1.0\nv -1.0 1.0 1.0\n\
+            s 1\nf 5 6 7 8\ns 2\nf 2 1 4 3\ns 3\nf 6 2 3 7\n\
+            s 4\nf 1 5 8 4\ns 5\nf 8 7 3 4\ns 6\nf 1 2 6 5\n";
+        let settings = ImportSettings{
+            normal_mode: NormalMode::Smooth{ crease_angle: 180.0 },
+            ..Default::default()
+        };
+        let model = Wavefront::new(None)
+            .import_bytes(obj.as_bytes(), "unused", &MemoryResolver::new(), &settings)
+            .unwrap();
+        assert_eq!(model.name, "cube");
+        assert_eq!(model.indeces.len(), 6*2*3, "index length");
+        let vertices = model.get_vertices().unwrap();
+        //the groups keep every side flat even though the crease angle would smooth it
+        assert_eq!(vertices.len(), 6*4, "vertex length");
+        for v in vertices.iter() {
+            let n = &v.norm;
+            assert!((n.x.abs() + n.y.abs() + n.z.abs() - 1.0).abs() < 1.0e-6, "axis aligned");
+            assert!(n.x*v.pos.x + n.y*v.pos.y + n.z*v.pos.z > 0.0, "outward normal");
+        }
+        //without the groups everything smooths together
+        let obj: String = obj.lines()
+            .filter(|l| !l.starts_with("s "))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let model = Wavefront::new(None)
+            .import_bytes(obj.as_bytes(), "unused", &MemoryResolver::new(), &settings)
+            .unwrap();
+        assert_eq!(model.get_vertices().unwrap().len(), 8, "vertex length");
+    }
+
+    //a size x size plane of quads, every vertex with its own uv and a shared normal, the
+    //material changes halfway along each row of quads
+    fn grid_obj(size: u32) -> String {
+        let plane = make_primitive(&Primitive::Plane{ size: 1.0, subdivisions: size }).unwrap();
+        let attributes = MeshAttributes::new(&plane.mesh);
+        let mut obj = String::from("o grid\nvn 0.0 -1.0 0.0\n");
+        for (p, uv) in attributes.positions.iter().zip(attributes.uvs.unwrap()) {
+            obj.push_str(&format!(
+                "v {:.6} {:.6} {:.6}\nvt {:.6} {:.6}\n", p[0], p[1], p[2], uv[0], uv[1],
+            ));
+        }
+        //two triangles per quad, a row of quads at a time
+        let row = 2 * size as usize;
+        for (t, triangle) in plane.indeces.chunks_exact(3).enumerate() {
+            if t % row == row / 2 {
+                obj.push_str(&format!("usemtl half_{}\n", t / row));
+            }
+            let [a, b, c] = [0, 1, 2].map(|k| triangle[k] + 1);
+            obj.push_str(&format!("f {a}/{a}/1 {b}/{b}/1 {c}/{c}/1\n"));
+        }
+        obj
+    }
+
+    #[test]
+    //big enough to be split across several chunks
+    fn wavefront_parallel_deterministic() {
+        let obj = grid_obj(80);
+        let import = |threads: Option<usize>| {
+            let settings = ImportSettings{
+                threads,
+                ..Default::default()
+            };
+            Wavefront::new(None)
+                .import_bytes(obj.as_bytes(), "unused", &MemoryResolver::new(), &settings)
+                .unwrap()
+        };
+        let single = import(Some(1));
+        assert_eq!(single.indeces.len(), 80*80*2*3, "index length");
+        assert_eq!(single.get_vertices().unwrap().len(), 81*81, "shared vertices");
+        assert_eq!(single.submeshes.len(), 80 + 1, "usemtl submeshes");
+        for threads in [Some(4), None] {
+            let parallel = import(threads);
+            assert_eq!(single.indeces, parallel.indeces, "{threads:?} indeces");
+            let bits = |m: &crate::model::Model| -> Vec<u32> {
+                m.get_vertices().unwrap().iter()
+                    .flat_map(|v| [v.pos.x, v.pos.y, v.pos.z, v.uv.x, v.uv.y, v.norm.z])
+                    .map(|f| f.to_bits())
+                    .collect()
+            };
+            assert_eq!(bits(&single), bits(&parallel), "{threads:?} vertices");
+            for (a, b) in single.submeshes.iter().zip(parallel.submeshes.iter()) {
+                assert_eq!(
+                    (&a.name, a.first_index, a.index_count),
+                    (&b.name, b.first_index, b.index_count),
+                );
+            }
+        }
+    }
+
+    #[test]
+    //the first bad line in the file is the one reported, however the work was split
+    fn wavefront_parallel_first_error() {
+        let mut obj = grid_obj(80);
+        obj = obj.replacen("\nf ", "\nf 2/2/9 ", 1);
+        obj.push_str("f 1/1/1 2/2/1 99999/1/1\n");
+        for threads in [Some(1), Some(4)] {
+            let settings = ImportSettings{
+                threads,
+                ..Default::default()
+            };
+            let err = Wavefront::new(None)
+                .import_bytes(obj.as_bytes(), "unused", &MemoryResolver::new(), &settings)
+                .err()
+                .unwrap();
+            assert!(err.contains("2/2/9 is out of range"), "{threads:?}: {err}");
+        }
+    }
 }
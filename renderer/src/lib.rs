@@ -1,6 +1,7 @@
 pub mod window;
 pub mod vulkan;
 pub mod importer;
+pub mod exporter;
 pub mod model;
 
 pub type ResultError = String;
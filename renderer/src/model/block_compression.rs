@@ -0,0 +1,813 @@
+/* Block compression, BC1, BC3, BC5 and BC7 encoded on the cpu at cook time and decoded
+ * for the cpu side and gpus without them, see texture_format.rs for the formats.
+ * A block's endpoints start as the ends of the line through its texels along their
+ * principal axis, each texel takes the nearest color on the line the format can
+ * represent and the endpoints are refit to those choices by least squares for as long
+ * as that lowers the error.
+ * BC1 blocks with texels under half alpha use its 3 color mode with transparent black.
+ * BC4, the alpha of BC3 and both channels of BC5, spans each block's min to max.
+ * BC7 is encoded in mode 6, one subset with rgba endpoints and 16 steps, which beats the
+ * others on smooth blocks and isn't far off on the rest. Decoding handles every mode.
+ * The bc7 partition tables are the ones in the BC7 specification: the two subset
+ * partitions as a bit per texel set for subset 1, the three subset ones as 2 bits per
+ * texel, and for each partition the texels whose index drops its top bit.
+ */
+
+use std::{
+    array::from_fn,
+    collections::HashMap,
+    sync::Arc,
+};
+
+use image::Rgba;
+use rayon::prelude::*;
+
+use super::{
+    material::{AlphaMode, SharedTexture, Texture, TextureImage, TextureSlotKind},
+    texture_format::{float_bytes, EncodedTexture, TextureFormat, BLOCK_SIZE},
+    Model,
+};
+
+//a block's texels, row by row
+type Block = [[u8; 4]; 16];
+
+//power iterations finding a block's principal axis
+const AXIS_ITERATIONS: usize = 8;
+//least squares refits after the first fit
+const REFITS: usize = 2;
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+//how a bc7 mode lays out its bits, in the order they're read
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    //a p-bit per endpoint, or one per subset shared by both its endpoints
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    //a second set of indices, for alpha or color as the selection bit picks
+    index_bits2: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode{ subsets: 3, partition_bits: 4, rotation_bits: 0, selection_bits: 0, color_bits: 4,
+        alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, index_bits2: 0 },
+    Bc7Mode{ subsets: 2, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 6,
+        alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, index_bits2: 0 },
+    Bc7Mode{ subsets: 3, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 5,
+        alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits2: 0 },
+    Bc7Mode{ subsets: 2, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 7,
+        alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index_bits2: 0 },
+    Bc7Mode{ subsets: 1, partition_bits: 0, rotation_bits: 2, selection_bits: 1, color_bits: 5,
+        alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits2: 3 },
+    Bc7Mode{ subsets: 1, partition_bits: 0, rotation_bits: 2, selection_bits: 0, color_bits: 7,
+        alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, index_bits2: 2 },
+    Bc7Mode{ subsets: 1, partition_bits: 0, rotation_bits: 0, selection_bits: 0, color_bits: 7,
+        alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, index_bits2: 0 },
+    Bc7Mode{ subsets: 2, partition_bits: 6, rotation_bits: 0, selection_bits: 0, color_bits: 5,
+        alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, index_bits2: 0 },
+];
+
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec,
+    0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce,
+    0x008c, 0x7310, 0x3100, 0x8cce, 0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0,
+    0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660, 0x0272, 0x04e4,
+    0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718,
+    0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+const PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0,
+    0x5a5a5050, 0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4,
+    0xa9a59450, 0x2a0a4250, 0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454,
+    0x6a6a4040, 0xa4a45000, 0x1a1a0500, 0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400,
+    0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200, 0xa9a58000, 0x5090a0a8, 0xa8a09050,
+    0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50, 0x500aa550, 0xaaaa4444,
+    0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600, 0xaa444444,
+    0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44,
+    0x2a4a5254,
+];
+
+const ANCHORS_3_1: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+];
+
+const ANCHORS_3_2: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+//the endpoints and indices picked for a block, and how far off they are
+struct Fit<const B: usize> {
+    bytes: [u8; B],
+    error: u32,
+    //how much of the second endpoint each texel takes, None for texels that don't count
+    weights: [Option<f32>; 16],
+}
+
+//little endian bits, the way the blocks are laid out
+#[derive(Default)]
+struct Bits {
+    bits: u128,
+    position: u32,
+}
+
+impl Bits {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits >> self.position) as u32 & ((1 << count) - 1);
+        self.position += count;
+        value
+    }
+
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u128) << self.position;
+        self.position += count;
+    }
+}
+
+//every level of an uncompressed texture in format
+pub fn compress_texture(texture: &Texture, format: TextureFormat) -> EncodedTexture {
+    EncodedTexture{
+        format,
+        width: texture.width(),
+        height: texture.height(),
+        layers: 1,
+        faces: 1,
+        levels: texture.levels().map(|level| encode_blocks(format, level)).collect(),
+    }
+}
+
+//the format a slot's texture is compressed to when format is asked for. BC5 only holds
+//normal maps' x and y, every other slot gets BC7 at the same size instead. A base color
+//whose alpha the material reads gets BC3 rather than BC1's 1 bit alpha. Base color and
+//emissive are srgb
+pub fn slot_format(format: TextureFormat, kind: TextureSlotKind, alpha: bool) -> TextureFormat {
+    let format = match (format.with_srgb(false), kind) {
+        (TextureFormat::Bc5, TextureSlotKind::Normal) => TextureFormat::Bc5,
+        (TextureFormat::Bc5, _) => TextureFormat::Bc7,
+        (TextureFormat::Bc1, TextureSlotKind::BaseColor) if alpha => TextureFormat::Bc3,
+        (format, _) => format,
+    };
+    format.with_srgb(kind.is_srgb())
+}
+
+//compresses every texture of the model's materials that isn't already, each slot in
+//slot_format. Textures shared between slots stay shared unless the slots need different
+//formats. Returns how many textures were compressed
+pub fn compress_textures(model: &mut Model, format: TextureFormat) -> usize {
+    let mut made: HashMap<(*const Texture, TextureFormat), SharedTexture> = HashMap::new();
+    for material in model.materials.iter_mut() {
+        let alpha = material.alpha_mode != AlphaMode::Opaque;
+        for kind in TextureSlotKind::ALL {
+            let Some(slot) = material.texture_mut(kind) else {
+                continue;
+            };
+            if slot.texture.encoded.is_some() {
+                continue;
+            }
+            let format = slot_format(format, kind, alpha);
+            let texture = made.entry((Arc::as_ptr(&slot.texture), format)).or_insert_with(|| {
+                Arc::new(Texture::from_encoded(compress_texture(&slot.texture, format)))
+            });
+            slot.texture = texture.clone();
+        }
+    }
+    made.len()
+}
+
+//an image in format, a row of blocks at a time on rayon's pool
+pub fn encode_blocks(format: TextureFormat, image: &TextureImage) -> Vec<u8> {
+    if format.is_float() {
+        let texels: Vec<[f32; 4]> = image.pixels()
+            .map(|texel| texel.0.map(|c| c as f32 / 255.0))
+            .collect();
+        return float_bytes(format, &texels);
+    }
+    if !format.is_compressed() {
+        return image.as_raw().clone();
+    }
+    let blocks_x = image.width().div_ceil(BLOCK_SIZE);
+    let rows: Vec<Vec<u8>> = (0..image.height().div_ceil(BLOCK_SIZE)).into_par_iter()
+        .map(|by| {
+            let mut row = Vec::with_capacity(blocks_x as usize * format.block_bytes());
+            for bx in 0..blocks_x {
+                encode_block(format, &read_block(image, bx, by), &mut row);
+            }
+            row
+        })
+        .collect();
+    rows.concat()
+}
+
+//width x height texels out of format's blocks
+pub fn decode_blocks(format: TextureFormat, width: u32, height: u32, data: &[u8]) -> TextureImage {
+    let mut image = TextureImage::new(width, height);
+    let blocks_x = width.div_ceil(BLOCK_SIZE);
+    for (i, block) in data.chunks_exact(format.block_bytes()).enumerate() {
+        let (bx, by) = (i as u32 % blocks_x * BLOCK_SIZE, i as u32 / blocks_x * BLOCK_SIZE);
+        for (j, texel) in decode_block(format, block).into_iter().enumerate() {
+            let (x, y) = (bx + j as u32 % BLOCK_SIZE, by + j as u32 / BLOCK_SIZE);
+            if x < width && y < height {
+                image.put_pixel(x, y, Rgba(texel));
+            }
+        }
+    }
+    image
+}
+
+//the edges repeat into blocks that hang over them
+fn read_block(image: &TextureImage, bx: u32, by: u32) -> Block {
+    from_fn(|i| {
+        let x = (bx * BLOCK_SIZE + i as u32 % BLOCK_SIZE).min(image.width() - 1);
+        let y = (by * BLOCK_SIZE + i as u32 / BLOCK_SIZE).min(image.height() - 1);
+        image.get_pixel(x, y).0
+    })
+}
+
+fn channel(block: &Block, channel: usize) -> [u8; 16] {
+    from_fn(|i| block[i][channel])
+}
+
+fn encode_block(format: TextureFormat, block: &Block, out: &mut Vec<u8>) {
+    match format {
+        TextureFormat::Rgba8 | TextureFormat::Rgba8Srgb
+        | TextureFormat::Rgba16F | TextureFormat::Rgba32F => {
+            unreachable!("uncompressed formats aren't blocks")
+        },
+        TextureFormat::Bc1 | TextureFormat::Bc1Srgb => out.extend(encode_bc1(block, true)),
+        TextureFormat::Bc3 | TextureFormat::Bc3Srgb => {
+            out.extend(encode_bc4(&channel(block, 3)));
+            out.extend(encode_bc1(block, false));
+        },
+        TextureFormat::Bc5 => {
+            out.extend(encode_bc4(&channel(block, 0)));
+            out.extend(encode_bc4(&channel(block, 1)));
+        },
+        TextureFormat::Bc7 | TextureFormat::Bc7Srgb => out.extend(encode_bc7(block)),
+    }
+}
+
+fn decode_block(format: TextureFormat, block: &[u8]) -> Block {
+    match format {
+        TextureFormat::Rgba8 | TextureFormat::Rgba8Srgb
+        | TextureFormat::Rgba16F | TextureFormat::Rgba32F => {
+            unreachable!("uncompressed formats aren't blocks")
+        },
+        TextureFormat::Bc1 | TextureFormat::Bc1Srgb => decode_bc1(block, false),
+        TextureFormat::Bc3 | TextureFormat::Bc3Srgb => {
+            let alpha = decode_bc4(&block[..8]);
+            let mut texels = decode_bc1(&block[8..], true);
+            for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                texel[3] = alpha;
+            }
+            texels
+        },
+        TextureFormat::Bc5 => {
+            let (red, green) = (decode_bc4(&block[..8]), decode_bc4(&block[8..]));
+            from_fn(|i| [red[i], green[i], 0, 255])
+        },
+        TextureFormat::Bc7 | TextureFormat::Bc7Srgb => decode_bc7(block),
+    }
+}
+
+//the two ends of the line through texels along their principal axis
+fn principal_line<const N: usize>(texels: &[[f32; N]]) -> ([f32; N], [f32; N]) {
+    let count = texels.len() as f32;
+    let mean: [f32; N] = from_fn(|c| texels.iter().map(|t| t[c]).sum::<f32>() / count);
+    let mut covariance = [[0.0f32; N]; N];
+    for texel in texels {
+        for i in 0..N {
+            for j in 0..N {
+                covariance[i][j] += (texel[i] - mean[i]) * (texel[j] - mean[j]);
+            }
+        }
+    }
+    //the row of the widest channel can't be orthogonal to the axis
+    let widest = (0..N).max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap_or(0);
+    let mut axis = covariance[widest];
+    for _ in 0..AXIS_ITERATIONS {
+        let length = axis.iter().map(|a| a * a).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            return (mean, mean);
+        }
+        let unit: [f32; N] = from_fn(|i| axis[i] / length);
+        axis = from_fn(|i| (0..N).map(|j| covariance[i][j] * unit[j]).sum());
+    }
+    let length = axis.iter().map(|a| a * a).sum::<f32>().sqrt();
+    if length < 1e-6 {
+        return (mean, mean);
+    }
+    let axis: [f32; N] = from_fn(|i| axis[i] / length);
+    let (mut low, mut high) = (f32::MAX, f32::MIN);
+    for texel in texels {
+        let t: f32 = (0..N).map(|c| (texel[c] - mean[c]) * axis[c]).sum();
+        low = low.min(t);
+        high = high.max(t);
+    }
+    (from_fn(|c| mean[c] + axis[c] * low), from_fn(|c| mean[c] + axis[c] * high))
+}
+
+//the endpoints that best give texels at the weights they were given, None when the
+//weights don't pin both of them down
+fn least_squares<const N: usize>(
+    texels: &[[f32; N]; 16], weights: &[Option<f32>; 16],
+) -> Option<([f32; N], [f32; N])> {
+    let (mut aa, mut ab, mut bb) = (0.0, 0.0, 0.0);
+    let (mut ax, mut bx) = ([0.0f32; N], [0.0f32; N]);
+    for (texel, weight) in texels.iter().zip(weights) {
+        let Some(b) = *weight else {
+            continue;
+        };
+        let a = 1.0 - b;
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        for c in 0..N {
+            ax[c] += a * texel[c];
+            bx[c] += b * texel[c];
+        }
+    }
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+    Some((
+        from_fn(|c| (bb * ax[c] - ab * bx[c]) / determinant),
+        from_fn(|c| (aa * bx[c] - ab * ax[c]) / determinant),
+    ))
+}
+
+//fits from the principal line, then refits while it helps
+fn refine<const B: usize, const N: usize>(
+    texels: &[[f32; N]; 16], start: ([f32; N], [f32; N]),
+    fit: impl Fn([f32; N], [f32; N]) -> Fit<B>,
+) -> [u8; B] {
+    let mut best = fit(start.0, start.1);
+    for _ in 0..REFITS {
+        let Some((a, b)) = least_squares(texels, &best.weights) else {
+            break;
+        };
+        let next = fit(a, b);
+        if next.error >= best.error {
+            break;
+        }
+        best = next;
+    }
+    best.bytes
+}
+
+fn squared_error(a: &[u8], b: &[u8]) -> u32 {
+    a.iter().zip(b).map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32).sum()
+}
+
+fn unpack_565(color: u16) -> [u32; 3] {
+    let (r, g, b) = ((color >> 11) as u32, (color >> 5) as u32 & 63, color as u32 & 31);
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+fn pack_565(color: [f32; 3]) -> u16 {
+    let quantize = |v: f32, max: f32| (v.clamp(0.0, 255.0) * max / 255.0).round() as u16;
+    quantize(color[0], 31.0) << 11 | quantize(color[1], 63.0) << 5 | quantize(color[2], 31.0)
+}
+
+//the colors a BC1 block picks from, 3 and transparent black when c0 <= c1 unless
+//four_colors, BC3's color blocks always have 4
+fn bc1_palette(c0: u16, c1: u16, four_colors: bool) -> [[u8; 4]; 4] {
+    let (a, b) = (unpack_565(c0), unpack_565(c1));
+    let mix = |wa: u32, wb: u32| -> [u8; 4] {
+        let total = wa + wb;
+        let [r, g, b] = from_fn(|c| ((a[c] * wa + b[c] * wb + total / 2) / total) as u8);
+        [r, g, b, 255]
+    };
+    if four_colors || c0 > c1 {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0; 4]]
+    }
+}
+
+fn decode_bc1(block: &[u8], four_colors: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let palette = bc1_palette(c0, c1, four_colors);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    from_fn(|i| palette[(indices >> (2 * i) & 3) as usize])
+}
+
+//punch_through allows the 3 color mode, BC3's color block can't use it
+fn encode_bc1(block: &Block, punch_through: bool) -> [u8; 8] {
+    let transparent = punch_through && block.iter().any(|t| t[3] < 128);
+    let counts = |t: &[u8; 4]| !transparent || t[3] >= 128;
+    let texels: [[f32; 3]; 16] = from_fn(|i| from_fn(|c| block[i][c] as f32));
+    let opaque: Vec<[f32; 3]> = (0..16).filter(|&i| counts(&block[i])).map(|i| texels[i])
+        .collect();
+    if opaque.is_empty() {
+        //3 color mode with every texel transparent
+        return [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+    }
+    refine(&texels, principal_line(&opaque), |a, b| {
+        let (a, b) = (pack_565(a), pack_565(b));
+        //4 colors need c0 > c1, 3 colors and transparent black c0 <= c1
+        let (c0, c1) = if transparent { (a.min(b), a.max(b)) } else { (a.max(b), a.min(b)) };
+        let four_colors = !punch_through || c0 > c1;
+        let palette = bc1_palette(c0, c1, four_colors);
+        //how much of c1 each index is
+        let share = if four_colors {
+            [0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0]
+        } else {
+            [0.0, 1.0, 0.5, 0.0]
+        };
+        let mut fit = Fit{ bytes: [0; 8], error: 0, weights: [None; 16] };
+        let mut indices = 0u32;
+        for (i, texel) in block.iter().enumerate() {
+            let index = if !counts(texel) {
+                3
+            } else {
+                let (index, error) = (0..4).filter(|&p| palette[p][3] == 255)
+                    .map(|p| (p, squared_error(&palette[p][..3], &texel[..3])))
+                    .min_by_key(|&(_, error)| error)
+                    .unwrap();
+                fit.error += error;
+                //weights are of the endpoint passed second, which may have become c0
+                fit.weights[i] = Some(if c1 == b { share[index] } else { 1.0 - share[index] });
+                index
+            };
+            indices |= (index as u32) << (2 * i);
+        }
+        fit.bytes[..2].copy_from_slice(&c0.to_le_bytes());
+        fit.bytes[2..4].copy_from_slice(&c1.to_le_bytes());
+        fit.bytes[4..].copy_from_slice(&indices.to_le_bytes());
+        fit
+    })
+}
+
+//8 values when a0 > a1, otherwise 6 and 0 and 255
+fn bc4_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let (a, b) = (a0 as u32, a1 as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    let steps = if a0 > a1 { 7 } else { 5 };
+    for i in 1..steps {
+        palette[i as usize + 1] = (((steps - i) * a + i * b + steps / 2) / steps) as u8;
+    }
+    palette
+}
+
+fn decode_bc4(block: &[u8]) -> [u8; 16] {
+    let palette = bc4_palette(block[0], block[1]);
+    let mut indices = [0; 8];
+    indices[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(indices);
+    from_fn(|i| palette[(indices >> (3 * i) & 7) as usize])
+}
+
+fn encode_bc4(values: &[u8; 16]) -> [u8; 8] {
+    let (low, high) = (*values.iter().min().unwrap(), *values.iter().max().unwrap());
+    let palette = bc4_palette(high, low);
+    let mut indices = 0u64;
+    for (i, &value) in values.iter().enumerate() {
+        let index = (0..8).min_by_key(|&p| palette[p].abs_diff(value)).unwrap();
+        indices |= (index as u64) << (3 * i);
+    }
+    let mut block = [high, low, 0, 0, 0, 0, 0, 0];
+    block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+fn bc7_interpolate(a: u32, b: u32, weight: u32) -> u8 {
+    (((64 - weight) * a + weight * b + 32) >> 6) as u8
+}
+
+fn bc7_weights(bits: u32) -> &'static [u32] {
+    match bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+fn bc7_subset(mode: &Bc7Mode, partition: usize, texel: usize) -> usize {
+    match mode.subsets {
+        2 => (PARTITIONS_2[partition] >> texel & 1) as usize,
+        3 => (PARTITIONS_3[partition] >> (2 * texel) & 3) as usize,
+        _ => 0,
+    }
+}
+
+//the texel whose index drops its top bit
+fn bc7_anchor(mode: &Bc7Mode, partition: usize, subset: usize) -> usize {
+    match (mode.subsets, subset) {
+        (_, 0) => 0,
+        (2, _) => ANCHORS_2[partition] as usize,
+        (_, 1) => ANCHORS_3_1[partition] as usize,
+        _ => ANCHORS_3_2[partition] as usize,
+    }
+}
+
+//a p-bit widens its endpoint by a bit, then the bits repeat down to 8
+fn bc7_expand(value: u32, bits: u32) -> u32 {
+    let value = value << (8 - bits);
+    value | value >> bits
+}
+
+fn decode_bc7(block: &[u8]) -> Block {
+    let bits = u128::from_le_bytes(block[..16].try_into().unwrap());
+    let mode_index = bits.trailing_zeros() as usize;
+    //no mode bit set is reserved, it decodes to transparent black
+    let Some(mode) = BC7_MODES.get(mode_index) else {
+        return [[0; 4]; 16];
+    };
+    let mut bits = Bits{ bits, position: mode_index as u32 + 1 };
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let selection = bits.read(mode.selection_bits);
+
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    let channels = if mode.alpha_bits > 0 { 4 } else { 3 };
+    for channel in 0..channels {
+        let channel_bits = if channel < 3 { mode.color_bits } else { mode.alpha_bits };
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[channel] = bits.read(channel_bits);
+            }
+        }
+    }
+    let pbit = mode.endpoint_pbits || mode.shared_pbits;
+    if pbit {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            let shared = bits.read(mode.shared_pbits as u32);
+            for endpoint in subset.iter_mut() {
+                let p = if mode.endpoint_pbits { bits.read(1) } else { shared };
+                for value in endpoint[..channels].iter_mut() {
+                    *value = *value << 1 | p;
+                }
+            }
+        }
+    }
+    for subset in endpoints.iter_mut().take(mode.subsets) {
+        for endpoint in subset.iter_mut() {
+            for (channel, value) in endpoint.iter_mut().enumerate() {
+                let channel_bits = if channel < 3 { mode.color_bits } else { mode.alpha_bits };
+                *value = if channel_bits == 0 {
+                    255
+                } else {
+                    bc7_expand(*value, channel_bits + pbit as u32)
+                };
+            }
+        }
+    }
+
+    let subsets: [usize; 16] = from_fn(|i| bc7_subset(mode, partition, i));
+    let anchors: [bool; 16] = from_fn(|i| bc7_anchor(mode, partition, subsets[i]) == i);
+    let indices: [u32; 16] = from_fn(|i| bits.read(mode.index_bits - anchors[i] as u32));
+    let indices2: [u32; 16] = from_fn(|i| {
+        if mode.index_bits2 == 0 {
+            0
+        } else {
+            bits.read(mode.index_bits2 - (i == 0) as u32)
+        }
+    });
+
+    from_fn(|i| {
+        let [a, b] = endpoints[subsets[i]];
+        let (color, alpha) = match (mode.index_bits2, selection) {
+            (0, _) => ((indices[i], mode.index_bits), (indices[i], mode.index_bits)),
+            (_, 0) => ((indices[i], mode.index_bits), (indices2[i], mode.index_bits2)),
+            _ => ((indices2[i], mode.index_bits2), (indices[i], mode.index_bits)),
+        };
+        let mut texel: [u8; 4] = from_fn(|c| {
+            let (index, index_bits) = if c < 3 { color } else { alpha };
+            bc7_interpolate(a[c], b[c], bc7_weights(index_bits)[index as usize])
+        });
+        if rotation > 0 {
+            texel.swap(rotation as usize - 1, 3);
+        }
+        texel
+    })
+}
+
+//mode 6, 7 bit rgba endpoints with a p-bit each and 4 bit indices
+fn encode_bc7(block: &Block) -> [u8; 16] {
+    let texels: [[f32; 4]; 16] = from_fn(|i| from_fn(|c| block[i][c] as f32));
+    refine(&texels, principal_line(&texels), |a, b| {
+        //every pair of p-bits, the best one
+        let candidates = (0..4).map(|pbits| {
+            let p = [pbits & 1, pbits >> 1];
+            let quantize = |e: [f32; 4], p: u32| -> [u32; 4] {
+                from_fn(|c| ((e[c] - p as f32) / 2.0).round().clamp(0.0, 127.0) as u32)
+            };
+            let ends = [quantize(a, p[0]), quantize(b, p[1])];
+            let palette: [[u8; 4]; 16] = from_fn(|w| {
+                from_fn(|c| {
+                    bc7_interpolate(ends[0][c] << 1 | p[0], ends[1][c] << 1 | p[1], WEIGHTS_4[w])
+                })
+            });
+            let mut error = 0;
+            let indices: [usize; 16] = from_fn(|i| {
+                let (index, e) = (0..16).map(|w| (w, squared_error(&palette[w], &block[i])))
+                    .min_by_key(|&(_, e)| e)
+                    .unwrap();
+                error += e;
+                index
+            });
+            (error, ends, p, indices)
+        });
+        let (error, mut ends, mut p, mut indices) = candidates.min_by_key(|c| c.0).unwrap();
+        let weights = from_fn(|i| Some(WEIGHTS_4[indices[i]] as f32 / 64.0));
+        //the first texel's index has no top bit, so it has to be in the lower half
+        if indices[0] >= 8 {
+            ends.swap(0, 1);
+            p.swap(0, 1);
+            indices = indices.map(|i| 15 - i);
+        }
+        let mut bits = Bits::default();
+        bits.write(1 << 6, 7);
+        for (&end0, &end1) in ends[0].iter().zip(&ends[1]) {
+            bits.write(end0, 7);
+            bits.write(end1, 7);
+        }
+        bits.write(p[0], 1);
+        bits.write(p[1], 1);
+        for (i, &index) in indices.iter().enumerate() {
+            bits.write(index as u32, if i == 0 { 3 } else { 4 });
+        }
+        Fit{ bytes: bits.bits.to_le_bytes(), error, weights }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::model::block_compression::{
+        compress_texture, compress_textures, decode_bc1, decode_bc7, decode_blocks, Bits,
+    };
+    use crate::model::material::{
+        AlphaMode, Material, Texture, TextureImage, TextureSlot, TextureSlotKind,
+    };
+    use crate::model::primitives::{make_primitive, Primitive};
+    use crate::model::texture_format::TextureFormat;
+    use crate::model::Model;
+
+    //root mean square error over the given channels
+    fn rmse(a: &TextureImage, b: &TextureImage, channels: usize) -> f32 {
+        let error: f32 = a.pixels().zip(b.pixels())
+            .flat_map(|(a, b)| (0..channels).map(|c| (a[c] as f32 - b[c] as f32).powi(2)))
+            .sum();
+        (error / (a.len() / 4 * channels) as f32).sqrt()
+    }
+
+    #[test]
+    fn block_compression_round_trips() {
+        //a diagonal blend, not a multiple of 4 so the last blocks are padded, alpha stays
+        //over half so BC1 keeps every texel
+        let (width, height) = (18, 10);
+        let image = TextureImage::from_fn(width, height, |x, y| {
+            let t = (x + y) as f32 / (width + height) as f32;
+            let mix = |a: f32, b: f32| (a + (b - a) * t).round() as u8;
+            Rgba([mix(20.0, 230.0), mix(200.0, 40.0), mix(60.0, 180.0), mix(255.0, 128.0)])
+        });
+        let texture = Texture::new(image.clone());
+        let round_trip = |format| {
+            let encoded = compress_texture(&texture, format);
+            assert_eq!(encoded.levels[0].len(), format.image_bytes(width, height));
+            encoded.check().unwrap();
+            encoded.decode(0, 0, 0)
+        };
+
+        let bc1 = round_trip(TextureFormat::Bc1);
+        assert!(rmse(&image, &bc1, 3) < 6.0, "{}", rmse(&image, &bc1, 3));
+        let bc3 = round_trip(TextureFormat::Bc3);
+        assert!(rmse(&image, &bc3, 3) < 6.0, "{}", rmse(&image, &bc3, 3));
+        assert!(rmse(&image, &bc3, 4) < 6.0, "{}", rmse(&image, &bc3, 4));
+        let bc5 = round_trip(TextureFormat::Bc5);
+        assert!(rmse(&image, &bc5, 2) < 3.0, "{}", rmse(&image, &bc5, 2));
+        assert!(bc5.pixels().all(|p| p[2] == 0 && p[3] == 255));
+        let bc7 = round_trip(TextureFormat::Bc7);
+        assert!(rmse(&image, &bc7, 4) < 2.0, "{}", rmse(&image, &bc7, 4));
+        assert!(rmse(&image, &bc7, 3) < rmse(&image, &bc1, 3));
+
+        //BC1 punches through texels under half alpha and leaves the rest opaque
+        let cutout = TextureImage::from_fn(8, 8, |x, y| {
+            Rgba([200, (x * 30) as u8, 40, if x < 3 && y < 5 { 0 } else { 255 }])
+        });
+        let bc1 = compress_texture(&Texture::new(cutout.clone()), TextureFormat::Bc1)
+            .decode(0, 0, 0);
+        assert!(cutout.pixels().zip(bc1.pixels()).all(|(a, b)| a[3] == b[3]));
+        let opaque: Vec<bool> = cutout.pixels().map(|p| p[3] == 255).collect();
+        let error = bc1.pixels().zip(cutout.pixels()).zip(opaque)
+            .filter(|(_, opaque)| *opaque)
+            .map(|((a, b), _)| (a[1] as i32 - b[1] as i32).abs())
+            .max();
+        assert!(error.unwrap() < 24, "{error:?}");
+
+        //a flat block in a color 565 holds comes back exactly, every level is encoded
+        let mut flat = Texture::new(TextureImage::from_pixel(8, 8, Rgba([255, 0, 0, 255])));
+        flat.mips = vec![TextureImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]))];
+        let encoded = compress_texture(&flat, TextureFormat::Bc1Srgb);
+        assert_eq!(encoded.level_count(), 2);
+        assert_eq!(encoded.decode(1, 0, 0), flat.mips[0]);
+        assert_eq!(encoded.decode(0, 0, 0), flat.image);
+    }
+
+    #[test]
+    fn block_compression_formats_per_slot() {
+        let model = || {
+            let mut model = make_primitive(&Primitive::from_id("builtin:cube").unwrap()).unwrap();
+            let slot = TextureSlot::new(TextureImage::new(4, 4), None);
+            let mut blended = Material::default();
+            blended.alpha_mode = AlphaMode::Blend;
+            for kind in TextureSlotKind::ALL {
+                blended.set_texture(kind, Some(slot.clone()));
+            }
+            let mut opaque = Material::default();
+            opaque.set_texture(TextureSlotKind::BaseColor, Some(slot));
+            model.materials = vec![blended, opaque];
+            model
+        };
+        let formats = |model: &Model, material: usize| -> Vec<TextureFormat> {
+            model.materials[material].textures()
+                .map(|(_, slot)| slot.texture.encoded.as_ref().unwrap().format)
+                .collect()
+        };
+
+        //BC5 is only for normal maps, the rest get BC7, color as srgb
+        let mut bc5 = model();
+        assert_eq!(compress_textures(&mut bc5, TextureFormat::Bc5), 3);
+        assert_eq!(formats(&bc5, 0), vec![
+            TextureFormat::Bc7Srgb, TextureFormat::Bc7, TextureFormat::Bc5,
+            TextureFormat::Bc7, TextureFormat::Bc7Srgb,
+        ]);
+        //a blended base color keeps its alpha in BC3, an opaque one is BC1
+        let mut bc1 = model();
+        assert_eq!(compress_textures(&mut bc1, TextureFormat::Bc1), 3);
+        assert_eq!(formats(&bc1, 0), vec![
+            TextureFormat::Bc3Srgb, TextureFormat::Bc1, TextureFormat::Bc1,
+            TextureFormat::Bc1, TextureFormat::Bc1Srgb,
+        ]);
+        assert_eq!(formats(&bc1, 1), vec![TextureFormat::Bc1Srgb]);
+    }
+
+    #[test]
+    fn block_compression_decodes_known_blocks() {
+        //red and blue, each index in turn
+        let mut block = vec![0x00, 0xf8, 0x1f, 0x00];
+        block.extend((0b11100100u32).to_le_bytes());
+        let texels = decode_bc1(&block, false);
+        assert_eq!(&texels[..4], &[[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255],
+            [85, 0, 170, 255]]);
+        let image = decode_blocks(TextureFormat::Bc1, 2, 1, &block);
+        assert_eq!(image.as_raw(), &[255, 0, 0, 255, 0, 0, 255, 255]);
+
+        //bc7 mode 1, partition 13 is the top half and the bottom half
+        let mut bits = Bits::default();
+        bits.write(0b10, 2);
+        bits.write(13, 6);
+        for channel in [[63, 0, 0, 0], [0, 0, 0, 0], [0, 0, 40, 0]] {
+            for value in channel {
+                bits.write(value, 6);
+            }
+        }
+        bits.write(1, 1);
+        bits.write(0, 1);
+        let texels = decode_bc7(&bits.bits.to_le_bytes());
+        assert!(texels[..8].iter().all(|t| *t == [255, 2, 2, 255]), "{texels:?}");
+        assert!(texels[8..].iter().all(|t| *t == [0, 0, 161, 255]), "{texels:?}");
+
+        //mode 4 with alpha rotated into red
+        let mut bits = Bits::default();
+        bits.write(0b10000, 5);
+        bits.write(1, 2);
+        bits.write(0, 1);
+        for value in [31, 0, 16, 0, 0, 0] {
+            bits.write(value, 5);
+        }
+        let texels = decode_bc7(&bits.bits.to_le_bytes());
+        assert!(texels.iter().all(|t| *t == [0, 132, 0, 255]), "{texels:?}");
+
+        //no mode is transparent black
+        assert_eq!(decode_bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+}
@@ -0,0 +1,325 @@
+/* Bounding volumes for a Model and each of its submeshes, what culling, picking,
+ * framing a camera and picking a level of detail start from.
+ * Importers compute them once, apply_conversion moves them along with the vertices and
+ * cooked models store them, so nothing has to walk the vertices again to get them.
+ * The sphere is Ritter's, "An Efficient Bounding Sphere", Graphics Gems 1990, within a
+ * few percent of the minimal one, or the box's circumscribed sphere when that happens
+ * to be smaller.
+ */
+
+use super::{
+    Model,
+};
+
+use crate::{
+    exporter::MeshAttributes,
+    RenderResult,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    //contains nothing, the union with anything is that thing
+    pub const EMPTY: Self = Self{
+        min: [f32::INFINITY; 3],
+        max: [f32::NEG_INFINITY; 3],
+    };
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a [f32; 3]>) -> Self {
+        let mut aabb = Self::EMPTY;
+        for p in points {
+            aabb.add(p);
+        }
+        aabb
+    }
+
+    pub fn add(&mut self, p: &[f32; 3]) {
+        for (k, c) in p.iter().enumerate() {
+            self.min[k] = self.min[k].min(*c);
+            self.max[k] = self.max[k].max(*c);
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb{
+            min: [0, 1, 2].map(|k| self.min[k].min(other.min[k])),
+            max: [0, 1, 2].map(|k| self.max[k].max(other.max[k])),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|k| self.min[k] > self.max[k])
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [0, 1, 2].map(|k| (self.min[k] + self.max[k]) * 0.5)
+    }
+
+    pub fn half_extents(&self) -> [f32; 3] {
+        [0, 1, 2].map(|k| (self.max[k] - self.min[k]) * 0.5)
+    }
+
+    pub fn contains(&self, p: &[f32; 3]) -> bool {
+        (0..3).all(|k| self.min[k] <= p[k] && p[k] <= self.max[k])
+    }
+
+    //the box around this one after an affine transform, column major like glsl,
+    //Arvo's method, https://www.realtimerendering.com/resources/GraphicsGems/gems/TransBox.c
+    pub fn transform(&self, m: &[[f32; 4]; 4]) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let mut out = Aabb{
+            min: [m[3][0], m[3][1], m[3][2]],
+            max: [m[3][0], m[3][1], m[3][2]],
+        };
+        for (column, axis) in m.iter().take(3).enumerate() {
+            for (row, scale) in axis.iter().take(3).enumerate() {
+                let a = scale * self.min[column];
+                let b = scale * self.max[column];
+                out.min[row] += a.min(b);
+                out.max[row] += a.max(b);
+            }
+        }
+        out
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    //a negative radius contains nothing
+    pub const EMPTY: Self = Self{
+        center: [0.0; 3],
+        radius: -1.0,
+    };
+
+    pub fn ritter(points: &[[f32; 3]]) -> Self {
+        let Some(first) = points.first() else {
+            return Self::EMPTY;
+        };
+        let farthest = |from: &[f32; 3]| -> [f32; 3] {
+            *points.iter()
+                .max_by(|a, b| distance_squared(from, a).total_cmp(&distance_squared(from, b)))
+                .unwrap()
+        };
+        let a = farthest(first);
+        let b = farthest(&a);
+        let mut sphere = Self{
+            center: [0, 1, 2].map(|k| (a[k] + b[k]) * 0.5),
+            radius: distance_squared(&a, &b).sqrt() * 0.5,
+        };
+        for p in points.iter() {
+            let d = distance_squared(&sphere.center, p).sqrt();
+            if d > sphere.radius {
+                //grow just enough to reach p, keeping the far side where it was
+                let radius = (sphere.radius + d) * 0.5;
+                let t = (radius - sphere.radius) / d;
+                let c = sphere.center;
+                sphere.center = [0, 1, 2].map(|k| c[k] + (p[k] - c[k]) * t);
+                sphere.radius = radius;
+            }
+        }
+        //floating point growth can leave a point a hair outside
+        let furthest = points.iter()
+            .map(|p| distance_squared(&sphere.center, p))
+            .fold(0.0f32, f32::max)
+            .sqrt();
+        sphere.radius = sphere.radius.max(furthest);
+        sphere
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.radius < 0.0
+    }
+
+    pub fn contains(&self, p: &[f32; 3]) -> bool {
+        distance_squared(&self.center, p) <= self.radius * self.radius
+    }
+
+    //the radius grows by the largest scale along any axis, so it stays conservative
+    //under non uniform scales and shears
+    pub fn transform(&self, m: &[[f32; 4]; 4]) -> BoundingSphere {
+        if self.is_empty() {
+            return *self;
+        }
+        let c = self.center;
+        let center = [0, 1, 2].map(|row| {
+            m[0][row] * c[0] + m[1][row] * c[1] + m[2][row] * c[2] + m[3][row]
+        });
+        let scale = (0..3)
+            .map(|column| (0..3).map(|row| m[column][row] * m[column][row]).sum::<f32>())
+            .fold(0.0f32, f32::max)
+            .sqrt();
+        BoundingSphere{
+            center,
+            radius: self.radius * scale,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    pub const EMPTY: Self = Self{
+        aabb: Aabb::EMPTY,
+        sphere: BoundingSphere::EMPTY,
+    };
+
+    pub fn from_points(points: &[[f32; 3]]) -> Self {
+        let aabb = Aabb::from_points(points);
+        let mut sphere = BoundingSphere::ritter(points);
+        let boxed = distance_squared(&aabb.min, &aabb.max).sqrt() * 0.5;
+        if !aabb.is_empty() && boxed < sphere.radius {
+            sphere = BoundingSphere{
+                center: aabb.center(),
+                radius: boxed,
+            };
+        }
+        Self{
+            aabb,
+            sphere,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aabb.is_empty()
+    }
+
+    pub fn transform(&self, m: &[[f32; 4]; 4]) -> Bounds {
+        Bounds{
+            aabb: self.aabb.transform(m),
+            sphere: self.sphere.transform(m),
+        }
+    }
+}
+
+fn distance_squared(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|k| (a[k] - b[k]) * (a[k] - b[k])).sum()
+}
+
+//the bounds of the whole model and of the vertices each submesh's indeces use
+pub fn compute_bounds(model: &mut Model) -> RenderResult<()> {
+    let positions = MeshAttributes::new(&model.mesh).positions;
+    model.bounds = Bounds::from_points(&positions);
+    for submesh in model.submeshes.iter_mut() {
+        let range = submesh.first_index..submesh.first_index + submesh.index_count;
+        let indeces = model.indeces.get(range.clone())
+            .ok_or(format!("submesh '{}' {range:?} is out of range", submesh.name))?;
+        let points = indeces.iter()
+            .map(|i| positions.get(*i as usize).copied()
+                .ok_or(format!("index {i} is out of range of {} vertices", positions.len())))
+            .collect::<RenderResult<Vec<[f32; 3]>>>()?;
+        submesh.bounds = Bounds::from_points(&points);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        importer::{
+            collada::Collada,
+            Importer, ImportSettings,
+        },
+        model::{
+            bounds::{compute_bounds, Aabb, BoundingSphere, Bounds},
+            conversion::Conversion,
+        },
+        exporter::MeshAttributes,
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    fn close(a: &[f32; 3], b: &[f32; 3]) -> bool {
+        (0..3).all(|k| (a[k] - b[k]).abs() < 1e-4)
+    }
+
+    #[test]
+    fn bounds_points() {
+        //a unit cube's corners and its center
+        let mut points: Vec<[f32; 3]> = (0..8)
+            .map(|i| [0, 1, 2].map(|k| ((i >> k) & 1) as f32))
+            .collect();
+        points.push([0.5; 3]);
+        let bounds = Bounds::from_points(&points);
+        assert_eq!(bounds.aabb, Aabb{min: [0.0; 3], max: [1.0; 3]});
+        assert!(close(&bounds.sphere.center, &[0.5; 3]), "{:?}", bounds.sphere);
+        assert!((bounds.sphere.radius - 3f32.sqrt() * 0.5).abs() < 1e-4);
+        //a spread out cloud still ends up inside, not much bigger than needed
+        let cloud: Vec<[f32; 3]> = (0..200)
+            .map(|i| {
+                let (a, b) = (i as f32 * 0.61, i as f32 * 1.37);
+                [a.cos() * b.sin() * 3.0, a.sin() * b.sin() * 3.0, b.cos() * 3.0 + 1.0]
+            })
+            .collect();
+        let sphere = BoundingSphere::ritter(&cloud);
+        assert!(cloud.iter().all(|p| sphere.contains(p)));
+        assert!(sphere.radius < 3.0 * 1.1, "{sphere:?}");
+        assert!(Bounds::from_points(&[]).is_empty());
+    }
+
+    #[test]
+    fn bounds_transform() {
+        let points: Vec<[f32; 3]> = vec![[1.0, 2.0, 3.0], [-1.0, 0.0, 5.0], [2.0, -3.0, 4.0]];
+        let bounds = Bounds::from_points(&points);
+        //rotate 90 degrees around z, scale by 2 and move
+        let m = [
+            [0.0, 2.0, 0.0, 0.0],
+            [-2.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 2.0, 0.0],
+            [10.0, 20.0, 30.0, 1.0],
+        ];
+        let moved: Vec<[f32; 3]> = points.iter()
+            .map(|p| [10.0 - 2.0 * p[1], 20.0 + 2.0 * p[0], 30.0 + 2.0 * p[2]])
+            .collect();
+        let transformed = bounds.transform(&m);
+        assert_eq!(transformed.aabb, Aabb::from_points(&moved));
+        assert!((transformed.sphere.radius - bounds.sphere.radius * 2.0).abs() < 1e-5);
+        assert!(moved.iter().all(|p| transformed.sphere.contains(p)));
+    }
+
+    #[test]
+    fn bounds_import_and_conversion() {
+        let loc = format!("{TEST_DIRECTORY}/collada_scene.dae");
+        let model = Collada::new().generate_model(&loc).unwrap();
+        let positions = MeshAttributes::new(&model.mesh).positions;
+        assert_eq!(model.bounds, Bounds::from_points(&positions));
+        for submesh in model.submeshes.iter() {
+            assert!(!submesh.bounds.is_empty(), "{}", submesh.name);
+            let indeces = &model.indeces[submesh.first_index..][..submesh.index_count];
+            for i in indeces.iter() {
+                assert!(submesh.bounds.aabb.contains(&positions[*i as usize]));
+            }
+            assert_eq!(submesh.bounds.aabb.union(&model.bounds.aabb), model.bounds.aabb);
+        }
+
+        //converted on import, the bounds follow the vertices
+        let settings = ImportSettings{
+            conversion: Conversion::blender_to_vulkan().with_handedness_flip(),
+            ..Default::default()
+        };
+        let mut converted = Collada::new().generate_model_with_settings(&loc, &settings).unwrap();
+        let moved = converted.bounds;
+        compute_bounds(&mut converted).unwrap();
+        assert!(close(&moved.aabb.min, &converted.bounds.aabb.min), "{moved:?}");
+        assert!(close(&moved.aabb.max, &converted.bounds.aabb.max), "{moved:?}");
+        let positions = MeshAttributes::new(&converted.mesh).positions;
+        assert!(positions.iter().all(|p| {
+            let d = [0, 1, 2].map(|k| p[k] - moved.sphere.center[k]);
+            (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt() <= moved.sphere.radius + 1e-4
+        }));
+    }
+}
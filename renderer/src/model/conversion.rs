@@ -0,0 +1,427 @@
+/* Coordinate system, unit and uv conventions applied at import.
+ * Every format (and every tool exporting it) has its own idea of up, of what a unit is
+ * and of where v = 0 is. A Conversion remaps the axes (any signed permutation), scales
+ * everything uniformly and optionally flips v. A remap with an odd number of negated or
+ * swapped axes mirrors the model, which turns every triangle inside out, so the winding
+ * gets swapped back along with it.
+ * The conversion that was applied is kept on the Model, applying another composes them.
+ */
+
+use super::{
+    bounds::{Aabb, BoundingSphere, Bounds},
+    Mesh, Model, NormalVector, PositionVector, TangentVector, TextureVector,
+};
+
+use crate::RenderResult;
+
+//a source axis, the sign is applied as it's moved
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+    X,
+    NegX,
+    Y,
+    NegY,
+    Z,
+    NegZ,
+}
+
+impl Axis {
+    fn index(&self) -> usize {
+        match self {
+            Self::X | Self::NegX => 0,
+            Self::Y | Self::NegY => 1,
+            Self::Z | Self::NegZ => 2,
+        }
+    }
+
+    fn sign(&self) -> f32 {
+        match self {
+            Self::X | Self::Y | Self::Z => 1.0,
+            _ => -1.0,
+        }
+    }
+
+    fn from_parts(index: usize, sign: f32) -> Self {
+        match (index, sign < 0.0) {
+            (0, false) => Self::X,
+            (0, true) => Self::NegX,
+            (1, false) => Self::Y,
+            (1, true) => Self::NegY,
+            (_, false) => Self::Z,
+            (_, true) => Self::NegZ,
+        }
+    }
+
+    fn negate(&self) -> Self {
+        Self::from_parts(self.index(), -self.sign())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Millimeters,
+    Centimeters,
+    Meters,
+    Inches,
+    Feet,
+}
+
+impl Unit {
+    pub fn meters(&self) -> f32 {
+        match self {
+            Self::Millimeters => 0.001,
+            Self::Centimeters => 0.01,
+            Self::Meters => 1.0,
+            Self::Inches => 0.0254,
+            Self::Feet => 0.3048,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conversion {
+    //the source axis that ends up along x, y and z
+    pub axes: [Axis; 3],
+    pub scale: f32,
+    //v = 1 - v, bottom left uv origin to top left or back
+    pub flip_v: bool,
+}
+
+impl Default for Conversion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Conversion {
+    pub const IDENTITY: Self = Self{
+        axes: [Axis::X, Axis::Y, Axis::Z],
+        scale: 1.0,
+        flip_v: false,
+    };
+
+    pub fn new(axes: [Axis; 3], scale: Option<f32>, flip_v: bool) -> RenderResult<Self> {
+        let scale = scale.unwrap_or(1.0);
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(format!("conversion scale must be positive, got {scale}"));
+        }
+        let mut used = [false; 3];
+        for axis in axes.iter() {
+            if used[axis.index()] {
+                return Err(format!("conversion axes {axes:?} use a source axis twice"));
+            }
+            used[axis.index()] = true;
+        }
+        Ok(Self{
+            axes,
+            scale,
+            flip_v,
+        })
+    }
+
+    //blender is right handed, z up, y forward, in meters with OpenGL uvs,
+    //vulkan is y down with z going into the screen and uvs from the top left
+    pub fn blender_to_vulkan() -> Self {
+        Self{
+            axes: [Axis::X, Axis::NegZ, Axis::Y],
+            scale: 1.0,
+            flip_v: true,
+        }
+    }
+
+    //y up to z up and the other way around, both right handed
+    pub fn y_up_to_z_up() -> Self {
+        Self{
+            axes: [Axis::X, Axis::NegZ, Axis::Y],
+            scale: 1.0,
+            flip_v: false,
+        }
+    }
+
+    pub fn z_up_to_y_up() -> Self {
+        Self{
+            axes: [Axis::X, Axis::Z, Axis::NegY],
+            scale: 1.0,
+            flip_v: false,
+        }
+    }
+
+    //source is authored in one unit, the model should be in another
+    pub fn with_units(mut self, source: Unit, target: Unit) -> Self {
+        self.scale *= source.meters() / target.meters();
+        self
+    }
+
+    //mirrors z, left handed to right handed or the reverse
+    pub fn with_handedness_flip(mut self) -> Self {
+        self.axes[2] = self.axes[2].negate();
+        self
+    }
+
+    pub fn with_flip_v(mut self) -> Self {
+        self.flip_v = !self.flip_v;
+        self
+    }
+
+    //true when the remap is a mirror, triangles need their winding swapped
+    pub fn flips_handedness(&self) -> bool {
+        let sign: f32 = self.axes.iter().map(|a| a.sign()).product();
+        //an odd permutation is a mirror on its own
+        let [a, b, c] = self.axes.map(|a| a.index());
+        let even = (a + 1) % 3 == b && (b + 1) % 3 == c;
+        (sign < 0.0) == even
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    //this followed by next, as a single conversion
+    pub fn then(&self, next: &Self) -> Self {
+        Self{
+            axes: next.axes.map(|a| {
+                let inner = self.axes[a.index()];
+                Axis::from_parts(inner.index(), inner.sign() * a.sign())
+            }),
+            scale: self.scale * next.scale,
+            flip_v: self.flip_v != next.flip_v,
+        }
+    }
+
+    fn remap(&self, v: [f32; 3]) -> [f32; 3] {
+        self.axes.map(|a| v[a.index()] * a.sign())
+    }
+
+    fn position(&self, p: &PositionVector) -> PositionVector {
+        let [x, y, z] = self.remap([p.x, p.y, p.z]).map(|c| c * self.scale);
+        PositionVector::new(x, y, z, p.w)
+    }
+
+    //a signed permutation is orthonormal, normals move the same way positions do
+    //and a uniform scale doesn't change their direction
+    fn normal(&self, n: &NormalVector) -> NormalVector {
+        let [x, y, z] = self.remap([n.x, n.y, n.z]);
+        NormalVector::new(x, y, z, n.w)
+    }
+
+    //the bitangent is rebuilt from a cross product that a mirror negates,
+    //flipping v reverses it too
+    fn tangent(&self, t: &TangentVector) -> TangentVector {
+        let [x, y, z] = self.remap([t.x, t.y, t.z]);
+        let mut w = t.w;
+        if self.flips_handedness() {
+            w = -w;
+        }
+        if self.flip_v {
+            w = -w;
+        }
+        TangentVector::new(x, y, z, w)
+    }
+
+    //exact, the box's corners land on corners of the new box
+    fn bounds(&self, b: &Bounds) -> Bounds {
+        if b.is_empty() {
+            return *b;
+        }
+        let (min, max) = (self.remap(b.aabb.min), self.remap(b.aabb.max));
+        let center = self.remap(b.sphere.center);
+        Bounds{
+            aabb: Aabb{
+                min: [0, 1, 2].map(|k| min[k].min(max[k]) * self.scale),
+                max: [0, 1, 2].map(|k| min[k].max(max[k]) * self.scale),
+            },
+            sphere: BoundingSphere{
+                center: center.map(|c| c * self.scale),
+                radius: b.sphere.radius * self.scale,
+            },
+        }
+    }
+
+    fn uv(&self, uv: &TextureVector) -> TextureVector {
+        if self.flip_v {
+            TextureVector::new(uv.x, 1.0 - uv.y)
+        } else {
+            *uv
+        }
+    }
+}
+
+pub fn apply_conversion(model: &mut Model, conversion: &Conversion) -> RenderResult<()> {
+    if conversion.is_identity() {
+        return Ok(());
+    }
+    if conversion.flips_handedness() && !model.indeces.len().is_multiple_of(3) {
+        return Err(format!(
+            "can't fix the winding of {} indeces, not a multiple of 3", model.indeces.len(),
+        ));
+    }
+    let c = conversion;
+    match &mut model.mesh {
+        Mesh::PositionMesh(vertices) => {
+            vertices.iter_mut().for_each(|v| v.0 = c.position(&v.0));
+        },
+        Mesh::TextureMesh(vertices) => {
+            for v in vertices.iter_mut() {
+                v.pos = c.position(&v.pos);
+                v.uv = c.uv(&v.uv);
+            }
+        },
+        Mesh::NormalMesh(vertices) => {
+            for v in vertices.iter_mut() {
+                v.pos = c.position(&v.pos);
+                v.uv = c.uv(&v.uv);
+                v.norm = c.normal(&v.norm);
+            }
+        },
+        Mesh::ColorMesh(vertices) => {
+            for v in vertices.iter_mut() {
+                v.pos = c.position(&v.pos);
+                v.uv = c.uv(&v.uv);
+                v.norm = c.normal(&v.norm);
+            }
+        },
+        Mesh::TangentMesh(vertices) => {
+            for v in vertices.iter_mut() {
+                v.pos = c.position(&v.pos);
+                v.uv = c.uv(&v.uv);
+                v.norm = c.normal(&v.norm);
+                v.tangent = c.tangent(&v.tangent);
+            }
+        },
+    }
+    //submeshes are whole triangles, swapping inside each one leaves their ranges alone
+    if conversion.flips_handedness() {
+        model.indeces.chunks_exact_mut(3).for_each(|t| t.swap(1, 2));
+        for lod in model.lods.iter_mut() {
+            lod.indeces.chunks_exact_mut(3).for_each(|t| t.swap(1, 2));
+        }
+        model.meshlets.triangles.iter_mut().for_each(|t| t.swap(1, 2));
+    }
+    //level of detail errors are distances, they scale with the model
+    for lod in model.lods.iter_mut() {
+        lod.error *= conversion.scale;
+        lod.submeshes.iter_mut().for_each(|s| s.bounds = c.bounds(&s.bounds));
+    }
+    model.bounds = c.bounds(&model.bounds);
+    model.submeshes.iter_mut().for_each(|s| s.bounds = c.bounds(&s.bounds));
+    //cone axes are directions like normals
+    for meshlet in model.meshlets.meshlets.iter_mut() {
+        meshlet.sphere.center = c.remap(meshlet.sphere.center).map(|x| x * c.scale);
+        meshlet.sphere.radius *= c.scale;
+        meshlet.cone_axis = c.remap(meshlet.cone_axis);
+    }
+    model.conversion = model.conversion.then(conversion);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{
+        conversion::{apply_conversion, Axis, Conversion, Unit},
+        normals::{cross, normalize, sub},
+        tangents::generate_tangents,
+        Mesh, Model, NormalVector, NormalVertex, PositionVector, SubMesh, TextureVector,
+    };
+
+    //a single triangle facing +z with uvs running along x and y
+    fn triangle() -> Model {
+        let vertex = |x: f32, y: f32| NormalVertex::new(
+            PositionVector::new(x, y, 0.0, 1.0),
+            TextureVector::new(x, y),
+            NormalVector::new(0.0, 0.0, 1.0, 1.0),
+        );
+        let mesh = Mesh::NormalMesh(vec!(vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)));
+        Model{
+            submeshes: vec!(SubMesh::new("triangle", 0, 3, None)),
+            ..Model::new("triangle", mesh, vec!(0, 1, 2))
+        }
+    }
+
+    //the normal the winding implies, has to agree with the stored one
+    fn winding_normal(model: &Model) -> [f32; 3] {
+        let v = model.get_vertices().unwrap();
+        let p: Vec<[f32; 3]> = model.indeces.iter()
+            .map(|i| v[*i as usize].pos)
+            .map(|p| [p.x, p.y, p.z])
+            .collect();
+        normalize(&cross(&sub(&p[1], &p[0]), &sub(&p[2], &p[0])))
+    }
+
+    #[test]
+    fn conversion_z_up_to_y_up() {
+        let mut model = triangle();
+        let conversion = Conversion::z_up_to_y_up();
+        assert!(!conversion.flips_handedness(), "a rotation");
+        apply_conversion(&mut model, &conversion).unwrap();
+        let v = model.get_vertices().unwrap();
+        //source +y (forward) ends up along -z, source +z (up) along +y
+        assert_eq!([v[2].pos.x, v[2].pos.y, v[2].pos.z], [0.0, 0.0, -1.0]);
+        assert_eq!([v[0].norm.x, v[0].norm.y, v[0].norm.z], [0.0, 1.0, 0.0]);
+        assert_eq!(model.indeces, vec!(0, 1, 2), "winding untouched");
+        assert_eq!(winding_normal(&model), [0.0, 1.0, 0.0]);
+        assert_eq!(model.conversion, conversion, "recorded");
+        //and back again
+        apply_conversion(&mut model, &Conversion::y_up_to_z_up()).unwrap();
+        assert!(model.conversion.is_identity(), "{:?}", model.conversion);
+        assert_eq!(model.get_vertices().unwrap()[2].pos.y, 1.0);
+    }
+
+    #[test]
+    fn conversion_handedness_fixes_winding() {
+        let mut model = triangle();
+        let conversion = Conversion::IDENTITY.with_handedness_flip();
+        assert!(conversion.flips_handedness());
+        apply_conversion(&mut model, &conversion).unwrap();
+        let v = model.get_vertices().unwrap();
+        assert_eq!(v[0].norm.z, -1.0, "normal mirrored");
+        assert_eq!(model.indeces, vec!(0, 2, 1), "winding swapped");
+        assert_eq!(winding_normal(&model), [0.0, 0.0, -1.0], "winding agrees with the normal");
+        //swapping two axes is a mirror too
+        let swap = Conversion::new([Axis::Y, Axis::X, Axis::Z], None, false).unwrap();
+        assert!(swap.flips_handedness());
+        assert!(!swap.then(&conversion).flips_handedness(), "two mirrors are a rotation");
+    }
+
+    #[test]
+    fn conversion_units_and_uvs() {
+        let mut model = triangle();
+        let conversion = Conversion::IDENTITY
+            .with_units(Unit::Centimeters, Unit::Meters)
+            .with_flip_v();
+        apply_conversion(&mut model, &conversion).unwrap();
+        let v = model.get_vertices().unwrap();
+        assert!((v[1].pos.x - 0.01).abs() < 1.0e-7, "{}", v[1].pos.x);
+        assert_eq!(v[0].pos.w, 1.0, "w isn't scaled");
+        assert_eq!([v[0].uv.y, v[2].uv.y], [1.0, 0.0], "v flipped");
+        assert_eq!(v[1].uv.x, 1.0, "u untouched");
+        assert_eq!(model.conversion, conversion);
+    }
+
+    #[test]
+    fn conversion_tangent_handedness() {
+        //converting the tangents has to match regenerating them after converting
+        let conversion = Conversion::blender_to_vulkan().with_handedness_flip();
+        let mut converted = triangle();
+        generate_tangents(&mut converted).unwrap();
+        apply_conversion(&mut converted, &conversion).unwrap();
+        let mut regenerated = triangle();
+        apply_conversion(&mut regenerated, &conversion).unwrap();
+        generate_tangents(&mut regenerated).unwrap();
+        let (a, b) = match (&converted.mesh, &regenerated.mesh) {
+            (Mesh::TangentMesh(a), Mesh::TangentMesh(b)) => (a, b),
+            _ => panic!("expected TangentMeshes"),
+        };
+        for (x, y) in a.iter().zip(b.iter()) {
+            let (t, u) = (x.tangent, y.tangent);
+            for (c, d) in [(t.x, u.x), (t.y, u.y), (t.z, u.z), (t.w, u.w)] {
+                assert!((c - d).abs() < 1.0e-5, "tangent {c} != {d}");
+            }
+        }
+    }
+
+    #[test]
+    fn conversion_bad_settings() {
+        assert!(Conversion::new([Axis::X, Axis::NegX, Axis::Z], None, false).is_err());
+        assert!(Conversion::new([Axis::X, Axis::Y, Axis::Z], Some(0.0), false).is_err());
+    }
+}
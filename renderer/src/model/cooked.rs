@@ -0,0 +1,1347 @@
+/* The cooked model format, .rmdl, what import-model writes and the renderer loads
+ * without going through an importer again. Every number is in the endianness given
+ * in the header, written in the writer's native order so the common case reads without
+ * swapping. Strings are a u32 byte length followed by that many bytes of utf8.
+ *
+ * header, 24 bytes
+ *    0  [u8; 4]  magic, "RMDL"
+ *    4  u16      version
+ *    6  u8       endianness, 1 little, 2 big
+ *    7  u8       index width in bytes, 2 if every index fits, otherwise 4
+ *    8  u32      vertex count
+ *   12  u32      index count
+ *   16  u32      submesh count
+ *   20  u32      material count
+ * name           string
+ * conversion     3 x u8 axes (0 x, 1 -x, 2 y, 3 -y, 4 z, 5 -z), u8 flip v, f32 scale
+ * vertex layout  u32 stride, u32 attribute count, then per attribute
+ *                u8 semantic (0 position, 1 uv, 2 normal, 3 color, 4 tangent),
+ *                u8 f32 component count, u16 byte offset into the vertex
+ * submesh table  per submesh, u32 first index, u32 index count,
+ *                u32 material (u32::MAX for none), string name
+ * material table per material, string name, 4 x f32 base color, f32 metallic,
+ *                f32 roughness, 3 x f32 emissive, u8 alpha mode (0 opaque, 1 mask,
+ *                2 blend), f32 alpha cutoff, u8 double sided, u8 texture slots, a bit per
+ *                slot in the order base color, metallic roughness, normal, occlusion,
+ *                emissive, then per slot string location (empty for none), u32 uv set,
+ *                u8 mag, min and mipmap filter (0 nearest, 1 linear), u8 u and v wrap
+ *                (0 repeat, 1 mirrored repeat, 2 clamp to edge), f32 scale, and the
+ *                texture, u32 width, u32 height, width * height rgba8 texels, since
+ *                version 6 followed by u32 mip count and each mip's rgba8 texels,
+ *                mip i is width >> i by height >> i, each at least 1. Since version 7
+ *                the texture starts with u8 encoded, 0 for the above, 1 for
+ *                u8 format (0 rgba8, 1 rgba8 srgb, 2 bc1, 3 bc1 srgb, 4 bc3,
+ *                5 bc3 srgb, 6 bc5, 7 bc7, 8 bc7 srgb, since version 8 9 rgba16f,
+ *                10 rgba32f), u32 width, u32 height, u32 array layers, u32 faces (1, or
+ *                6 for a cubemap), u32 level count, then each level, every layer's faces
+ *                in turn, in whole 4 x 4 blocks when the format is compressed, the float
+ *                formats' halfs and floats in the file's endianness
+ *                before version 5 only the name and a base color texture, string
+ *                location and the texture
+ * bounds table   since version 3, the model's bounds then each submesh's, each one
+ *                3 x f32 box min, 3 x f32 box max, 3 x f32 sphere center, f32 radius
+ * lod table      since version 2, u32 level count, then per level f32 error,
+ *                u32 index count, and per submesh u32 first index, u32 index count
+ * meshlet table  since version 4, u32 meshlet count, u32 meshlet vertex count, u32
+ *                meshlet triangle count, then per meshlet u32 submesh, u32 vertex offset,
+ *                u32 vertex count, u32 triangle offset, u32 triangle count,
+ *                3 x f32 sphere center, f32 radius, 3 x f32 cone axis, f32 cone cutoff,
+ *                then the meshlet vertices as u32s and the triangles as 3 x u8s
+ * quantized      since version 9, u8 1 when there's a quantized copy of the vertices,
+ *                otherwise 0 and nothing else, then u32 stride, u32 attribute count, per
+ *                attribute u8 semantic, u8 component type (0 f32, 1 f16, 2 unorm16,
+ *                3 snorm16, 4 unorm8), u8 component count, u16 byte offset into the
+ *                vertex, then the Dequantize push constants, 12 x f32
+ * vertex data    padded to a 16 byte boundary, vertex count * stride bytes, f32s
+ * index data     padded to a 16 byte boundary, index count * index width bytes, then
+ *                the indeces of each level of detail in the same width
+ * quantized data since version 9 when there's a quantized copy, padded to a 16 byte
+ *                boundary, vertex count * quantized stride bytes, every component in
+ *                the file's endianness, drawn with the same indeces as the vertex data
+ *
+ * Readers check everything against the header and the data before building the Model,
+ * a bad file is an error, never a panic or an out of range index at draw time.
+ */
+
+use std::{
+    io::Write,
+    ops::Range,
+    sync::Arc,
+};
+
+use super::{
+    bounds::{Aabb, BoundingSphere, Bounds},
+    conversion::{Axis, Conversion},
+    layout::{
+        layout_stride, mesh_layout, ComponentType, Semantic, VertexAttribute, VertexLayout,
+        MESH_LAYOUTS,
+    },
+    lod::Lod,
+    material::{
+        slot_texture, AlphaMode, Material, SamplerSettings, Texture, TextureFilter, TextureImage,
+        TextureSlot, TextureSlotKind, TextureWrap,
+    },
+    texture_format::{EncodedTexture, TextureFormat},
+    meshlet::{Meshlet, Meshlets},
+    mipmap::{mip_level_count, mip_size},
+    optimize::{optimize_order, OptimizationReport, OptimizeSettings},
+    quantize::{quantized_indeces, Dequantize, QuantizationReport, QuantizedMesh},
+    ColorVertex, IndexCoord, Mesh, Model, NormalVertex, PositionVector, PostionVertex,
+    SubMesh, TangentVertex, TextureVector, TextureVertex,
+};
+
+use crate::RenderResult;
+
+pub const RMDL_MAGIC: &[u8; 4] = b"RMDL";
+pub const RMDL_VERSION: u16 = 9;
+//the oldest version still read, 1 has no lod table, before 3 the bounds are computed
+//from the vertices as the file is read, before 5 materials are only a texture, before 6
+//textures have no mips, before 7 they can't be encoded, before 8 they can't be floats,
+//before 9 there's no quantized copy
+const RMDL_MIN_VERSION: u16 = 1;
+
+const RMDL_LITTLE_ENDIAN: u8 = 1;
+const RMDL_BIG_ENDIAN: u8 = 2;
+const RMDL_NO_MATERIAL: u32 = u32::MAX;
+const RMDL_DATA_ALIGNMENT: usize = 16;
+
+//textures are little endian in memory, a no-op for the 8 bit formats
+fn swap_components(data: &mut [u8], size: usize) {
+    if size > 1 {
+        data.chunks_exact_mut(size).for_each(|c| c.reverse());
+    }
+}
+
+fn mesh_len(mesh: &Mesh) -> usize {
+    match mesh {
+        Mesh::PositionMesh(vertices) => vertices.len(),
+        Mesh::TextureMesh(vertices) => vertices.len(),
+        Mesh::NormalMesh(vertices) => vertices.len(),
+        Mesh::ColorMesh(vertices) => vertices.len(),
+        Mesh::TangentMesh(vertices) => vertices.len(),
+    }
+}
+
+//a quantized vertex with each attribute's components in the other byte order
+fn swap_quantized(vertex: &mut [u8], layout: &[VertexAttribute]) {
+    for attribute in layout {
+        let range = attribute.offset as usize..attribute.offset as usize + attribute.size();
+        swap_components(&mut vertex[range], attribute.component_type.size());
+    }
+}
+
+//every component of every vertex, in layout order
+pub(crate) fn mesh_floats(mesh: &Mesh) -> Vec<f32> {
+    let v4 = |v: &PositionVector| [v.x, v.y, v.z, v.w];
+    let v2 = |v: &TextureVector| [v.x, v.y];
+    match mesh {
+        Mesh::PositionMesh(vertices) => vertices.iter().flat_map(|v| v4(&v.0)).collect(),
+        Mesh::TextureMesh(vertices) => vertices.iter()
+            .flat_map(|v| v4(&v.pos).into_iter().chain(v2(&v.uv)))
+            .collect(),
+        Mesh::NormalMesh(vertices) => vertices.iter()
+            .flat_map(|v| v4(&v.pos).into_iter().chain(v2(&v.uv)).chain(v4(&v.norm)))
+            .collect(),
+        Mesh::ColorMesh(vertices) => vertices.iter()
+            .flat_map(|v| {
+                v4(&v.pos).into_iter().chain(v2(&v.uv)).chain(v4(&v.norm)).chain(v4(&v.color))
+            })
+            .collect(),
+        Mesh::TangentMesh(vertices) => vertices.iter()
+            .flat_map(|v| {
+                v4(&v.pos).into_iter().chain(v2(&v.uv)).chain(v4(&v.norm))
+                    .chain(v4(&v.tangent))
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn mesh_from_floats(layout: &[VertexAttribute], floats: &[f32]) -> Mesh {
+    let stride = layout_stride(layout) / 4;
+    let vertices = floats.chunks_exact(stride);
+    let v4 = |f: &[f32]| PositionVector::new(f[0], f[1], f[2], f[3]);
+    let v2 = |f: &[f32]| TextureVector::new(f[0], f[1]);
+    if layout == PostionVertex::ATTRIBUTES {
+        Mesh::PositionMesh(vertices.map(|f| PostionVertex(v4(f))).collect())
+    } else if layout == TextureVertex::ATTRIBUTES {
+        Mesh::TextureMesh(vertices.map(|f| TextureVertex{
+            pos: v4(f),
+            uv: v2(&f[4..]),
+        }).collect())
+    } else if layout == NormalVertex::ATTRIBUTES {
+        Mesh::NormalMesh(vertices.map(|f| {
+            NormalVertex::new(v4(f), v2(&f[4..]), v4(&f[6..]))
+        }).collect())
+    } else if layout == ColorVertex::ATTRIBUTES {
+        Mesh::ColorMesh(vertices.map(|f| ColorVertex{
+            pos: v4(f),
+            uv: v2(&f[4..]),
+            norm: v4(&f[6..]),
+            color: v4(&f[10..]),
+        }).collect())
+    } else {
+        Mesh::TangentMesh(vertices.map(|f| TangentVertex{
+            pos: v4(f),
+            uv: v2(&f[4..]),
+            norm: v4(&f[6..]),
+            tangent: v4(&f[10..]),
+        }).collect())
+    }
+}
+
+const AXES: [Axis; 6] = [Axis::X, Axis::NegX, Axis::Y, Axis::NegY, Axis::Z, Axis::NegZ];
+const ALPHA_MODES: [AlphaMode; 3] = [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend];
+const FILTERS: [TextureFilter; 2] = [TextureFilter::Nearest, TextureFilter::Linear];
+const WRAPS: [TextureWrap; 3] = [
+    TextureWrap::Repeat, TextureWrap::MirroredRepeat, TextureWrap::ClampToEdge,
+];
+
+//the code of a value is its position in one of the tables above
+fn code<T: PartialEq>(table: &[T], value: &T) -> u8 {
+    table.iter().position(|v| v == value).unwrap() as u8
+}
+
+struct RmdlWriter {
+    data: Vec<u8>,
+    big_endian: bool,
+}
+
+impl RmdlWriter {
+    fn u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        let bytes = if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        self.data.extend_from_slice(&bytes);
+    }
+
+    fn u32(&mut self, v: u32) {
+        let bytes = if self.big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        self.data.extend_from_slice(&bytes);
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.u32(v.to_bits());
+    }
+
+    fn count(&mut self, v: usize, what: &str) -> RenderResult<()> {
+        let v: u32 = v.try_into().map_err(|_| format!("too many {what} for an rmdl file: {v}"))?;
+        self.u32(v);
+        Ok(())
+    }
+
+    fn string(&mut self, v: &str) -> RenderResult<()> {
+        self.count(v.len(), "string bytes")?;
+        self.data.extend_from_slice(v.as_bytes());
+        Ok(())
+    }
+
+    fn bounds(&mut self, b: &Bounds) {
+        let floats = b.aabb.min.iter()
+            .chain(b.aabb.max.iter())
+            .chain(b.sphere.center.iter())
+            .chain(std::iter::once(&b.sphere.radius));
+        floats.for_each(|f| self.f32(*f));
+    }
+
+    fn texture(&mut self, texture: &Texture) {
+        if let Some(encoded) = &texture.encoded {
+            self.u8(1);
+            self.u8(code(&TextureFormat::ALL, &encoded.format));
+            self.u32(encoded.width);
+            self.u32(encoded.height);
+            self.u32(encoded.layers);
+            self.u32(encoded.faces);
+            self.u32(encoded.level_count());
+            let size = encoded.format.component_bytes();
+            for level in encoded.levels.iter() {
+                let start = self.data.len();
+                self.data.extend_from_slice(level);
+                if self.big_endian {
+                    swap_components(&mut self.data[start..], size);
+                }
+            }
+            return;
+        }
+        self.u8(0);
+        self.u32(texture.width());
+        self.u32(texture.height());
+        self.data.extend_from_slice(texture.as_raw());
+        self.u32(texture.mips.len() as u32);
+        for mip in texture.mips.iter() {
+            self.data.extend_from_slice(mip.as_raw());
+        }
+    }
+
+    fn material(&mut self, material: &Material) -> RenderResult<()> {
+        self.string(&material.name)?;
+        material.base_color.iter().for_each(|f| self.f32(*f));
+        self.f32(material.metallic);
+        self.f32(material.roughness);
+        material.emissive.iter().for_each(|f| self.f32(*f));
+        self.u8(code(&ALPHA_MODES, &material.alpha_mode));
+        self.f32(material.alpha_cutoff);
+        self.u8(material.double_sided as u8);
+        let slots = material.textures()
+            .fold(0u8, |bits, (kind, _)| bits | (1 << code(&TextureSlotKind::ALL, &kind)));
+        self.u8(slots);
+        for (_, slot) in material.textures() {
+            self.string(slot.location.as_deref().unwrap_or(""))?;
+            self.u32(slot.uv_set);
+            let sampler = &slot.sampler;
+            for filter in [sampler.mag_filter, sampler.min_filter, sampler.mipmap_filter] {
+                self.u8(code(&FILTERS, &filter));
+            }
+            self.u8(code(&WRAPS, &sampler.wrap_u));
+            self.u8(code(&WRAPS, &sampler.wrap_v));
+            self.f32(slot.scale);
+            self.texture(&slot.texture);
+        }
+        Ok(())
+    }
+
+    fn align(&mut self) {
+        while !self.data.len().is_multiple_of(RMDL_DATA_ALIGNMENT) {
+            self.data.push(0);
+        }
+    }
+}
+
+pub fn write_model(model: &Model, out: &mut dyn Write) -> RenderResult<()> {
+    write_model_endian(model, out, cfg!(target_endian = "big"))
+}
+
+//cooking proper, triangles and vertices reordered for the GPU on the way out
+pub fn write_optimized(
+    model: &Model, out: &mut dyn Write, settings: &OptimizeSettings,
+) -> RenderResult<OptimizationReport> {
+    let optimized = optimize_order(model, settings)?;
+    let lods: Vec<&[IndexCoord]> = optimized.lods.iter().map(|l| l.as_slice()).collect();
+    write_parts(
+        model, &optimized.indeces, &lods, Some(&optimized.vertex_order), out,
+        cfg!(target_endian = "big"),
+    )?;
+    Ok(optimized.report)
+}
+
+pub(crate) fn write_model_endian(
+    model: &Model, out: &mut dyn Write, big_endian: bool,
+) -> RenderResult<()> {
+    let lods: Vec<&[IndexCoord]> = model.lods.iter().map(|l| l.indeces.as_slice()).collect();
+    write_parts(model, &model.indeces, &lods, None, out, big_endian)
+}
+
+//the model with its indeces replaced, vertex_order[new] = old
+fn write_parts(
+    model: &Model,
+    indeces: &[IndexCoord],
+    lod_indeces: &[&[IndexCoord]],
+    vertex_order: Option<&[usize]>,
+    out: &mut dyn Write,
+    big_endian: bool,
+) -> RenderResult<()> {
+    let vertex_count = mesh_len(&model.mesh);
+    let index_width: u8 = if vertex_count <= u16::MAX as usize + 1 { 2 } else { 4 };
+    let mut w = RmdlWriter{
+        data: vec![],
+        big_endian,
+    };
+    w.data.extend_from_slice(RMDL_MAGIC);
+    w.u16(RMDL_VERSION);
+    w.u8(if big_endian { RMDL_BIG_ENDIAN } else { RMDL_LITTLE_ENDIAN });
+    w.u8(index_width);
+    w.count(vertex_count, "vertices")?;
+    w.count(indeces.len(), "indeces")?;
+    w.count(model.submeshes.len(), "submeshes")?;
+    w.count(model.materials.len(), "materials")?;
+    w.string(&model.name)?;
+    for axis in model.conversion.axes.iter() {
+        w.u8(code(&AXES, axis));
+    }
+    w.u8(model.conversion.flip_v as u8);
+    w.f32(model.conversion.scale);
+    let layout = mesh_layout(&model.mesh);
+    w.count(layout_stride(layout), "vertex bytes")?;
+    w.count(layout.len(), "attributes")?;
+    for attribute in layout {
+        w.u8(attribute.semantic.code());
+        w.u8(attribute.components);
+        w.u16(attribute.offset);
+    }
+    for submesh in model.submeshes.iter() {
+        w.count(submesh.first_index, "indeces")?;
+        w.count(submesh.index_count, "indeces")?;
+        match submesh.material {
+            Some(material) => w.count(material, "materials")?,
+            None => w.u32(RMDL_NO_MATERIAL),
+        }
+        w.string(&submesh.name)?;
+    }
+    for material in model.materials.iter() {
+        w.material(material)?;
+    }
+    w.bounds(&model.bounds);
+    model.submeshes.iter().for_each(|s| w.bounds(&s.bounds));
+    w.count(model.lods.len(), "levels of detail")?;
+    for (lod, lod_indeces) in model.lods.iter().zip(lod_indeces.iter()) {
+        if lod.submeshes.len() != model.submeshes.len() {
+            return Err(format!(
+                "a level of detail has {} submeshes, the model has {}",
+                lod.submeshes.len(), model.submeshes.len(),
+            ));
+        }
+        w.f32(lod.error);
+        w.count(lod_indeces.len(), "indeces")?;
+        for submesh in lod.submeshes.iter() {
+            w.count(submesh.first_index, "indeces")?;
+            w.count(submesh.index_count, "indeces")?;
+        }
+    }
+    //meshlets refer to vertices, which may have been reordered
+    let renumbered;
+    let meshlets = match vertex_order {
+        Some(vertex_order) if !model.meshlets.is_empty() => {
+            let mut meshlets = model.meshlets.clone();
+            meshlets.renumber(vertex_order);
+            renumbered = meshlets;
+            &renumbered
+        },
+        _ => &model.meshlets,
+    };
+    w.count(meshlets.meshlets.len(), "meshlets")?;
+    w.count(meshlets.vertices.len(), "meshlet vertices")?;
+    w.count(meshlets.triangles.len(), "meshlet triangles")?;
+    for m in meshlets.meshlets.iter() {
+        for v in [m.submesh, m.vertex_offset, m.vertex_count, m.triangle_offset, m.triangle_count] {
+            w.u32(v);
+        }
+        m.sphere.center.iter().for_each(|f| w.f32(*f));
+        w.f32(m.sphere.radius);
+        m.cone_axis.iter().for_each(|f| w.f32(*f));
+        w.f32(m.cone_cutoff);
+    }
+    meshlets.vertices.iter().for_each(|v| w.u32(*v));
+    meshlets.triangles.iter().for_each(|t| w.data.extend_from_slice(t));
+    match &model.quantized {
+        Some(quantized) => {
+            let count = quantized.mesh_slices().vertex_count();
+            if count != vertex_count {
+                return Err(format!(
+                    "the quantized copy has {count} vertices, the model has {vertex_count}",
+                ));
+            }
+            w.u8(1);
+            w.count(layout_stride(&quantized.layout), "quantized vertex bytes")?;
+            w.count(quantized.layout.len(), "quantized attributes")?;
+            for attribute in quantized.layout.iter() {
+                w.u8(attribute.semantic.code());
+                w.u8(attribute.component_type.code());
+                w.u8(attribute.components);
+                w.u16(attribute.offset);
+            }
+            let d = &quantized.dequantize;
+            for f in d.position_offset.iter().chain(&d.position_scale).chain(&d.uv_offset_scale) {
+                w.f32(*f);
+            }
+        },
+        None => w.u8(0),
+    }
+    w.align();
+    let floats = mesh_floats(&model.mesh);
+    match vertex_order {
+        Some(vertex_order) => {
+            let stride = layout_stride(layout) / 4;
+            for old in vertex_order {
+                for f in &floats[old * stride..(old + 1) * stride] {
+                    w.f32(*f);
+                }
+            }
+        },
+        None => floats.into_iter().for_each(|f| w.f32(f)),
+    }
+    w.align();
+    for i in indeces.iter().chain(lod_indeces.iter().copied().flatten()) {
+        match index_width {
+            2 => w.u16(*i as u16),
+            _ => w.u32(*i),
+        }
+    }
+    if let Some(quantized) = &model.quantized {
+        w.align();
+        let stride = layout_stride(&quantized.layout);
+        let swap = big_endian != cfg!(target_endian = "big");
+        for v in 0..vertex_count {
+            let old = vertex_order.map_or(v, |order| order[v]);
+            let mut vertex = quantized.vertices[old * stride..(old + 1) * stride].to_vec();
+            if swap {
+                swap_quantized(&mut vertex, &quantized.layout);
+            }
+            w.data.extend_from_slice(&vertex);
+        }
+    }
+    out.write_all(&w.data).map_err(|e| e.to_string())
+}
+
+struct RmdlReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> RmdlReader<'a> {
+    fn bytes(&mut self, len: usize, what: &str) -> RenderResult<&'a [u8]> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(format!("rmdl file is truncated reading {what} at byte {}", self.pos))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self, what: &str) -> RenderResult<u8> {
+        Ok(self.bytes(1, what)?[0])
+    }
+
+    fn u16(&mut self, what: &str) -> RenderResult<u16> {
+        let bytes: [u8; 2] = self.bytes(2, what)?.try_into().unwrap();
+        Ok(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&mut self, what: &str) -> RenderResult<u32> {
+        let bytes: [u8; 4] = self.bytes(4, what)?.try_into().unwrap();
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn f32(&mut self, what: &str) -> RenderResult<f32> {
+        Ok(f32::from_bits(self.u32(what)?))
+    }
+
+    fn string(&mut self, what: &str) -> RenderResult<String> {
+        let len = self.u32(what)? as usize;
+        let bytes = self.bytes(len, what)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| format!("rmdl {what} is not valid utf8"))
+    }
+
+    fn bounds(&mut self, what: &str) -> RenderResult<Bounds> {
+        let mut floats = [0.0; 10];
+        for f in floats.iter_mut() {
+            *f = self.f32(what)?;
+        }
+        Ok(Bounds{
+            aabb: Aabb{
+                min: [floats[0], floats[1], floats[2]],
+                max: [floats[3], floats[4], floats[5]],
+            },
+            sphere: BoundingSphere{
+                center: [floats[6], floats[7], floats[8]],
+                radius: floats[9],
+            },
+        })
+    }
+
+    //one of the values in table, by its code
+    fn coded<T: Copy>(&mut self, table: &[T], what: &str) -> RenderResult<T> {
+        let code = self.u8(what)?;
+        table.get(code as usize).copied().ok_or(format!("rmdl {what} has an unknown code: {code}"))
+    }
+
+    fn texture(&mut self, what: &str) -> RenderResult<TextureImage> {
+        let (width, height) = (self.u32(what)?, self.u32(what)?);
+        self.texels(width, height, what)
+    }
+
+    fn texels(&mut self, width: u32, height: u32, what: &str) -> RenderResult<TextureImage> {
+        let len = (width as usize).checked_mul(height as usize)
+            .and_then(|texels| texels.checked_mul(4))
+            .ok_or(format!("rmdl {what} is too large"))?;
+        let texels = self.bytes(len, what)?;
+        TextureImage::from_raw(width, height, texels.to_vec())
+            .ok_or(format!("rmdl {what} is a bad texture"))
+    }
+
+    fn mips(&mut self, image: TextureImage, what: &str) -> RenderResult<Texture> {
+        let (width, height) = image.dimensions();
+        let count = self.u32(what)?;
+        if count >= mip_level_count(width, height) {
+            return Err(format!("rmdl {what} has {count} mips, too many for {width} x {height}"));
+        }
+        let mips = (1..=count).map(|level| {
+            let (width, height) = mip_size(width, height, level);
+            self.texels(width, height, what)
+        }).collect::<RenderResult<Vec<TextureImage>>>()?;
+        Ok(Texture{
+            image,
+            mips,
+            encoded: None,
+        })
+    }
+
+    fn encoded(&mut self, what: &str) -> RenderResult<Texture> {
+        let mut encoded = EncodedTexture{
+            format: self.coded(&TextureFormat::ALL, what)?,
+            width: self.u32(what)?,
+            height: self.u32(what)?,
+            layers: self.u32(what)?,
+            faces: self.u32(what)?,
+            levels: vec![],
+        };
+        let count = self.u32(what)?;
+        if count == 0 || count > mip_level_count(encoded.width, encoded.height) {
+            return Err(format!("rmdl {what} has {count} levels, a bad count for {} x {}",
+                encoded.width, encoded.height));
+        }
+        for level in 0..count as usize {
+            let len = encoded.level_bytes(level);
+            let mut data = self.bytes(len, what)?.to_vec();
+            if self.big_endian {
+                swap_components(&mut data, encoded.format.component_bytes());
+            }
+            encoded.levels.push(data);
+        }
+        encoded.check().map_err(|e| format!("rmdl {what} is a bad texture: {e}"))?;
+        slot_texture(encoded).map_err(|e| format!("rmdl {what}: {e}"))
+    }
+
+    fn location(&mut self, what: &str) -> RenderResult<Option<String>> {
+        let location = self.string(what)?;
+        Ok((!location.is_empty()).then_some(location))
+    }
+
+    fn material(&mut self, version: u16) -> RenderResult<Material> {
+        let what = "material table";
+        let mut material = Material::default();
+        material.name = self.string(what)?;
+        let texture_what = format!("material '{}' texture", material.name);
+        if version < 5 {
+            let location = self.location(what)?;
+            let slot = TextureSlot::new(self.texture(&texture_what)?, location);
+            material.set_texture(TextureSlotKind::BaseColor, Some(slot));
+            return Ok(material);
+        }
+        for f in material.base_color.iter_mut() {
+            *f = self.f32(what)?;
+        }
+        material.metallic = self.f32(what)?;
+        material.roughness = self.f32(what)?;
+        for f in material.emissive.iter_mut() {
+            *f = self.f32(what)?;
+        }
+        material.alpha_mode = self.coded(&ALPHA_MODES, "material alpha mode")?;
+        material.alpha_cutoff = self.f32(what)?;
+        material.double_sided = self.u8(what)? != 0;
+        let slots = self.u8(what)?;
+        for (bit, kind) in TextureSlotKind::ALL.into_iter().enumerate() {
+            if slots & (1 << bit) == 0 {
+                continue;
+            }
+            let location = self.location(what)?;
+            let uv_set = self.u32(what)?;
+            let sampler = SamplerSettings{
+                mag_filter: self.coded(&FILTERS, "sampler filter")?,
+                min_filter: self.coded(&FILTERS, "sampler filter")?,
+                mipmap_filter: self.coded(&FILTERS, "sampler filter")?,
+                wrap_u: self.coded(&WRAPS, "sampler wrap")?,
+                wrap_v: self.coded(&WRAPS, "sampler wrap")?,
+            };
+            let scale = self.f32(what)?;
+            let encoded = version >= 7 && self.coded(&[false, true], &texture_what)?;
+            let texture = if encoded {
+                self.encoded(&texture_what)?
+            } else if version >= 6 {
+                let image = self.texture(&texture_what)?;
+                self.mips(image, &texture_what)?
+            } else {
+                Texture::new(self.texture(&texture_what)?)
+            };
+            material.set_texture(kind, Some(TextureSlot{
+                texture: Arc::new(texture),
+                location,
+                uv_set,
+                sampler,
+                scale,
+            }));
+        }
+        if slots >> TextureSlotKind::ALL.len() != 0 {
+            return Err(format!("rmdl material '{}' has unknown texture slots", material.name));
+        }
+        Ok(material)
+    }
+
+    fn align(&mut self, what: &str) -> RenderResult<()> {
+        let padding = self.pos.next_multiple_of(RMDL_DATA_ALIGNMENT) - self.pos;
+        self.bytes(padding, what)?;
+        Ok(())
+    }
+}
+
+pub(crate) struct RmdlQuantized {
+    pub layout: Vec<VertexAttribute>,
+    pub dequantize: Dequantize,
+    //byte range of the data
+    pub vertices: Range<usize>,
+}
+
+pub(crate) struct RmdlLod {
+    pub error: f32,
+    pub submeshes: Vec<SubMesh>,
+    pub indeces: Range<usize>,
+}
+
+//everything but the vertex and index data, which are left where they are in the file
+pub(crate) struct RmdlContents {
+    pub name: String,
+    pub conversion: Conversion,
+    pub layout: &'static [VertexAttribute],
+    pub submeshes: Vec<SubMesh>,
+    pub materials: Vec<Material>,
+    pub big_endian: bool,
+    pub index_width: usize,
+    //byte ranges of the data
+    pub vertices: Range<usize>,
+    pub indeces: Range<usize>,
+    pub lods: Vec<RmdlLod>,
+    pub bounds: Bounds,
+    pub meshlets: Meshlets,
+    pub quantized: Option<RmdlQuantized>,
+}
+
+impl RmdlContents {
+    pub fn stride(&self) -> usize {
+        layout_stride(self.layout)
+    }
+
+    pub fn native_endian(&self) -> bool {
+        self.big_endian == cfg!(target_endian = "big")
+    }
+}
+
+fn read_index(b: &[u8], big_endian: bool) -> IndexCoord {
+    match (b.len(), big_endian) {
+        (2, false) => u16::from_le_bytes([b[0], b[1]]) as u32,
+        (2, true) => u16::from_be_bytes([b[0], b[1]]) as u32,
+        (_, false) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (_, true) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+    }
+}
+
+//validates the whole file, indeces included, without copying the vertex or index data
+pub(crate) fn read_contents(data: &[u8]) -> RenderResult<RmdlContents> {
+    if data.len() < 8 || &data[0..4] != RMDL_MAGIC {
+        return Err("not an rmdl file, bad magic number".to_string());
+    }
+    let big_endian = match data[6] {
+        RMDL_LITTLE_ENDIAN => false,
+        RMDL_BIG_ENDIAN => true,
+        e => return Err(format!("rmdl file has an unknown endianness: {e}")),
+    };
+    let mut r = RmdlReader{
+        data,
+        pos: 4,
+        big_endian,
+    };
+    let version = r.u16("version")?;
+    if !(RMDL_MIN_VERSION..=RMDL_VERSION).contains(&version) {
+        return Err(format!("unsupported rmdl version {version}, expected {RMDL_VERSION}"));
+    }
+    r.u8("endianness")?;
+    let index_width = r.u8("index width")?;
+    if index_width != 2 && index_width != 4 {
+        return Err(format!("rmdl file has an unsupported index width: {index_width}"));
+    }
+    let vertex_count = r.u32("vertex count")? as usize;
+    let index_count = r.u32("index count")? as usize;
+    let submesh_count = r.u32("submesh count")? as usize;
+    let material_count = r.u32("material count")? as usize;
+    let name = r.string("name")?;
+
+    let mut axes = [Axis::X; 3];
+    for axis in axes.iter_mut() {
+        let a = r.u8("conversion")? as usize;
+        *axis = *AXES.get(a).ok_or(format!("rmdl conversion has an unknown axis: {a}"))?;
+    }
+    let flip_v = r.u8("conversion")? != 0;
+    let scale = r.f32("conversion")?;
+    let conversion = Conversion::new(axes, Some(scale), flip_v)
+        .map_err(|e| format!("rmdl file has a bad conversion: {e}"))?;
+
+    let stride = r.u32("vertex layout")? as usize;
+    let attribute_count = r.u32("vertex layout")? as usize;
+    let mut layout: Vec<VertexAttribute> = vec![];
+    for _ in 0..attribute_count.min(data.len()) {
+        let code = r.u8("vertex layout")?;
+        let semantic = Semantic::from_code(code)
+            .ok_or(format!("rmdl vertex layout has an unknown semantic: {code}"))?;
+        let components = r.u8("vertex layout")?;
+        let offset = r.u16("vertex layout")? as usize;
+        layout.push(VertexAttribute::new(semantic, components, offset));
+    }
+    let layout = MESH_LAYOUTS
+        .into_iter()
+        .find(|l| *l == layout.as_slice() && layout_stride(l) == stride)
+        .ok_or(format!("rmdl file has an unsupported vertex layout: {layout:?}, {stride}"))?;
+
+    let mut submeshes: Vec<SubMesh> = vec![];
+    for _ in 0..submesh_count.min(data.len()) {
+        let first_index = r.u32("submesh table")? as usize;
+        let count = r.u32("submesh table")? as usize;
+        let material = match r.u32("submesh table")? {
+            RMDL_NO_MATERIAL => None,
+            m if (m as usize) < material_count => Some(m as usize),
+            m => return Err(format!("rmdl submesh uses material {m} of {material_count}")),
+        };
+        let name = r.string("submesh table")?;
+        if first_index.checked_add(count).filter(|end| *end <= index_count).is_none() {
+            return Err(format!("rmdl submesh '{name}' is out of range of {index_count} indeces"));
+        }
+        submeshes.push(SubMesh::new(&name, first_index, count, material));
+    }
+
+    let mut materials: Vec<Material> = vec![];
+    for _ in 0..material_count.min(data.len()) {
+        materials.push(r.material(version)?);
+    }
+
+    let mut bounds = Bounds::EMPTY;
+    if version >= 3 {
+        bounds = r.bounds("bounds table")?;
+        for submesh in submeshes.iter_mut() {
+            submesh.bounds = r.bounds("bounds table")?;
+        }
+    }
+
+    //index ranges within the lod's own indeces for now
+    let mut lods: Vec<(f32, usize, Vec<SubMesh>)> = vec![];
+    let lod_count = if version >= 2 { r.u32("lod table")? as usize } else { 0 };
+    for l in 0..lod_count.min(data.len()) {
+        let error = r.f32("lod table")?;
+        let count = r.u32("lod table")? as usize;
+        let mut lod_submeshes: Vec<SubMesh> = vec![];
+        for submesh in submeshes.iter() {
+            let first_index = r.u32("lod table")? as usize;
+            let index_count = r.u32("lod table")? as usize;
+            if first_index.checked_add(index_count).filter(|end| *end <= count).is_none() {
+                return Err(format!("rmdl lod {l} submesh '{}' is out of range", submesh.name));
+            }
+            lod_submeshes.push(SubMesh{
+                first_index,
+                index_count,
+                ..submesh.clone()
+            });
+        }
+        lods.push((error, count, lod_submeshes));
+    }
+
+    let meshlets = if version >= 4 {
+        read_meshlets(&mut r, vertex_count, submeshes.len())?
+    } else {
+        Meshlets::default()
+    };
+    let quantized = if version >= 9 && r.u8("quantized table")? != 0 {
+        Some(read_quantized_table(&mut r)?)
+    } else {
+        None
+    };
+
+    r.align("vertex data")?;
+    let vertex_len = vertex_count.checked_mul(stride)
+        .ok_or("rmdl vertex count is too large".to_string())?;
+    let start = r.pos;
+    r.bytes(vertex_len, "vertex data")?;
+    let vertices = start..r.pos;
+
+    r.align("index data")?;
+    let total = lods.iter().try_fold(index_count, |total, (_, count, _)| total.checked_add(*count))
+        .and_then(|total| total.checked_mul(index_width as usize))
+        .ok_or("rmdl index count is too large".to_string())?;
+    let start = r.pos;
+    let bytes = r.bytes(total, "index data")?;
+    let indeces = start..start + index_count * index_width as usize;
+    let mut lod_start = indeces.end;
+    let lods: Vec<RmdlLod> = lods.into_iter().map(|(error, count, submeshes)| {
+        let range = lod_start..lod_start + count * index_width as usize;
+        lod_start = range.end;
+        RmdlLod{
+            error,
+            submeshes,
+            indeces: range,
+        }
+    }).collect();
+    let out_of_range = bytes.chunks_exact(index_width as usize)
+        .map(|b| read_index(b, big_endian))
+        .find(|i| *i as usize >= vertex_count);
+    if let Some(i) = out_of_range {
+        return Err(format!("rmdl index {i} is out of range of {vertex_count} vertices"));
+    }
+
+    let quantized = match quantized {
+        Some((layout, dequantize)) => {
+            r.align("quantized data")?;
+            //the stride was checked against the layout, which can't be empty
+            let len = vertex_count.checked_mul(layout_stride(&layout))
+                .ok_or("rmdl vertex count is too large".to_string())?;
+            let start = r.pos;
+            r.bytes(len, "quantized data")?;
+            Some(RmdlQuantized{
+                layout,
+                dequantize,
+                vertices: start..r.pos,
+            })
+        },
+        None => None,
+    };
+
+    let mut contents = RmdlContents{
+        name,
+        conversion,
+        layout,
+        submeshes,
+        materials,
+        big_endian,
+        index_width: index_width as usize,
+        vertices,
+        indeces,
+        lods,
+        bounds,
+        meshlets,
+        quantized,
+    };
+    if version < 3 {
+        compute_contents_bounds(data, &mut contents);
+    }
+    Ok(contents)
+}
+
+fn read_meshlets(
+    r: &mut RmdlReader, vertex_count: usize, submesh_count: usize,
+) -> RenderResult<Meshlets> {
+    let meshlet_count = r.u32("meshlet table")? as usize;
+    let meshlet_vertices = r.u32("meshlet table")? as usize;
+    let meshlet_triangles = r.u32("meshlet table")? as usize;
+    let mut meshlets = Meshlets::default();
+    for m in 0..meshlet_count.min(r.data.len()) {
+        let mut header = [0; 5];
+        for v in header.iter_mut() {
+            *v = r.u32("meshlet table")?;
+        }
+        let [submesh, vertex_offset, vertex_count, triangle_offset, triangle_count] = header;
+        let mut floats = [0.0; 8];
+        for f in floats.iter_mut() {
+            *f = r.f32("meshlet table")?;
+        }
+        let fits = |offset: u32, count: u32, len: usize| {
+            (offset as usize).checked_add(count as usize).is_some_and(|end| end <= len)
+        };
+        if submesh as usize >= submesh_count.max(1)
+            || vertex_count > 256
+            || !fits(vertex_offset, vertex_count, meshlet_vertices)
+            || !fits(triangle_offset, triangle_count, meshlet_triangles) {
+            return Err(format!("rmdl meshlet {m} is out of range"));
+        }
+        meshlets.meshlets.push(Meshlet{
+            submesh,
+            vertex_offset,
+            vertex_count,
+            triangle_offset,
+            triangle_count,
+            sphere: BoundingSphere{
+                center: [floats[0], floats[1], floats[2]],
+                radius: floats[3],
+            },
+            cone_axis: [floats[4], floats[5], floats[6]],
+            cone_cutoff: floats[7],
+        });
+    }
+    for _ in 0..meshlet_vertices.min(r.data.len()) {
+        let v = r.u32("meshlet vertices")?;
+        if v as usize >= vertex_count {
+            return Err(format!("rmdl meshlet vertex {v} is out of range of {vertex_count}"));
+        }
+        meshlets.vertices.push(v);
+    }
+    let len = meshlet_triangles.checked_mul(3)
+        .ok_or("rmdl meshlet triangle count is too large".to_string())?;
+    let triangles = r.bytes(len, "meshlet triangles")?;
+    meshlets.triangles = triangles.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+    for (i, m) in meshlets.meshlets.iter().enumerate() {
+        let first = m.triangle_offset as usize;
+        let local = meshlets.triangles[first..first + m.triangle_count as usize]
+            .iter()
+            .flatten()
+            .find(|v| **v as u32 >= m.vertex_count);
+        if let Some(v) = local {
+            return Err(format!("rmdl meshlet {i} uses vertex {v} of {}", m.vertex_count));
+        }
+    }
+    Ok(meshlets)
+}
+
+fn read_quantized_table(r: &mut RmdlReader) -> RenderResult<(Vec<VertexAttribute>, Dequantize)> {
+    let stride = r.u32("quantized table")? as usize;
+    let attribute_count = r.u32("quantized table")? as usize;
+    let mut layout: Vec<VertexAttribute> = vec![];
+    for _ in 0..attribute_count.min(r.data.len()) {
+        let code = r.u8("quantized table")?;
+        let semantic = Semantic::from_code(code)
+            .ok_or(format!("rmdl quantized layout has an unknown semantic: {code}"))?;
+        let code = r.u8("quantized table")?;
+        let component_type = ComponentType::from_code(code)
+            .ok_or(format!("rmdl quantized layout has an unknown component type: {code}"))?;
+        let components = r.u8("quantized table")?;
+        let offset = r.u16("quantized table")? as usize;
+        let attribute = VertexAttribute::typed(semantic, component_type, components, offset);
+        if !(1..=4).contains(&components)
+            || offset + attribute.size() > stride
+            || layout.iter().any(|a| a.semantic == semantic) {
+            return Err(format!("rmdl quantized layout has a bad attribute: {attribute:?}"));
+        }
+        layout.push(attribute);
+    }
+    if !layout.iter().any(|a| a.semantic == Semantic::Position) || stride == 0 {
+        return Err(format!("rmdl quantized layout has no position: {layout:?}, {stride}"));
+    }
+    let mut floats = [0.0; 12];
+    for f in floats.iter_mut() {
+        *f = r.f32("quantized table")?;
+    }
+    let vec4 = |i: usize| [floats[i], floats[i + 1], floats[i + 2], floats[i + 3]];
+    Ok((layout, Dequantize{
+        position_offset: vec4(0),
+        position_scale: vec4(4),
+        uv_offset_scale: vec4(8),
+    }))
+}
+
+//the same as bounds::compute_bounds, straight from the file's validated data
+fn compute_contents_bounds(data: &[u8], contents: &mut RmdlContents) {
+    let stride = contents.stride();
+    let big_endian = contents.big_endian;
+    let float = |at: usize| -> f32 {
+        let b: [u8; 4] = data[at..at + 4].try_into().unwrap();
+        f32::from_bits(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    };
+    //every layout has an f32 position
+    let offset = contents.layout.iter()
+        .find(|a| a.semantic == Semantic::Position)
+        .map_or(0, |a| a.offset as usize);
+    let positions: Vec<[f32; 3]> = contents.vertices.clone().step_by(stride)
+        .map(|v| [0, 4, 8].map(|k| float(v + offset + k)))
+        .collect();
+    contents.bounds = Bounds::from_points(&positions);
+    let indeces = &data[contents.indeces.clone()];
+    let index_width = contents.index_width;
+    for submesh in contents.submeshes.iter_mut() {
+        let points: Vec<[f32; 3]> = indeces[submesh.first_index * index_width..]
+            .chunks_exact(index_width)
+            .take(submesh.index_count)
+            .map(|b| positions[read_index(b, big_endian) as usize])
+            .collect();
+        submesh.bounds = Bounds::from_points(&points);
+    }
+    for lod in contents.lods.iter_mut() {
+        for (s, base) in lod.submeshes.iter_mut().zip(contents.submeshes.iter()) {
+            s.bounds = base.bounds;
+        }
+    }
+}
+
+pub fn read_model(data: &[u8]) -> RenderResult<Model> {
+    let contents = read_contents(data)?;
+    let big_endian = contents.big_endian;
+    let floats: Vec<f32> = data[contents.vertices.clone()].chunks_exact(4).map(|b| {
+        let b: [u8; 4] = b.try_into().unwrap();
+        f32::from_bits(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    }).collect();
+    let read_indeces = |range: &Range<usize>| -> Vec<IndexCoord> {
+        data[range.clone()]
+            .chunks_exact(contents.index_width)
+            .map(|b| read_index(b, big_endian))
+            .collect()
+    };
+    let indeces = read_indeces(&contents.indeces);
+    let lods: Vec<Lod> = contents.lods.iter().map(|lod| Lod{
+        indeces: read_indeces(&lod.indeces),
+        submeshes: lod.submeshes.clone(),
+        error: lod.error,
+    }).collect();
+    //the report is of quantizing, it isn't stored
+    let quantized = contents.quantized.as_ref().map(|q| {
+        let stride = layout_stride(&q.layout);
+        let mut vertices = data[q.vertices.clone()].to_vec();
+        if !contents.native_endian() {
+            vertices.chunks_exact_mut(stride).for_each(|v| swap_quantized(v, &q.layout));
+        }
+        QuantizedMesh{
+            layout: q.layout.clone(),
+            indeces: quantized_indeces(&indeces, vertices.len() / stride),
+            vertices,
+            dequantize: q.dequantize,
+            report: QuantizationReport::default(),
+        }
+    });
+    Ok(Model{
+        quantized,
+        submeshes: contents.submeshes,
+        materials: contents.materials,
+        conversion: contents.conversion,
+        lods,
+        bounds: contents.bounds,
+        meshlets: contents.meshlets,
+        ..Model::new(&contents.name, mesh_from_floats(contents.layout, &floats), indeces)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use crate::{
+        importer::{
+            collada::Collada,
+            ply::Ply,
+            Importer,
+        },
+        model::{
+            block_compression::compress_textures,
+            conversion::Conversion,
+            cooked::{
+                read_model, write_model, write_model_endian, write_optimized, RMDL_VERSION,
+            },
+            layout::mesh_layout,
+            lod::{generate_lods, Lod, LodSettings},
+            material::{Texture, TextureSlot, TextureSlotKind},
+            meshlet::{generate_meshlets, MeshletSettings},
+            mipmap::{generate_mips, MipSettings},
+            optimize::OptimizeSettings,
+            quantize::{generate_quantized, PositionEncoding, QuantizeSettings},
+            tangents::generate_tangents,
+            texture_format::{EncodedTexture, TextureFormat},
+            Mesh, Model, ModelView, NormalVertex,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    fn cook(model: &Model) -> Vec<u8> {
+        let mut data = vec![];
+        write_model(model, &mut data).unwrap();
+        data
+    }
+
+    fn floats(mesh: &Mesh) -> Vec<u32> {
+        super::mesh_floats(mesh).iter().map(|f| f.to_bits()).collect()
+    }
+
+    //everything, bit for bit
+    fn assert_same_model(a: &Model, b: &Model) {
+        assert_eq!(a.name, b.name, "name");
+        assert_eq!(mesh_layout(&a.mesh), mesh_layout(&b.mesh), "layout");
+        assert_eq!(floats(&a.mesh), floats(&b.mesh), "vertices");
+        assert_eq!(a.indeces, b.indeces, "indeces");
+        assert_eq!(a.conversion, b.conversion, "conversion");
+        assert_eq!(a.submeshes.len(), b.submeshes.len(), "submesh length");
+        for (x, y) in a.submeshes.iter().zip(b.submeshes.iter()) {
+            assert_eq!(
+                (&x.name, x.first_index, x.index_count, x.material),
+                (&y.name, y.first_index, y.index_count, y.material),
+            );
+        }
+        assert_eq!(a.materials.len(), b.materials.len(), "material length");
+        for (x, y) in a.materials.iter().zip(b.materials.iter()) {
+            assert_eq!(x, y, "{} material", x.name);
+        }
+        assert_eq!(a.bounds, b.bounds, "bounds");
+        assert_eq!(a.meshlets, b.meshlets, "meshlets");
+        for (x, y) in a.submeshes.iter().zip(b.submeshes.iter()) {
+            assert_eq!(x.bounds, y.bounds, "{} bounds", x.name);
+        }
+        assert_eq!(a.lods.len(), b.lods.len(), "lod length");
+        for (x, y) in a.lods.iter().zip(b.lods.iter()) {
+            assert_eq!(x.error.to_bits(), y.error.to_bits(), "lod error");
+            assert_eq!(x.indeces, y.indeces, "lod indeces");
+            let ranges = |l: &Lod| -> Vec<(usize, usize)> {
+                l.submeshes.iter().map(|s| (s.first_index, s.index_count)).collect()
+            };
+            assert_eq!(ranges(x), ranges(y), "lod submeshes");
+        }
+        match (a.quantized(), b.quantized()) {
+            (Some(x), Some(y)) => {
+                assert_eq!(x.mesh.layout, y.mesh.layout, "quantized layout");
+                assert_eq!(x.mesh.vertices, y.mesh.vertices, "quantized vertices");
+                let indeces = (x.mesh.indeces.as_bytes(), y.mesh.indeces.as_bytes());
+                assert_eq!(indeces.0, indeces.1, "quantized indeces");
+                assert_eq!(x.dequantize, y.dequantize, "dequantize");
+            },
+            (x, y) => assert_eq!(x.is_some(), y.is_some(), "quantized"),
+        }
+    }
+
+    #[test]
+    fn cooked_round_trip() {
+        let mut collada = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        collada.conversion = Conversion::blender_to_vulkan();
+        assert_same_model(&collada, &read_model(&cook(&collada)).unwrap());
+        //a ColorMesh
+        let ply = Ply::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/ply_ascii_colors.ply"))
+            .unwrap();
+        assert!(matches!(ply.mesh, Mesh::ColorMesh(_)));
+        assert_same_model(&ply, &read_model(&cook(&ply)).unwrap());
+        //and a TangentMesh
+        generate_tangents(&mut collada).unwrap();
+        assert_same_model(&collada, &read_model(&cook(&collada)).unwrap());
+        //with levels of detail and meshlets
+        generate_lods(&mut collada, &LodSettings::default()).unwrap();
+        assert!(!collada.lods.is_empty());
+        generate_meshlets(&mut collada, &MeshletSettings::default()).unwrap();
+        assert!(!collada.meshlets.is_empty());
+        assert_same_model(&collada, &read_model(&cook(&collada)).unwrap());
+        //and mipmapped textures
+        assert!(generate_mips(&mut collada, &MipSettings::default()) > 0);
+        assert_same_model(&collada, &read_model(&cook(&collada)).unwrap());
+        //block compressed
+        assert!(compress_textures(&mut collada, TextureFormat::Bc7) > 0);
+        assert_same_model(&collada, &read_model(&cook(&collada)).unwrap());
+        //and in floats, either endianness swaps them
+        let texels: Vec<[f32; 4]> = (0..16).map(|i| [i as f32 * 100.0, -1.0, 0.5, 1.0]).collect();
+        for (kind, format) in [
+            (TextureSlotKind::Occlusion, TextureFormat::Rgba16F),
+            (TextureSlotKind::Emissive, TextureFormat::Rgba32F),
+        ] {
+            let texture = Texture::from_encoded(EncodedTexture::from_floats(format, 4, 4, &texels));
+            let slot = TextureSlot::shared(Arc::new(texture), None);
+            collada.materials[0].set_texture(kind, Some(slot));
+        }
+        assert_same_model(&collada, &read_model(&cook(&collada)).unwrap());
+        //with a quantized copy, its f16 and 16 bit components swapped too
+        for position in [PositionEncoding::Normalized, PositionEncoding::Half] {
+            generate_quantized(&mut collada, &QuantizeSettings{ position }).unwrap();
+            assert_same_model(&collada, &read_model(&cook(&collada)).unwrap());
+        }
+        let mut swapped = vec![];
+        write_model_endian(&collada, &mut swapped, cfg!(target_endian = "little")).unwrap();
+        assert_same_model(&collada, &read_model(&swapped).unwrap());
+    }
+
+    #[test]
+    fn cooked_header() {
+        let model = Ply::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/ply_ascii_cube.ply"))
+            .unwrap();
+        let data = cook(&model);
+        assert_eq!(&data[0..4], b"RMDL");
+        assert_eq!(u16::from_ne_bytes([data[4], data[5]]), RMDL_VERSION);
+        assert_eq!(data[7], 2, "small models get u16 indeces");
+        //the other endianness reads back the same
+        let mut swapped = vec![];
+        write_model_endian(&model, &mut swapped, cfg!(target_endian = "little")).unwrap();
+        assert_ne!(data, swapped);
+        assert_same_model(&model, &read_model(&swapped).unwrap());
+    }
+
+    #[test]
+    fn cooked_validation() {
+        let model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        let data = cook(&model);
+        let error = |data: &[u8]| read_model(data).err().expect("should fail");
+        assert!(error(b"OBJ1").contains("bad magic"));
+        let mut bad = data.clone();
+        bad[4..6].copy_from_slice(&(RMDL_VERSION + 1).to_ne_bytes());
+        assert!(error(&bad).contains("unsupported rmdl version"), "{}", error(&bad));
+        let mut bad = data.clone();
+        bad[7] = 3;
+        assert!(error(&bad).contains("index width"));
+        //every truncation is caught
+        for len in [8, 24, 40, data.len() / 2, data.len() - 1] {
+            assert!(read_model(&data[..len]).is_err(), "truncated to {len}");
+        }
+        //an index past the end of the vertices
+        let mut bad = data.clone();
+        let last = bad.len() - 2;
+        bad[last..].copy_from_slice(&u16::MAX.to_ne_bytes());
+        assert!(error(&bad).contains("out of range"), "{}", error(&bad));
+    }
+
+    #[test]
+    fn cooked_optimized() {
+        let mut model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        generate_lods(&mut model, &LodSettings::default()).unwrap();
+        generate_meshlets(&mut model, &MeshletSettings::default()).unwrap();
+        generate_quantized(&mut model, &QuantizeSettings::default()).unwrap();
+        let mut data = vec![];
+        let report = write_optimized(&model, &mut data, &OptimizeSettings::default()).unwrap();
+        assert!(report.after.acmr <= report.before.acmr, "{report}");
+        let cooked = read_model(&data).unwrap();
+        assert_eq!(cooked.indeces.len(), model.indeces.len());
+        //the same triangles, vertex for vertex, in the same submeshes
+        let vertices = model.get_vertices().unwrap();
+        let cooked_vertices = cooked.get_vertices().unwrap();
+        let triangle = |vertices: &[NormalVertex], t: &[u32]| -> Vec<u32> {
+            t.iter()
+                .flat_map(|i| {
+                    let v = &vertices[*i as usize];
+                    [v.pos.x, v.pos.y, v.pos.z, v.uv.x, v.uv.y, v.norm.x, v.norm.y, v.norm.z]
+                })
+                .map(|f| f.to_bits())
+                .collect()
+        };
+        //the levels of detail too
+        let levels = std::iter::once((&model.indeces, &model.submeshes, &cooked.indeces))
+            .chain(model.lods.iter().zip(cooked.lods.iter())
+                .map(|(l, c)| (&l.indeces, &l.submeshes, &c.indeces)));
+        for (indeces, submeshes, cooked_indeces) in levels {
+            assert_eq!(cooked_indeces.len(), indeces.len());
+            for s in submeshes.iter() {
+                let range = s.first_index..s.first_index + s.index_count;
+                let mut expected: Vec<Vec<u32>> = indeces[range.clone()].chunks_exact(3)
+                    .map(|t| triangle(vertices, t))
+                    .collect();
+                let mut found: Vec<Vec<u32>> = cooked_indeces[range].chunks_exact(3)
+                    .map(|t| triangle(cooked_vertices, t))
+                    .collect();
+                expected.sort();
+                found.sort();
+                assert_eq!(found, expected, "{}", s.name);
+            }
+        }
+        //meshlets keep their triangles with the vertices renumbered
+        assert_eq!(cooked.meshlets.meshlets, model.meshlets.meshlets);
+        for meshlet in model.meshlets.meshlets.iter() {
+            let expected: Vec<Vec<u32>> = model.meshlets.triangles(meshlet)
+                .map(|t| triangle(vertices, &t))
+                .collect();
+            let found: Vec<Vec<u32>> = cooked.meshlets.triangles(meshlet)
+                .map(|t| triangle(cooked_vertices, &t))
+                .collect();
+            assert_eq!(found, expected);
+        }
+        //the quantized copy is reordered with the vertices it was made from
+        let quantized = model.quantized().unwrap();
+        let cooked_quantized = cooked.quantized().unwrap();
+        let stride = quantized.mesh.stride;
+        let vertex_bits = |v: &NormalVertex| -> Vec<u32> {
+            [v.pos.x, v.pos.y, v.pos.z, v.uv.x, v.uv.y, v.norm.x, v.norm.y, v.norm.z]
+                .map(|f| f.to_bits())
+                .to_vec()
+        };
+        let expected: HashMap<Vec<u32>, &[u8]> = vertices.iter()
+            .zip(quantized.mesh.vertices.chunks_exact(stride))
+            .map(|(v, q)| (vertex_bits(v), q))
+            .collect();
+        assert_eq!(cooked_quantized.mesh.vertex_count(), cooked_vertices.len());
+        let cooked_chunks = cooked_quantized.mesh.vertices.chunks_exact(stride);
+        for (v, q) in cooked_vertices.iter().zip(cooked_chunks) {
+            assert_eq!(expected[&vertex_bits(v)], q);
+        }
+        let quantized_indeces: Vec<u32> = (0..cooked_quantized.mesh.indeces.len())
+            .filter_map(|i| cooked_quantized.mesh.indeces.get(i))
+            .collect();
+        assert_eq!(quantized_indeces, cooked.indeces);
+    }
+}
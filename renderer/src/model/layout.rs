@@ -0,0 +1,356 @@
+/* Vertex layouts, which attributes each vertex type has and where they sit, with the
+ * offsets taken from the structs themselves. The pipeline's vertex input is generated
+ * from a layout, every attribute goes to the shader location of its semantic:
+ *    0 position, 1 uv, 2 normal, 3 color, 4 tangent
+ * A shader can ask for attributes a mesh doesn't have, those are read from a second
+ * binding holding a single vertex of defaults, stepped per instance so every vertex
+ * sees the same value.
+ */
+
+use std::mem::{offset_of, size_of};
+
+use ash::vk;
+
+use super::{
+    ColorVertex, Mesh, NormalVertex, PostionVertex, TangentVertex, TextureVertex,
+};
+
+pub const VERTEX_BINDING: u32 = 0;
+pub const DEFAULTS_BINDING: u32 = 1;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Semantic {
+    Position,
+    Uv,
+    Normal,
+    Color,
+    Tangent,
+}
+
+impl Semantic {
+    pub const ALL: [Semantic; 5] = [
+        Semantic::Position, Semantic::Uv, Semantic::Normal, Semantic::Color, Semantic::Tangent,
+    ];
+
+    //also the shader input location
+    pub fn code(self) -> u8 {
+        match self {
+            Semantic::Position => 0,
+            Semantic::Uv => 1,
+            Semantic::Normal => 2,
+            Semantic::Color => 3,
+            Semantic::Tangent => 4,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    pub fn location(self) -> u32 {
+        self.code() as u32
+    }
+
+    //what a shader sees when the mesh doesn't have the attribute
+    pub fn default_value(self) -> &'static [f32] {
+        match self {
+            Semantic::Position => &[0.0, 0.0, 0.0, 1.0],
+            Semantic::Uv => &[0.0, 0.0],
+            Semantic::Normal => &[0.0, 0.0, 1.0, 0.0],
+            Semantic::Color => &[1.0, 1.0, 1.0, 1.0],
+            Semantic::Tangent => &[1.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+//how each component is stored, the normalized ones read as floats in the shader,
+//unorm in [0, 1] and snorm in [-1, 1]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ComponentType {
+    F32,
+    F16,
+    Unorm16,
+    Snorm16,
+    Unorm8,
+}
+
+impl ComponentType {
+    pub const ALL: [ComponentType; 5] = [
+        ComponentType::F32, ComponentType::F16, ComponentType::Unorm16, ComponentType::Snorm16,
+        ComponentType::Unorm8,
+    ];
+
+    pub fn code(self) -> u8 {
+        Self::ALL.iter().position(|t| *t == self).unwrap() as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            ComponentType::F32 => 4,
+            ComponentType::F16 | ComponentType::Unorm16 | ComponentType::Snorm16 => 2,
+            ComponentType::Unorm8 => 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct VertexAttribute {
+    pub semantic: Semantic,
+    pub component_type: ComponentType,
+    pub components: u8,
+    pub offset: u16,
+}
+
+impl VertexAttribute {
+    //f32 components, what every Mesh variant uses
+    pub const fn new(semantic: Semantic, components: u8, offset: usize) -> Self {
+        Self::typed(semantic, ComponentType::F32, components, offset)
+    }
+
+    pub const fn typed(
+        semantic: Semantic, component_type: ComponentType, components: u8, offset: usize,
+    ) -> Self {
+        Self{
+            semantic,
+            component_type,
+            components,
+            offset: offset as u16,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.component_type.size() * self.components as usize
+    }
+
+    pub fn format(&self) -> vk::Format {
+        match (self.component_type, self.components) {
+            (ComponentType::F32, components) => float_format(components),
+            (ComponentType::F16, 1) => vk::Format::R16_SFLOAT,
+            (ComponentType::F16, 2) => vk::Format::R16G16_SFLOAT,
+            (ComponentType::F16, _) => vk::Format::R16G16B16A16_SFLOAT,
+            (ComponentType::Unorm16, 1) => vk::Format::R16_UNORM,
+            (ComponentType::Unorm16, 2) => vk::Format::R16G16_UNORM,
+            (ComponentType::Unorm16, _) => vk::Format::R16G16B16A16_UNORM,
+            (ComponentType::Snorm16, 1) => vk::Format::R16_SNORM,
+            (ComponentType::Snorm16, 2) => vk::Format::R16G16_SNORM,
+            (ComponentType::Snorm16, _) => vk::Format::R16G16B16A16_SNORM,
+            (ComponentType::Unorm8, 1) => vk::Format::R8_UNORM,
+            (ComponentType::Unorm8, 2) => vk::Format::R8G8_UNORM,
+            (ComponentType::Unorm8, _) => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+fn float_format(components: u8) -> vk::Format {
+    match components {
+        1 => vk::Format::R32_SFLOAT,
+        2 => vk::Format::R32G32_SFLOAT,
+        3 => vk::Format::R32G32B32_SFLOAT,
+        _ => vk::Format::R32G32B32A32_SFLOAT,
+    }
+}
+
+//implemented by every vertex type, attributes in field order
+pub trait VertexLayout: Sized {
+    const ATTRIBUTES: &'static [VertexAttribute];
+
+    fn stride() -> usize {
+        size_of::<Self>()
+    }
+}
+
+impl VertexLayout for PostionVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(Semantic::Position, 4, 0),
+    ];
+}
+
+impl VertexLayout for TextureVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(Semantic::Position, 4, offset_of!(TextureVertex, pos)),
+        VertexAttribute::new(Semantic::Uv, 2, offset_of!(TextureVertex, uv)),
+    ];
+}
+
+impl VertexLayout for NormalVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(Semantic::Position, 4, offset_of!(NormalVertex, pos)),
+        VertexAttribute::new(Semantic::Uv, 2, offset_of!(NormalVertex, uv)),
+        VertexAttribute::new(Semantic::Normal, 4, offset_of!(NormalVertex, norm)),
+    ];
+}
+
+impl VertexLayout for ColorVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(Semantic::Position, 4, offset_of!(ColorVertex, pos)),
+        VertexAttribute::new(Semantic::Uv, 2, offset_of!(ColorVertex, uv)),
+        VertexAttribute::new(Semantic::Normal, 4, offset_of!(ColorVertex, norm)),
+        VertexAttribute::new(Semantic::Color, 4, offset_of!(ColorVertex, color)),
+    ];
+}
+
+impl VertexLayout for TangentVertex {
+    const ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::new(Semantic::Position, 4, offset_of!(TangentVertex, pos)),
+        VertexAttribute::new(Semantic::Uv, 2, offset_of!(TangentVertex, uv)),
+        VertexAttribute::new(Semantic::Normal, 4, offset_of!(TangentVertex, norm)),
+        VertexAttribute::new(Semantic::Tangent, 4, offset_of!(TangentVertex, tangent)),
+    ];
+}
+
+//every layout a Mesh can have
+pub const MESH_LAYOUTS: [&[VertexAttribute]; 5] = [
+    PostionVertex::ATTRIBUTES,
+    TextureVertex::ATTRIBUTES,
+    NormalVertex::ATTRIBUTES,
+    ColorVertex::ATTRIBUTES,
+    TangentVertex::ATTRIBUTES,
+];
+
+pub fn mesh_layout(mesh: &Mesh) -> &'static [VertexAttribute] {
+    match mesh {
+        Mesh::PositionMesh(_) => PostionVertex::ATTRIBUTES,
+        Mesh::TextureMesh(_) => TextureVertex::ATTRIBUTES,
+        Mesh::NormalMesh(_) => NormalVertex::ATTRIBUTES,
+        Mesh::ColorMesh(_) => ColorVertex::ATTRIBUTES,
+        Mesh::TangentMesh(_) => TangentVertex::ATTRIBUTES,
+    }
+}
+
+//attributes are packed, no padding
+pub fn layout_stride(attributes: &[VertexAttribute]) -> usize {
+    attributes.iter().map(|a| a.size()).sum()
+}
+
+//everything needed for a PipelineVertexInputStateCreateInfo, plus the contents of the
+//defaults buffer to bind at DEFAULTS_BINDING when there are any
+pub struct VertexInput {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+    pub defaults: Vec<f32>,
+}
+
+impl VertexInput {
+    //shader_inputs are the semantics the vertex shader declares
+    pub fn new(attributes: &[VertexAttribute], shader_inputs: &[Semantic]) -> Self {
+        let mut bindings = vec![vk::VertexInputBindingDescription {
+            binding: VERTEX_BINDING,
+            stride: layout_stride(attributes) as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }];
+        let mut descriptions: Vec<vk::VertexInputAttributeDescription> = attributes.iter()
+            .filter(|a| shader_inputs.contains(&a.semantic))
+            .map(|a| vk::VertexInputAttributeDescription {
+                location: a.semantic.location(),
+                binding: VERTEX_BINDING,
+                format: a.format(),
+                offset: a.offset as u32,
+            })
+            .collect();
+        let mut defaults: Vec<f32> = vec![];
+        for semantic in shader_inputs {
+            if attributes.iter().any(|a| a.semantic == *semantic) {
+                continue;
+            }
+            let value = semantic.default_value();
+            descriptions.push(vk::VertexInputAttributeDescription {
+                location: semantic.location(),
+                binding: DEFAULTS_BINDING,
+                format: float_format(value.len() as u8),
+                offset: (defaults.len() * 4) as u32,
+            });
+            defaults.extend_from_slice(value);
+        }
+        if !defaults.is_empty() {
+            bindings.push(vk::VertexInputBindingDescription {
+                binding: DEFAULTS_BINDING,
+                stride: (defaults.len() * 4) as u32,
+                input_rate: vk::VertexInputRate::INSTANCE,
+            });
+        }
+        Self{
+            bindings,
+            attributes: descriptions,
+            defaults,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use ash::vk;
+
+    use crate::model::{
+        layout::{
+            layout_stride, Semantic, VertexInput, VertexLayout, DEFAULTS_BINDING,
+            MESH_LAYOUTS, VERTEX_BINDING,
+        },
+        ColorVertex, NormalVertex, PostionVertex, TangentVertex, TextureVertex,
+    };
+
+    const TEXTURE_SHADER: &[Semantic] = &[Semantic::Position, Semantic::Uv, Semantic::Normal];
+
+    #[test]
+    fn layout_matches_structs() {
+        assert_eq!(layout_stride(PostionVertex::ATTRIBUTES), size_of::<PostionVertex>());
+        assert_eq!(layout_stride(TextureVertex::ATTRIBUTES), size_of::<TextureVertex>());
+        assert_eq!(layout_stride(NormalVertex::ATTRIBUTES), size_of::<NormalVertex>());
+        assert_eq!(layout_stride(ColorVertex::ATTRIBUTES), size_of::<ColorVertex>());
+        assert_eq!(layout_stride(TangentVertex::ATTRIBUTES), size_of::<TangentVertex>());
+        for layout in MESH_LAYOUTS {
+            //packed, in order, no attribute overlapping the next
+            let mut offset = 0;
+            for attribute in layout {
+                assert_eq!(attribute.offset as usize, offset, "{:?}", attribute.semantic);
+                offset += attribute.components as usize * 4;
+            }
+        }
+    }
+
+    #[test]
+    fn layout_vertex_input() {
+        //everything the shader wants comes from the vertex
+        let input = VertexInput::new(NormalVertex::ATTRIBUTES, TEXTURE_SHADER);
+        assert_eq!(input.bindings.len(), 1);
+        assert_eq!(input.bindings[0].stride as usize, size_of::<NormalVertex>());
+        assert!(input.defaults.is_empty());
+        let locations: Vec<(u32, u32, vk::Format)> = input.attributes.iter()
+            .map(|a| (a.location, a.offset, a.format))
+            .collect();
+        assert_eq!(locations, vec![
+            (0, 0, vk::Format::R32G32B32A32_SFLOAT),
+            (1, 16, vk::Format::R32G32_SFLOAT),
+            (2, 24, vk::Format::R32G32B32A32_SFLOAT),
+        ]);
+        //attributes the shader doesn't read are left out
+        let input = VertexInput::new(ColorVertex::ATTRIBUTES, TEXTURE_SHADER);
+        assert_eq!(input.attributes.len(), 3);
+        assert_eq!(input.bindings[0].stride as usize, size_of::<ColorVertex>());
+
+        //missing ones come from the defaults binding
+        let input = VertexInput::new(PostionVertex::ATTRIBUTES, TEXTURE_SHADER);
+        assert_eq!(input.bindings.len(), 2);
+        assert_eq!(input.bindings[1].binding, DEFAULTS_BINDING);
+        assert_eq!(input.bindings[1].input_rate, vk::VertexInputRate::INSTANCE);
+        assert_eq!(input.defaults, vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let uv = input.attributes.iter().find(|a| a.location == 1).unwrap();
+        let norm = input.attributes.iter().find(|a| a.location == 2).unwrap();
+        assert_eq!((uv.binding, uv.offset), (DEFAULTS_BINDING, 0));
+        assert_eq!((norm.binding, norm.offset), (DEFAULTS_BINDING, 8));
+        let pos = input.attributes.iter().find(|a| a.location == 0).unwrap();
+        assert_eq!(pos.binding, VERTEX_BINDING);
+        //every shader location is fed exactly once
+        for layout in MESH_LAYOUTS {
+            let input = VertexInput::new(layout, TEXTURE_SHADER);
+            let mut locations: Vec<u32> = input.attributes.iter().map(|a| a.location).collect();
+            locations.sort();
+            assert_eq!(locations, vec![0, 1, 2]);
+        }
+    }
+}
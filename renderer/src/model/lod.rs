@@ -0,0 +1,618 @@
+/* Levels of detail, simplified copies of a Model's triangles made with quadric error
+ * metrics, Garland and Heckbert, https://www.cs.cmu.edu/~garland/Papers/quadrics.pdf
+ * Edges collapse onto one of their own vertices, so every level reuses the Model's
+ * vertices and only has indeces of its own.
+ * Vertices that share a position with another vertex are on a uv or normal seam, they
+ * collapse together, each onto the vertex across the edge on its own side of the seam,
+ * and only along edges where every one of them has exactly one such vertex, so hard
+ * edges and seams move along themselves rather than across. Vertices where submeshes
+ * meet, on an open border or on a non manifold edge never move, that's what keeps
+ * material boundaries and holes where they were. A target can't always be met, when
+ * nothing is left that can collapse a level keeps what it got down to. Flat shaded
+ * triangles, every corner a vertex of its own, have nothing to collapse onto at all.
+ * Each target continues from the level before it, a ratio keeps that fraction of the
+ * Model's triangles, an error stops before the surface would move further than that.
+ * The error of a level is in model units, screen_space_error turns it into pixels for
+ * picking a level per draw.
+ */
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use super::{
+    submesh_ranges, IndexCoord, Model, SubMesh,
+};
+
+use crate::{
+    exporter::MeshAttributes,
+    RenderResult,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LodTarget {
+    //the fraction of the Model's triangles to keep
+    Ratio(f32),
+    //the furthest the surface may move, in model units
+    Error(f32),
+}
+
+#[derive(Clone, Debug)]
+pub struct LodSettings {
+    pub targets: Vec<LodTarget>,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self{
+            targets: vec![LodTarget::Ratio(0.5), LodTarget::Ratio(0.25), LodTarget::Ratio(0.125)],
+        }
+    }
+}
+
+pub struct Lod {
+    pub indeces: Vec<IndexCoord>,
+    //one per Model submesh, same names and materials, ranges into these indeces
+    pub submeshes: Vec<SubMesh>,
+    pub error: f32,
+}
+
+//how many pixels tall error looks at distance with a perspective projection
+pub fn screen_space_error(error: f32, distance: f32, fov_y: f32, viewport_height: f32) -> f32 {
+    if distance <= 0.0 {
+        return f32::MAX;
+    }
+    error / (2.0 * distance * (fov_y / 2.0).tan()) * viewport_height
+}
+
+//the coarsest level that stays under max_pixels, 0 is the Model itself, lod i is i + 1
+pub fn select_lod(
+    errors: &[f32], distance: f32, fov_y: f32, viewport_height: f32, max_pixels: f32,
+) -> usize {
+    errors.iter()
+        .rposition(|e| screen_space_error(*e, distance, fov_y, viewport_height) <= max_pixels)
+        .map_or(0, |i| i + 1)
+}
+
+//symmetric 4x4, the upper triangle
+#[derive(Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(a: [f64; 3], b: [f64; 3], c: [f64; 3]) -> Self {
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        if length == 0.0 {
+            return Self::default();
+        }
+        let [x, y, z] = n.map(|n| n / length);
+        let d = -(x * a[0] + y * a[1] + z * a[2]);
+        Self([x * x, x * y, x * z, x * d, y * y, y * z, y * d, z * z, z * d, d * d])
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for i in 0..10 {
+            self.0[i] += other.0[i];
+        }
+    }
+
+    //squared distance to every plane summed up
+    fn error(&self, p: [f64; 3]) -> f64 {
+        let q = &self.0;
+        let [x, y, z] = p;
+        let e = q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9];
+        e.max(0.0)
+    }
+}
+
+//u collapses onto v, cheapest first out of the heap
+struct Collapse {
+    cost: f64,
+    u: usize,
+    v: usize,
+    stamp: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost).then(other.u.cmp(&self.u))
+    }
+}
+
+fn normal(p: [[f64; 3]; 3]) -> [f64; 3] {
+    let u = [p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2]];
+    let v = [p[2][0] - p[0][0], p[2][1] - p[0][1], p[2][2] - p[0][2]];
+    [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+//vertices sharing a position are one group, groups are what collapse, every vertex in
+//the group moving onto the vertex of the other group across the edge from it
+struct Simplifier {
+    group: Vec<usize>,
+    members: Vec<Vec<usize>>,
+    //of each group
+    positions: Vec<[f64; 3]>,
+    triangles: Vec<[usize; 3]>,
+    //which submesh each triangle belongs to
+    triangle_submesh: Vec<usize>,
+    alive: Vec<bool>,
+    alive_count: usize,
+    //of each vertex
+    vertex_triangles: Vec<Vec<usize>>,
+    //the rest are of each group
+    quadrics: Vec<Quadric>,
+    locked: Vec<bool>,
+    stamps: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+    error: f64,
+}
+
+impl Simplifier {
+    //ranges from submesh_ranges, whole triangles within the indeces
+    fn new(model: &Model, ranges: &[std::ops::Range<usize>]) -> RenderResult<Self> {
+        let vertex_positions = MeshAttributes::new(&model.mesh).positions;
+        let vertex_count = vertex_positions.len();
+        let mut triangles: Vec<[usize; 3]> = vec![];
+        let mut triangle_submesh: Vec<usize> = vec![];
+        for (s, range) in ranges.iter().enumerate() {
+            for t in model.indeces[range.clone()].chunks_exact(3) {
+                if let Some(i) = t.iter().find(|i| **i as usize >= vertex_count) {
+                    return Err(format!("index {i} is out of range of {vertex_count} vertices"));
+                }
+                triangles.push([t[0] as usize, t[1] as usize, t[2] as usize]);
+                triangle_submesh.push(s);
+            }
+        }
+
+        let mut groups: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut members: Vec<Vec<usize>> = vec![];
+        let mut positions: Vec<[f64; 3]> = vec![];
+        let group: Vec<usize> = vertex_positions.iter().enumerate().map(|(i, p)| {
+            let g = *groups.entry(p.map(|c| c.to_bits())).or_insert(members.len());
+            if g == members.len() {
+                members.push(vec![]);
+                positions.push(p.map(|c| c as f64));
+            }
+            members[g].push(i);
+            g
+        }).collect();
+        let group_count = members.len();
+
+        let mut vertex_triangles: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+        let mut quadrics = vec![Quadric::default(); group_count];
+        let mut locked = vec![false; group_count];
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut group_submesh: Vec<Option<usize>> = vec![None; group_count];
+        for (t, triangle) in triangles.iter().enumerate() {
+            let corners = triangle.map(|v| group[v]);
+            let plane = Quadric::plane(
+                positions[corners[0]], positions[corners[1]], positions[corners[2]],
+            );
+            for (k, g) in corners.iter().enumerate() {
+                vertex_triangles[triangle[k]].push(t);
+                quadrics[*g].add(&plane);
+                let submesh = triangle_submesh[t];
+                if *group_submesh[*g].get_or_insert(submesh) != submesh {
+                    locked[*g] = true;
+                }
+                let h = corners[(k + 1) % 3];
+                *edges.entry((*g.min(&h), *g.max(&h))).or_insert(0) += 1;
+            }
+        }
+        //open borders and non manifold edges, a seam's two sides still make two triangles
+        for ((a, b), count) in edges {
+            if count != 2 {
+                locked[a] = true;
+                locked[b] = true;
+            }
+        }
+        let alive_count = triangles.len();
+        let mut simplifier = Self{
+            group,
+            members,
+            positions,
+            alive: vec![true; triangles.len()],
+            triangles,
+            triangle_submesh,
+            alive_count,
+            vertex_triangles,
+            quadrics,
+            locked,
+            stamps: vec![0; group_count],
+            heap: BinaryHeap::new(),
+            error: 0.0,
+        };
+        for u in 0..group_count {
+            simplifier.push_best(u);
+        }
+        Ok(simplifier)
+    }
+
+    fn corners(&self, t: usize) -> [usize; 3] {
+        self.triangles[t].map(|v| self.group[v])
+    }
+
+    //the living triangles using the group
+    fn group_triangles(&self, u: usize) -> Vec<usize> {
+        let mut triangles: Vec<usize> = self.members[u].iter()
+            .flat_map(|m| self.vertex_triangles[*m].iter().copied())
+            .filter(|t| self.alive[*t])
+            .collect();
+        triangles.sort_unstable();
+        triangles.dedup();
+        triangles
+    }
+
+    fn neighbors(&self, u: usize) -> Vec<usize> {
+        let mut neighbors: Vec<usize> = self.group_triangles(u).into_iter()
+            .flat_map(|t| self.corners(t))
+            .filter(|g| *g != u)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    //the vertex of v each vertex of u becomes, the one it shares a triangle with. A
+    //vertex of u on none of the triangles across the edge, or on triangles with
+    //different vertices of v, would need its attributes made up, so the seam stays put
+    fn targets(&self, u: usize, v: usize) -> Option<Vec<(usize, usize)>> {
+        let mut targets: Vec<(usize, usize)> = vec![];
+        for a in &self.members[u] {
+            let mut alive = self.vertex_triangles[*a].iter().filter(|t| self.alive[**t]).peekable();
+            if alive.peek().is_none() {
+                continue;
+            }
+            let mut across: Vec<usize> = alive
+                .flat_map(|t| self.triangles[*t])
+                .filter(|b| self.group[*b] == v)
+                .collect();
+            across.sort_unstable();
+            across.dedup();
+            match across[..] {
+                [b] => targets.push((*a, b)),
+                _ => return None,
+            }
+        }
+        Some(targets)
+    }
+
+    //no triangle turns by more than 60 degrees or collapses to nothing, and u and
+    //v only share the two groups across the edge, otherwise the surface would fold
+    fn valid(&self, u: usize, v: usize) -> bool {
+        if self.targets(u, v).is_none() {
+            return false;
+        }
+        let (nu, nv) = (self.neighbors(u), self.neighbors(v));
+        let shared = nu.iter().filter(|n| nv.binary_search(n).is_ok()).count();
+        let triangles = self.group_triangles(u);
+        let across = triangles.iter().filter(|t| self.corners(**t).contains(&v)).count();
+        if shared != across {
+            return false;
+        }
+        triangles.iter()
+            .map(|t| self.corners(*t))
+            .filter(|corners| !corners.contains(&v))
+            .all(|corners| {
+                let before = normal(corners.map(|g| self.positions[g]));
+                let after = normal(corners.map(|g| self.positions[if g == u { v } else { g }]));
+                let (b, a) = (dot(before, before), dot(after, after));
+                a > 0.0 && dot(before, after) > 0.5 * (a * b).sqrt()
+            })
+    }
+
+    fn cost(&self, u: usize, v: usize) -> f64 {
+        let mut q = self.quadrics[u];
+        q.add(&self.quadrics[v]);
+        q.error(self.positions[v])
+    }
+
+    fn push_best(&mut self, u: usize) {
+        self.stamps[u] += 1;
+        if self.locked[u] {
+            return;
+        }
+        let best = self.neighbors(u).into_iter()
+            .filter(|v| self.valid(u, *v))
+            .map(|v| (self.cost(u, v), v))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((cost, v)) = best {
+            self.heap.push(Collapse{
+                cost,
+                u,
+                v,
+                stamp: self.stamps[u],
+            });
+        }
+    }
+
+    fn collapse(&mut self, u: usize, v: usize, cost: f64) {
+        let targets = self.targets(u, v).unwrap_or_default();
+        for (a, b) in targets {
+            for t in std::mem::take(&mut self.vertex_triangles[a]) {
+                if !self.alive[t] {
+                    continue;
+                }
+                if self.corners(t).contains(&v) {
+                    self.alive[t] = false;
+                    self.alive_count -= 1;
+                } else {
+                    self.triangles[t] = self.triangles[t].map(|i| if i == a { b } else { i });
+                    self.vertex_triangles[b].push(t);
+                }
+            }
+        }
+        let q = self.quadrics[u];
+        self.quadrics[v].add(&q);
+        self.locked[u] = true;
+        self.error = self.error.max(cost.sqrt());
+        self.push_best(v);
+        for n in self.neighbors(v) {
+            self.push_best(n);
+        }
+    }
+
+    //collapses until the target is met or nothing can collapse
+    fn run(&mut self, target: &LodTarget, initial: usize) {
+        while let Some(next) = self.heap.pop() {
+            if next.stamp != self.stamps[next.u] || self.locked[next.u] {
+                continue;
+            }
+            let done = match target {
+                LodTarget::Ratio(r) => {
+                    self.alive_count <= (initial as f32 * r.clamp(0.0, 1.0)).ceil() as usize
+                },
+                LodTarget::Error(e) => next.cost.sqrt() > *e as f64,
+            };
+            if done {
+                self.heap.push(next);
+                return;
+            }
+            //the neighborhood may have changed since it was pushed
+            if !self.valid(next.u, next.v) {
+                self.push_best(next.u);
+                continue;
+            }
+            self.collapse(next.u, next.v, next.cost);
+        }
+    }
+
+    fn snapshot(&self, model: &Model) -> Lod {
+        let mut indeces: Vec<IndexCoord> = vec![];
+        let mut submeshes: Vec<SubMesh> = vec![];
+        let submesh_count = model.submeshes.len().max(1);
+        for s in 0..submesh_count {
+            let first_index = indeces.len();
+            for (t, triangle) in self.triangles.iter().enumerate() {
+                if self.alive[t] && self.triangle_submesh[t] == s {
+                    indeces.extend(triangle.iter().map(|i| *i as IndexCoord));
+                }
+            }
+            //a level only drops vertices, the base submesh's bounds still hold
+            if let Some(submesh) = model.submeshes.get(s) {
+                submeshes.push(SubMesh{
+                    first_index,
+                    index_count: indeces.len() - first_index,
+                    ..submesh.clone()
+                });
+            }
+        }
+        Lod{
+            indeces,
+            submeshes,
+            error: self.error as f32,
+        }
+    }
+}
+
+//a level per target, from the finest to the coarsest
+pub fn simplify(model: &Model, settings: &LodSettings) -> RenderResult<Vec<Lod>> {
+    let ranges = submesh_ranges(&model.submeshes, model.indeces.len())?;
+    let mut simplifier = Simplifier::new(model, &ranges)?;
+    let initial = simplifier.alive_count;
+    let mut lods: Vec<Lod> = vec![];
+    for target in settings.targets.iter() {
+        simplifier.run(target, initial);
+        lods.push(simplifier.snapshot(model));
+    }
+    Ok(lods)
+}
+
+pub fn generate_lods(model: &mut Model, settings: &LodSettings) -> RenderResult<()> {
+    model.lods = simplify(model, settings)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::{
+        exporter::MeshAttributes,
+        model::{
+            lod::{generate_lods, screen_space_error, select_lod, LodSettings, LodTarget},
+            primitives::{make_primitive, Primitive},
+            Mesh, Model, SubMesh,
+        },
+    };
+
+    const SIZE: u32 = 24;
+
+    //a SIZE x SIZE plane of quads facing -y, raised to y = height(x, z), the left and right
+    //halves are separate submeshes, a column in the left half has its vertices split by a
+    //uv seam
+    fn grid(height: impl Fn(f32, f32) -> f32) -> (Model, Vec<u32>, Vec<(u32, u32)>) {
+        let plane = Primitive::Plane{ size: SIZE as f32, subdivisions: SIZE };
+        let mut model = make_primitive(&plane).unwrap();
+        let Mesh::TangentMesh(vertices) = &mut model.mesh else {
+            panic!("primitives are TangentMeshes");
+        };
+        for v in vertices.iter_mut() {
+            v.pos.y = height(v.pos.x, v.pos.z);
+        }
+        //a second copy of a column for the seam, used by the triangles right of it
+        let seam_x = -(SIZE as f32) / 4.0;
+        let mut seam: HashMap<u32, u32> = HashMap::new();
+        for i in 0..vertices.len() {
+            if vertices[i].pos.x == seam_x {
+                let mut copy = vertices[i];
+                copy.uv.x = 0.0;
+                seam.insert(i as u32, vertices.len() as u32);
+                vertices.push(copy);
+            }
+        }
+        //the column where the submeshes meet never moves
+        let fixed: Vec<u32> = (0..vertices.len() as u32)
+            .filter(|i| vertices[*i as usize].pos.x == 0.0)
+            .collect();
+        let (mut left, mut right): (Vec<u32>, Vec<u32>) = (vec![], vec![]);
+        for t in model.indeces.chunks_exact(3) {
+            let xs = t.iter().map(|i| vertices[*i as usize].pos.x);
+            let (min, max) = xs.fold((f32::MAX, f32::MIN), |(a, b), x| (a.min(x), b.max(x)));
+            let mut t = t.to_vec();
+            if max > seam_x {
+                t.iter_mut().for_each(|i| *i = *seam.get(i).unwrap_or(i));
+            }
+            if min < 0.0 { &mut left } else { &mut right }.extend_from_slice(&t);
+        }
+        let split = left.len();
+        model.indeces = left.into_iter().chain(right).collect();
+        model.submeshes = vec![
+            SubMesh::new("left", 0, split, Some(0)),
+            SubMesh::new("right", split, model.indeces.len() - split, None),
+        ];
+        (model, fixed, seam.into_iter().collect())
+    }
+
+    fn check_lods(model: &Model, fixed: &[u32], seam: &[(u32, u32)]) {
+        let positions = MeshAttributes::new(&model.mesh).positions;
+        let edge = SIZE as f32 / 2.0;
+        let border = |i: u32| {
+            let p = positions[i as usize];
+            p[0].abs() == edge || p[2].abs() == edge
+        };
+        let borders: Vec<u32> = (0..positions.len() as u32).filter(|i| border(*i)).collect();
+        let x = |i: &u32| positions[*i as usize][0];
+        for (l, lod) in model.lods.iter().enumerate() {
+            let used: HashSet<u32> = lod.indeces.iter().copied().collect();
+            for i in fixed.iter().chain(borders.iter()) {
+                assert!(used.contains(i), "lod {l} dropped fixed vertex {i}");
+            }
+            //both sides of the seam move together, each keeping to its own side
+            for (a, b) in seam {
+                assert_eq!(used.contains(a), used.contains(b), "lod {l} split seam {a} {b}");
+            }
+            let seam_x = x(&seam[0].0);
+            let copies: HashSet<u32> = seam.iter().map(|(_, b)| *b).collect();
+            for t in lod.indeces.chunks_exact(3) {
+                let right = t.iter().any(|i| x(i) > seam_x);
+                for i in t.iter().filter(|i| x(i) == seam_x) {
+                    assert_eq!(copies.contains(i), right, "lod {l} crossed the seam");
+                }
+            }
+            assert_eq!(lod.submeshes.len(), model.submeshes.len());
+            for (s, base) in lod.submeshes.iter().zip(model.submeshes.iter()) {
+                assert_eq!((&s.name, s.material), (&base.name, base.material));
+                //every triangle stays on its own side
+                for i in &lod.indeces[s.first_index..s.first_index + s.index_count] {
+                    let inside = if s.name == "left" { x(i) <= 0.0 } else { x(i) >= 0.0 };
+                    assert!(inside, "lod {l} {} uses x {}", s.name, x(i));
+                }
+            }
+            //heightfields stay facing down
+            for t in lod.indeces.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|k| positions[t[k] as usize]);
+                let n = (b[2] - a[2]) * (c[0] - a[0]) - (b[0] - a[0]) * (c[2] - a[2]);
+                assert!(n < 0.0, "lod {l} flipped a triangle");
+            }
+        }
+    }
+
+    #[test]
+    fn lod_ratio_targets() {
+        let (mut model, fixed, seam) = grid(|_, _| 0.0);
+        let triangles = model.indeces.len() / 3;
+        generate_lods(&mut model, &LodSettings::default()).unwrap();
+        assert_eq!(model.lods.len(), 3);
+        let counts: Vec<usize> = model.lods.iter().map(|l| l.indeces.len() / 3).collect();
+        assert!(counts[0] <= triangles / 2 && counts[1] <= triangles / 4, "{counts:?}");
+        assert!(counts[2] < counts[1], "{counts:?}");
+        //a flat plane simplifies for free
+        assert!(model.lods.iter().all(|l| l.error < 1e-4));
+        check_lods(&model, &fixed, &seam);
+    }
+
+    #[test]
+    fn lod_error_targets() {
+        let (mut model, fixed, seam) = grid(|x, z| (x * 0.4).sin() * (z * 0.3).cos());
+        let settings = LodSettings{
+            targets: vec![LodTarget::Error(0.05), LodTarget::Error(0.5)],
+        };
+        generate_lods(&mut model, &settings).unwrap();
+        let (fine, coarse) = (&model.lods[0], &model.lods[1]);
+        assert!(fine.error <= 0.05 && coarse.error <= 0.5, "{} {}", fine.error, coarse.error);
+        assert!(coarse.indeces.len() < fine.indeces.len());
+        assert!(fine.indeces.len() < model.indeces.len());
+        check_lods(&model, &fixed, &seam);
+    }
+
+    #[test]
+    fn lod_hard_edges() {
+        //every vertex along the cube's edges is split between the faces meeting there
+        let cube = Primitive::Cube{ size: 2.0, subdivisions: 8 };
+        let mut model = make_primitive(&cube).unwrap();
+        let triangles = model.indeces.len() / 3;
+        generate_lods(&mut model, &LodSettings::default()).unwrap();
+        let coarsest = &model.lods[2];
+        assert!(coarsest.indeces.len() / 3 <= triangles / 8, "{}", coarsest.indeces.len() / 3);
+        //flat faces and straight edges, nothing moves off them
+        assert!(coarsest.error < 1e-4, "{}", coarsest.error);
+        let attributes = MeshAttributes::new(&model.mesh);
+        let corners: HashSet<[u32; 3]> = coarsest.indeces.iter()
+            .map(|i| attributes.positions[*i as usize].map(|c| c.to_bits()))
+            .filter(|p| p.iter().all(|c| f32::from_bits(*c).abs() == 1.0))
+            .collect();
+        assert_eq!(corners.len(), 8);
+        //every face keeps its own normal, no triangle spans two of them
+        let normals = attributes.normals.unwrap();
+        for t in coarsest.indeces.chunks_exact(3) {
+            let n: Vec<[f32; 3]> = t.iter().map(|i| normals[*i as usize]).collect();
+            assert!(n[0] == n[1] && n[1] == n[2], "{n:?}");
+        }
+    }
+
+    #[test]
+    fn lod_selection() {
+        let fov = std::f32::consts::FRAC_PI_2;
+        //at distance 1 with a 90 degree fov the viewport is 2 units tall
+        assert!((screen_space_error(0.01, 1.0, fov, 1000.0) - 5.0).abs() < 1e-3);
+        let errors = [0.001, 0.01, 0.1];
+        assert_eq!(select_lod(&errors, 1.0, fov, 1000.0, 1.0), 1);
+        assert_eq!(select_lod(&errors, 100.0, fov, 1000.0, 1.0), 3);
+        assert_eq!(select_lod(&errors, 0.01, fov, 1000.0, 1.0), 0);
+    }
+}
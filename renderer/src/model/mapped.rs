@@ -0,0 +1,197 @@
+/* Cooked models loaded without a copy. The .rmdl file is memory mapped and validated
+ * once with the same checks as read_model, after that the vertex and index data are
+ * handed out as slices straight into the map, nothing gets parsed or converted.
+ * Only files in the machine's endianness can be mapped, the others have to go through
+ * read_model, which swaps as it copies.
+ */
+
+use std::{fs::File, ops::Range};
+
+use memmap2::Mmap;
+
+use super::{
+    bounds::Bounds,
+    conversion::Conversion,
+    cooked::{read_contents, RmdlContents},
+    layout::layout_stride,
+    material::Material,
+    meshlet::Meshlets,
+    IndexSlice, LodSlices, MeshSlices, ModelView, QuantizedSlices, SubMesh,
+};
+
+use crate::RenderResult;
+
+pub struct MappedModel {
+    map: Mmap,
+    contents: RmdlContents,
+}
+
+impl MappedModel {
+    pub fn open(loc: &str) -> RenderResult<Self> {
+        let file = File::open(loc).map_err(|e| format!("can't open '{loc}': {e}"))?;
+        //the map is read only, a file truncated underneath it is the caller's problem,
+        //same as with every other memory mapped file
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| format!("can't map '{loc}': {e}"))?;
+        let contents = read_contents(&map).map_err(|e| format!("'{loc}': {e}"))?;
+        if !contents.native_endian() {
+            return Err(format!(
+                "'{loc}' was cooked with the other endianness and can't be mapped, re-cook it",
+            ));
+        }
+        Ok(Self{
+            map,
+            contents,
+        })
+    }
+
+    pub fn conversion(&self) -> &Conversion {
+        &self.contents.conversion
+    }
+
+    fn index_slice(&self, range: &Range<usize>) -> IndexSlice<'_> {
+        //maps are page aligned and the indeces start 16 byte aligned within the file,
+        //each level of detail after them keeps the index width's alignment, so the
+        //casts can't fail
+        let indeces = &self.map[range.clone()];
+        match self.contents.index_width {
+            2 => IndexSlice::U16(bytemuck::cast_slice(indeces)),
+            _ => IndexSlice::U32(bytemuck::cast_slice(indeces)),
+        }
+    }
+}
+
+impl ModelView for MappedModel {
+    fn name(&self) -> &str {
+        &self.contents.name
+    }
+
+    fn mesh_slices(&self) -> MeshSlices<'_> {
+        MeshSlices{
+            vertices: &self.map[self.contents.vertices.clone()],
+            stride: self.contents.stride(),
+            layout: self.contents.layout,
+            indeces: self.index_slice(&self.contents.indeces),
+        }
+    }
+
+    fn submeshes(&self) -> &[SubMesh] {
+        &self.contents.submeshes
+    }
+
+    fn materials(&self) -> &[Material] {
+        &self.contents.materials
+    }
+
+    fn bounds(&self) -> &Bounds {
+        &self.contents.bounds
+    }
+
+    fn meshlets(&self) -> &Meshlets {
+        &self.contents.meshlets
+    }
+
+    fn lods(&self) -> Vec<LodSlices<'_>> {
+        self.contents.lods.iter().map(|lod| LodSlices{
+            error: lod.error,
+            indeces: self.index_slice(&lod.indeces),
+            submeshes: &lod.submeshes,
+        }).collect()
+    }
+
+    fn quantized(&self) -> Option<QuantizedSlices<'_>> {
+        self.contents.quantized.as_ref().map(|quantized| QuantizedSlices{
+            mesh: MeshSlices{
+                vertices: &self.map[quantized.vertices.clone()],
+                stride: layout_stride(&quantized.layout),
+                layout: &quantized.layout,
+                indeces: self.index_slice(&self.contents.indeces),
+            },
+            dequantize: &quantized.dequantize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::Write,
+    };
+
+    use crate::{
+        importer::{
+            collada::Collada,
+            Importer,
+        },
+        model::{
+            cooked::write_model_endian,
+            lod::{generate_lods, LodSettings},
+            mapped::MappedModel,
+            quantize::{generate_quantized, QuantizeSettings},
+            IndexSlice, ModelView,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    fn write_temp(name: &str, data: &[u8]) -> String {
+        let loc = std::env::temp_dir()
+            .join(format!("{}_{name}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        File::create(&loc).unwrap().write_all(data).unwrap();
+        loc
+    }
+
+    #[test]
+    fn mapped_matches_model() {
+        let mut model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        generate_lods(&mut model, &LodSettings::default()).unwrap();
+        generate_quantized(&mut model, &QuantizeSettings::default()).unwrap();
+        let native_big = cfg!(target_endian = "big");
+        let mut data = vec![];
+        write_model_endian(&model, &mut data, native_big).unwrap();
+        let loc = write_temp("mapped.rmdl", &data);
+        let mapped = MappedModel::open(&loc).unwrap();
+        let (expected, slices) = (model.mesh_slices(), mapped.mesh_slices());
+        assert_eq!(mapped.name(), model.name);
+        assert_eq!(slices.stride, expected.stride, "stride");
+        assert_eq!(slices.vertices, expected.vertices, "vertex bytes");
+        //few enough vertices for 16 bit indeces
+        assert!(matches!(slices.indeces, IndexSlice::U16(_)));
+        assert_eq!(slices.indeces.len(), model.indeces.len());
+        for (i, index) in model.indeces.iter().enumerate() {
+            assert_eq!(slices.indeces.get(i), Some(*index));
+        }
+        assert_eq!(mapped.submeshes().len(), model.submeshes.len());
+        assert_eq!(*mapped.bounds(), model.bounds);
+        assert_eq!(mapped.materials(), &model.materials[..]);
+        let lods = mapped.lods();
+        assert_eq!(lods.len(), model.lods.len());
+        for (lod, expected) in lods.iter().zip(model.lods.iter()) {
+            assert_eq!(lod.error, expected.error);
+            assert_eq!(lod.submeshes.len(), expected.submeshes.len());
+            let indeces: Vec<_> = (0..lod.indeces.len())
+                .filter_map(|i| lod.indeces.get(i))
+                .collect();
+            assert_eq!(indeces, expected.indeces);
+        }
+        let (expected, quantized) = (model.quantized().unwrap(), mapped.quantized().unwrap());
+        assert_eq!(quantized.mesh.layout, expected.mesh.layout);
+        assert_eq!(quantized.mesh.stride, expected.mesh.stride, "quantized stride");
+        assert_eq!(quantized.mesh.vertices, expected.mesh.vertices, "quantized bytes");
+        assert_eq!(quantized.mesh.indeces.len(), model.indeces.len());
+        assert_eq!(quantized.dequantize, expected.dequantize);
+
+        //the other endianness has to be read, not mapped
+        let mut data = vec![];
+        write_model_endian(&model, &mut data, !native_big).unwrap();
+        let swapped = write_temp("mapped_swapped.rmdl", &data);
+        assert!(MappedModel::open(&swapped).is_err());
+        assert!(MappedModel::open(&format!("{TEST_DIRECTORY}/collada_scene.dae")).is_err());
+        fs::remove_file(loc).unwrap();
+        fs::remove_file(swapped).unwrap();
+    }
+}
@@ -1,46 +1,576 @@
-/* A model can have more than one material.
- * Only using textures for now, and only supporting
- * a single material for now.
- * Might have to write some sort of "(Texture|Image)Manager" or something
- * if I end up having a bunch of duplicates, good enough for now.
+/* A model can have more than one material, Model.materials is the list and each
+ * SubMesh refers to one by its index.
+ * Materials are glTF's metallic-roughness model: a base color, metallic and roughness
+ * factor, each multiplied by its texture when there is one, plus emissive, an alpha mode
+ * and double sidedness. Every texture slot has its own uv set and sampler settings.
+ * OBJ's MTL and COLLADA describe Phong materials, importers fill in a Phong and convert
+ * it with Material::from_phong.
+ * Slots hold a SharedTexture, materials using the same image share one decoded copy,
+ * texture_manager::TextureManager hands those out by location. A Texture is the image
+ * and its mip chain, see mipmap.rs, and derefs to the image, the first level.
+ * KTX2 and DDS files, textures block compressed at cook time, and float textures, .hdr
+ * and .exr, also keep the EncodedTexture the gpu is given, see texture_format.rs. Slots
+ * are sampled as 2D images, arrays and cubemaps are refused when they're loaded.
  */
 
 use std::{
     fs::{
         metadata, File
-    }, io::Read, path::PathBuf,
-    rc::{Rc, Weak},
+    }, io::Read, ops::Deref, path::{Path, PathBuf}, sync::Arc,
 };
 
-use image::RgbaImage;
+use ash::vk;
+use half::f16;
+use image::{DynamicImage, ImageFormat, RgbaImage};
 
-use crate::RenderResult;
+use crate::{
+    importer::{
+        dds::{read_dds, DDS_MAGIC},
+        ktx2::{read_ktx2, KTX2_IDENTIFIER},
+    },
+    RenderResult,
+};
+
+use super::{
+    texture_format::{EncodedTexture, TextureFormat},
+    texture_manager::TextureManager,
+};
 
 pub type TextureImage = RgbaImage;
+//decoded once, shared by every slot that uses it
+pub type SharedTexture = Arc<Texture>;
+
+pub const DEFAULT_MATERIAL_NAME: &str = "default";
+
+//how Blender's OBJ exporter turns roughness into a Phong exponent, Ns = (1 - r)^2 * 1000,
+//so models coming out of Blender convert back to what they were made with
+const MAX_SHININESS: f32 = 1000.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    //alpha below Material.alpha_cutoff is discarded, the rest is opaque
+    Mask,
+    Blend,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+impl TextureFilter {
+    pub fn vk_filter(self) -> vk::Filter {
+        match self {
+            TextureFilter::Nearest => vk::Filter::NEAREST,
+            TextureFilter::Linear => vk::Filter::LINEAR,
+        }
+    }
+
+    pub fn vk_mipmap_mode(self) -> vk::SamplerMipmapMode {
+        match self {
+            TextureFilter::Nearest => vk::SamplerMipmapMode::NEAREST,
+            TextureFilter::Linear => vk::SamplerMipmapMode::LINEAR,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureWrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+impl TextureWrap {
+    pub fn vk_address_mode(self) -> vk::SamplerAddressMode {
+        match self {
+            TextureWrap::Repeat => vk::SamplerAddressMode::REPEAT,
+            TextureWrap::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+            TextureWrap::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerSettings {
+    pub mag_filter: TextureFilter,
+    pub min_filter: TextureFilter,
+    pub mipmap_filter: TextureFilter,
+    pub wrap_u: TextureWrap,
+    pub wrap_v: TextureWrap,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self{
+            mag_filter: TextureFilter::Linear,
+            min_filter: TextureFilter::Linear,
+            mipmap_filter: TextureFilter::Linear,
+            wrap_u: TextureWrap::Repeat,
+            wrap_v: TextureWrap::Repeat,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSlotKind {
+    //rgb color, a alpha, srgb
+    BaseColor,
+    //glTF's packing, g roughness, b metallic, linear
+    MetallicRoughness,
+    //tangent space, linear
+    Normal,
+    //r, linear
+    Occlusion,
+    //rgb, srgb
+    Emissive,
+}
+
+impl TextureSlotKind {
+    pub const ALL: [TextureSlotKind; 5] = [
+        TextureSlotKind::BaseColor,
+        TextureSlotKind::MetallicRoughness,
+        TextureSlotKind::Normal,
+        TextureSlotKind::Occlusion,
+        TextureSlotKind::Emissive,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextureSlotKind::BaseColor => "base color",
+            TextureSlotKind::MetallicRoughness => "metallic roughness",
+            TextureSlotKind::Normal => "normal",
+            TextureSlotKind::Occlusion => "occlusion",
+            TextureSlotKind::Emissive => "emissive",
+        }
+    }
 
+    //base color and emissive are colors, the rest are data and stay linear
+    pub fn is_srgb(self) -> bool {
+        matches!(self, TextureSlotKind::BaseColor | TextureSlotKind::Emissive)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Texture {
+    pub image: TextureImage,
+    //the levels after image, each half the size of the one before rounded down to at
+    //least 1, down to 1 x 1. Empty when there are no mips
+    pub mips: Vec<TextureImage>,
+    //what gets uploaded when it's set, image is then its first level's first face
+    //decoded and mips are empty
+    pub encoded: Option<EncodedTexture>,
+}
+
+impl Texture {
+    pub fn new(image: TextureImage) -> Self {
+        Self{
+            image,
+            mips: vec![],
+            encoded: None,
+        }
+    }
+
+    //a single rgba8 image is as good as a plain texture, anything else stays encoded,
+    //encoded has to have passed its check
+    pub fn from_encoded(encoded: EncodedTexture) -> Self {
+        let rgba8 = !encoded.format.is_compressed() && !encoded.format.is_float();
+        if rgba8 && encoded.layers == 1 && encoded.faces == 1 {
+            let mut levels = (0..encoded.levels.len()).map(|level| encoded.decode(level, 0, 0));
+            return Self{
+                image: levels.next().expect("checked texture has no levels"),
+                mips: levels.collect(),
+                encoded: None,
+            };
+        }
+        Self{
+            image: encoded.decode(0, 0, 0),
+            mips: vec![],
+            encoded: Some(encoded),
+        }
+    }
+
+    //image, then the mips
+    pub fn levels(&self) -> impl Iterator<Item = &TextureImage> {
+        std::iter::once(&self.image).chain(self.mips.iter())
+    }
+
+    pub fn level_count(&self) -> u32 {
+        match &self.encoded {
+            Some(encoded) => encoded.level_count(),
+            None => 1 + self.mips.len() as u32,
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        match &self.encoded {
+            Some(encoded) => encoded.bytes(),
+            None => self.levels().map(|level| level.as_raw().len() as u64).sum(),
+        }
+    }
+}
+
+impl Deref for Texture {
+    type Target = TextureImage;
+
+    fn deref(&self) -> &TextureImage {
+        &self.image
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureSlot {
+    pub texture: SharedTexture,
+    //where the texture was loaded from, as written in the model file,
+    //exporters reference this instead of writing the texture back out
+    pub location: Option<String>,
+    //which uv coordinates it's sampled with, 0 is the mesh's uv
+    pub uv_set: u32,
+    pub sampler: SamplerSettings,
+    //the normal map's strength, the occlusion's, 1 for the others
+    pub scale: f32,
+}
+
+impl TextureSlot {
+    pub fn new(texture: TextureImage, location: Option<String>) -> Self {
+        Self::shared(Arc::new(Texture::new(texture)), location)
+    }
+
+    pub fn shared(texture: SharedTexture, location: Option<String>) -> Self {
+        Self{
+            texture,
+            location,
+            uv_set: 0,
+            sampler: SamplerSettings::default(),
+            scale: 1.0,
+        }
+    }
+
+    pub fn from_reader(reader: &mut dyn Read) -> RenderResult<Self> {
+        Ok(Self::shared(Arc::new(load_texture(reader)?), None))
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> RenderResult<Self> {
+        Ok(Self::shared(Arc::new(load_texture_bytes(buffer)?), None))
+    }
+}
+
+//KTX2 and DDS files are kept as they are, float images are kept as floats, anything
+//else the image crate reads is decoded to rgba8
+pub fn load_texture(reader: &mut dyn Read) -> RenderResult<Texture> {
+    let mut buffer = vec![];
+    reader.read_to_end(&mut buffer).map_err(|e| e.to_string())?;
+    load_texture_bytes(&buffer)
+}
+
+fn load_texture_bytes(buffer: &[u8]) -> RenderResult<Texture> {
+    if buffer.starts_with(KTX2_IDENTIFIER) {
+        slot_texture(read_ktx2(buffer)?)
+    } else if buffer.starts_with(DDS_MAGIC) {
+        slot_texture(read_dds(buffer)?)
+    } else {
+        let image = image::load_from_memory(buffer).map_err(|e| e.to_string())?;
+        match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let hdr = image::guess_format(buffer).ok() == Some(ImageFormat::Hdr);
+                Ok(Texture::from_encoded(float_texture(image, hdr)))
+            },
+            _ => Ok(Texture::new(image.to_rgba8())),
+        }
+    }
+}
+
+//slots are sampled as a single 2D image, arrays and cubemaps can't go in one
+pub(crate) fn slot_texture(encoded: EncodedTexture) -> RenderResult<Texture> {
+    if encoded.layers != 1 || encoded.faces != 1 {
+        return Err(format!(
+            "a material's texture has to be a single 2D image, not {} layers of {} faces",
+            encoded.layers, encoded.faces,
+        ));
+    }
+    Ok(Texture::from_encoded(encoded))
+}
+
+//Radiance's 8 bit mantissas fit in a half, as long as the values are in its range.
+//OpenEXR is as often data as color and stays in full floats
+fn float_texture(image: DynamicImage, hdr: bool) -> EncodedTexture {
+    let image = image.into_rgba32f();
+    let texels: Vec<[f32; 4]> = image.pixels().map(|texel| texel.0).collect();
+    let fits_half = texels.iter().flatten().all(|c| c.abs() <= f16::MAX.to_f32());
+    let format = if hdr && fits_half { TextureFormat::Rgba16F } else { TextureFormat::Rgba32F };
+    EncodedTexture::from_floats(format, image.width(), image.height(), &texels)
+}
+
+//the parameters OBJ's MTL and COLLADA's profile_COMMON describe, in MTL's terms
+#[derive(Clone, Debug, PartialEq)]
+pub struct Phong {
+    //Kd
+    pub diffuse: [f32; 3],
+    //Ks
+    pub specular: [f32; 3],
+    //Ns, 0 to 1000
+    pub shininess: f32,
+    //Ke
+    pub emissive: [f32; 3],
+    //d, or 1 - Tr
+    pub opacity: f32,
+    //illum, 3 and up turn on reflections
+    pub illumination: u32,
+    //the PBR extension's Pr and Pm, taken as they are when given
+    pub roughness: Option<f32>,
+    pub metallic: Option<f32>,
+}
+
+impl Default for Phong {
+    //white rather than MTL's 0.8 grey, so a material that's only a map_Kd looks like its
+    //texture
+    fn default() -> Self {
+        Self{
+            diffuse: [1.0; 3],
+            specular: [0.0; 3],
+            shininess: 0.0,
+            emissive: [0.0; 3],
+            opacity: 1.0,
+            illumination: 2,
+            roughness: None,
+            metallic: None,
+        }
+    }
+}
+
+pub fn shininess_to_roughness(shininess: f32) -> f32 {
+    1.0 - (shininess / MAX_SHININESS).clamp(0.0, 1.0).sqrt()
+}
+
+pub fn roughness_to_shininess(roughness: f32) -> f32 {
+    (1.0 - roughness.clamp(0.0, 1.0)).powi(2) * MAX_SHININESS
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Material {
-    //do i want this to be public for direct modification?
-    pub texture: TextureImage,
+    pub name: String,
+    //linear rgba
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    //linear rgb
+    pub emissive: [f32; 3],
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+    //by TextureSlotKind, see texture and set_texture
+    textures: [Option<TextureSlot>; 5],
+}
+
+impl Default for Material {
+    //glTF's defaults, but a dielectric, most of what gets imported isn't metal
+    fn default() -> Self {
+        Self{
+            name: DEFAULT_MATERIAL_NAME.to_string(),
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            textures: Default::default(),
+        }
+    }
 }
 
 impl Material {
+    //a material that's only a base color texture
     pub fn new(tex_loc: &PathBuf) -> RenderResult<Material> {
-        let mut file = File::open(&tex_loc).map_err(|e| e.to_string())?;
-        let metadata = metadata(&tex_loc).map_err(|e| e.to_string())?;
-        let mut buffer = vec![0; metadata.len() as usize];
-        file.read(&mut buffer).map_err(|e| e.to_string())?;
-        let texture = image::load_from_memory(&buffer).map_err(|e| e.to_string())?
-            .to_rgba8();
-        Ok(
-            Material{
-                texture,
-            }
-        )
-    }
-
-    /*
-    pub fn get_texture(&self) -> Weak<TextureImage> {
-        Rc::downgrade(&self.texture)
-    }
-    */
+        let mut file = File::open(tex_loc).map_err(|e| e.to_string())?;
+        let metadata = metadata(tex_loc).map_err(|e| e.to_string())?;
+        let mut buffer = Vec::with_capacity(metadata.len() as usize);
+        file.read_to_end(&mut buffer).map_err(|e| e.to_string())?;
+        let mut slot = TextureSlot::from_bytes(&buffer)?;
+        slot.location = Some(tex_loc.to_string_lossy().to_string());
+        Ok(Self::textured(slot))
+    }
+
+    //like new, but a texture already held through textures isn't decoded again
+    pub fn shared(tex_loc: &Path, textures: &TextureManager) -> RenderResult<Material> {
+        let location = tex_loc.to_string_lossy().to_string();
+        let texture = textures.get_texture_by_path(tex_loc)?;
+        Ok(Self::textured(TextureSlot::shared(texture, Some(location))))
+    }
+
+    //for textures handed over by an importer's Resolver
+    pub fn from_reader(reader: &mut dyn Read) -> RenderResult<Material> {
+        Ok(Self::textured(TextureSlot::from_reader(reader)?))
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> RenderResult<Material> {
+        Ok(Self::textured(TextureSlot::from_bytes(buffer)?))
+    }
+
+    fn textured(slot: TextureSlot) -> Material {
+        let mut material = Material::default();
+        material.set_texture(TextureSlotKind::BaseColor, Some(slot));
+        material
+    }
+
+    //roughness comes from the shininess the way Blender writes it, metals have no
+    //diffuse so without Pm a reflective material (illum 3 and up) takes its specular
+    //brightness as metallic, anything less is a dielectric
+    pub fn from_phong(name: &str, phong: &Phong) -> Material {
+        let specular = phong.specular.iter().sum::<f32>() / 3.0;
+        let reflective = if phong.illumination >= 3 { specular } else { 0.0 };
+        let metallic = phong.metallic.unwrap_or(reflective);
+        let [r, g, b] = phong.diffuse;
+        Material{
+            name: name.to_string(),
+            base_color: [r, g, b, phong.opacity.clamp(0.0, 1.0)],
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: phong.roughness.unwrap_or(shininess_to_roughness(phong.shininess))
+                .clamp(0.0, 1.0),
+            emissive: phong.emissive,
+            alpha_mode: if phong.opacity < 1.0 { AlphaMode::Blend } else { AlphaMode::Opaque },
+            ..Default::default()
+        }
+    }
+
+    pub fn texture(&self, kind: TextureSlotKind) -> Option<&TextureSlot> {
+        self.textures[kind.index()].as_ref()
+    }
+
+    pub fn texture_mut(&mut self, kind: TextureSlotKind) -> Option<&mut TextureSlot> {
+        self.textures[kind.index()].as_mut()
+    }
+
+    pub fn set_texture(&mut self, kind: TextureSlotKind, slot: Option<TextureSlot>) {
+        self.textures[kind.index()] = slot;
+    }
+
+    //the slots that have a texture, in TextureSlotKind::ALL's order
+    pub fn textures(&self) -> impl Iterator<Item = (TextureSlotKind, &TextureSlot)> {
+        TextureSlotKind::ALL.into_iter()
+            .filter_map(|kind| self.texture(kind).map(|slot| (kind, slot)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{codecs::hdr::HdrEncoder, DynamicImage, ImageFormat, Rgb, Rgb32FImage, Rgba};
+
+    use crate::exporter::ktx2::write_ktx2;
+    use crate::model::material::{
+        load_texture, roughness_to_shininess, shininess_to_roughness, AlphaMode, Material,
+        Phong, TextureSlot, TextureSlotKind, TextureImage,
+    };
+    use crate::model::texture_format::{EncodedTexture, TextureFormat};
+
+    #[test]
+    fn material_from_phong() {
+        let phong = Phong{
+            diffuse: [0.8, 0.1, 0.1],
+            shininess: 250.0,
+            emissive: [0.0, 0.5, 0.0],
+            opacity: 0.5,
+            ..Default::default()
+        };
+        let material = Material::from_phong("red", &phong);
+        assert_eq!(material.name, "red");
+        assert_eq!(material.base_color, [0.8, 0.1, 0.1, 0.5]);
+        assert_eq!(material.alpha_mode, AlphaMode::Blend);
+        assert_eq!(material.emissive, [0.0, 0.5, 0.0]);
+        assert_eq!(material.metallic, 0.0);
+        assert!((material.roughness - 0.5).abs() < 1e-6, "{}", material.roughness);
+        //Blender's mapping goes both ways
+        for roughness in [0.0, 0.25, 0.5, 1.0] {
+            let back = shininess_to_roughness(roughness_to_shininess(roughness));
+            assert!((back - roughness).abs() < 1e-6, "{roughness} {back}");
+        }
+
+        //the PBR extension wins, a reflective material is as metallic as it is specular
+        let pbr = Phong{ roughness: Some(0.3), metallic: Some(1.0), ..phong.clone() };
+        let material = Material::from_phong("pbr", &pbr);
+        assert_eq!((material.roughness, material.metallic), (0.3, 1.0));
+        let mirror = Phong{ specular: [0.9; 3], illumination: 3, opacity: 1.0, ..phong };
+        let material = Material::from_phong("mirror", &mirror);
+        assert!((material.metallic - 0.9).abs() < 1e-6);
+        assert_eq!(material.alpha_mode, AlphaMode::Opaque);
+
+        let mut material = Material::default();
+        assert_eq!(material.textures().count(), 0);
+        let slot = TextureSlot::new(TextureImage::new(1, 1), None);
+        material.set_texture(TextureSlotKind::Normal, Some(slot.clone()));
+        material.set_texture(TextureSlotKind::BaseColor, Some(slot));
+        let kinds: Vec<TextureSlotKind> = material.textures().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![TextureSlotKind::BaseColor, TextureSlotKind::Normal]);
+    }
+
+    #[test]
+    fn float_textures() {
+        //a sky, 0 to 1 on the left and a sun well past it on the right
+        let sky = Rgb32FImage::from_fn(4, 2, |x, y| match x {
+            3 => Rgb([5000.0, 4000.0, 3000.0]),
+            _ => Rgb([x as f32 / 4.0, y as f32 / 2.0, 0.25]),
+        });
+        //older image 0.25 releases only take hdr through its encoder, not write_to
+        let hdr = |image: &Rgb32FImage| {
+            let mut data = vec![];
+            let texels: Vec<Rgb<f32>> = image.pixels().copied().collect();
+            HdrEncoder::new(&mut data)
+                .encode(&texels, image.width() as usize, image.height() as usize)
+                .unwrap();
+            load_texture(&mut Cursor::new(data)).unwrap()
+        };
+        //radiance keeps 8 bits of mantissa, plenty for a half
+        let texture = hdr(&sky);
+        let encoded = texture.encoded.as_ref().unwrap();
+        assert_eq!(encoded.format, TextureFormat::Rgba16F);
+        assert_eq!(encoded.bytes(), 4 * 2 * 8);
+        let sun = encoded.decode_floats(0, 0, 0)[3];
+        assert!((sun[0] - 5000.0).abs() < 40.0 && sun[3] == 1.0, "{sun:?}");
+        //openexr is read back exactly, into floats
+        let mut data = Cursor::new(vec![]);
+        DynamicImage::ImageRgb32F(sky.clone()).write_to(&mut data, ImageFormat::OpenExr).unwrap();
+        data.set_position(0);
+        let exr = load_texture(&mut data).unwrap();
+        let encoded = exr.encoded.as_ref().unwrap();
+        assert_eq!(encoded.format, TextureFormat::Rgba32F);
+        let texels = encoded.decode_floats(0, 0, 0);
+        assert!(sky.pixels().zip(texels.iter()).all(|(a, b)| a.0 == b[..3]));
+        //the cpu side is clamped
+        assert_eq!(*exr.get_pixel(3, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*exr.get_pixel(2, 1), Rgba([128, 128, 64, 255]));
+        //and a half's range is the limit
+        let mut brighter = sky.clone();
+        brighter.put_pixel(0, 0, Rgb([1e6, 0.0, 0.0]));
+        assert_eq!(hdr(&brighter).encoded.unwrap().format, TextureFormat::Rgba32F);
+    }
+
+    #[test]
+    fn slot_textures_are_2d() {
+        let ktx2 = |layers: u32, faces: u32| {
+            let texture = EncodedTexture{
+                format: TextureFormat::Rgba8,
+                width: 2,
+                height: 2,
+                layers,
+                faces,
+                levels: vec![vec![128; 16 * (layers * faces) as usize]],
+            };
+            let mut data = vec![];
+            write_ktx2(&texture, &mut data).unwrap();
+            load_texture(&mut Cursor::new(data))
+        };
+        assert!(ktx2(1, 1).is_ok());
+        //a cubemap or an array would be bound to the shader's sampler2D
+        let error = ktx2(1, 6).expect_err("a cubemap should fail");
+        assert!(error.contains("single 2D image"), "{error}");
+        assert!(ktx2(3, 1).is_err(), "an array should fail");
+    }
 }
@@ -0,0 +1,385 @@
+/* Meshlets, small clusters of a Model's triangles for GPU driven and cluster culled
+ * drawing, each one little enough for a mesh shader workgroup (64 vertices and 124
+ * triangles by default, what most hardware is happiest with).
+ * A meshlet has its own list of Model vertices and triangles made of u8 indeces into
+ * that list, the same split meshoptimizer and the mesh shader samples use.
+ * They're built greedily one SubMesh at a time, a meshlet keeps taking the triangle
+ * next to it that adds the fewest new vertices until it's full, so every triangle
+ * lands in exactly one meshlet and no meshlet mixes materials.
+ * Each one gets a bounding sphere for frustum and occlusion culling and a normal cone
+ * for skipping clusters that face away from the camera entirely.
+ */
+
+use std::collections::HashMap;
+
+use super::{
+    bounds::BoundingSphere,
+    submesh_ranges, IndexCoord, Model,
+};
+
+use crate::{
+    exporter::MeshAttributes,
+    RenderResult,
+};
+
+#[derive(Clone, Debug)]
+pub struct MeshletSettings {
+    //at most 256, the triangles index the meshlet's vertices with a u8
+    pub max_vertices: usize,
+    pub max_triangles: usize,
+}
+
+impl Default for MeshletSettings {
+    fn default() -> Self {
+        Self{
+            max_vertices: 64,
+            max_triangles: 124,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Meshlet {
+    //the Model submesh the triangles came from, 0 for a Model without submeshes
+    pub submesh: u32,
+    //ranges of Meshlets.vertices and Meshlets.triangles
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+    pub sphere: BoundingSphere,
+    //the average facing, every triangle is within the cone around it
+    pub cone_axis: [f32; 3],
+    //the sine of the cone's half angle, 1 when it's too wide to ever cull
+    pub cone_cutoff: f32,
+}
+
+impl Meshlet {
+    //true when every triangle faces away from a camera at camera_position
+    pub fn backfacing(&self, camera_position: &[f32; 3]) -> bool {
+        if self.cone_cutoff >= 1.0 {
+            return false;
+        }
+        let d = [0, 1, 2].map(|k| self.sphere.center[k] - camera_position[k]);
+        let length = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        let along = d[0] * self.cone_axis[0] + d[1] * self.cone_axis[1] + d[2] * self.cone_axis[2];
+        along >= self.cone_cutoff * length + self.sphere.radius
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Meshlets {
+    pub meshlets: Vec<Meshlet>,
+    //Model vertex indeces, each meshlet's run of them
+    pub vertices: Vec<IndexCoord>,
+    //indeces into the meshlet's run of vertices
+    pub triangles: Vec<[u8; 3]>,
+}
+
+impl Meshlets {
+    pub fn is_empty(&self) -> bool {
+        self.meshlets.is_empty()
+    }
+
+    //the meshlet's triangles as Model vertex indeces
+    pub fn triangles(&self, meshlet: &Meshlet) -> impl Iterator<Item = [IndexCoord; 3]> + '_ {
+        let vertices = &self.vertices[meshlet.vertex_offset as usize..];
+        let first = meshlet.triangle_offset as usize;
+        self.triangles[first..first + meshlet.triangle_count as usize]
+            .iter()
+            .map(move |t| t.map(|i| vertices[i as usize]))
+    }
+
+    //after the Model's vertices were reordered, vertex_order[new] = old
+    pub(crate) fn renumber(&mut self, vertex_order: &[usize]) {
+        let mut new = vec![0; vertex_order.len()];
+        for (n, old) in vertex_order.iter().enumerate() {
+            new[*old] = n as IndexCoord;
+        }
+        self.vertices.iter_mut().for_each(|v| *v = new[*v as usize]);
+    }
+}
+
+//the meshlet being filled
+struct Building {
+    vertices: Vec<IndexCoord>,
+    local: HashMap<IndexCoord, u8>,
+    triangles: Vec<[u8; 3]>,
+}
+
+impl Building {
+    fn new() -> Self {
+        Self{
+            vertices: vec![],
+            local: HashMap::new(),
+            triangles: vec![],
+        }
+    }
+
+    fn new_vertices(&self, triangle: &[IndexCoord; 3]) -> usize {
+        let mut count = 0;
+        for (k, v) in triangle.iter().enumerate() {
+            //a triangle can use the same vertex twice
+            if !self.local.contains_key(v) && !triangle[..k].contains(v) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    fn fits(&self, triangle: &[IndexCoord; 3], settings: &MeshletSettings) -> bool {
+        self.triangles.len() < settings.max_triangles
+            && self.vertices.len() + self.new_vertices(triangle) <= settings.max_vertices
+    }
+
+    fn add(&mut self, triangle: &[IndexCoord; 3]) {
+        let local = triangle.map(|v| {
+            *self.local.entry(v).or_insert_with(|| {
+                self.vertices.push(v);
+                (self.vertices.len() - 1) as u8
+            })
+        });
+        self.triangles.push(local);
+    }
+}
+
+fn sub(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn unit_normal(p: [[f32; 3]; 3]) -> Option<[f32; 3]> {
+    let (u, v) = (sub(&p[1], &p[0]), sub(&p[2], &p[0]));
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    (length > 0.0).then(|| n.map(|c| c / length))
+}
+
+//the cone every non degenerate triangle's normal is inside of
+fn normal_cone(triangles: &[[IndexCoord; 3]], positions: &[[f32; 3]]) -> ([f32; 3], f32) {
+    let normals: Vec<[f32; 3]> = triangles.iter()
+        .filter_map(|t| unit_normal(t.map(|i| positions[i as usize])))
+        .collect();
+    let mut axis = normals.iter().fold([0.0; 3], |a, n| [a[0] + n[0], a[1] + n[1], a[2] + n[2]]);
+    let length = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+    if length == 0.0 {
+        return ([0.0, 0.0, 1.0], 1.0);
+    }
+    axis = axis.map(|c| c / length);
+    let min_dot = normals.iter()
+        .map(|n| n[0] * axis[0] + n[1] * axis[1] + n[2] * axis[2])
+        .fold(1.0f32, f32::min);
+    //past about 84 degrees from the axis there's hardly a view it culls from
+    if min_dot <= 0.1 {
+        return (axis, 1.0);
+    }
+    (axis, (1.0 - min_dot * min_dot).sqrt())
+}
+
+pub fn build_meshlets(model: &Model, settings: &MeshletSettings) -> RenderResult<Meshlets> {
+    if settings.max_vertices < 3 || settings.max_vertices > 256 || settings.max_triangles == 0 {
+        return Err(format!(
+            "meshlets need 3 to 256 vertices and at least a triangle, got {} and {}",
+            settings.max_vertices, settings.max_triangles,
+        ));
+    }
+    let positions = MeshAttributes::new(&model.mesh).positions;
+    let ranges = submesh_ranges(&model.submeshes, model.indeces.len())?;
+    let mut meshlets = Meshlets::default();
+    for (s, range) in ranges.into_iter().enumerate() {
+        let triangles: Vec<[IndexCoord; 3]> = model.indeces[range].chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        if let Some(i) = triangles.iter().flatten().find(|i| **i as usize >= positions.len()) {
+            return Err(format!("index {i} is out of range of {} vertices", positions.len()));
+        }
+        let mut vertex_triangles: HashMap<IndexCoord, Vec<usize>> = HashMap::new();
+        for (t, triangle) in triangles.iter().enumerate() {
+            for v in triangle.iter() {
+                vertex_triangles.entry(*v).or_default().push(t);
+            }
+        }
+
+        let mut used = vec![false; triangles.len()];
+        let mut next_unused = 0;
+        let mut building = Building::new();
+        let flush = |building: &mut Building, meshlets: &mut Meshlets| {
+            if building.triangles.is_empty() {
+                return;
+            }
+            let built = std::mem::replace(building, Building::new());
+            let global: Vec<[IndexCoord; 3]> = built.triangles.iter()
+                .map(|t| t.map(|i| built.vertices[i as usize]))
+                .collect();
+            let points: Vec<[f32; 3]> = built.vertices.iter()
+                .map(|v| positions[*v as usize])
+                .collect();
+            let (cone_axis, cone_cutoff) = normal_cone(&global, &positions);
+            meshlets.meshlets.push(Meshlet{
+                submesh: s as u32,
+                vertex_offset: meshlets.vertices.len() as u32,
+                vertex_count: built.vertices.len() as u32,
+                triangle_offset: meshlets.triangles.len() as u32,
+                triangle_count: built.triangles.len() as u32,
+                sphere: BoundingSphere::ritter(&points),
+                cone_axis,
+                cone_cutoff,
+            });
+            meshlets.vertices.extend(built.vertices);
+            meshlets.triangles.extend(built.triangles);
+        };
+        loop {
+            //the neighbor adding the fewest vertices, the first one on a tie
+            let neighbor = building.vertices.iter()
+                .flat_map(|v| vertex_triangles[v].iter().copied())
+                .filter(|t| !used[*t])
+                .min_by_key(|t| (building.new_vertices(&triangles[*t]), *t));
+            let next = match neighbor {
+                Some(t) => t,
+                None => {
+                    while next_unused < triangles.len() && used[next_unused] {
+                        next_unused += 1;
+                    }
+                    if next_unused == triangles.len() {
+                        break;
+                    }
+                    next_unused
+                },
+            };
+            if !building.fits(&triangles[next], settings) {
+                flush(&mut building, &mut meshlets);
+            }
+            building.add(&triangles[next]);
+            used[next] = true;
+        }
+        flush(&mut building, &mut meshlets);
+    }
+    Ok(meshlets)
+}
+
+pub fn generate_meshlets(model: &mut Model, settings: &MeshletSettings) -> RenderResult<()> {
+    model.meshlets = build_meshlets(model, settings)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        importer::{
+            collada::Collada,
+            Importer,
+        },
+        model::{
+            conversion::{apply_conversion, Conversion},
+            meshlet::{build_meshlets, unit_normal, Meshlets, MeshletSettings},
+            primitives::{make_primitive, Primitive},
+            Model, SubMesh,
+        },
+        exporter::MeshAttributes,
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    //a size x size plane of quads, facing -y, the left and right halves are submeshes
+    fn grid(size: u32) -> Model {
+        let plane = Primitive::Plane{ size: size as f32, subdivisions: size };
+        let mut model = make_primitive(&plane).unwrap();
+        //two triangles per quad, a row of quads at a time
+        let (mut left, mut right): (Vec<u32>, Vec<u32>) = (vec![], vec![]);
+        for (q, quad) in model.indeces.chunks_exact(6).enumerate() {
+            let x = q as u32 % size;
+            if x < size / 2 { &mut left } else { &mut right }.extend_from_slice(quad);
+        }
+        let split = left.len();
+        model.indeces = left.into_iter().chain(right).collect();
+        model.submeshes = vec![
+            SubMesh::new("left", 0, split, None),
+            SubMesh::new("right", split, model.indeces.len() - split, None),
+        ];
+        model
+    }
+
+    //every triangle of every submesh in exactly one meshlet of that submesh
+    fn check_meshlets(model: &Model, meshlets: &Meshlets, settings: &MeshletSettings) {
+        let positions = MeshAttributes::new(&model.mesh).positions;
+        let mut found: Vec<Vec<[u32; 3]>> = vec![vec![]; model.submeshes.len()];
+        for meshlet in meshlets.meshlets.iter() {
+            assert!(meshlet.vertex_count as usize <= settings.max_vertices);
+            assert!(meshlet.triangle_count as usize <= settings.max_triangles);
+            assert!(meshlet.triangle_count > 0);
+            let vertices = &meshlets.vertices[meshlet.vertex_offset as usize..]
+                [..meshlet.vertex_count as usize];
+            for v in vertices.iter() {
+                let p = positions[*v as usize];
+                let d = [0, 1, 2].map(|k| p[k] - meshlet.sphere.center[k]);
+                let distance = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+                assert!(distance <= meshlet.sphere.radius * 1.0001 + 1e-5);
+            }
+            found[meshlet.submesh as usize].extend(meshlets.triangles(meshlet));
+        }
+        for (submesh, mut found) in model.submeshes.iter().zip(found) {
+            let mut expected: Vec<[u32; 3]> = model.indeces[submesh.first_index..]
+                [..submesh.index_count]
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect();
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected, "{}", submesh.name);
+        }
+    }
+
+    #[test]
+    fn meshlets_cover_every_triangle_once() {
+        let model = grid(40);
+        let settings = MeshletSettings::default();
+        let meshlets = build_meshlets(&model, &settings).unwrap();
+        check_meshlets(&model, &meshlets, &settings);
+        //greedy growth keeps them reasonably full
+        let triangles = model.indeces.len() / 3;
+        assert!(meshlets.meshlets.len() < triangles / 124 * 2, "{}", meshlets.meshlets.len());
+
+        let small = MeshletSettings{
+            max_vertices: 8,
+            max_triangles: 5,
+        };
+        check_meshlets(&model, &build_meshlets(&model, &small).unwrap(), &small);
+        let collada = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        check_meshlets(&collada, &build_meshlets(&collada, &small).unwrap(), &small);
+
+        let bad = MeshletSettings{
+            max_vertices: 300,
+            ..Default::default()
+        };
+        assert!(build_meshlets(&model, &bad).is_err());
+    }
+
+    #[test]
+    fn meshlets_normal_cones() {
+        let model = grid(8);
+        let meshlets = build_meshlets(&model, &MeshletSettings::default()).unwrap();
+        for meshlet in meshlets.meshlets.iter() {
+            assert_eq!(meshlet.cone_axis, [0.0, -1.0, 0.0]);
+            assert!(meshlet.cone_cutoff < 1e-3, "{}", meshlet.cone_cutoff);
+            //the grid faces -y, from above every triangle faces away
+            assert!(meshlet.backfacing(&[0.0, 20.0, 0.0]));
+            assert!(!meshlet.backfacing(&[0.0, -20.0, 0.0]));
+            //and edge on nothing can be said
+            assert!(!meshlet.backfacing(&[-50.0, 0.0, 0.0]));
+        }
+
+        //mirrored and turned z up to y up, the cones and triangles go along
+        let mut model = model;
+        model.meshlets = meshlets;
+        let conversion = Conversion::z_up_to_y_up().with_handedness_flip();
+        apply_conversion(&mut model, &conversion).unwrap();
+        let positions = MeshAttributes::new(&model.mesh).positions;
+        let t = &model.indeces[0..3];
+        let normal = unit_normal([0, 1, 2].map(|k| positions[t[k] as usize])).unwrap();
+        for meshlet in model.meshlets.meshlets.iter() {
+            assert_eq!(meshlet.cone_axis, normal);
+        }
+        check_meshlets(&model, &model.meshlets, &MeshletSettings::default());
+    }
+}
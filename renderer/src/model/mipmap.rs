@@ -0,0 +1,448 @@
+/* Mip chains, made on the cpu so they can be cooked and uploaded as they are.
+ * Each level is filtered from the one before it in f32, at any size, a level is half
+ * the one before rounded down so odd sizes don't need to be padded to a power of two.
+ * The filter's footprint is scaled by the actual ratio, 2 to 3 for an odd size, and
+ * the edges clamp.
+ *  Box, the area average, cheap and soft.
+ *  Kaiser, a Kaiser windowed sinc, sharper without much ringing.
+ *  Lanczos, 3 lobes, the sharpest, clamped since its negative lobes can overshoot.
+ * Color textures are filtered in linear light, averaging srgb values darkens.
+ * Alpha tested textures thin out as alpha averages towards the cutoff, with
+ * alpha_coverage each level's alpha is scaled so the share of texels passing the cutoff
+ * stays what it is in the first level, see Castaño, "Computing Alpha Mipmaps".
+ * Float textures are already linear and aren't clamped, HDR values go well past 1.
+ */
+
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+    sync::Arc,
+};
+
+use image::Rgba;
+
+use super::{
+    material::{AlphaMode, SharedTexture, Texture, TextureImage, TextureSlotKind},
+    texture_format::{float_bytes, EncodedTexture},
+    Model,
+};
+
+//half width of the windowed sincs in source texels of the level below
+const KERNEL_RADIUS: f32 = 3.0;
+const KAISER_ALPHA: f32 = 4.0;
+//coverage search steps, plenty for 8 bit alpha
+const COVERAGE_STEPS: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    Box,
+    Kaiser,
+    Lanczos,
+}
+
+impl MipFilter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "box" => Some(MipFilter::Box),
+            "kaiser" => Some(MipFilter::Kaiser),
+            "lanczos" => Some(MipFilter::Lanczos),
+            _ => None,
+        }
+    }
+
+    fn kernel(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            MipFilter::Box => (x <= 0.5) as u32 as f32,
+            MipFilter::Kaiser if x < KERNEL_RADIUS => {
+                let t = x / KERNEL_RADIUS;
+                sinc(x) * bessel_i0(KAISER_ALPHA * (1.0 - t * t).sqrt())
+                    / bessel_i0(KAISER_ALPHA)
+            },
+            MipFilter::Lanczos if x < KERNEL_RADIUS => sinc(x) * sinc(x / KERNEL_RADIUS),
+            _ => 0.0,
+        }
+    }
+
+    fn radius(self) -> f32 {
+        match self {
+            MipFilter::Box => 0.5,
+            MipFilter::Kaiser | MipFilter::Lanczos => KERNEL_RADIUS,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MipSettings {
+    pub filter: MipFilter,
+    //scale the alpha of mask materials' base color so cutouts don't fade with distance
+    pub alpha_coverage: bool,
+}
+
+impl Default for MipSettings {
+    fn default() -> Self {
+        Self{
+            filter: MipFilter::Box,
+            alpha_coverage: false,
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+//modified Bessel function of the first kind, order 0, by its power series
+fn bessel_i0(x: f32) -> f32 {
+    let (mut sum, mut term, mut k) = (1.0, 1.0, 1.0);
+    while term > sum * 1e-8 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+//the number of levels a full chain has, the image included
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+pub fn mip_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+//a level in f32, rgb linear when it's a color texture
+struct Level {
+    width: u32,
+    height: u32,
+    texels: Vec<[f32; 4]>,
+}
+
+impl Level {
+    fn from_image(image: &TextureImage, srgb: bool) -> Self {
+        let lut: Vec<f32> = (0..=u8::MAX).map(|c| {
+            let c = c as f32 / 255.0;
+            if srgb { srgb_to_linear(c) } else { c }
+        }).collect();
+        let texels = image.pixels().map(|Rgba([r, g, b, a])| {
+            [lut[*r as usize], lut[*g as usize], lut[*b as usize], *a as f32 / 255.0]
+        }).collect();
+        Self{
+            width: image.width(),
+            height: image.height(),
+            texels,
+        }
+    }
+
+    fn to_image(&self, srgb: bool, alpha_scale: f32) -> TextureImage {
+        let quantize = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let color = |c: f32| quantize(if srgb { linear_to_srgb(c.max(0.0)) } else { c });
+        TextureImage::from_fn(self.width, self.height, |x, y| {
+            let [r, g, b, a] = self.texels[(y * self.width + x) as usize];
+            Rgba([color(r), color(g), color(b), quantize(a * alpha_scale)])
+        })
+    }
+
+    //the share of texels whose scaled alpha passes the cutoff, the way a Mask
+    //material tests it
+    fn coverage(&self, cutoff: f32, alpha_scale: f32) -> f32 {
+        let passing = self.texels.iter().filter(|t| (t[3] * alpha_scale).min(1.0) >= cutoff);
+        passing.count() as f32 / self.texels.len() as f32
+    }
+
+    //the alpha scale whose coverage comes closest to target, up to one that lets the
+    //faintest texel through
+    fn coverage_scale(&self, cutoff: f32, target: f32) -> f32 {
+        let faintest = self.texels.iter().map(|t| t[3]).filter(|a| *a > 0.0).fold(1.0, f32::min);
+        let (mut low, mut high) = (0.0, (cutoff / faintest).max(1.0) * 2.0);
+        let mut best = (f32::MAX, 1.0);
+        for _ in 0..COVERAGE_STEPS {
+            let scale = (low + high) / 2.0;
+            let coverage = self.coverage(cutoff, scale);
+            if (coverage - target).abs() < best.0 {
+                best = ((coverage - target).abs(), scale);
+            }
+            if coverage < target {
+                low = scale;
+            } else {
+                high = scale;
+            }
+        }
+        best.1
+    }
+
+    fn downsample(&self, filter: MipFilter, clamp: bool) -> Self {
+        let (width, height) = mip_size(self.width, self.height, 1);
+        let columns = weights(self.width, width, filter);
+        let rows = weights(self.height, height, filter);
+        //rows first, then columns
+        let mut across = vec![[0.0; 4]; (width * self.height) as usize];
+        for y in 0..self.height as usize {
+            for (x, taps) in columns.iter().enumerate() {
+                across[y * width as usize + x] = taps.iter().fold([0.0; 4], |sum, &(s, w)| {
+                    let texel = self.texels[y * self.width as usize + s];
+                    std::array::from_fn(|c| sum[c] + texel[c] * w)
+                });
+            }
+        }
+        let mut texels = vec![[0.0; 4]; (width * height) as usize];
+        for (y, taps) in rows.iter().enumerate() {
+            for x in 0..width as usize {
+                let texel = taps.iter().fold([0.0; 4], |sum, &(s, w)| {
+                    let texel = across[s * width as usize + x];
+                    std::array::from_fn(|c| sum[c] + texel[c] * w)
+                });
+                //keeps the sincs' overshoot from building up level after level
+                texels[y * width as usize + x] = match clamp {
+                    true => texel.map(|c| c.clamp(0.0, 1.0)),
+                    false => texel,
+                };
+            }
+        }
+        Self{
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+//per destination texel, the source texels it's filtered from and their weights,
+//normalized so flat areas stay flat
+fn weights(source: u32, destination: u32, filter: MipFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = source as f32 / destination as f32;
+    let support = filter.radius() * scale;
+    (0..destination).map(|d| {
+        let center = (d as f32 + 0.5) * scale;
+        let first = (center - support).floor() as i64;
+        let last = (center + support).ceil() as i64;
+        let mut taps: Vec<(usize, f32)> = vec![];
+        for s in first..=last {
+            let weight = match filter {
+                //the exact overlap with the footprint, odd sizes get partial texels
+                MipFilter::Box => {
+                    let (low, high) = (center - scale / 2.0, center + scale / 2.0);
+                    ((s + 1) as f32).min(high) - (s as f32).max(low)
+                },
+                _ => filter.kernel((s as f32 + 0.5 - center) / scale),
+            };
+            if weight.abs() <= f32::EPSILON {
+                continue;
+            }
+            let s = s.clamp(0, source as i64 - 1) as usize;
+            match taps.iter_mut().find(|(t, _)| *t == s) {
+                Some(tap) => tap.1 += weight,
+                None => taps.push((s, weight)),
+            }
+        }
+        let total: f32 = taps.iter().map(|(_, w)| w).sum();
+        taps.iter().map(|&(s, w)| (s, w / total)).collect()
+    }).collect()
+}
+
+//the levels after image, down to 1 x 1. srgb filters the color in linear light,
+//alpha_cutoff keeps the alpha tested coverage
+pub fn mip_chain(
+    image: &TextureImage, filter: MipFilter, srgb: bool, alpha_cutoff: Option<f32>,
+) -> Vec<TextureImage> {
+    let mut level = Level::from_image(image, srgb);
+    let target = alpha_cutoff.map(|cutoff| (cutoff, level.coverage(cutoff, 1.0)));
+    (1..mip_level_count(image.width(), image.height())).map(|_| {
+        level = level.downsample(filter, true);
+        let alpha_scale = target.map_or(1.0, |(cutoff, coverage)| {
+            level.coverage_scale(cutoff, coverage)
+        });
+        level.to_image(srgb, alpha_scale)
+    }).collect()
+}
+
+//a float texture's first level and the levels after it, down to 1 x 1, for every
+//layer and face
+pub fn float_mip_chain(texture: &EncodedTexture, filter: MipFilter) -> EncodedTexture {
+    let count = mip_level_count(texture.width, texture.height) as usize;
+    let mut levels = vec![vec![]; count];
+    levels[0] = texture.levels[0].clone();
+    for layer in 0..texture.layers {
+        for face in 0..texture.faces {
+            let mut level = Level{
+                width: texture.width,
+                height: texture.height,
+                texels: texture.decode_floats(0, layer, face),
+            };
+            for data in levels.iter_mut().skip(1) {
+                level = level.downsample(filter, false);
+                data.extend(float_bytes(texture.format, &level.texels));
+            }
+        }
+    }
+    EncodedTexture{
+        format: texture.format,
+        width: texture.width,
+        height: texture.height,
+        layers: texture.layers,
+        faces: texture.faces,
+        levels,
+    }
+}
+
+//gives every texture of the model's materials a mip chain, textures that already have
+//one are left alone, so are encoded ones, they keep the chain they came with, except
+//float textures with a single level. Textures shared between slots stay shared unless
+//the slots need different chains. Returns how many chains were made
+pub fn generate_mips(model: &mut Model, settings: &MipSettings) -> usize {
+    let mut made: HashMap<(*const Texture, bool, Option<u32>), SharedTexture> = HashMap::new();
+    for material in model.materials.iter_mut() {
+        let cutoff = (settings.alpha_coverage && material.alpha_mode == AlphaMode::Mask)
+            .then_some(material.alpha_cutoff);
+        for kind in TextureSlotKind::ALL {
+            let alpha_cutoff = cutoff.filter(|_| kind == TextureSlotKind::BaseColor);
+            let Some(slot) = material.texture_mut(kind) else {
+                continue;
+            };
+            let float = match &slot.texture.encoded {
+                Some(encoded) if encoded.format.is_float() && encoded.level_count() == 1 => true,
+                Some(_) => continue,
+                None if !slot.texture.mips.is_empty() => continue,
+                None => false,
+            };
+            //floats are filtered the same whatever the slot
+            let (srgb, alpha_cutoff) = match float {
+                true => (false, None),
+                false => (kind.is_srgb(), alpha_cutoff),
+            };
+            let key = (Arc::as_ptr(&slot.texture), srgb, alpha_cutoff.map(f32::to_bits));
+            let texture = made.entry(key).or_insert_with(|| {
+                let image = slot.texture.image.clone();
+                Arc::new(match &slot.texture.encoded {
+                    Some(encoded) => Texture{
+                        encoded: Some(float_mip_chain(encoded, settings.filter)),
+                        ..Texture::new(image)
+                    },
+                    None => Texture{
+                        mips: mip_chain(&image, settings.filter, srgb, alpha_cutoff),
+                        ..Texture::new(image)
+                    },
+                })
+            });
+            slot.texture = texture.clone();
+        }
+    }
+    made.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use image::Rgba;
+
+    use crate::model::{
+        material::{AlphaMode, Material, Texture, TextureImage, TextureSlot, TextureSlotKind},
+        mipmap::{generate_mips, mip_chain, mip_level_count, MipFilter, MipSettings},
+        primitives::{make_primitive, Primitive},
+        texture_format::{EncodedTexture, TextureFormat},
+    };
+
+    #[test]
+    fn mip_chain_sizes_and_filters() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(5, 3), 3);
+        let flat = TextureImage::from_pixel(7, 3, Rgba([200, 100, 50, 255]));
+        for filter in [MipFilter::Box, MipFilter::Kaiser, MipFilter::Lanczos] {
+            let chain = mip_chain(&flat, filter, true, None);
+            let sizes: Vec<(u32, u32)> = chain.iter().map(|l| l.dimensions()).collect();
+            assert_eq!(sizes, vec![(3, 1), (1, 1)], "{filter:?}");
+            //flat stays flat, whatever the weights and the gamma round trip
+            for level in chain.iter() {
+                assert!(level.pixels().all(|p| *p == Rgba([200, 100, 50, 255])), "{filter:?}");
+            }
+        }
+
+        //black and white stripes average to half the light, which is 188 in srgb
+        //rather than 128
+        let stripes = TextureImage::from_fn(4, 4, |x, _| Rgba([255 * (x % 2) as u8; 4]));
+        let linear = mip_chain(&stripes, MipFilter::Box, false, None);
+        assert_eq!(linear[0].get_pixel(0, 0)[0], 128);
+        let srgb = mip_chain(&stripes, MipFilter::Box, true, None);
+        assert_eq!(srgb[0].get_pixel(0, 0)[0], 188);
+        assert_eq!(srgb[0].get_pixel(0, 0)[3], 128);
+    }
+
+    #[test]
+    fn mip_alpha_coverage() {
+        //one opaque texel in every 2 x 2 and faint ones around it, fading out to the
+        //left. An eighth of it passes a 0.5 cutoff, averaged none of it does and the
+        //cutout would vanish with distance
+        let cutout = TextureImage::from_fn(32, 32, |x, y| {
+            let fade = (x as f32 + 0.5) / 32.0;
+            let alpha = if x % 2 == 0 && y % 2 == 0 { 1.0 } else { 0.3 };
+            Rgba([255, 255, 255, (fade * alpha * 255.0).round() as u8])
+        });
+        let coverage = |image: &TextureImage| {
+            image.pixels().filter(|p| p[3] >= 128).count() as f32 / image.pixels().count() as f32
+        };
+        let target = coverage(&cutout);
+        let plain = mip_chain(&cutout, MipFilter::Box, true, None);
+        let kept = mip_chain(&cutout, MipFilter::Box, true, Some(0.5));
+        for (plain, kept) in plain.iter().zip(kept.iter()).take(2) {
+            let (plain, kept) = ((coverage(plain) - target).abs(), (coverage(kept) - target).abs());
+            assert!(kept < 0.05 && kept < plain, "{target} {plain} {kept}");
+        }
+
+        let mut model = make_primitive(&Primitive::from_id("builtin:cube").unwrap()).unwrap();
+        let slot = TextureSlot::new(cutout, None);
+        let mut masked = Material::default();
+        masked.alpha_mode = AlphaMode::Mask;
+        masked.set_texture(TextureSlotKind::BaseColor, Some(slot.clone()));
+        masked.set_texture(TextureSlotKind::Occlusion, Some(slot.clone()));
+        let mut plain = Material::default();
+        plain.set_texture(TextureSlotKind::BaseColor, Some(slot));
+        model.materials = vec![masked, plain];
+        let settings = MipSettings{ filter: MipFilter::Kaiser, alpha_coverage: true };
+        //masked base color, linear occlusion, and the plain base color
+        assert_eq!(generate_mips(&mut model, &settings), 3);
+        let texture = |m: usize, kind| &model.materials[m].texture(kind).unwrap().texture;
+        let masked = texture(0, TextureSlotKind::BaseColor);
+        assert_eq!(masked.level_count(), 6);
+        assert_ne!(masked.mips, texture(1, TextureSlotKind::BaseColor).mips);
+        assert_eq!(generate_mips(&mut model, &settings), 0);
+    }
+
+    #[test]
+    fn float_mip_chains() {
+        //a sun in one corner, averaged rather than clamped to 1
+        let texels: Vec<[f32; 4]> = (0..16).map(|i| match i {
+            0 => [64.0, 32.0, 16.0, 1.0],
+            _ => [0.0, 0.0, 0.0, 1.0],
+        }).collect();
+        let sky = EncodedTexture::from_floats(TextureFormat::Rgba16F, 4, 4, &texels);
+        let slot = TextureSlot::shared(Arc::new(Texture::from_encoded(sky)), None);
+        let mut model = make_primitive(&Primitive::from_id("builtin:cube").unwrap()).unwrap();
+        let mut material = Material::default();
+        material.set_texture(TextureSlotKind::BaseColor, Some(slot.clone()));
+        material.set_texture(TextureSlotKind::Emissive, Some(slot));
+        model.materials = vec![material];
+        //one chain for both slots, floats don't care about srgb
+        assert_eq!(generate_mips(&mut model, &MipSettings::default()), 1);
+        let texture = &model.materials[0].texture(TextureSlotKind::Emissive).unwrap().texture;
+        let encoded = texture.encoded.as_ref().unwrap();
+        assert_eq!(encoded.level_count(), 3);
+        assert_eq!(encoded.decode_floats(1, 0, 0)[0], [16.0, 8.0, 4.0, 1.0]);
+        assert_eq!(encoded.decode_floats(2, 0, 0), vec![[4.0, 2.0, 1.0, 1.0]]);
+        assert_eq!(generate_mips(&mut model, &MipSettings::default()), 0);
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
     fs::File,
     cell::RefCell,
+    io::{BufWriter, Write},
+    ops::Range,
 };
 
 //use serde::{Serialize, Deserialize};
@@ -10,14 +12,42 @@ pub use glm::Vector2;
 
 pub mod model_manager;
 pub mod material;
+pub mod texture_manager;
+pub mod normals;
+pub mod tangents;
+pub mod block_compression;
+pub mod bounds;
+pub mod conversion;
+pub mod cooked;
+pub mod layout;
+pub mod lod;
+pub mod mapped;
+pub mod meshlet;
+pub mod mipmap;
+pub mod optimize;
+pub mod primitives;
+pub mod quantize;
+pub mod texture_format;
+pub mod validate;
+pub mod weld;
 
 use material::Material;
+use bounds::Bounds;
+use conversion::Conversion;
+use layout::{layout_stride, mesh_layout, VertexAttribute};
+use lod::Lod;
+use meshlet::Meshlets;
+use quantize::{Dequantize, QuantizedMesh};
+
+use crate::RenderResult;
 
 //#[derive(Serialize, Deserialize, Debug)]
 pub enum Mesh {
     PositionMesh(Vec<PostionVertex>),
     TextureMesh(Vec<TextureVertex>),
     NormalMesh(Vec<NormalVertex>),
+    ColorMesh(Vec<ColorVertex>),
+    TangentMesh(Vec<TangentVertex>),
 }
 
 //Note: this only works because a Mesh is a Vec underneath
@@ -49,6 +79,14 @@ type TextureCoord = f32;
 pub type NormalVector = Vector4<NormalCoord>;
 type NormalCoord = f32;
 
+//rgba, each channel normalized to [0, 1]
+pub type ColorVector = Vector4<ColorCoord>;
+type ColorCoord = f32;
+
+//xyz tangent, w the handedness of the bitangent
+pub type TangentVector = Vector4<TangentCoord>;
+type TangentCoord = f32;
+
 pub type IndexVector = Vec<IndexCoord>;
 pub type IndexCoord = u32;
 
@@ -59,7 +97,8 @@ pub type IndexCoord = u32;
 //}
 
 //#[derive(Serialize, Deserialize, Debug)]
-pub struct PostionVertex(PositionVector);
+#[repr(C)]
+pub struct PostionVertex(pub PositionVector);
 
 /*
 impl Serialize for PositionVector {
@@ -73,6 +112,7 @@ impl Serialize for PositionVector {
 
 //#[derive(Serialize, Deserialize, Debug)]
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct TextureVertex {
     pub pos: PositionVector,
     pub uv: TextureVector,
@@ -80,6 +120,7 @@ pub struct TextureVertex {
 
 //#[derive(Serialize, Deserialize, Debug)]
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct NormalVertex {
     pub pos: PositionVector,
     pub uv: TextureVector,
@@ -101,15 +142,92 @@ impl NormalVertex {
     }
 }
 
+//#[derive(Serialize, Deserialize, Debug)]
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct ColorVertex {
+    pub pos: PositionVector,
+    pub uv: TextureVector,
+    pub norm: NormalVector,
+    pub color: ColorVector,
+}
+
+//bitangent = tangent.w * cross(norm, tangent.xyz)
+//#[derive(Serialize, Deserialize, Debug)]
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct TangentVertex {
+    pub pos: PositionVector,
+    pub uv: TextureVector,
+    pub norm: NormalVector,
+    pub tangent: TangentVector,
+}
+
 pub const DEFAULT_MODEL_NAME: &str = "unnamed";
 
+//a contiguous run of Model.indeces drawn with a single material,
+//material is an index into Model.materials
+#[derive(Clone)]
+pub struct SubMesh {
+    pub name: String,
+    pub first_index: usize,
+    pub index_count: usize,
+    pub material: Option<usize>,
+    //of the vertices its indeces use, see bounds::compute_bounds
+    pub bounds: Bounds,
+}
+
+impl SubMesh {
+    pub fn new(
+        name: &str, first_index: usize, index_count: usize, material: Option<usize>,
+    ) -> Self {
+        Self{
+            name: name.to_string(),
+            first_index,
+            index_count,
+            material,
+            bounds: Bounds::EMPTY,
+        }
+    }
+}
+
+//each submesh's run of the indeces, all of them when there are no submeshes, an error
+//unless every run is whole triangles within the index_count indeces
+pub fn submesh_ranges(
+    submeshes: &[SubMesh], index_count: usize,
+) -> RenderResult<Vec<Range<usize>>> {
+    let mut ranges: Vec<Range<usize>> = submeshes.iter()
+        .map(|s| s.first_index..s.first_index + s.index_count)
+        .collect();
+    if ranges.is_empty() {
+        ranges.push(0..index_count);
+    }
+    let bad = ranges.iter().find(|r| r.end > index_count || !r.len().is_multiple_of(3));
+    if let Some(range) = bad {
+        return Err(format!("{range:?} isn't a range of whole triangles in the indeces"));
+    }
+    Ok(ranges)
+}
+
 //#[derive(Debug)]
 //#[derive(Serialize, Deserialize, Debug)]
 pub struct Model {
     pub name: String,
     pub mesh: Mesh,
     pub indeces: Vec<IndexCoord>,
+    pub submeshes: Vec<SubMesh>,
     pub materials: Vec<Material>,
+    //the coordinate, unit and uv conversion applied since import
+    pub conversion: Conversion,
+    //simplified levels of detail sharing the vertices, coarsest last, see lod
+    pub lods: Vec<Lod>,
+    //of every vertex, kept in step with them by the importers and apply_conversion
+    pub bounds: Bounds,
+    //clusters of the triangles for culled drawing, empty until built, see meshlet
+    pub meshlets: Meshlets,
+    //a compressed copy of the vertices for drawing, made last from the final vertices,
+    //see quantize
+    pub quantized: Option<QuantizedMesh>,
     //was going to support multiple materials in a Vec
     //but haven't figured out how to get something out of a Vec
     //without cloning yet
@@ -117,19 +235,179 @@ pub struct Model {
 }
 
 impl Model {
+    //no submeshes or materials, nothing converted, bounds computed or built from it yet
+    pub fn new(name: &str, mesh: Mesh, indeces: Vec<IndexCoord>) -> Self {
+        Self{
+            name: name.to_string(),
+            mesh,
+            indeces,
+            submeshes: vec![],
+            materials: vec![],
+            conversion: Conversion::IDENTITY,
+            lods: vec![],
+            bounds: Bounds::EMPTY,
+            meshlets: Meshlets::default(),
+            quantized: None,
+        }
+    }
+
+    //as .rmdl, see cooked for the format
     pub fn write_to_disk(&self, file: &mut File) -> Result<(), String> {
-        //bincode::serialize_into(file, self)
-        todo!("not implemented");
+        let mut out = BufWriter::new(file);
+        cooked::write_model(self, &mut out)?;
+        out.flush().map_err(|e| e.to_string())
     }
 
-    pub fn get_vertices(&self) -> &[NormalVertex] {
+    //the vertices when they're NormalVertex, see mesh_slices for any mesh
+    pub fn get_vertices(&self) -> Option<&[NormalVertex]> {
         match &self.mesh {
-            Mesh::NormalMesh(vertices) => {
-                vertices.as_slice()
-            },
-            _ => {
-                panic!("only normal vertices supported")
-            },
+            Mesh::NormalMesh(vertices) => Some(vertices.as_slice()),
+            _ => None,
+        }
+    }
+}
+
+//the vertex types are repr(C) and nothing but f32s, so there's no padding to expose.
+//Private, nothing outside this module can claim that for another type
+trait PlainVertex {}
+
+impl PlainVertex for PostionVertex {}
+impl PlainVertex for TextureVertex {}
+impl PlainVertex for NormalVertex {}
+impl PlainVertex for ColorVertex {}
+impl PlainVertex for TangentVertex {}
+
+fn vertex_bytes<T: PlainVertex>(vertices: &[T]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(vertices.as_ptr() as *const u8, std::mem::size_of_val(vertices))
+    }
+}
+
+//indeces the way they're stored, 16 bit ones are drawn with vk::IndexType::UINT16
+pub enum IndexSlice<'a> {
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+impl<'a> IndexSlice<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            IndexSlice::U16(indeces) => indeces.len(),
+            IndexSlice::U32(indeces) => indeces.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        match self {
+            IndexSlice::U16(indeces) => bytemuck::cast_slice(indeces),
+            IndexSlice::U32(indeces) => bytemuck::cast_slice(indeces),
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Option<IndexCoord> {
+        match self {
+            IndexSlice::U16(indeces) => indeces.get(i).map(|i| *i as IndexCoord),
+            IndexSlice::U32(indeces) => indeces.get(i).copied(),
         }
     }
 }
+
+//borrowed vertex and index data, ready to be copied straight into a buffer
+pub struct MeshSlices<'a> {
+    //interleaved, stride bytes per vertex, laid out like the Mesh variant's vertex
+    pub vertices: &'a [u8],
+    pub stride: usize,
+    pub layout: &'a [VertexAttribute],
+    pub indeces: IndexSlice<'a>,
+}
+
+impl MeshSlices<'_> {
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / self.stride
+    }
+}
+
+//a level of detail drawn with the vertices of the model's MeshSlices
+pub struct LodSlices<'a> {
+    pub error: f32,
+    pub indeces: IndexSlice<'a>,
+    pub submeshes: &'a [SubMesh],
+}
+
+//a quantized copy of the vertices, drawn with dequantize pushed to shader/quantized
+pub struct QuantizedSlices<'a> {
+    pub mesh: MeshSlices<'a>,
+    pub dequantize: &'a Dequantize,
+}
+
+//what drawing needs from a model, whether it owns its data or borrows it from a
+//memory mapped cooked file
+pub trait ModelView {
+    fn name(&self) -> &str;
+    fn mesh_slices(&self) -> MeshSlices<'_>;
+    fn submeshes(&self) -> &[SubMesh];
+    fn materials(&self) -> &[Material];
+    fn bounds(&self) -> &Bounds;
+    fn meshlets(&self) -> &Meshlets;
+    //coarsest last
+    fn lods(&self) -> Vec<LodSlices<'_>>;
+    fn quantized(&self) -> Option<QuantizedSlices<'_>>;
+}
+
+impl ModelView for Model {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn mesh_slices(&self) -> MeshSlices<'_> {
+        let vertices = match &self.mesh {
+            Mesh::PositionMesh(v) => vertex_bytes(v),
+            Mesh::TextureMesh(v) => vertex_bytes(v),
+            Mesh::NormalMesh(v) => vertex_bytes(v),
+            Mesh::ColorMesh(v) => vertex_bytes(v),
+            Mesh::TangentMesh(v) => vertex_bytes(v),
+        };
+        let layout = mesh_layout(&self.mesh);
+        MeshSlices{
+            vertices,
+            stride: layout_stride(layout),
+            layout,
+            indeces: IndexSlice::U32(&self.indeces),
+        }
+    }
+
+    fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
+
+    fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    fn bounds(&self) -> &Bounds {
+        &self.bounds
+    }
+
+    fn meshlets(&self) -> &Meshlets {
+        &self.meshlets
+    }
+
+    fn lods(&self) -> Vec<LodSlices<'_>> {
+        self.lods.iter().map(|lod| LodSlices{
+            error: lod.error,
+            indeces: IndexSlice::U32(&lod.indeces),
+            submeshes: &lod.submeshes,
+        }).collect()
+    }
+
+    fn quantized(&self) -> Option<QuantizedSlices<'_>> {
+        self.quantized.as_ref().map(|q| QuantizedSlices{
+            mesh: q.mesh_slices(),
+            dequantize: &q.dequantize,
+        })
+    }
+}
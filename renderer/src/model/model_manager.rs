@@ -1,6 +1,12 @@
 /* The purpose of this module is to maintain a hash table by model id, each request
  * to a model with a given id returns an Rc pointer to the model, loading the model
  * if necessary.  Actual hash structure has form Hash<String, Weak<Model>>.
+ * Views are the cheaper way to get something drawable, cooked models are memory
+ * mapped instead of copied, everything else is imported like get_model_by_id.
+ * Ids starting with "builtin:" are procedural shapes, see primitives::Primitive::from_id,
+ * they never touch the asset source.
+ * Imported models share their textures through one TextureManager, ten models using
+ * the same image decode it once.
  */
 
 use std::{
@@ -8,34 +14,56 @@ use std::{
     rc::{
         Rc, Weak
     },
+    sync::Arc,
 };
 
 use asset::{
     source::AssetSource,
-    asset::{
-        AssetType,
-        ModelType,
-    },
+    asset::{AssetType, ModelType},
 };
 
-use crate::{importer::{wavefront::Wavefront, Importer}, RenderResult};
+use crate::{
+    importer::{registry::ImporterRegistry, ImportSettings},
+    RenderResult,
+};
 
-use super::Model;
+use super::{
+    mapped::MappedModel,
+    primitives::{make_primitive, Primitive},
+    texture_manager::TextureManager,
+    Model, ModelView,
+};
 
 pub struct ModelManager {
     model_map: HashMap<String, Weak<Model>>,
+    mapped_map: HashMap<String, Weak<MappedModel>>,
     asset_source: Box<dyn AssetSource>,
+    importers: ImporterRegistry,
+    textures: Arc<TextureManager>,
 }
 
 impl ModelManager {
-    pub fn new(asset_source: Box<dyn AssetSource>) -> Self {
+    pub fn new(asset_source: Box<dyn AssetSource>, importers: ImporterRegistry) -> Self {
         Self{
             model_map: HashMap::new(),
+            mapped_map: HashMap::new(),
             asset_source,
+            importers,
+            textures: Arc::new(TextureManager::new()),
         }
     }
 
-    fn load_model(&mut self, id: &str) -> RenderResult<Rc<Model>> {
+    //what's decoded and who still holds it, see TextureManager::usage
+    pub fn textures(&self) -> &TextureManager {
+        &self.textures
+    }
+
+    //for registering custom importers after the fact
+    pub fn importers_mut(&mut self) -> &mut ImporterRegistry {
+        &mut self.importers
+    }
+
+    fn model_asset(&self, id: &str) -> RenderResult<(ModelType, String)> {
         let asset = self.asset_source.get_by_id(id)?;
         let model_type = match asset.asset_type {
             AssetType::Model(model_type) => model_type,
@@ -43,17 +71,19 @@ impl ModelManager {
                 return Err(format!("asset is not a model for id: {id}"));   
             },
         };
-        //wanted to make importer generic, but wasn't letting me do: Box<dyn Importer>
-        //for some reason, concrete for now
-        let importer = match model_type {
-            ModelType::Wavefront => {
-                Wavefront::new(Some(id.to_string()))
-            },
-        };
         if let None = asset.location {
             return Err(format!("location required to load model, id: {id}"));
         }
-        let model = importer.generate_model(&asset.location.unwrap())?;
+        Ok((model_type, asset.location.unwrap()))
+    }
+
+    fn load_model(&mut self, id: &str) -> RenderResult<Rc<Model>> {
+        if let Some(primitive) = Primitive::from_id(id) {
+            return Ok(Rc::new(make_primitive(&primitive)?));
+        }
+        let (model_type, location) = self.model_asset(id)?;
+        let model = self.importers.get_by_type(&model_type)?
+            .generate_model_with_textures(&location, &ImportSettings::default(), &self.textures)?;
         Ok(Rc::new(model))
     }
 
@@ -76,6 +106,23 @@ impl ModelManager {
         }
     }
 
+    //cooked models are mapped and shared while anything still holds them
+    pub fn get_model_view_by_id(&mut self, id: &str) -> RenderResult<Rc<dyn ModelView>> {
+        if Primitive::from_id(id).is_some() {
+            return Ok(self.get_model_by_id(id)?);
+        }
+        let (model_type, location) = self.model_asset(id)?;
+        if !matches!(model_type, ModelType::Cooked) {
+            return Ok(self.get_model_by_id(id)?);
+        }
+        if let Some(mapped) = self.mapped_map.get(id).and_then(|m| m.upgrade()) {
+            return Ok(mapped);
+        }
+        let mapped = Rc::new(MappedModel::open(&location)?);
+        self.mapped_map.insert(id.to_string(), Rc::downgrade(&mapped));
+        Ok(mapped)
+    }
+
     //probably not the most efficient, but good enough for now
     /*
     pub fn get_model_by_id(&mut self, id: &str) -> Result<Rc<Model>, String> {
@@ -0,0 +1,331 @@
+/* Normal generation for importers that don't get normals from the source, or when the
+ * import settings ask for them to be regenerated.
+ * Flat gives every triangle its own normal. Smooth averages the faces around a position,
+ * weighted by the angle each face makes at that corner so the result doesn't depend on
+ * how the surface was triangulated. Faces meeting at more than the crease angle don't
+ * contribute to each other, which splits the vertex along hard edges.
+ * Vertices are matched on their exact position, so uv seams still smooth across.
+ */
+
+use std::collections::HashMap;
+
+use super::{
+    IndexCoord, Mesh, Model, NormalVector, NormalVertex, TextureVector,
+};
+
+use crate::RenderResult;
+
+//degrees, anything sharper than this is treated as a hard edge
+pub const DEFAULT_CREASE_ANGLE: f32 = 60.0;
+//generated normals are quantized before deduplicating vertices on them,
+//faces that should agree rarely do to the last bit
+const NORMAL_QUANTIZATION: f32 = 1.0e5;
+
+pub(super) type Vec3 = [f32; 3];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalMode {
+    //every triangle gets its own vertices and face normal
+    Flat,
+    //crease_angle in degrees, 180 smooths everything sharing a position
+    Smooth { crease_angle: f32 },
+}
+
+impl Default for NormalMode {
+    fn default() -> Self {
+        Self::Smooth{ crease_angle: DEFAULT_CREASE_ANGLE }
+    }
+}
+
+//replaces whatever normals the model has, PositionMesh and TextureMesh become a NormalMesh,
+//TangentMesh drops back to a NormalMesh as the tangents would no longer match
+//smoothing_groups holds one group per triangle (obj "s"), faces only smooth across each
+//other inside the same group, group 0 is smoothing off and always flat
+//vertices not referenced by any triangle are dropped, models without indeces are untouched
+pub fn generate_normals(
+    model: &mut Model, mode: NormalMode, smoothing_groups: Option<&[u32]>,
+) -> RenderResult<()> {
+    if model.indeces.is_empty() {
+        return Ok(());
+    }
+    if !model.indeces.len().is_multiple_of(3) {
+        return Err(format!(
+            "can't generate normals for '{}', index count {} is not a multiple of 3",
+            model.name, model.indeces.len(),
+        ));
+    }
+    let triangle_count = model.indeces.len() / 3;
+    if let Some(groups) = smoothing_groups {
+        if groups.len() != triangle_count {
+            return Err(format!(
+                "can't generate normals for '{}', {} smoothing groups for {} triangles",
+                model.name, groups.len(), triangle_count,
+            ));
+        }
+    }
+    let positions = mesh_positions(&model.mesh);
+    if let Some(index) = model.indeces.iter().find(|i| **i as usize >= positions.len()) {
+        return Err(format!(
+            "can't generate normals for '{}', index {index} is out of range", model.name,
+        ));
+    }
+    let corner_normals = corner_normals(&positions, &model.indeces, mode, smoothing_groups);
+    //(source vertex, quantized normal) -> new vertex
+    let mut vertex_map: HashMap<(IndexCoord, [i32; 3]), IndexCoord> = HashMap::new();
+    let mut sources: Vec<(usize, Vec3)> = vec![];
+    let indeces = model.indeces.iter().zip(corner_normals.iter()).map(|(i, n)| {
+        let key = n.map(|c| (c * NORMAL_QUANTIZATION).round() as i32);
+        *vertex_map.entry((*i, key)).or_insert_with(|| {
+            sources.push((*i as usize, *n));
+            (sources.len() - 1) as IndexCoord
+        })
+    }).collect();
+    model.mesh = rebuild_mesh(&model.mesh, &sources);
+    model.indeces = indeces;
+    Ok(())
+}
+
+fn mesh_positions(mesh: &Mesh) -> Vec<Vec3> {
+    match mesh {
+        Mesh::PositionMesh(vertices) => {
+            vertices.iter().map(|v| [v.0.x, v.0.y, v.0.z]).collect()
+        },
+        Mesh::TextureMesh(vertices) => {
+            vertices.iter().map(|v| [v.pos.x, v.pos.y, v.pos.z]).collect()
+        },
+        Mesh::NormalMesh(vertices) => {
+            vertices.iter().map(|v| [v.pos.x, v.pos.y, v.pos.z]).collect()
+        },
+        Mesh::ColorMesh(vertices) => {
+            vertices.iter().map(|v| [v.pos.x, v.pos.y, v.pos.z]).collect()
+        },
+        Mesh::TangentMesh(vertices) => {
+            vertices.iter().map(|v| [v.pos.x, v.pos.y, v.pos.z]).collect()
+        },
+    }
+}
+
+//one normal per entry in indeces
+fn corner_normals(
+    positions: &[Vec3], indeces: &[IndexCoord], mode: NormalMode,
+    smoothing_groups: Option<&[u32]>,
+) -> Vec<Vec3> {
+    let triangles: Vec<[usize; 3]> = indeces.chunks_exact(3)
+        .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+        .collect();
+    let face_normals: Vec<Vec3> = triangles.iter().map(|t| {
+        let (a, b, c) = (&positions[t[0]], &positions[t[1]], &positions[t[2]]);
+        normalize(&cross(&sub(b, a), &sub(c, a)))
+    }).collect();
+    let cos_crease = match mode {
+        NormalMode::Flat => {
+            return face_normals.iter().flat_map(|n| [*n; 3]).collect();
+        },
+        NormalMode::Smooth{ crease_angle } => crease_angle.clamp(0.0, 180.0).to_radians().cos(),
+    };
+    //exact position -> every (triangle, corner) touching it
+    let mut position_ids: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut corners: Vec<Vec<(usize, usize)>> = vec![];
+    let corner_position: Vec<usize> = indeces.iter().enumerate().map(|(i, index)| {
+        let key = positions[*index as usize].map(|c| c.to_bits());
+        let id = *position_ids.entry(key).or_insert_with(|| {
+            corners.push(vec![]);
+            corners.len() - 1
+        });
+        corners[id].push((i / 3, i % 3));
+        id
+    }).collect();
+    let group = |t: usize| smoothing_groups.map(|g| g[t]);
+    (0..indeces.len()).map(|i| {
+        let t = i / 3;
+        let face = &face_normals[t];
+        if group(t) == Some(0) || *face == [0.0; 3] {
+            return *face;
+        }
+        let mut sum = [0.0; 3];
+        for (other, corner) in corners[corner_position[i]].iter() {
+            let other_face = &face_normals[*other];
+            if group(*other) != group(t) || dot(face, other_face) < cos_crease {
+                continue;
+            }
+            let weight = corner_angle(positions, &triangles[*other], *corner);
+            for (s, n) in sum.iter_mut().zip(other_face.iter()) {
+                *s += n * weight;
+            }
+        }
+        let norm = normalize(&sum);
+        if norm == [0.0; 3] { *face } else { norm }
+    }).collect()
+}
+
+//angle at triangle[corner] between its two edges, in radians
+fn corner_angle(positions: &[Vec3], triangle: &[usize; 3], corner: usize) -> f32 {
+    let p = &positions[triangle[corner]];
+    let a = normalize(&sub(&positions[triangle[(corner + 1) % 3]], p));
+    let b = normalize(&sub(&positions[triangle[(corner + 2) % 3]], p));
+    dot(&a, &b).clamp(-1.0, 1.0).acos()
+}
+
+//keeps everything but the normal from the source vertex,
+//keeping the homogenous 1.0 the wavefront importer uses
+fn rebuild_mesh(mesh: &Mesh, sources: &[(usize, Vec3)]) -> Mesh {
+    let norm_fn = |n: &Vec3| NormalVector::new(n[0], n[1], n[2], 1.0);
+    match mesh {
+        Mesh::PositionMesh(vertices) => Mesh::NormalMesh(sources.iter().map(|(i, n)| {
+            NormalVertex::new(vertices[*i].0, TextureVector::new(0.0, 0.0), norm_fn(n))
+        }).collect()),
+        Mesh::TextureMesh(vertices) => Mesh::NormalMesh(sources.iter().map(|(i, n)| {
+            NormalVertex::new(vertices[*i].pos, vertices[*i].uv, norm_fn(n))
+        }).collect()),
+        Mesh::NormalMesh(vertices) => Mesh::NormalMesh(sources.iter().map(|(i, n)| {
+            NormalVertex{ norm: norm_fn(n), ..vertices[*i] }
+        }).collect()),
+        Mesh::ColorMesh(vertices) => Mesh::ColorMesh(sources.iter().map(|(i, n)| {
+            let mut vertex = vertices[*i];
+            vertex.norm = norm_fn(n);
+            vertex
+        }).collect()),
+        //the old tangents are meaningless against the new normals
+        Mesh::TangentMesh(vertices) => Mesh::NormalMesh(sources.iter().map(|(i, n)| {
+            NormalVertex::new(vertices[*i].pos, vertices[*i].uv, norm_fn(n))
+        }).collect()),
+    }
+}
+
+pub(super) fn sub(a: &Vec3, b: &Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(super) fn dot(a: &Vec3, b: &Vec3) -> f32 {
+    a[0]*b[0] + a[1]*b[1] + a[2]*b[2]
+}
+
+pub(super) fn cross(a: &Vec3, b: &Vec3) -> Vec3 {
+    [a[1]*b[2] - a[2]*b[1], a[2]*b[0] - a[0]*b[2], a[0]*b[1] - a[1]*b[0]]
+}
+
+pub(super) fn normalize(a: &Vec3) -> Vec3 {
+    let len = dot(a, a).sqrt();
+    if len > 0.0 {
+        [a[0] / len, a[1] / len, a[2] / len]
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{
+        normals::{generate_normals, NormalMode},
+        Mesh, Model, PositionVector, PostionVertex, SubMesh, TextureVector, TextureVertex,
+    };
+
+    //8 shared corners, 2 triangles per side, counter clockwise from outside
+    fn cube() -> Model {
+        let corners = [
+            [-1.0, -1.0, -1.0], [1.0, -1.0, -1.0], [1.0, 1.0, -1.0], [-1.0, 1.0, -1.0],
+            [-1.0, -1.0, 1.0], [1.0, -1.0, 1.0], [1.0, 1.0, 1.0], [-1.0, 1.0, 1.0],
+        ];
+        let sides = [
+            [4, 5, 6, 7], [1, 0, 3, 2], [5, 1, 2, 6], [0, 4, 7, 3], [7, 6, 2, 3], [0, 1, 5, 4],
+        ];
+        let indeces: Vec<u32> = sides.iter()
+            .flat_map(|s| [s[0], s[1], s[2], s[0], s[2], s[3]])
+            .collect();
+        let mesh = Mesh::PositionMesh(corners.iter()
+            .map(|c| PostionVertex(PositionVector::new(c[0], c[1], c[2], 1.0)))
+            .collect());
+        Model{
+            submeshes: vec!(SubMesh::new("cube", 0, indeces.len(), None)),
+            ..Model::new("cube", mesh, indeces)
+        }
+    }
+
+    fn vertex_count(model: &Model) -> usize {
+        match &model.mesh {
+            Mesh::NormalMesh(vertices) => vertices.len(),
+            _ => panic!("expected a NormalMesh"),
+        }
+    }
+
+    #[test]
+    fn normals_flat_cube() {
+        let mut model = cube();
+        generate_normals(&mut model, NormalMode::Flat, None).unwrap();
+        assert_eq!(model.indeces.len(), 36, "index length");
+        assert_eq!(vertex_count(&model), 24, "4 vertices per side");
+        let vertices = model.get_vertices().unwrap();
+        for t in model.indeces.chunks(3) {
+            let n = vertices[t[0] as usize].norm;
+            let len = n.x.abs() + n.y.abs() + n.z.abs();
+            assert!((len - 1.0).abs() < 1.0e-6, "axis aligned face normal");
+            for i in t {
+                let p = vertices[*i as usize].pos;
+                assert!(p.x*n.x + p.y*n.y + p.z*n.z > 0.0, "outward face normal");
+            }
+        }
+    }
+
+    #[test]
+    fn normals_smooth_crease() {
+        //90 degree edges are sharper than the default crease, same as flat
+        let mut model = cube();
+        generate_normals(&mut model, NormalMode::default(), None).unwrap();
+        assert_eq!(vertex_count(&model), 24, "split at the creases");
+        //everything smooths together, angle weighting makes every corner symmetric
+        let mut model = cube();
+        generate_normals(&mut model, NormalMode::Smooth{ crease_angle: 180.0 }, None).unwrap();
+        assert_eq!(vertex_count(&model), 8, "one vertex per corner");
+        let expected = 1.0 / 3.0_f32.sqrt();
+        for v in model.get_vertices().unwrap() {
+            assert!((v.norm.x - v.pos.x * expected).abs() < 1.0e-5, "smooth normal x");
+            assert!((v.norm.y - v.pos.y * expected).abs() < 1.0e-5, "smooth normal y");
+            assert!((v.norm.z - v.pos.z * expected).abs() < 1.0e-5, "smooth normal z");
+        }
+    }
+
+    #[test]
+    fn normals_smoothing_groups() {
+        let mode = NormalMode::Smooth{ crease_angle: 180.0 };
+        let mut model = cube();
+        generate_normals(&mut model, mode, Some(&[1; 12])).unwrap();
+        assert_eq!(vertex_count(&model), 8, "one group smooths everything");
+        //a group per side is a hard edge everywhere
+        let groups: Vec<u32> = (0..12).map(|t| t / 2 + 1).collect();
+        let mut model = cube();
+        generate_normals(&mut model, mode, Some(&groups)).unwrap();
+        assert_eq!(vertex_count(&model), 24, "a group per side");
+        //s off
+        let mut model = cube();
+        generate_normals(&mut model, mode, Some(&[0; 12])).unwrap();
+        assert_eq!(vertex_count(&model), 24, "smoothing off");
+    }
+
+    #[test]
+    fn normals_smooth_across_uv_seam() {
+        //two triangles in the z = 0 plane folded up along x = 1, the shared edge
+        //has different uvs on each side
+        let positions = [
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0], [2.0, 0.0, 1.0], [1.0, 1.0, 0.0],
+        ];
+        let mesh = Mesh::TextureMesh(positions.iter().enumerate().map(|(i, p)| TextureVertex{
+            pos: PositionVector::new(p[0], p[1], p[2], 1.0),
+            uv: TextureVector::new(i as f32, 0.0),
+        }).collect());
+        let mut model = Model::new("seam", mesh, vec![0, 1, 2, 3, 4, 5]);
+        generate_normals(&mut model, NormalMode::Smooth{ crease_angle: 60.0 }, None).unwrap();
+        let vertices = model.get_vertices().unwrap();
+        assert_eq!(vertices.len(), 6, "uvs keep the seam vertices apart");
+        assert_eq!(vertices[1].norm, vertices[3].norm, "seam vertices share a normal");
+        assert_eq!(vertices[1].uv, TextureVector::new(1.0, 0.0));
+        assert!(vertices[0].norm.z > vertices[1].norm.z, "seam normal is bent");
+    }
+
+    #[test]
+    #[should_panic(expected = "2 smoothing groups for 12 triangles")]
+    fn normals_bad_smoothing_groups() {
+        let mut model = cube();
+        generate_normals(&mut model, NormalMode::default(), Some(&[1, 1])).unwrap();
+    }
+}
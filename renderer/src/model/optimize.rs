@@ -0,0 +1,458 @@
+/* Reorders a Model for the GPU without changing what's drawn, triangles first, then
+ * vertices.
+ * Triangles are sorted for the post-transform vertex cache with Forsyth's linear speed
+ * algorithm, https://tomforsyth1000.github.io/papers/fast_vert_cache_opt.html
+ * Optionally they're then sorted for overdraw, the cache optimized order is split into
+ * clusters wherever the cache starts over anyway and the clusters facing out from the
+ * middle of the mesh go first, so they hide what's behind them. That's only kept if it
+ * doesn't cost more than overdraw_threshold times the cache optimized ACMR.
+ * Every SubMesh is sorted on its own, so they keep their ranges.
+ * Levels of detail get their triangles sorted the same way.
+ * Last the vertices are renumbered in the order the indeces first use them, so fetching
+ * them walks forward through memory. Unused vertices are kept, at the end. Meshlets keep
+ * their triangles, only the vertices they refer to are renumbered, a quantized copy is
+ * reordered along with the vertices.
+ *
+ * ACMR is the average cache misses per triangle, ATVR the misses per vertex used, both
+ * measured with a FIFO cache of cache_size entries, 0.5 and 1.0 are the best possible.
+ */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+};
+
+use super::{
+    submesh_ranges, Mesh, Model, SubMesh,
+};
+
+use crate::{
+    exporter::MeshAttributes,
+    RenderResult,
+};
+
+//scoring, from the paper
+const SCORE_CACHE_SIZE: usize = 32;
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+#[derive(Copy, Clone, Debug)]
+pub struct OptimizeSettings {
+    //the FIFO cache the statistics are measured with
+    pub cache_size: usize,
+    pub overdraw: bool,
+    pub overdraw_threshold: f32,
+}
+
+impl Default for OptimizeSettings {
+    fn default() -> Self {
+        Self{
+            cache_size: 16,
+            overdraw: false,
+            overdraw_threshold: 1.05,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub acmr: f32,
+    pub atvr: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OptimizationReport {
+    pub before: CacheStats,
+    pub after: CacheStats,
+}
+
+impl Display for OptimizationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f, "ACMR {:.3} -> {:.3}, ATVR {:.3} -> {:.3}",
+            self.before.acmr, self.after.acmr, self.before.atvr, self.after.atvr,
+        )
+    }
+}
+
+//new triangle order and vertex numbering, vertex_order[new] = old
+pub struct Optimized {
+    pub indeces: Vec<u32>,
+    //the same for each level of detail
+    pub lods: Vec<Vec<u32>>,
+    pub vertex_order: Vec<usize>,
+    pub report: OptimizationReport,
+}
+
+pub fn cache_stats(indeces: &[u32], cache_size: usize) -> CacheStats {
+    let mut cache: VecDeque<u32> = VecDeque::with_capacity(cache_size + 1);
+    let mut misses = 0;
+    let mut used: Vec<u32> = indeces.to_vec();
+    used.sort_unstable();
+    used.dedup();
+    for i in indeces {
+        if !cache.contains(i) {
+            misses += 1;
+            cache.push_back(*i);
+            if cache.len() > cache_size {
+                cache.pop_front();
+            }
+        }
+    }
+    let triangles = indeces.len() / 3;
+    CacheStats{
+        acmr: if triangles == 0 { 0.0 } else { misses as f32 / triangles as f32 },
+        atvr: if used.is_empty() { 0.0 } else { misses as f32 / used.len() as f32 },
+    }
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let mut score = match cache_position {
+        None => 0.0,
+        Some(p) if p < 3 => LAST_TRIANGLE_SCORE,
+        Some(p) => {
+            let scale = 1.0 / (SCORE_CACHE_SIZE - 3) as f32;
+            (1.0 - (p - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        },
+    };
+    score += VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER);
+    score
+}
+
+//Forsyth, on indeces numbered 0..vertex_count
+fn forsyth(indeces: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indeces.len() / 3;
+    let mut adjacency: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+    for t in 0..triangle_count {
+        for i in &indeces[t * 3..t * 3 + 3] {
+            adjacency[*i as usize].push(t);
+        }
+    }
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = adjacency.iter().map(|a| vertex_score(None, a.len())).collect();
+    let triangle_score = |t: usize, scores: &[f32]| -> f32 {
+        indeces[t * 3..t * 3 + 3].iter().map(|i| scores[*i as usize]).sum()
+    };
+    let mut triangle_scores: Vec<f32> =
+        (0..triangle_count).map(|t| triangle_score(t, &scores)).collect();
+    let mut added = vec![false; triangle_count];
+    let mut cache: Vec<u32> = vec![];
+    let mut out: Vec<u32> = Vec::with_capacity(indeces.len());
+    let mut next_unadded = 0;
+    for _ in 0..triangle_count {
+        //the best triangle touching the cache, otherwise the next one not drawn yet
+        let best = cache.iter()
+            .flat_map(|v| adjacency[*v as usize].iter())
+            .max_by(|a, b| triangle_scores[**a].total_cmp(&triangle_scores[**b]))
+            .copied();
+        let best = match best {
+            Some(best) => best,
+            None => {
+                while added[next_unadded] {
+                    next_unadded += 1;
+                }
+                next_unadded
+            },
+        };
+        added[best] = true;
+        let triangle = &indeces[best * 3..best * 3 + 3];
+        out.extend_from_slice(triangle);
+        for v in triangle {
+            adjacency[*v as usize].retain(|t| *t != best);
+            cache.retain(|c| c != v);
+        }
+        let mut touched: Vec<u32> = triangle.to_vec();
+        touched.extend_from_slice(&cache);
+        cache.splice(0..0, triangle.iter().copied());
+        if cache.len() > SCORE_CACHE_SIZE {
+            cache.truncate(SCORE_CACHE_SIZE);
+        }
+        for v in touched {
+            let v = v as usize;
+            cache_position[v] = cache.iter().position(|c| *c as usize == v);
+            scores[v] = vertex_score(cache_position[v], adjacency[v].len());
+        }
+        for v in cache.iter().chain(triangle.iter()) {
+            for t in adjacency[*v as usize].iter() {
+                triangle_scores[*t] = triangle_score(*t, &scores);
+            }
+        }
+    }
+    out
+}
+
+//numbers the vertices a run of indeces uses from 0, back is local -> original
+fn localize(indeces: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut local: HashMap<u32, u32> = HashMap::new();
+    let mut back: Vec<u32> = vec![];
+    let indeces = indeces.iter().map(|i| {
+        *local.entry(*i).or_insert_with(|| {
+            back.push(*i);
+            back.len() as u32 - 1
+        })
+    }).collect();
+    (indeces, back)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+//clusters start wherever every vertex of a triangle misses, outward facing ones first
+fn sort_overdraw(indeces: &[u32], positions: &[[f32; 3]], cache_size: usize) -> Vec<u32> {
+    let mut clusters: Vec<std::ops::Range<usize>> = vec![];
+    let mut cache: VecDeque<u32> = VecDeque::new();
+    let mut start = 0;
+    for (t, triangle) in indeces.chunks_exact(3).enumerate() {
+        let misses = triangle.iter().filter(|i| !cache.contains(i)).count();
+        if misses == 3 && t > start {
+            clusters.push(start..t);
+            start = t;
+        }
+        for i in triangle {
+            if !cache.contains(i) {
+                cache.push_back(*i);
+                if cache.len() > cache_size {
+                    cache.pop_front();
+                }
+            }
+        }
+    }
+    clusters.push(start..indeces.len() / 3);
+
+    let mut center = [0.0; 3];
+    for i in indeces {
+        for c in 0..3 {
+            center[c] += positions[*i as usize][c] / indeces.len() as f32;
+        }
+    }
+    let mut keyed: Vec<(f32, std::ops::Range<usize>)> = clusters.into_iter().map(|cluster| {
+        let mut centroid = [0.0; 3];
+        let mut normal = [0.0; 3];
+        for t in cluster.clone() {
+            let [a, b, c] = [0, 1, 2].map(|v| positions[indeces[t * 3 + v] as usize]);
+            let n = cross(sub(b, a), sub(c, a));
+            for k in 0..3 {
+                normal[k] += n[k];
+                centroid[k] += (a[k] + b[k] + c[k]) / (3 * cluster.len()) as f32;
+            }
+        }
+        let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2])
+            .sqrt()
+            .max(f32::MIN_POSITIVE);
+        let out = sub(centroid, center);
+        let key = (out[0] * normal[0] + out[1] * normal[1] + out[2] * normal[2]) / length;
+        (key, cluster)
+    }).collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter()
+        .flat_map(|(_, cluster)| indeces[cluster.start * 3..cluster.end * 3].to_vec())
+        .collect()
+}
+
+//the triangles of each submesh sorted in place
+fn sort_triangles(
+    indeces: &mut [u32],
+    submeshes: &[SubMesh],
+    positions: &[[f32; 3]],
+    settings: &OptimizeSettings,
+) -> RenderResult<()> {
+    let vertex_count = positions.len();
+    if let Some(i) = indeces.iter().find(|i| **i as usize >= vertex_count) {
+        return Err(format!("index {i} is out of range of {vertex_count} vertices"));
+    }
+    for range in submesh_ranges(submeshes, indeces.len())? {
+        let (local, back) = localize(&indeces[range.clone()]);
+        let mut sorted = forsyth(&local, back.len());
+        if settings.overdraw {
+            let local_positions: Vec<[f32; 3]> =
+                back.iter().map(|v| positions[*v as usize]).collect();
+            let overdraw = sort_overdraw(&sorted, &local_positions, settings.cache_size);
+            let limit = cache_stats(&sorted, settings.cache_size).acmr
+                * settings.overdraw_threshold;
+            if cache_stats(&overdraw, settings.cache_size).acmr <= limit {
+                sorted = overdraw;
+            }
+        }
+        for (i, local) in indeces[range].iter_mut().zip(sorted) {
+            *i = back[local as usize];
+        }
+    }
+    Ok(())
+}
+
+//the optimized order without touching the model, what cooking uses
+pub fn optimize_order(model: &Model, settings: &OptimizeSettings) -> RenderResult<Optimized> {
+    let positions = MeshAttributes::new(&model.mesh).positions;
+    let vertex_count = positions.len();
+    let mut indeces = model.indeces.clone();
+    sort_triangles(&mut indeces, &model.submeshes, &positions, settings)?;
+    let mut lods: Vec<Vec<u32>> = vec![];
+    for lod in model.lods.iter() {
+        let mut lod_indeces = lod.indeces.clone();
+        sort_triangles(&mut lod_indeces, &lod.submeshes, &positions, settings)?;
+        lods.push(lod_indeces);
+    }
+
+    //first use order, the levels of detail only use vertices the model does unless
+    //they were made elsewhere, then whatever's left
+    let mut new_index: Vec<Option<u32>> = vec![None; vertex_count];
+    let mut vertex_order: Vec<usize> = Vec::with_capacity(vertex_count);
+    for i in indeces.iter_mut().chain(lods.iter_mut().flatten()) {
+        let old = *i as usize;
+        *i = *new_index[old].get_or_insert_with(|| {
+            vertex_order.push(old);
+            vertex_order.len() as u32 - 1
+        });
+    }
+    for (old, new) in new_index.iter().enumerate() {
+        if new.is_none() {
+            vertex_order.push(old);
+        }
+    }
+
+    let report = OptimizationReport{
+        before: cache_stats(&model.indeces, settings.cache_size),
+        after: cache_stats(&indeces, settings.cache_size),
+    };
+    Ok(Optimized{
+        indeces,
+        lods,
+        vertex_order,
+        report,
+    })
+}
+
+//vertex_order[new] = old, without needing the vertices to be Clone
+fn reorder<T>(vertices: &mut Vec<T>, vertex_order: &[usize]) {
+    let mut slots: Vec<Option<T>> = vertices.drain(..).map(Some).collect();
+    vertices.extend(vertex_order.iter().map(|old| slots[*old].take().unwrap()));
+}
+
+pub fn optimize(
+    model: &mut Model, settings: &OptimizeSettings,
+) -> RenderResult<OptimizationReport> {
+    let optimized = optimize_order(model, settings)?;
+    let order = &optimized.vertex_order;
+    match &mut model.mesh {
+        Mesh::PositionMesh(vertices) => reorder(vertices, order),
+        Mesh::TextureMesh(vertices) => reorder(vertices, order),
+        Mesh::NormalMesh(vertices) => reorder(vertices, order),
+        Mesh::ColorMesh(vertices) => reorder(vertices, order),
+        Mesh::TangentMesh(vertices) => reorder(vertices, order),
+    }
+    model.indeces = optimized.indeces;
+    for (lod, indeces) in model.lods.iter_mut().zip(optimized.lods) {
+        lod.indeces = indeces;
+    }
+    model.meshlets.renumber(order);
+    if let Some(quantized) = &mut model.quantized {
+        quantized.renumber(order, &model.indeces);
+    }
+    Ok(optimized.report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exporter::MeshAttributes,
+        model::{
+            optimize::{cache_stats, optimize, OptimizeSettings},
+            primitives::{make_primitive, Primitive},
+            Model, SubMesh,
+        },
+    };
+
+    //a size x size plane of quads, the bottom and top halves are the submeshes, each with
+    //its triangles shuffled so the cache does badly
+    fn shuffled_grid(size: u32) -> Model {
+        let plane = Primitive::Plane{ size: size as f32, subdivisions: size };
+        let mut model = make_primitive(&plane).unwrap();
+        let mut triangles: Vec<[u32; 3]> = model.indeces.chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        let half = triangles.len() / 2;
+        let mut seed = 12345u32;
+        for half in triangles.chunks_mut(half) {
+            for i in (1..half.len()).rev() {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                half.swap(i, (seed >> 8) as usize % (i + 1));
+            }
+        }
+        model.indeces = triangles.into_iter().flatten().collect();
+        let half = half * 3;
+        model.submeshes = vec![
+            SubMesh::new("a", 0, half, None),
+            SubMesh::new("b", half, model.indeces.len() - half, None),
+        ];
+        model
+    }
+
+    //each submesh's triangles by position, rotated to start at the smallest, sorted
+    fn triangles(model: &Model) -> Vec<Vec<[[u32; 3]; 3]>> {
+        let positions = MeshAttributes::new(&model.mesh).positions;
+        model.submeshes.iter().map(|s| {
+            let mut triangles: Vec<[[u32; 3]; 3]> = model.indeces
+                [s.first_index..s.first_index + s.index_count]
+                .chunks_exact(3)
+                .map(|t| {
+                    let t = [0, 1, 2].map(|k| positions[t[k] as usize].map(|c| c.to_bits()));
+                    let first = (0..3).min_by_key(|i| t[*i]).unwrap();
+                    [t[first], t[(first + 1) % 3], t[(first + 2) % 3]]
+                })
+                .collect();
+            triangles.sort();
+            triangles
+        }).collect()
+    }
+
+    #[test]
+    fn optimize_vertex_cache_and_fetch() {
+        let mut model = shuffled_grid(32);
+        let expected = triangles(&model);
+        let report = optimize(&mut model, &OptimizeSettings::default()).unwrap();
+        assert_eq!(triangles(&model), expected, "same triangles, same windings, same submeshes");
+        assert!(report.after.acmr < report.before.acmr * 0.5, "{report}");
+        assert!(report.after.acmr < 0.8 && report.after.atvr < 1.5, "{report}");
+        assert_eq!(report.after, cache_stats(&model.indeces, 16));
+        //vertices in first use order
+        let mut next = 0;
+        for i in model.indeces.iter() {
+            assert!(*i <= next, "vertex {i} used before {next}");
+            if *i == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, MeshAttributes::new(&model.mesh).positions.len());
+    }
+
+    #[test]
+    fn optimize_overdraw() {
+        let mut cache_only = shuffled_grid(16);
+        let cache_report = optimize(&mut cache_only, &OptimizeSettings::default()).unwrap();
+        let mut model = shuffled_grid(16);
+        let expected = triangles(&model);
+        let settings = OptimizeSettings{
+            overdraw: true,
+            ..Default::default()
+        };
+        let report = optimize(&mut model, &settings).unwrap();
+        assert_eq!(triangles(&model), expected);
+        assert!(
+            report.after.acmr <= cache_report.after.acmr * settings.overdraw_threshold + 0.01,
+            "{report} vs {cache_report}",
+        );
+        //bad ranges are errors
+        model.submeshes[0].index_count += 1;
+        assert!(optimize(&mut model, &settings).is_err());
+    }
+}
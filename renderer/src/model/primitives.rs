@@ -0,0 +1,513 @@
+/* Procedural shapes, so the basics don't need a Blender export each.
+ * Every shape is built the way Blender would model it, z up, counter clockwise from
+ * outside, uvs from the bottom left, then converted with Conversion::blender_to_vulkan,
+ * so it lands in the renderer's space exactly like an imported Blender model would.
+ * They come out as TangentMeshes, smooth shapes get analytic normals and hard edges
+ * (cube sides, caps) get their own vertices, tangents are MikkTSpace like imports.
+ * The defaults are built in assets, "builtin:uv_sphere" and so on, see Primitive::from_id.
+ */
+
+use std::f32::consts::{PI, TAU};
+
+use super::{
+    bounds::compute_bounds,
+    conversion::{apply_conversion, Conversion},
+    tangents::generate_tangents,
+    IndexCoord, Mesh, Model, NormalVector, NormalVertex, PositionVector, SubMesh,
+    TextureVector,
+};
+
+use crate::RenderResult;
+
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+//sizes are in meters, segments go around the z axis, rings go from bottom to top
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    //in the xy plane facing +z, subdivisions quads along each side
+    Plane { size: f32, subdivisions: u32 },
+    Cube { size: f32, subdivisions: u32 },
+    UvSphere { radius: f32, segments: u32, rings: u32 },
+    //an icosahedron with each triangle split in 4, subdivisions times
+    Icosphere { radius: f32, subdivisions: u32 },
+    Cylinder { radius: f32, height: f32, segments: u32 },
+    Cone { radius: f32, height: f32, segments: u32 },
+    //height is the straight part between the two hemispheres, rings are per hemisphere
+    Capsule { radius: f32, height: f32, segments: u32, rings: u32 },
+    Torus { major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32 },
+}
+
+impl Primitive {
+    //Blender's defaults, more or less
+    pub const DEFAULTS: [Primitive; 8] = [
+        Primitive::Plane{ size: 2.0, subdivisions: 1 },
+        Primitive::Cube{ size: 2.0, subdivisions: 1 },
+        Primitive::UvSphere{ radius: 1.0, segments: 32, rings: 16 },
+        Primitive::Icosphere{ radius: 1.0, subdivisions: 2 },
+        Primitive::Cylinder{ radius: 1.0, height: 2.0, segments: 32 },
+        Primitive::Cone{ radius: 1.0, height: 2.0, segments: 32 },
+        Primitive::Capsule{ radius: 0.5, height: 1.0, segments: 32, rings: 8 },
+        Primitive::Torus{
+            major_radius: 1.0, minor_radius: 0.25, major_segments: 48, minor_segments: 12,
+        },
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Primitive::Plane{..} => "plane",
+            Primitive::Cube{..} => "cube",
+            Primitive::UvSphere{..} => "uv_sphere",
+            Primitive::Icosphere{..} => "icosphere",
+            Primitive::Cylinder{..} => "cylinder",
+            Primitive::Cone{..} => "cone",
+            Primitive::Capsule{..} => "capsule",
+            Primitive::Torus{..} => "torus",
+        }
+    }
+
+    //the built in asset id of the shape's defaults
+    pub fn id(&self) -> String {
+        format!("{BUILTIN_PREFIX}{}", self.name())
+    }
+
+    //"builtin:cube" and the like, None for anything else
+    pub fn from_id(id: &str) -> Option<Primitive> {
+        let name = id.strip_prefix(BUILTIN_PREFIX)?;
+        Self::DEFAULTS.into_iter().find(|p| p.name() == name)
+    }
+}
+
+//a point on the outline spun around the z axis, normal is (radial, z)
+struct ProfilePoint {
+    radius: f32,
+    z: f32,
+    normal: [f32; 2],
+    v: f32,
+}
+
+#[derive(Default)]
+struct Builder {
+    vertices: Vec<NormalVertex>,
+    indeces: Vec<IndexCoord>,
+}
+
+impl Builder {
+    fn vertex(&mut self, p: [f32; 3], uv: [f32; 2], n: [f32; 3]) -> IndexCoord {
+        self.vertices.push(NormalVertex::new(
+            PositionVector::new(p[0], p[1], p[2], 1.0),
+            TextureVector::new(uv[0], uv[1]),
+            NormalVector::new(n[0], n[1], n[2], 0.0),
+        ));
+        (self.vertices.len() - 1) as IndexCoord
+    }
+
+    //a size x size square of subdivisions^2 quads facing cross(u, v), centered on
+    //center, uvs along u and v
+    fn grid(&mut self, center: [f32; 3], u: [f32; 3], v: [f32; 3], size: f32, subdivisions: u32) {
+        let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+        let row = subdivisions + 1;
+        let first = self.vertices.len() as IndexCoord;
+        for j in 0..row {
+            for i in 0..row {
+                let (s, t) = (i as f32 / subdivisions as f32, j as f32 / subdivisions as f32);
+                let p = [0, 1, 2].map(|k| center[k] + ((s - 0.5) * u[k] + (t - 0.5) * v[k]) * size);
+                self.vertex(p, [s, t], n);
+            }
+        }
+        for j in 0..subdivisions {
+            for i in 0..subdivisions {
+                let a = first + j * row + i;
+                self.indeces.extend_from_slice(&[a, a + 1, a + row + 1, a, a + row + 1, a + row]);
+            }
+        }
+    }
+
+    //the profile spun around z, bottom to top, poles (radius 0) get no sliver triangles
+    fn lathe(&mut self, profile: &[ProfilePoint], segments: u32) {
+        let row = segments + 1;
+        let first = self.vertices.len() as IndexCoord;
+        for point in profile.iter() {
+            for j in 0..row {
+                let u = j as f32 / segments as f32;
+                let (sin, cos) = (u * TAU).sin_cos();
+                let (sin, cos) = if j == segments { (0.0, 1.0) } else { (sin, cos) };
+                self.vertex(
+                    [point.radius * cos, point.radius * sin, point.z],
+                    [u, point.v],
+                    [point.normal[0] * cos, point.normal[0] * sin, point.normal[1]],
+                );
+            }
+        }
+        for (i, pair) in profile.windows(2).enumerate() {
+            for j in 0..segments {
+                let a = first + i as IndexCoord * row + j;
+                let (b, c, d) = (a + 1, a + row + 1, a + row);
+                if pair[0].radius != 0.0 {
+                    self.indeces.extend_from_slice(&[a, b, c]);
+                }
+                if pair[1].radius != 0.0 {
+                    self.indeces.extend_from_slice(&[a, c, d]);
+                }
+            }
+        }
+    }
+
+    //a flat cap at z, facing +z when up, otherwise -z
+    fn disc(&mut self, z: f32, radius: f32, segments: u32, up: bool) {
+        let sign = if up { 1.0 } else { -1.0 };
+        let center = self.vertex([0.0, 0.0, z], [0.5, 0.5], [0.0, 0.0, sign]);
+        for j in 0..=segments {
+            let (sin, cos) = (j as f32 / segments as f32 * TAU).sin_cos();
+            //seen from below the circle runs the other way, so do the uvs
+            self.vertex(
+                [radius * cos, radius * sin, z],
+                [0.5 + 0.5 * cos, 0.5 + 0.5 * sin * sign],
+                [0.0, 0.0, sign],
+            );
+        }
+        for j in 0..segments {
+            let (a, b) = (center + 1 + j, center + 2 + j);
+            let triangle = if up { [center, a, b] } else { [center, b, a] };
+            self.indeces.extend_from_slice(&triangle);
+        }
+    }
+
+    fn build(self, name: &str) -> RenderResult<Model> {
+        let mut model = Model{
+            submeshes: vec![SubMesh::new(name, 0, self.indeces.len(), None)],
+            ..Model::new(name, Mesh::NormalMesh(self.vertices), self.indeces)
+        };
+        generate_tangents(&mut model)?;
+        compute_bounds(&mut model)?;
+        apply_conversion(&mut model, &Conversion::blender_to_vulkan())?;
+        Ok(model)
+    }
+}
+
+//a hemisphere's outline, from its pole (bottom when below) to its equator or the other way
+fn hemisphere(radius: f32, z: f32, rings: u32, below: bool) -> Vec<ProfilePoint> {
+    (0..=rings).map(|i| {
+        let phi = if below {
+            -PI / 2.0 + PI / 2.0 * i as f32 / rings as f32
+        } else {
+            PI / 2.0 * i as f32 / rings as f32
+        };
+        let (sin, cos) = phi.sin_cos();
+        let pole = (below && i == 0) || (!below && i == rings);
+        ProfilePoint{
+            radius: if pole { 0.0 } else { radius * cos },
+            z: z + radius * sin,
+            normal: if pole { [0.0, sin.signum()] } else { [cos, sin] },
+            v: 0.0,
+        }
+    }).collect()
+}
+
+//v from 0 to 1 along the outline's length
+fn arc_length_v(profile: &mut [ProfilePoint]) {
+    let mut lengths = vec![0.0];
+    for pair in profile.windows(2) {
+        let (dr, dz) = (pair[1].radius - pair[0].radius, pair[1].z - pair[0].z);
+        lengths.push(lengths.last().unwrap() + (dr * dr + dz * dz).sqrt());
+    }
+    let total = lengths.last().copied().unwrap_or(0.0).max(f32::MIN_POSITIVE);
+    profile.iter_mut().zip(lengths).for_each(|(p, l)| p.v = l / total);
+}
+
+fn icosphere(builder: &mut Builder, radius: f32, subdivisions: u32) {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut points: Vec<[f32; 3]> = vec![
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+    ];
+    let mut triangles: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+    let unit = |p: [f32; 3]| {
+        let length = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt();
+        p.map(|c| c / length)
+    };
+    points = points.into_iter().map(unit).collect();
+    for _ in 0..subdivisions {
+        let mut midpoints: std::collections::HashMap<(usize, usize), usize> = Default::default();
+        let mut midpoint = |a: usize, b: usize, points: &mut Vec<[f32; 3]>| -> usize {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push(unit([0, 1, 2].map(|k| points[a][k] + points[b][k])));
+                points.len() - 1
+            })
+        };
+        triangles = triangles.into_iter().flat_map(|[a, b, c]| {
+            let ab = midpoint(a, b, &mut points);
+            let bc = midpoint(b, c, &mut points);
+            let ca = midpoint(c, a, &mut points);
+            [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+        }).collect();
+    }
+    //equirectangular uvs, a triangle straddling the seam gets copies of the vertices on
+    //the u = 0 side moved to u = 1, shared by every triangle that needs them
+    let uv = |p: &[f32; 3]| [p[1].atan2(p[0]) / TAU + 0.5, 0.5 + p[2].asin() / PI];
+    let first = builder.vertices.len() as IndexCoord;
+    for p in points.iter() {
+        builder.vertex(p.map(|c| c * radius), uv(p), *p);
+    }
+    let mut copies: std::collections::HashMap<usize, IndexCoord> = Default::default();
+    for triangle in triangles {
+        let uvs = triangle.map(|i| uv(&points[i]));
+        let wraps = uvs.iter().any(|uv| uv[0] > 0.75) && uvs.iter().any(|uv| uv[0] < 0.25);
+        let corners = triangle.map(|i| {
+            let [u, v] = uv(&points[i]);
+            if wraps && u < 0.5 {
+                *copies.entry(i).or_insert_with(|| {
+                    builder.vertex(points[i].map(|c| c * radius), [u + 1.0, v], points[i])
+                })
+            } else {
+                first + i as IndexCoord
+            }
+        });
+        builder.indeces.extend_from_slice(&corners);
+    }
+}
+
+fn check(primitive: &Primitive) -> RenderResult<()> {
+    //(count, the least it can be)
+    let (sizes, counts): (Vec<f32>, Vec<(u32, u32)>) = match *primitive {
+        Primitive::Plane{ size, subdivisions } => (vec![size], vec![(subdivisions, 1)]),
+        Primitive::Cube{ size, subdivisions } => (vec![size], vec![(subdivisions, 1)]),
+        Primitive::UvSphere{ radius, segments, rings } => {
+            (vec![radius], vec![(segments, 3), (rings, 2)])
+        },
+        Primitive::Icosphere{ radius, subdivisions } => {
+            //past 8 it's millions of triangles
+            if subdivisions > 8 {
+                return Err(format!("icosphere subdivisions {subdivisions} is more than 8"));
+            }
+            (vec![radius], vec![])
+        },
+        Primitive::Cylinder{ radius, height, segments } => {
+            (vec![radius, height], vec![(segments, 3)])
+        },
+        Primitive::Cone{ radius, height, segments } => (vec![radius, height], vec![(segments, 3)]),
+        Primitive::Capsule{ radius, height, segments, rings } => {
+            //no straight part is a sphere
+            if height < 0.0 || !height.is_finite() {
+                return Err(format!("capsule height {height} is negative"));
+            }
+            (vec![radius], vec![(segments, 3), (rings, 1)])
+        },
+        Primitive::Torus{ major_radius, minor_radius, major_segments, minor_segments } => {
+            if minor_radius >= major_radius {
+                return Err(format!(
+                    "torus minor radius {minor_radius} has to be less than {major_radius}",
+                ));
+            }
+            (vec![major_radius, minor_radius], vec![(major_segments, 3), (minor_segments, 3)])
+        },
+    };
+    if let Some(size) = sizes.iter().find(|s| !(s.is_finite() && **s > 0.0)) {
+        return Err(format!("{} sizes must be positive, got {size}", primitive.name()));
+    }
+    if let Some((count, least)) = counts.iter().find(|(count, least)| count < least) {
+        return Err(format!("{} needs at least {least} segments, got {count}", primitive.name()));
+    }
+    Ok(())
+}
+
+pub fn make_primitive(primitive: &Primitive) -> RenderResult<Model> {
+    check(primitive)?;
+    let mut builder = Builder::default();
+    match *primitive {
+        Primitive::Plane{ size, subdivisions } => {
+            builder.grid([0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], size, subdivisions);
+        },
+        Primitive::Cube{ size, subdivisions } => {
+            let h = size / 2.0;
+            //(normal, u, v), cross(u, v) is the normal
+            let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+                ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+                ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+                ([0.0, 1.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+                ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+                ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+                ([0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            ];
+            for (n, u, v) in faces {
+                builder.grid(n.map(|c| c * h), u, v, size, subdivisions);
+            }
+        },
+        Primitive::UvSphere{ radius, segments, rings } => {
+            let profile: Vec<ProfilePoint> = (0..=rings).map(|i| {
+                let phi = -PI / 2.0 + PI * i as f32 / rings as f32;
+                let (sin, cos) = phi.sin_cos();
+                let pole = i == 0 || i == rings;
+                ProfilePoint{
+                    radius: if pole { 0.0 } else { radius * cos },
+                    z: radius * sin,
+                    normal: if pole { [0.0, sin.signum()] } else { [cos, sin] },
+                    v: i as f32 / rings as f32,
+                }
+            }).collect();
+            builder.lathe(&profile, segments);
+        },
+        Primitive::Icosphere{ radius, subdivisions } => {
+            icosphere(&mut builder, radius, subdivisions);
+        },
+        Primitive::Cylinder{ radius, height, segments } => {
+            let h = height / 2.0;
+            let side = |z: f32, v: f32| ProfilePoint{ radius, z, normal: [1.0, 0.0], v };
+            builder.lathe(&[side(-h, 0.0), side(h, 1.0)], segments);
+            builder.disc(-h, radius, segments, false);
+            builder.disc(h, radius, segments, true);
+        },
+        Primitive::Cone{ radius, height, segments } => {
+            let h = height / 2.0;
+            let slant = (height * height + radius * radius).sqrt();
+            let normal = [height / slant, radius / slant];
+            builder.lathe(&[
+                ProfilePoint{ radius, z: -h, normal, v: 0.0 },
+                ProfilePoint{ radius: 0.0, z: h, normal, v: 1.0 },
+            ], segments);
+            builder.disc(-h, radius, segments, false);
+        },
+        Primitive::Capsule{ radius, height, segments, rings } => {
+            let h = height / 2.0;
+            let mut profile = hemisphere(radius, -h, rings, true);
+            profile.extend(hemisphere(radius, h, rings, false));
+            arc_length_v(&mut profile);
+            builder.lathe(&profile, segments);
+        },
+        Primitive::Torus{ major_radius, minor_radius, major_segments, minor_segments } => {
+            //around the tube starting on the inside, which is where the outline heads down
+            let profile: Vec<ProfilePoint> = (0..=minor_segments).map(|i| {
+                let v = i as f32 / minor_segments as f32;
+                let (sin, cos) = (-PI + v * TAU).sin_cos();
+                let (sin, cos) = if i == minor_segments { (0.0, -1.0) } else { (sin, cos) };
+                ProfilePoint{
+                    radius: major_radius + minor_radius * cos,
+                    z: minor_radius * sin,
+                    normal: [cos, sin],
+                    v,
+                }
+            }).collect();
+            builder.lathe(&profile, major_segments);
+        },
+    }
+    builder.build(primitive.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use crate::{
+        exporter::MeshAttributes,
+        model::{
+            primitives::{make_primitive, Primitive},
+            Mesh, Model,
+        },
+    };
+
+    //divergence theorem, positive when every triangle faces out
+    fn volume(model: &Model) -> f32 {
+        let positions = MeshAttributes::new(&model.mesh).positions;
+        model.indeces.chunks_exact(3).map(|t| {
+            let [a, b, c] = [0, 1, 2].map(|k| positions[t[k] as usize]);
+            let cross = [
+                b[1] * c[2] - b[2] * c[1], b[2] * c[0] - b[0] * c[2], b[0] * c[1] - b[1] * c[0],
+            ];
+            (a[0] * cross[0] + a[1] * cross[1] + a[2] * cross[2]) / 6.0
+        }).sum()
+    }
+
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+    }
+
+    #[test]
+    fn primitives_are_well_formed() {
+        for primitive in Primitive::DEFAULTS.iter() {
+            let model = make_primitive(primitive).unwrap();
+            let name = primitive.name();
+            let Mesh::TangentMesh(vertices) = &model.mesh else {
+                panic!("{name} isn't a TangentMesh");
+            };
+            assert!(!model.indeces.is_empty() && model.indeces.len().is_multiple_of(3), "{name}");
+            assert!(model.indeces.iter().all(|i| (*i as usize) < vertices.len()), "{name}");
+            assert_eq!(model.submeshes[0].index_count, model.indeces.len());
+            for v in vertices.iter() {
+                let n = [v.norm.x, v.norm.y, v.norm.z];
+                let t = [v.tangent.x, v.tangent.y, v.tangent.z];
+                assert!((dot(&n, &n) - 1.0).abs() < 1e-4, "{name} normal {n:?}");
+                assert!(dot(&n, &t).abs() < 1e-3, "{name} tangent {t:?} normal {n:?}");
+                assert!(v.tangent.w.abs() == 1.0, "{name}");
+                assert!((0.0..=1.0).contains(&v.uv.y), "{name} uv {}", v.uv.y);
+                let p = [v.pos.x, v.pos.y, v.pos.z];
+                assert!(model.bounds.aabb.contains(&p), "{name} bounds");
+            }
+            //vertex normals agree with the winding
+            let positions = MeshAttributes::new(&model.mesh).positions;
+            for t in model.indeces.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|k| positions[t[k] as usize]);
+                let (u, w) = ([0, 1, 2].map(|k| b[k] - a[k]), [0, 1, 2].map(|k| c[k] - a[k]));
+                let face = [
+                    u[1] * w[2] - u[2] * w[1],
+                    u[2] * w[0] - u[0] * w[2],
+                    u[0] * w[1] - u[1] * w[0],
+                ];
+                for i in t.iter() {
+                    let n = &vertices[*i as usize].norm;
+                    assert!(dot(&face, &[n.x, n.y, n.z]) > 0.0, "{name} faces in at {i}");
+                }
+            }
+            assert_eq!(Primitive::from_id(&primitive.id()), Some(*primitive));
+        }
+        assert_eq!(Primitive::from_id("builtin:teapot"), None);
+        assert_eq!(Primitive::from_id("cube"), None);
+    }
+
+    #[test]
+    fn primitives_volumes() {
+        let close = |primitive: Primitive, expected: f32, tolerance: f32| {
+            let found = volume(&make_primitive(&primitive).unwrap());
+            assert!((found - expected).abs() <= expected * tolerance, "{primitive:?} {found}");
+        };
+        close(Primitive::Cube{ size: 2.0, subdivisions: 3 }, 8.0, 1e-5);
+        let sphere = 4.0 / 3.0 * PI;
+        close(Primitive::UvSphere{ radius: 1.0, segments: 64, rings: 32 }, sphere, 0.01);
+        close(Primitive::Icosphere{ radius: 1.0, subdivisions: 4 }, sphere, 0.01);
+        close(Primitive::Cylinder{ radius: 1.0, height: 2.0, segments: 128 }, 2.0 * PI, 0.01);
+        close(Primitive::Cone{ radius: 1.0, height: 3.0, segments: 128 }, PI, 0.01);
+        let capsule = PI * 0.25 * 2.0 + 4.0 / 3.0 * PI * 0.125;
+        close(
+            Primitive::Capsule{ radius: 0.5, height: 2.0, segments: 64, rings: 16 }, capsule, 0.01,
+        );
+        let torus = 2.0 * PI * PI * 2.0 * 0.25;
+        close(
+            Primitive::Torus{
+                major_radius: 2.0, minor_radius: 0.5, major_segments: 96, minor_segments: 32,
+            },
+            torus, 0.01,
+        );
+        //the plane isn't closed, it's a flat square
+        let plane = make_primitive(&Primitive::Plane{ size: 2.0, subdivisions: 4 }).unwrap();
+        assert_eq!(plane.indeces.len(), 4 * 4 * 6);
+        assert!(volume(&plane).abs() < 1e-6);
+
+        //tessellation is what was asked for
+        let sphere = make_primitive(&Primitive::UvSphere{ radius: 1.0, segments: 8, rings: 4 })
+            .unwrap();
+        assert_eq!(sphere.indeces.len() / 3, 2 * 8 * (4 - 1));
+        let ico = make_primitive(&Primitive::Icosphere{ radius: 1.0, subdivisions: 2 }).unwrap();
+        assert_eq!(ico.indeces.len() / 3, 20 * 16);
+        let thin = Primitive::UvSphere{ radius: 1.0, segments: 2, rings: 4 };
+        assert!(make_primitive(&thin).is_err());
+        assert!(make_primitive(&Primitive::Cube{ size: -1.0, subdivisions: 1 }).is_err());
+        let bad_torus = Primitive::Torus{
+            major_radius: 1.0, minor_radius: 1.0, major_segments: 8, minor_segments: 8,
+        };
+        assert!(make_primitive(&bad_torus).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+/* Quantized vertices, a compressed copy of a Model's mesh for the GPU. Each attribute the
+ * mesh has is packed, in order:
+ *    position   4 x unorm16 against the model bounds, or 4 x f16 as is, w is padding
+ *    uv         2 x unorm16 against the uv bounds
+ *    normal     2 x snorm16, octahedral
+ *    color      4 x unorm8
+ *    tangent    4 x snorm16, octahedral xy, the bitangent sign, padding
+ * A NormalVertex goes from 40 bytes to 16. Normalized values come out of the vertex
+ * fetch in [0, 1] and Dequantize has the offset and scale that take them back, w of
+ * the position included, see shader/quantized/quantized.vert for the decode.
+ * Indeces are 16 bit whenever every vertex can be reached with them.
+ * Quantizing also decodes every vertex again and reports the worst error per attribute.
+ * generate_quantized keeps the copy with the Model, cooked models store it and the
+ * renderer draws it instead of the f32 vertices when there is one.
+ */
+
+use std::fmt::Display;
+
+use half::f16;
+
+use super::{
+    layout::{layout_stride, mesh_layout, ComponentType, Semantic, VertexAttribute},
+    IndexCoord, IndexSlice, MeshSlices, Model,
+};
+
+use crate::{
+    exporter::MeshAttributes,
+    RenderResult,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum PositionEncoding {
+    //unorm16 against the bounds, the same precision everywhere in the model
+    #[default]
+    Normalized,
+    //f16, more precision near the origin, no bounds needed
+    Half,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct QuantizeSettings {
+    pub position: PositionEncoding,
+}
+
+//push constants for the vertex shader, decoded = offset + quantized * scale
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Dequantize {
+    pub position_offset: [f32; 4],
+    pub position_scale: [f32; 4],
+    //xy offset, zw scale
+    pub uv_offset_scale: [f32; 4],
+}
+
+pub enum QuantizedIndeces {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+pub struct QuantizedMesh {
+    pub layout: Vec<VertexAttribute>,
+    pub vertices: Vec<u8>,
+    pub indeces: QuantizedIndeces,
+    pub dequantize: Dequantize,
+    pub report: QuantizationReport,
+}
+
+impl QuantizedMesh {
+    //after the model's vertices were reordered, vertex_order[new] = old, and its
+    //indeces replaced by these
+    pub(crate) fn renumber(&mut self, vertex_order: &[usize], indeces: &[IndexCoord]) {
+        let stride = layout_stride(&self.layout);
+        let old = std::mem::take(&mut self.vertices);
+        self.vertices = vertex_order.iter()
+            .flat_map(|v| &old[v * stride..(v + 1) * stride])
+            .copied()
+            .collect();
+        self.indeces = quantized_indeces(indeces, vertex_order.len());
+    }
+
+    pub fn mesh_slices(&self) -> MeshSlices<'_> {
+        MeshSlices{
+            vertices: &self.vertices,
+            stride: layout_stride(&self.layout),
+            layout: &self.layout,
+            indeces: match &self.indeces {
+                QuantizedIndeces::U16(indeces) => IndexSlice::U16(indeces),
+                QuantizedIndeces::U32(indeces) => IndexSlice::U32(indeces),
+            },
+        }
+    }
+}
+
+//the worst error of any vertex, positions and uvs as distances, directions in degrees
+#[derive(Clone, Debug, Default)]
+pub struct QuantizationReport {
+    pub position_error: f32,
+    pub uv_error: f32,
+    pub normal_error: f32,
+    pub tangent_error: f32,
+    pub color_error: f32,
+    pub vertex_bytes: usize,
+    pub quantized_vertex_bytes: usize,
+    pub index_bytes: usize,
+    pub quantized_index_bytes: usize,
+}
+
+impl Display for QuantizationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f, "vertices {} -> {} bytes, indeces {} -> {} bytes",
+            self.vertex_bytes, self.quantized_vertex_bytes,
+            self.index_bytes, self.quantized_index_bytes,
+        )?;
+        writeln!(f, "max position error {:e}", self.position_error)?;
+        writeln!(f, "max uv error {:e}", self.uv_error)?;
+        writeln!(f, "max normal error {:e} degrees", self.normal_error)?;
+        writeln!(f, "max tangent error {:e} degrees", self.tangent_error)?;
+        write!(f, "max color error {:e}", self.color_error)
+    }
+}
+
+fn unorm16(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+fn from_unorm16(q: u16) -> f32 {
+    q as f32 / 65535.0
+}
+
+fn snorm16(v: f32) -> i16 {
+    (v.clamp(-1.0, 1.0) * 32767.0).round() as i16
+}
+
+fn from_snorm16(q: i16) -> f32 {
+    (q as f32 / 32767.0).max(-1.0)
+}
+
+fn unorm8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn sign(v: f32) -> f32 {
+    if v >= 0.0 { 1.0 } else { -1.0 }
+}
+
+//the unit sphere folded onto a square, [-1, 1] on both axes
+pub fn octahedral_encode(n: [f32; 3]) -> [f32; 2] {
+    let l1 = n[0].abs() + n[1].abs() + n[2].abs();
+    if l1 == 0.0 {
+        return [0.0, 0.0];
+    }
+    let (x, y) = (n[0] / l1, n[1] / l1);
+    if n[2] >= 0.0 {
+        [x, y]
+    } else {
+        [(1.0 - y.abs()) * sign(x), (1.0 - x.abs()) * sign(y)]
+    }
+}
+
+pub fn octahedral_decode(e: [f32; 2]) -> [f32; 3] {
+    let mut n = [e[0], e[1], 1.0 - e[0].abs() - e[1].abs()];
+    let t = (-n[2]).max(0.0);
+    n[0] -= t * sign(n[0]);
+    n[1] -= t * sign(n[1]);
+    normalize(n)
+}
+
+fn normalize(n: [f32; 3]) -> [f32; 3] {
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length == 0.0 {
+        return n;
+    }
+    [n[0] / length, n[1] / length, n[2] / length]
+}
+
+fn distance<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+}
+
+fn angle_degrees(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let (a, b) = (normalize(a), normalize(b));
+    let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    dot.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+//min and max of every component
+fn bounds<const N: usize>(values: &[[f32; N]]) -> ([f32; N], [f32; N]) {
+    let mut min = [f32::MAX; N];
+    let mut max = [f32::MIN; N];
+    for value in values {
+        for c in 0..N {
+            min[c] = min[c].min(value[c]);
+            max[c] = max[c].max(value[c]);
+        }
+    }
+    if values.is_empty() {
+        return ([0.0; N], [0.0; N]);
+    }
+    (min, max)
+}
+
+//quantized = (v - offset) / scale, a flat axis keeps a scale of 0 and decodes to offset
+fn to_unit(v: f32, offset: f32, scale: f32) -> f32 {
+    if scale == 0.0 { 0.0 } else { (v - offset) / scale }
+}
+
+//same cutoff as the cooked format
+pub(crate) fn quantized_indeces(indeces: &[IndexCoord], vertex_count: usize) -> QuantizedIndeces {
+    if vertex_count <= u16::MAX as usize + 1 {
+        QuantizedIndeces::U16(indeces.iter().map(|i| *i as u16).collect())
+    } else {
+        QuantizedIndeces::U32(indeces.to_vec())
+    }
+}
+
+pub fn quantize(model: &Model, settings: &QuantizeSettings) -> RenderResult<QuantizedMesh> {
+    let attributes = MeshAttributes::new(&model.mesh);
+    let vertex_count = attributes.positions.len();
+    let mut report = QuantizationReport{
+        vertex_bytes: vertex_count * layout_stride(mesh_layout(&model.mesh)),
+        index_bytes: model.indeces.len() * 4,
+        ..Default::default()
+    };
+
+    let mut layout: Vec<VertexAttribute> = vec![];
+    let mut stride = 0;
+    let mut add = |semantic, component_type, components| {
+        let attribute = VertexAttribute::typed(semantic, component_type, components, stride);
+        stride += attribute.size();
+        layout.push(attribute);
+    };
+    add(Semantic::Position, match settings.position {
+        PositionEncoding::Normalized => ComponentType::Unorm16,
+        PositionEncoding::Half => ComponentType::F16,
+    }, 4);
+    if attributes.uvs.is_some() {
+        add(Semantic::Uv, ComponentType::Unorm16, 2);
+    }
+    if attributes.normals.is_some() {
+        add(Semantic::Normal, ComponentType::Snorm16, 2);
+    }
+    if attributes.colors.is_some() {
+        add(Semantic::Color, ComponentType::Unorm8, 4);
+    }
+    if attributes.tangents.is_some() {
+        add(Semantic::Tangent, ComponentType::Snorm16, 4);
+    }
+
+    let (position_offset, position_scale) = match settings.position {
+        PositionEncoding::Normalized => {
+            let (min, max) = bounds(&attributes.positions);
+            (
+                [min[0], min[1], min[2], 1.0],
+                [max[0] - min[0], max[1] - min[1], max[2] - min[2], 0.0],
+            )
+        },
+        PositionEncoding::Half => {
+            let too_large = attributes.positions.iter().flatten()
+                .find(|c| !f16::from_f32(**c).is_finite());
+            if let Some(c) = too_large {
+                return Err(format!(
+                    "position component {c} of '{}' is out of range of f16", model.name,
+                ));
+            }
+            ([0.0; 4], [1.0; 4])
+        },
+    };
+    let (uv_min, uv_max) = bounds(attributes.uvs.as_deref().unwrap_or(&[]));
+    let uv_offset_scale = [uv_min[0], uv_min[1], uv_max[0] - uv_min[0], uv_max[1] - uv_min[1]];
+
+    let mut vertices: Vec<u8> = Vec::with_capacity(vertex_count * stride);
+    for (i, position) in attributes.positions.iter().enumerate() {
+        let decoded: [f32; 3] = match settings.position {
+            PositionEncoding::Normalized => {
+                let mut decoded = [0.0; 3];
+                for c in 0..3 {
+                    let q = unorm16(to_unit(position[c], position_offset[c], position_scale[c]));
+                    vertices.extend_from_slice(&q.to_ne_bytes());
+                    decoded[c] = position_offset[c] + from_unorm16(q) * position_scale[c];
+                }
+                vertices.extend_from_slice(&0u16.to_ne_bytes());
+                decoded
+            },
+            PositionEncoding::Half => {
+                let mut decoded = [0.0; 3];
+                for c in 0..3 {
+                    let q = f16::from_f32(position[c]);
+                    vertices.extend_from_slice(&q.to_ne_bytes());
+                    decoded[c] = q.to_f32();
+                }
+                vertices.extend_from_slice(&f16::ONE.to_ne_bytes());
+                decoded
+            },
+        };
+        report.position_error = report.position_error.max(distance(position, &decoded));
+
+        if let Some(uvs) = &attributes.uvs {
+            let mut decoded = [0.0; 2];
+            for c in 0..2 {
+                let (offset, scale) = (uv_offset_scale[c], uv_offset_scale[c + 2]);
+                let q = unorm16(to_unit(uvs[i][c], offset, scale));
+                vertices.extend_from_slice(&q.to_ne_bytes());
+                decoded[c] = offset + from_unorm16(q) * scale;
+            }
+            report.uv_error = report.uv_error.max(distance(&uvs[i], &decoded));
+        }
+        if let Some(normals) = &attributes.normals {
+            let q = octahedral_encode(normals[i]).map(snorm16);
+            q.iter().for_each(|q| vertices.extend_from_slice(&q.to_ne_bytes()));
+            let decoded = octahedral_decode(q.map(from_snorm16));
+            if normals[i] != [0.0; 3] {
+                report.normal_error = report.normal_error.max(angle_degrees(normals[i], decoded));
+            }
+        }
+        if let Some(colors) = &attributes.colors {
+            let q = colors[i].map(unorm8);
+            vertices.extend_from_slice(&q);
+            let decoded = q.map(|q| q as f32 / 255.0);
+            for c in 0..4 {
+                let error = (colors[i][c].clamp(0.0, 1.0) - decoded[c]).abs();
+                report.color_error = report.color_error.max(error);
+            }
+        }
+        if let Some(tangents) = &attributes.tangents {
+            let [x, y, z, w] = tangents[i];
+            let [ex, ey] = octahedral_encode([x, y, z]).map(snorm16);
+            for q in [ex, ey, snorm16(sign(w)), 0] {
+                vertices.extend_from_slice(&q.to_ne_bytes());
+            }
+            let decoded = octahedral_decode([from_snorm16(ex), from_snorm16(ey)]);
+            if [x, y, z] != [0.0; 3] {
+                report.tangent_error = report.tangent_error.max(angle_degrees([x, y, z], decoded));
+            }
+        }
+    }
+
+    let indeces = quantized_indeces(&model.indeces, vertex_count);
+    report.quantized_vertex_bytes = vertices.len();
+    report.quantized_index_bytes = match &indeces {
+        QuantizedIndeces::U16(indeces) => indeces.len() * 2,
+        QuantizedIndeces::U32(indeces) => indeces.len() * 4,
+    };
+    Ok(QuantizedMesh{
+        layout,
+        vertices,
+        indeces,
+        dequantize: Dequantize{
+            position_offset,
+            position_scale,
+            uv_offset_scale,
+        },
+        report,
+    })
+}
+
+pub fn generate_quantized(model: &mut Model, settings: &QuantizeSettings) -> RenderResult<()> {
+    model.quantized = Some(quantize(model, settings)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use crate::{
+        importer::{
+            collada::Collada,
+            ply::Ply,
+            Importer,
+        },
+        model::{
+            layout::{Semantic, VertexInput},
+            optimize::{optimize, OptimizeSettings},
+            quantize::{
+                generate_quantized, octahedral_decode, octahedral_encode, quantize,
+                PositionEncoding, QuantizeSettings, QuantizedIndeces,
+            },
+            tangents::generate_tangents,
+            Mesh, Model, PostionVertex, Vector4,
+        },
+    };
+
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    #[test]
+    fn quantize_octahedral() {
+        let steps = 24;
+        for i in 0..=steps {
+            for j in 0..steps {
+                let theta = std::f32::consts::PI * i as f32 / steps as f32;
+                let phi = 2.0 * std::f32::consts::PI * j as f32 / steps as f32;
+                let n = [theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()];
+                let e = octahedral_encode(n);
+                assert!(e[0].abs() <= 1.0 && e[1].abs() <= 1.0, "{n:?} encodes to {e:?}");
+                let d = octahedral_decode(e);
+                for c in 0..3 {
+                    assert!((n[c] - d[c]).abs() < 1e-5, "{n:?} decodes to {d:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_collada() {
+        let mut model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        let quantized = quantize(&model, &QuantizeSettings::default()).unwrap();
+        let slices = quantized.mesh_slices();
+        assert_eq!(slices.stride, 16, "NormalVertex down from 40 bytes");
+        assert_eq!(slices.vertex_count(), model.get_vertices().unwrap().len());
+        assert!(matches!(quantized.indeces, QuantizedIndeces::U16(_)));
+        assert_eq!(slices.indeces.len(), model.indeces.len());
+        let report = &quantized.report;
+        assert_eq!(report.quantized_index_bytes * 2, report.index_bytes);
+        let extent = quantized.dequantize.position_scale.iter().fold(0.0f32, |a, b| a.max(*b));
+        assert!(report.position_error <= extent / 65535.0, "{report}");
+        assert!(report.uv_error <= 1.0 / 65535.0, "{report}");
+        assert!(report.normal_error < 0.05, "{report}");
+        assert_eq!(report.tangent_error, 0.0, "no tangents");
+
+        let input = VertexInput::new(slices.layout, &[Semantic::Position, Semantic::Normal]);
+        let formats: Vec<vk::Format> = input.attributes.iter().map(|a| a.format).collect();
+        assert_eq!(formats, vec![
+            vk::Format::R16G16B16A16_UNORM, vk::Format::R16G16_SNORM,
+        ]);
+
+        generate_tangents(&mut model).unwrap();
+        let settings = QuantizeSettings{
+            position: PositionEncoding::Half,
+        };
+        let quantized = quantize(&model, &settings).unwrap();
+        assert_eq!(quantized.mesh_slices().stride, 24);
+        assert!(quantized.report.tangent_error < 0.05, "{}", quantized.report);
+        assert!(quantized.report.position_error < 0.01, "{}", quantized.report);
+        assert_eq!(quantized.dequantize.position_scale, [1.0; 4]);
+    }
+
+    #[test]
+    fn quantize_renumber() {
+        let mut model = Collada::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/collada_scene.dae"))
+            .unwrap();
+        generate_quantized(&mut model, &QuantizeSettings::default()).unwrap();
+        optimize(&mut model, &OptimizeSettings::default()).unwrap();
+        //reordering the copy is the same as quantizing the reordered vertices
+        let renumbered = model.quantized.as_ref().unwrap().mesh_slices();
+        let quantized = quantize(&model, &QuantizeSettings::default()).unwrap();
+        let expected = quantized.mesh_slices();
+        assert_eq!(renumbered.vertices, expected.vertices);
+        assert_eq!(renumbered.indeces.as_bytes(), expected.indeces.as_bytes());
+    }
+
+    #[test]
+    fn quantize_colors_and_limits() {
+        let ply = Ply::new()
+            .generate_model(&format!("{TEST_DIRECTORY}/ply_ascii_colors.ply"))
+            .unwrap();
+        let quantized = quantize(&ply, &QuantizeSettings::default()).unwrap();
+        assert!(quantized.report.color_error <= 0.5 / 255.0, "{}", quantized.report);
+
+        //too many vertices for 16 bit indeces
+        let count = u16::MAX as usize + 2;
+        let mesh = Mesh::PositionMesh(
+            (0..count).map(|i| PostionVertex(Vector4::new(i as f32, 0.0, 0.0, 1.0))).collect(),
+        );
+        let model = Model::new("points", mesh, vec![0, 1, count as u32 - 1]);
+        let quantized = quantize(&model, &QuantizeSettings::default()).unwrap();
+        assert!(matches!(quantized.indeces, QuantizedIndeces::U32(_)));
+        //and too far out for f16
+        let settings = QuantizeSettings{
+            position: PositionEncoding::Half,
+        };
+        assert!(quantize(&model, &settings).is_err());
+    }
+}
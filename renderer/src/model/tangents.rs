@@ -0,0 +1,306 @@
+/* Tangent generation following MikkTSpace, the tangent space Blender bakes normal maps in,
+ * so baked maps come out the same as they do in Blender.
+ * Per triangle the uv derivative of the position gives a tangent direction and whether
+ * the uv mapping is mirrored. At every corner that direction is projected onto the
+ * vertex normal's plane and summed, weighted by the corner angle, across all triangles
+ * sharing the vertex with the same mirroring. Vertices are matched on their exact
+ * position, normal and uv, same as MikkTSpace's own welding.
+ * Differences from the reference implementation: a vertex whose fan is split into
+ * disconnected pieces (non manifold) is treated as one group, and the angular
+ * threshold splitting of groups is always off, which is MikkTSpace's default anyway.
+ */
+
+use std::collections::HashMap;
+
+use super::{
+    normals::{cross, dot, normalize, sub, Vec3},
+    IndexCoord, Mesh, Model, NormalVertex, TangentVector, TangentVertex,
+};
+
+use crate::RenderResult;
+
+//same idea as the normals, tangents are quantized before deduplicating on them
+const TANGENT_QUANTIZATION: f32 = 1.0e5;
+
+struct TriangleSpace {
+    //unit direction of increasing u, zero if the uvs are degenerate
+    tangent: Vec3,
+    //uv winding matches the position winding, false for mirrored uvs
+    orientation_preserving: bool,
+}
+
+//NormalMesh or TangentMesh in, TangentMesh out
+//vertices shared by mirrored and non mirrored triangles are split, the handedness differs
+pub fn generate_tangents(model: &mut Model) -> RenderResult<()> {
+    let vertices: Vec<NormalVertex> = match &model.mesh {
+        Mesh::NormalMesh(vertices) => vertices.clone(),
+        Mesh::TangentMesh(vertices) => vertices.iter()
+            .map(|v| NormalVertex::new(v.pos, v.uv, v.norm))
+            .collect(),
+        _ => {
+            return Err(format!(
+                "can't generate tangents for '{}', it needs normals and uvs", model.name,
+            ));
+        },
+    };
+    if !model.indeces.len().is_multiple_of(3) {
+        return Err(format!(
+            "can't generate tangents for '{}', index count {} is not a multiple of 3",
+            model.name, model.indeces.len(),
+        ));
+    }
+    if let Some(index) = model.indeces.iter().find(|i| **i as usize >= vertices.len()) {
+        return Err(format!(
+            "can't generate tangents for '{}', index {index} is out of range", model.name,
+        ));
+    }
+    let corner_tangents = corner_tangents(&vertices, &model.indeces);
+    //(source vertex, quantized tangent, handedness) -> new vertex
+    let mut vertex_map: HashMap<(IndexCoord, [i32; 4]), IndexCoord> = HashMap::new();
+    let mut tangent_vertices: Vec<TangentVertex> = vec![];
+    let indeces = model.indeces.iter().zip(corner_tangents.iter()).map(|(i, t)| {
+        let key = t.map(|c| (c * TANGENT_QUANTIZATION).round() as i32);
+        *vertex_map.entry((*i, key)).or_insert_with(|| {
+            let vertex = &vertices[*i as usize];
+            tangent_vertices.push(TangentVertex{
+                pos: vertex.pos,
+                uv: vertex.uv,
+                norm: vertex.norm,
+                tangent: TangentVector::new(t[0], t[1], t[2], t[3]),
+            });
+            (tangent_vertices.len() - 1) as IndexCoord
+        })
+    }).collect();
+    model.mesh = Mesh::TangentMesh(tangent_vertices);
+    model.indeces = indeces;
+    Ok(())
+}
+
+fn position(vertex: &NormalVertex) -> Vec3 {
+    [vertex.pos.x, vertex.pos.y, vertex.pos.z]
+}
+
+fn normal(vertex: &NormalVertex) -> Vec3 {
+    [vertex.norm.x, vertex.norm.y, vertex.norm.z]
+}
+
+//MikkTSpace's per triangle setup, the tangent is flipped back for mirrored uvs so it
+//always points towards increasing u
+fn triangle_space(vertices: &[NormalVertex], triangle: &[IndexCoord]) -> TriangleSpace {
+    let [v1, v2, v3] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+    let d1 = sub(&position(v2), &position(v1));
+    let d2 = sub(&position(v3), &position(v1));
+    let (t21x, t21y) = (v2.uv.x - v1.uv.x, v2.uv.y - v1.uv.y);
+    let (t31x, t31y) = (v3.uv.x - v1.uv.x, v3.uv.y - v1.uv.y);
+    let signed_area = t21x*t31y - t21y*t31x;
+    let orientation_preserving = signed_area > 0.0;
+    if signed_area == 0.0 {
+        return TriangleSpace{ tangent: [0.0; 3], orientation_preserving };
+    }
+    let sign = if orientation_preserving { 1.0 } else { -1.0 };
+    let os = [0, 1, 2].map(|c| sign * (t31y*d1[c] - t21y*d2[c]));
+    TriangleSpace{ tangent: normalize(&os), orientation_preserving }
+}
+
+fn project(v: &Vec3, n: &Vec3) -> Vec3 {
+    let d = dot(n, v);
+    [v[0] - n[0]*d, v[1] - n[1]*d, v[2] - n[2]*d]
+}
+
+//any unit vector perpendicular to n, for vertices where the uvs give nothing to go on
+fn perpendicular(n: &Vec3) -> Vec3 {
+    let axis = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    normalize(&cross(n, &axis))
+}
+
+//one [x, y, z, handedness] per entry in indeces
+fn corner_tangents(vertices: &[NormalVertex], indeces: &[IndexCoord]) -> Vec<[f32; 4]> {
+    let triangles: Vec<TriangleSpace> = indeces.chunks_exact(3)
+        .map(|t| triangle_space(vertices, t))
+        .collect();
+    //exact position, normal and uv -> welded id
+    let mut weld_map: HashMap<[u32; 8], usize> = HashMap::new();
+    let welded: Vec<usize> = vertices.iter().map(|v| {
+        let key = [
+            v.pos.x, v.pos.y, v.pos.z, v.norm.x, v.norm.y, v.norm.z, v.uv.x, v.uv.y,
+        ].map(|c| c.to_bits());
+        let next = weld_map.len();
+        *weld_map.entry(key).or_insert(next)
+    }).collect();
+    //(welded vertex, orientation preserving) -> angle weighted tangent sum
+    let mut sums: HashMap<(usize, bool), Vec3> = HashMap::new();
+    for (i, index) in indeces.iter().enumerate() {
+        let (t, corner) = (i / 3, i % 3);
+        let space = &triangles[t];
+        if space.tangent == [0.0; 3] {
+            continue;
+        }
+        let vertex = &vertices[*index as usize];
+        let n = normal(vertex);
+        let tangent = normalize(&project(&space.tangent, &n));
+        let p = position(vertex);
+        let prev = position(&vertices[indeces[t*3 + (corner + 2) % 3] as usize]);
+        let next = position(&vertices[indeces[t*3 + (corner + 1) % 3] as usize]);
+        let a = normalize(&project(&sub(&prev, &p), &n));
+        let b = normalize(&project(&sub(&next, &p), &n));
+        let angle = dot(&a, &b).clamp(-1.0, 1.0).acos();
+        let sum = sums.entry((welded[*index as usize], space.orientation_preserving))
+            .or_insert([0.0; 3]);
+        for (s, c) in sum.iter_mut().zip(tangent.iter()) {
+            *s += c * angle;
+        }
+    }
+    indeces.iter().enumerate().map(|(i, index)| {
+        let space = &triangles[i / 3];
+        let id = welded[*index as usize];
+        //degenerate uvs join whatever group the vertex already has
+        let (sum, orientation_preserving) = match sums.get(&(id, space.orientation_preserving)) {
+            Some(sum) => (Some(sum), space.orientation_preserving),
+            None => match sums.get(&(id, !space.orientation_preserving)) {
+                Some(sum) => (Some(sum), !space.orientation_preserving),
+                None => (None, space.orientation_preserving),
+            },
+        };
+        let n = normal(&vertices[*index as usize]);
+        let tangent = match sum.map(normalize) {
+            Some(tangent) if tangent != [0.0; 3] => tangent,
+            _ => perpendicular(&n),
+        };
+        let handedness = if orientation_preserving { 1.0 } else { -1.0 };
+        [tangent[0], tangent[1], tangent[2], handedness]
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+
+    use crate::{
+        importer::{
+            wavefront::Wavefront,
+            ImportSettings, Importer, MemoryResolver,
+        },
+        model::{
+            tangents::generate_tangents,
+            Mesh, Model, NormalVertex, PositionVector, TangentVertex, TextureVector, Vector4,
+        },
+    };
+
+    const ASSET_DIRECTORY: &str = "../assets/models/wavefront";
+    const TEST_DIRECTORY: &str = "src/importer/testdata";
+
+    //the sample assets point their .mtl at a texture that only exists on the
+    //machine that exported them, the geometry is all that matters here
+    fn load_asset(file_name: &str) -> Model {
+        let path = format!("{ASSET_DIRECTORY}/{file_name}");
+        let data = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not open '{path}' for reading."));
+        let obj: Vec<&str> = data.lines().filter(|l| !l.starts_with("mtllib ")).collect();
+        Wavefront::new(None).import_bytes(
+            obj.join("\n").as_bytes(), file_name, &MemoryResolver::new(),
+            &ImportSettings::default(),
+        ).unwrap()
+    }
+
+    fn tangent_vertices(model: &Model) -> &[TangentVertex] {
+        match &model.mesh {
+            Mesh::TangentMesh(vertices) => vertices.as_slice(),
+            _ => panic!("expected a TangentMesh"),
+        }
+    }
+
+    fn assert_tangent_space(v: &TangentVertex) {
+        let (n, t) = (&v.norm, &v.tangent);
+        let len = (t.x*t.x + t.y*t.y + t.z*t.z).sqrt();
+        assert!((len - 1.0).abs() < 1.0e-5, "tangent length {len}");
+        assert!((n.x*t.x + n.y*t.y + n.z*t.z).abs() < 1.0e-4, "tangent not orthogonal");
+        assert!(t.w == 1.0 || t.w == -1.0, "handedness {}", t.w);
+    }
+
+    #[test]
+    fn tangents_cube() {
+        let mut model = load_asset("cube.obj");
+        generate_tangents(&mut model).unwrap();
+        //face normal -> tangent, worked out from the uv layout blender exported,
+        //none of the sides are mirrored
+        let reference = [
+            ([0.0, 1.0, 0.0], [-1.0, 0.0, 0.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+            ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+        let vertices = tangent_vertices(&model);
+        assert_eq!(vertices.len(), 6*4, "vertex length");
+        for v in vertices {
+            assert_tangent_space(v);
+            let n = [v.norm.x, v.norm.y, v.norm.z];
+            let (_, expected) = reference.iter()
+                .find(|(r, _)| (0..3).all(|c| (r[c] - n[c]).abs() < 1.0e-4))
+                .expect("unexpected cube normal");
+            let t = [v.tangent.x, v.tangent.y, v.tangent.z];
+            for c in 0..3 {
+                assert!((t[c] - expected[c]).abs() < 1.0e-5, "{n:?} tangent {t:?}");
+            }
+            assert_eq!(v.tangent.w, 1.0, "{n:?} handedness");
+        }
+    }
+
+    #[test]
+    fn tangents_sphere() {
+        let mut model = load_asset("sphere.obj");
+        let index_count = model.indeces.len();
+        generate_tangents(&mut model).unwrap();
+        assert_eq!(model.indeces.len(), index_count, "index length");
+        //MikkTSpace's own output for the same corners
+        let path = format!("{TEST_DIRECTORY}/tangents_sphere.txt");
+        let reference: Vec<Vec<f32>> = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not open '{path}' for reading."))
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .map(|l| l.split(' ').map(|f| f.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(reference.len(), index_count, "reference length");
+        let vertices = tangent_vertices(&model);
+        for (corner, (i, expected)) in model.indeces.iter().zip(&reference).enumerate() {
+            let v = &vertices[*i as usize];
+            assert_tangent_space(v);
+            let t = [v.tangent.x, v.tangent.y, v.tangent.z, v.tangent.w];
+            assert!(
+                (0..4).all(|c| (t[c] - expected[c]).abs() < 1.0e-4),
+                "corner {corner} tangent {t:?}, reference {expected:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn tangents_mirrored_uvs() {
+        //two triangles in the z = 0 plane sharing the x = 1 edge, the right one has
+        //its uvs mirrored across that edge, like a symmetric model sharing a texture
+        let vertex = |x: f32, y: f32, u: f32, v: f32| NormalVertex::new(
+            PositionVector::new(x, y, 0.0, 1.0),
+            TextureVector::new(u, v),
+            Vector4::new(0.0, 0.0, 1.0, 1.0),
+        );
+        let mesh = Mesh::NormalMesh(vec![
+            vertex(0.0, 0.0, 0.0, 0.0), vertex(1.0, 0.0, 1.0, 0.0),
+            vertex(1.0, 1.0, 1.0, 1.0), vertex(2.0, 0.0, 0.0, 0.0),
+        ]);
+        let mut model = Model::new("mirrored", mesh, vec![0, 1, 2, 1, 3, 2]);
+        generate_tangents(&mut model).unwrap();
+        let vertices = tangent_vertices(&model);
+        //the shared edge splits, one copy per handedness
+        assert_eq!(vertices.len(), 6, "vertex length");
+        for t in model.indeces.chunks(3).take(1) {
+            for i in t {
+                assert_eq!(vertices[*i as usize].tangent, Vector4::new(1.0, 0.0, 0.0, 1.0));
+            }
+        }
+        for t in model.indeces.chunks(3).skip(1) {
+            for i in t {
+                assert_eq!(vertices[*i as usize].tangent, Vector4::new(-1.0, 0.0, 0.0, -1.0));
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+/* Textures as the gpu takes them. Most textures are decoded to rgba8 and that's what
+ * gets uploaded, but KTX2 and DDS files hold textures already in a gpu format, usually
+ * block compressed, with their mips, array layers and cubemap faces, and
+ * block_compression.rs makes the same at cook time. Those are kept as an EncodedTexture
+ * and uploaded as they are.
+ * The block compressed formats work on 4 x 4 texel blocks, a level that isn't a multiple
+ * of 4 is padded out to whole blocks:
+ *  BC1, rgb and 1 bit alpha, 8 bytes a block.
+ *  BC3, BC1's rgb and 8 bit alpha, 16 bytes.
+ *  BC5, two channels, normal maps' x and y, 16 bytes.
+ *  BC7, rgba, better than BC1 and BC3 at the same 16 bytes as BC3.
+ * HDR environment maps and float data textures are kept in RGBA16F or RGBA32F, little
+ * endian halfs or floats, the way the gpu and KTX2 have them.
+ */
+
+use ash::vk;
+use half::f16;
+use image::{DynamicImage, Rgba32FImage};
+
+use crate::RenderResult;
+
+use super::{
+    block_compression::decode_blocks,
+    material::{Texture, TextureImage},
+    mipmap::{mip_level_count, mip_size},
+};
+
+pub const BLOCK_SIZE: u32 = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    Rgba8,
+    Rgba8Srgb,
+    Bc1,
+    Bc1Srgb,
+    Bc3,
+    Bc3Srgb,
+    Bc5,
+    Bc7,
+    Bc7Srgb,
+    Rgba16F,
+    Rgba32F,
+}
+
+impl TextureFormat {
+    pub const ALL: [TextureFormat; 11] = [
+        TextureFormat::Rgba8,
+        TextureFormat::Rgba8Srgb,
+        TextureFormat::Bc1,
+        TextureFormat::Bc1Srgb,
+        TextureFormat::Bc3,
+        TextureFormat::Bc3Srgb,
+        TextureFormat::Bc5,
+        TextureFormat::Bc7,
+        TextureFormat::Bc7Srgb,
+        TextureFormat::Rgba16F,
+        TextureFormat::Rgba32F,
+    ];
+
+    //the compressed formats by the name import-model takes, srgb is picked per slot
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bc1" => Some(TextureFormat::Bc1),
+            "bc3" => Some(TextureFormat::Bc3),
+            "bc5" => Some(TextureFormat::Bc5),
+            "bc7" => Some(TextureFormat::Bc7),
+            _ => None,
+        }
+    }
+
+    pub fn vk_format(self) -> vk::Format {
+        match self {
+            TextureFormat::Rgba8 => vk::Format::R8G8B8A8_UNORM,
+            TextureFormat::Rgba8Srgb => vk::Format::R8G8B8A8_SRGB,
+            TextureFormat::Bc1 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            TextureFormat::Bc1Srgb => vk::Format::BC1_RGBA_SRGB_BLOCK,
+            TextureFormat::Bc3 => vk::Format::BC3_UNORM_BLOCK,
+            TextureFormat::Bc3Srgb => vk::Format::BC3_SRGB_BLOCK,
+            TextureFormat::Bc5 => vk::Format::BC5_UNORM_BLOCK,
+            TextureFormat::Bc7 => vk::Format::BC7_UNORM_BLOCK,
+            TextureFormat::Bc7Srgb => vk::Format::BC7_SRGB_BLOCK,
+            TextureFormat::Rgba16F => vk::Format::R16G16B16A16_SFLOAT,
+            TextureFormat::Rgba32F => vk::Format::R32G32B32A32_SFLOAT,
+        }
+    }
+
+    pub fn from_vk_format(format: vk::Format) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.vk_format() == format)
+    }
+
+    pub fn is_compressed(self) -> bool {
+        !matches!(self, TextureFormat::Rgba8 | TextureFormat::Rgba8Srgb
+            | TextureFormat::Rgba16F | TextureFormat::Rgba32F)
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, TextureFormat::Rgba16F | TextureFormat::Rgba32F)
+    }
+
+    pub fn is_srgb(self) -> bool {
+        matches!(self, TextureFormat::Rgba8Srgb | TextureFormat::Bc1Srgb
+            | TextureFormat::Bc3Srgb | TextureFormat::Bc7Srgb)
+    }
+
+    //the same format with or without srgb, BC5 and the float formats are only ever linear
+    pub fn with_srgb(self, srgb: bool) -> Self {
+        match (self, srgb) {
+            (TextureFormat::Rgba8 | TextureFormat::Rgba8Srgb, false) => TextureFormat::Rgba8,
+            (TextureFormat::Rgba8 | TextureFormat::Rgba8Srgb, true) => TextureFormat::Rgba8Srgb,
+            (TextureFormat::Bc1 | TextureFormat::Bc1Srgb, false) => TextureFormat::Bc1,
+            (TextureFormat::Bc1 | TextureFormat::Bc1Srgb, true) => TextureFormat::Bc1Srgb,
+            (TextureFormat::Bc3 | TextureFormat::Bc3Srgb, false) => TextureFormat::Bc3,
+            (TextureFormat::Bc3 | TextureFormat::Bc3Srgb, true) => TextureFormat::Bc3Srgb,
+            (TextureFormat::Bc5, _) => TextureFormat::Bc5,
+            (TextureFormat::Bc7 | TextureFormat::Bc7Srgb, false) => TextureFormat::Bc7,
+            (TextureFormat::Bc7 | TextureFormat::Bc7Srgb, true) => TextureFormat::Bc7Srgb,
+            (TextureFormat::Rgba16F, _) => TextureFormat::Rgba16F,
+            (TextureFormat::Rgba32F, _) => TextureFormat::Rgba32F,
+        }
+    }
+
+    //texels per block side, 1 for the uncompressed formats
+    pub fn block_extent(self) -> u32 {
+        if self.is_compressed() { BLOCK_SIZE } else { 1 }
+    }
+
+    //bytes per block, or per texel for the uncompressed formats
+    pub fn block_bytes(self) -> usize {
+        match self {
+            TextureFormat::Rgba8 | TextureFormat::Rgba8Srgb => 4,
+            TextureFormat::Bc1 | TextureFormat::Bc1Srgb | TextureFormat::Rgba16F => 8,
+            _ => 16,
+        }
+    }
+
+    //bytes per number in the data, the size byte order applies to
+    pub fn component_bytes(self) -> usize {
+        match self {
+            TextureFormat::Rgba16F => 2,
+            TextureFormat::Rgba32F => 4,
+            _ => 1,
+        }
+    }
+
+    //one image of width x height, padded out to whole blocks
+    pub fn image_bytes(self, width: u32, height: u32) -> usize {
+        let extent = self.block_extent();
+        let (blocks_x, blocks_y) = (width.div_ceil(extent) as usize, height.div_ceil(extent));
+        blocks_x.saturating_mul(blocks_y as usize).saturating_mul(self.block_bytes())
+    }
+}
+
+//a texture in a gpu format, with every level, layer and face it came with
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodedTexture {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    //array layers, 1 when it isn't an array
+    pub layers: u32,
+    //6 for a cubemap, +x, -x, +y, -y, +z, -z, otherwise 1
+    pub faces: u32,
+    //largest first, each level is every layer in turn, each layer its faces in turn
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl EncodedTexture {
+    //an uncompressed texture's levels as they are
+    pub fn from_texture(texture: &Texture, srgb: bool) -> Self {
+        Self{
+            format: TextureFormat::Rgba8.with_srgb(srgb),
+            width: texture.width(),
+            height: texture.height(),
+            layers: 1,
+            faces: 1,
+            levels: texture.levels().map(|level| level.as_raw().clone()).collect(),
+        }
+    }
+
+    //a single float image, texels are rgba
+    pub fn from_floats(
+        format: TextureFormat, width: u32, height: u32, texels: &[[f32; 4]],
+    ) -> Self {
+        Self{
+            format,
+            width,
+            height,
+            layers: 1,
+            faces: 1,
+            levels: vec![float_bytes(format, texels)],
+        }
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.faces == 6
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        mip_size(self.width, self.height, level as u32)
+    }
+
+    //the bytes of one face of one layer at level
+    pub fn image_bytes(&self, level: usize) -> usize {
+        let (width, height) = self.level_size(level);
+        self.format.image_bytes(width, height)
+    }
+
+    //every layer's faces at level, saturates rather than overflow for sizes read from files
+    pub fn level_bytes(&self, level: usize) -> usize {
+        self.image_bytes(level)
+            .saturating_mul(self.layers as usize)
+            .saturating_mul(self.faces as usize)
+    }
+
+    pub fn image(&self, level: usize, layer: u32, face: u32) -> &[u8] {
+        let size = self.image_bytes(level);
+        let start = (layer * self.faces + face) as usize * size;
+        &self.levels[level][start..start + size]
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.levels.iter().map(|level| level.len() as u64).sum()
+    }
+
+    //the sizes agree with each other, anything read from a file goes through this
+    pub fn check(&self) -> RenderResult<()> {
+        if self.width == 0 || self.height == 0 || self.layers == 0 {
+            return Err(format!("texture is {} x {} with {} layers",
+                self.width, self.height, self.layers));
+        }
+        if self.faces != 1 && self.faces != 6 {
+            return Err(format!("texture has {} faces, expected 1 or 6", self.faces));
+        }
+        if self.is_cubemap() && self.width != self.height {
+            return Err(format!("cubemap is {} x {}, faces have to be square",
+                self.width, self.height));
+        }
+        let max_levels = mip_level_count(self.width, self.height);
+        if self.levels.is_empty() || self.level_count() > max_levels {
+            return Err(format!("texture has {} levels, expected 1 to {max_levels}",
+                self.levels.len()));
+        }
+        for (level, data) in self.levels.iter().enumerate() {
+            let expected = self.level_bytes(level);
+            if data.len() != expected {
+                return Err(format!("texture level {level} is {} bytes, expected {expected}",
+                    data.len()));
+            }
+        }
+        Ok(())
+    }
+
+    //one image of a float format as it is
+    pub fn decode_floats(&self, level: usize, layer: u32, face: u32) -> Vec<[f32; 4]> {
+        let data = self.image(level, layer, face);
+        let components: Vec<f32> = match self.format {
+            TextureFormat::Rgba16F => data.chunks_exact(2)
+                .map(|c| f16::from_le_bytes([c[0], c[1]]).to_f32())
+                .collect(),
+            TextureFormat::Rgba32F => data.chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+            _ => panic!("{:?} isn't a float format", self.format),
+        };
+        components.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]).collect()
+    }
+
+    //one image back to rgba8, for the cpu side and gpus without the format. Floats are
+    //clamped to 0 to 1, as the image crate does
+    pub fn decode(&self, level: usize, layer: u32, face: u32) -> TextureImage {
+        let (width, height) = self.level_size(level);
+        let data = self.image(level, layer, face);
+        if self.format.is_float() {
+            let texels = self.decode_floats(level, layer, face).concat();
+            let image = Rgba32FImage::from_raw(width, height, texels)
+                .expect("checked texture level is too short");
+            DynamicImage::ImageRgba32F(image).to_rgba8()
+        } else if self.format.is_compressed() {
+            decode_blocks(self.format, width, height, data)
+        } else {
+            TextureImage::from_raw(width, height, data.to_vec())
+                .expect("checked texture level is too short")
+        }
+    }
+}
+
+//rgba texels in a float format's bytes
+pub fn float_bytes(format: TextureFormat, texels: &[[f32; 4]]) -> Vec<u8> {
+    let components = texels.iter().flatten();
+    match format {
+        TextureFormat::Rgba16F => {
+            components.flat_map(|c| f16::from_f32(*c).to_le_bytes()).collect()
+        },
+        TextureFormat::Rgba32F => components.flat_map(|c| c.to_le_bytes()).collect(),
+        _ => panic!("{format:?} isn't a float format"),
+    }
+}
@@ -0,0 +1,163 @@
+/* Textures are decoded once and shared. The manager keeps a Weak per location the way
+ * ModelManager does for models, so every material asking for the same location gets
+ * the same SharedTexture, and a texture lives for as long as some material holds it.
+ * The next request after the last handle is dropped decodes it again.
+ * Locations are whatever key the caller gives, FileResolver uses the full path so
+ * models in different directories can't mix up their "diffuse.png"s.
+ * It's behind a Mutex because importers parse on a thread pool, the gpu side of the
+ * same idea is in vulkan.rs.
+ */
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError, Weak
+    },
+};
+
+use crate::{
+    importer::ImportSource,
+    RenderResult,
+};
+
+use super::material::{load_texture, SharedTexture, Texture};
+
+//what one texture costs, for the manager's and the renderer's reports
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureUsage {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u64,
+    //handles held outside of the manager
+    pub references: usize,
+}
+
+impl TextureUsage {
+    pub fn new(name: &str, texture: &Texture, references: usize) -> Self {
+        Self{
+            name: name.to_string(),
+            width: texture.width(),
+            height: texture.height(),
+            bytes: texture.bytes(),
+            references,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TextureManager {
+    texture_map: Mutex<HashMap<String, Weak<Texture>>>,
+}
+
+impl TextureManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //a panic while decoding leaves nothing half written, the map is still good
+    fn texture_map(&self) -> MutexGuard<'_, HashMap<String, Weak<Texture>>> {
+        self.texture_map.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    //the texture at location, only opened and decoded when nothing holds it already
+    pub fn get_texture(
+        &self, location: &str, open: impl FnOnce() -> RenderResult<Box<dyn ImportSource>>,
+    ) -> RenderResult<SharedTexture> {
+        let mut texture_map = self.texture_map();
+        if let Some(texture) = texture_map.get(location).and_then(|t| t.upgrade()) {
+            return Ok(texture);
+        }
+        let texture = Arc::new(load_texture(open()?.as_mut())?);
+        texture_map.retain(|_, t| t.strong_count() > 0);
+        texture_map.insert(location.to_string(), Arc::downgrade(&texture));
+        Ok(texture)
+    }
+
+    pub fn get_texture_by_path(&self, path: &Path) -> RenderResult<SharedTexture> {
+        let location = path.to_string_lossy();
+        self.get_texture(&location, || {
+            let file = File::open(path).map_err(|e| {
+                format!("could not open '{location}' for reading: {e}")
+            })?;
+            Ok(Box::new(BufReader::new(file)))
+        })
+    }
+
+    //every texture still held somewhere, largest first
+    pub fn usage(&self) -> Vec<TextureUsage> {
+        let mut usage: Vec<TextureUsage> = self.texture_map().iter()
+            .filter_map(|(location, texture)| {
+                let references = texture.strong_count();
+                texture.upgrade().map(|t| TextureUsage::new(location, &t, references))
+            })
+            .collect();
+        usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        usage
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.usage().iter().map(|u| u.bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        io::Cursor,
+        sync::Arc,
+    };
+
+    use image::ImageFormat;
+
+    use crate::importer::ImportSource;
+    use crate::model::material::TextureImage;
+    use crate::model::texture_manager::TextureManager;
+    use crate::RenderResult;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Cursor::new(vec![]);
+        TextureImage::new(width, height).write_to(&mut png, ImageFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    #[test]
+    fn texture_manager_shares() {
+        let manager = TextureManager::new();
+        let opened = Cell::new(0);
+        let open = |data: Vec<u8>| {
+            let opened = &opened;
+            move || -> RenderResult<Box<dyn ImportSource>> {
+                opened.set(opened.get() + 1);
+                Ok(Box::new(Cursor::new(data)))
+            }
+        };
+        let a = manager.get_texture("a.png", open(png(4, 2))).unwrap();
+        let again = manager.get_texture("a.png", open(png(4, 2))).unwrap();
+        let b = manager.get_texture("b.png", open(png(2, 2))).unwrap();
+        assert!(Arc::ptr_eq(&a, &again));
+        assert!(!Arc::ptr_eq(&a, &b));
+        assert_eq!(opened.get(), 2);
+
+        let usage = manager.usage();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].name, "a.png");
+        assert_eq!((usage[0].bytes, usage[0].references), (32, 2));
+        assert_eq!((usage[1].width, usage[1].height, usage[1].references), (2, 2, 1));
+        assert_eq!(manager.total_bytes(), 48);
+
+        //dropped everywhere, gone from the report and decoded again on the next request
+        drop((a, again));
+        assert_eq!(manager.usage().len(), 1);
+        let a = manager.get_texture("a.png", open(png(4, 2))).unwrap();
+        assert_eq!(opened.get(), 3);
+        assert_eq!(a.dimensions(), (4, 2));
+
+        assert!(manager.get_texture("bad.png", open(vec![1, 2, 3])).is_err());
+        assert_eq!(manager.usage().len(), 2);
+    }
+}
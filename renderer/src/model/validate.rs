@@ -0,0 +1,569 @@
+/* Checks an imported Model for what trips up everything after it, and repairs it.
+ * validate reports each of these with where it is:
+ *  - indeces past the end of the vertices
+ *  - vertices with a NaN or infinite attribute
+ *  - degenerate triangles, two corners at one position or less than min_area
+ *  - duplicate vertices, every attribute the same as an earlier vertex
+ *  - vertices no triangle uses
+ *  - inconsistent winding, an edge both its triangles run along the same way
+ *  - non manifold edges, shared by more than two triangles
+ * Edges join positions rather than vertices, so a uv or normal seam doesn't split a
+ * surface in two.
+ * repair welds the duplicates, removes degenerate triangles and any using a bad index or
+ * vertex, makes the winding agree across each connected surface, facing out for closed
+ * ones, and compacts away the vertices left unused. Non manifold edges are left alone,
+ * there's no one right way to split them, and winding isn't carried across them.
+ * Levels of detail, meshlets and quantized copies are built from the triangles and
+ * vertices, so repair before generating them.
+ */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{Debug, Display},
+};
+
+use super::{
+    bounds::compute_bounds,
+    cooked::{mesh_floats, mesh_from_floats},
+    layout::{layout_stride, mesh_layout},
+    weld::WeldGrid,
+    Model,
+};
+
+use crate::RenderResult;
+
+//how many locations of each problem Display lists
+const SHOWN_LOCATIONS: usize = 8;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ValidateSettings {
+    //positions closer than this are one when welding and joining edges, 0 is exact
+    pub weld_distance: f32,
+    //triangles with less area are degenerate
+    pub min_area: f32,
+}
+
+impl Default for ValidateSettings {
+    fn default() -> Self {
+        Self{
+            weld_distance: 0.0,
+            min_area: 1e-12,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    //positions in the index buffer
+    pub out_of_range_indeces: Vec<usize>,
+    pub non_finite_vertices: Vec<usize>,
+    //triangle numbers, triangle t starts at index 3 * t
+    pub degenerate_triangles: Vec<usize>,
+    //(duplicate, the earlier vertex it duplicates)
+    pub duplicate_vertices: Vec<(usize, usize)>,
+    pub unreferenced_vertices: Vec<usize>,
+    //edges as the vertices of one of their triangles
+    pub inconsistent_edges: Vec<[u32; 2]>,
+    pub non_manifold_edges: Vec<[u32; 2]>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.out_of_range_indeces.is_empty()
+            && self.non_finite_vertices.is_empty()
+            && self.degenerate_triangles.is_empty()
+            && self.duplicate_vertices.is_empty()
+            && self.unreferenced_vertices.is_empty()
+            && self.inconsistent_edges.is_empty()
+            && self.non_manifold_edges.is_empty()
+    }
+}
+
+fn write_locations<T: Debug>(
+    f: &mut std::fmt::Formatter<'_>, name: &str, locations: &[T],
+) -> std::fmt::Result {
+    write!(f, "\n{name}: {}", locations.len())?;
+    if !locations.is_empty() {
+        let shown = &locations[..locations.len().min(SHOWN_LOCATIONS)];
+        let more = if locations.len() > shown.len() { " ..." } else { "" };
+        write!(f, " {shown:?}{more}")?;
+    }
+    Ok(())
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} vertices, {} triangles", self.vertex_count, self.triangle_count)?;
+        write_locations(f, "indeces out of range", &self.out_of_range_indeces)?;
+        write_locations(f, "NaN or infinite vertices", &self.non_finite_vertices)?;
+        write_locations(f, "degenerate triangles", &self.degenerate_triangles)?;
+        write_locations(f, "duplicate vertices", &self.duplicate_vertices)?;
+        write_locations(f, "unreferenced vertices", &self.unreferenced_vertices)?;
+        write_locations(f, "inconsistent winding edges", &self.inconsistent_edges)?;
+        write_locations(f, "non manifold edges", &self.non_manifold_edges)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepairReport {
+    pub welded_vertices: usize,
+    pub removed_triangles: usize,
+    pub flipped_triangles: usize,
+    pub removed_vertices: usize,
+}
+
+impl Display for RepairReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f, "welded {} vertices, removed {} triangles, flipped {} triangles, \
+                removed {} unused vertices",
+            self.welded_vertices, self.removed_triangles, self.flipped_triangles,
+            self.removed_vertices,
+        )
+    }
+}
+
+//the vertices as rows of floats, positions are always the first 3
+struct Vertices {
+    floats: Vec<f32>,
+    stride: usize,
+}
+
+impl Vertices {
+    fn new(model: &Model) -> Self {
+        Self{
+            floats: mesh_floats(&model.mesh),
+            stride: layout_stride(mesh_layout(&model.mesh)) / 4,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.floats.len() / self.stride
+    }
+
+    fn get(&self, i: usize) -> &[f32] {
+        &self.floats[i * self.stride..][..self.stride]
+    }
+
+    fn position(&self, i: u32) -> [f32; 3] {
+        let v = self.get(i as usize);
+        [v[0], v[1], v[2]]
+    }
+
+    fn is_finite(&self, i: usize) -> bool {
+        self.get(i).iter().all(|f| f.is_finite())
+    }
+}
+
+//+ 0.0 so -0.0 and 0.0 are the same
+fn float_key(f: f32) -> i64 {
+    (f + 0.0).to_bits() as i64
+}
+
+//each vertex's position numbered, so vertices at one position share it
+fn position_ids(vertices: &Vertices, weld_distance: f32) -> Vec<u32> {
+    let mut grid: WeldGrid<u32> = WeldGrid::new(weld_distance);
+    let mut next = 0;
+    (0..vertices.len()).map(|i| {
+        let p = vertices.position(i as u32);
+        grid.find(&p, |_| true).unwrap_or_else(|| {
+            grid.insert(p, next);
+            next += 1;
+            next - 1
+        })
+    }).collect()
+}
+
+//the first vertex like each one, itself when there's none before it, positions within
+//weld_distance of each other and every other attribute the same
+fn first_duplicates(vertices: &Vertices, weld_distance: f32) -> Vec<usize> {
+    let mut grid: WeldGrid<usize> = WeldGrid::new(weld_distance);
+    let same = |i: usize, j: usize| {
+        vertices.get(i)[3..].iter().zip(&vertices.get(j)[3..])
+            .all(|(a, b)| float_key(*a) == float_key(*b))
+    };
+    (0..vertices.len()).map(|i| {
+        let p = vertices.position(i as u32);
+        grid.find(&p, |j| same(i, j)).unwrap_or_else(|| {
+            grid.insert(p, i);
+            i
+        })
+    }).collect()
+}
+
+fn triangles(indeces: &[u32]) -> Vec<[u32; 3]> {
+    indeces.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect()
+}
+
+fn area(vertices: &Vertices, t: &[u32; 3]) -> f32 {
+    let [a, b, c] = t.map(|i| vertices.position(i));
+    let (u, v) = ([0, 1, 2].map(|k| b[k] - a[k]), [0, 1, 2].map(|k| c[k] - a[k]));
+    let cross = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt() * 0.5
+}
+
+//what a triangle can't be used with, in order of what's checked
+#[derive(Copy, Clone, PartialEq)]
+enum Broken {
+    OutOfRange,
+    NonFinite,
+    Degenerate,
+}
+
+fn broken(
+    vertices: &Vertices, ids: &[u32], t: &[u32; 3], settings: &ValidateSettings,
+) -> Option<Broken> {
+    if t.iter().any(|i| *i as usize >= vertices.len()) {
+        return Some(Broken::OutOfRange);
+    }
+    if !t.iter().all(|i| vertices.is_finite(*i as usize)) {
+        return Some(Broken::NonFinite);
+    }
+    let [a, b, c] = t.map(|i| ids[i as usize]);
+    if a == b || b == c || c == a || area(vertices, t) < settings.min_area {
+        return Some(Broken::Degenerate);
+    }
+    None
+}
+
+//one side of an edge, the triangle and the vertices it runs along the edge from and to
+#[derive(Copy, Clone)]
+struct HalfEdge {
+    triangle: usize,
+    from: u32,
+    to: u32,
+}
+
+//half edges of the usable triangles by their positions, lowest id first
+fn edges(triangles: &[[u32; 3]], usable: &[bool], ids: &[u32]) -> HashMap<[u32; 2], Vec<HalfEdge>> {
+    let mut edges: HashMap<[u32; 2], Vec<HalfEdge>> = HashMap::new();
+    for (triangle, t) in triangles.iter().enumerate().filter(|(i, _)| usable[*i]) {
+        for k in 0..3 {
+            let (from, to) = (t[k], t[(k + 1) % 3]);
+            let (a, b) = (ids[from as usize], ids[to as usize]);
+            edges.entry([a.min(b), a.max(b)]).or_default().push(HalfEdge{ triangle, from, to });
+        }
+    }
+    edges
+}
+
+//whether the half edge runs from the edge's lower position id to its higher one
+fn forward(ids: &[u32], half: &HalfEdge) -> bool {
+    ids[half.from as usize] < ids[half.to as usize]
+}
+
+pub fn validate(model: &Model, settings: &ValidateSettings) -> ValidationReport {
+    let vertices = Vertices::new(model);
+    let ids = position_ids(&vertices, settings.weld_distance);
+    let triangles = triangles(&model.indeces);
+    let mut report = ValidationReport{
+        vertex_count: vertices.len(),
+        triangle_count: triangles.len(),
+        ..Default::default()
+    };
+    report.out_of_range_indeces = model.indeces.iter().enumerate()
+        .filter(|(_, i)| **i as usize >= vertices.len())
+        .map(|(position, _)| position)
+        .collect();
+    report.non_finite_vertices = (0..vertices.len()).filter(|i| !vertices.is_finite(*i)).collect();
+    let broken: Vec<Option<Broken>> = triangles.iter()
+        .map(|t| broken(&vertices, &ids, t, settings))
+        .collect();
+    report.degenerate_triangles = broken.iter().enumerate()
+        .filter(|(_, b)| **b == Some(Broken::Degenerate))
+        .map(|(t, _)| t)
+        .collect();
+    report.duplicate_vertices = first_duplicates(&vertices, settings.weld_distance).into_iter()
+        .enumerate()
+        .filter(|(i, first)| i != first)
+        .collect();
+    let mut used = vec![false; vertices.len()];
+    for i in model.indeces.iter().filter(|i| (**i as usize) < vertices.len()) {
+        used[*i as usize] = true;
+    }
+    report.unreferenced_vertices = (0..vertices.len()).filter(|i| !used[*i]).collect();
+
+    let usable: Vec<bool> = broken.iter().map(|b| b.is_none()).collect();
+    for halves in edges(&triangles, &usable, &ids).values() {
+        let location = [halves[0].from, halves[0].to];
+        if halves.len() > 2 {
+            report.non_manifold_edges.push(location);
+        } else if halves.len() == 2 && forward(&ids, &halves[0]) == forward(&ids, &halves[1]) {
+            report.inconsistent_edges.push(location);
+        }
+    }
+    report.non_manifold_edges.sort();
+    report.inconsistent_edges.sort();
+    report
+}
+
+//which triangles to flip so neighbours across manifold edges run along them opposite ways,
+//closed surfaces end up facing out
+fn unify_winding(triangles: &[[u32; 3]], ids: &[u32], vertices: &Vertices) -> Vec<bool> {
+    let usable = vec![true; triangles.len()];
+    let edges = edges(triangles, &usable, ids);
+    let edge_of = |from: u32, to: u32| {
+        let (a, b) = (ids[from as usize], ids[to as usize]);
+        &edges[&[a.min(b), a.max(b)]]
+    };
+    let mut flipped = vec![false; triangles.len()];
+    let mut visited = vec![false; triangles.len()];
+    for seed in 0..triangles.len() {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut component = vec![seed];
+        let mut closed = true;
+        let mut queue = VecDeque::from([seed]);
+        while let Some(triangle) = queue.pop_front() {
+            let t = &triangles[triangle];
+            for k in 0..3 {
+                let halves = edge_of(t[k], t[(k + 1) % 3]);
+                if halves.len() != 2 {
+                    closed = false;
+                    continue;
+                }
+                let (this, other) = if halves[0].triangle == triangle {
+                    (&halves[0], &halves[1])
+                } else {
+                    (&halves[1], &halves[0])
+                };
+                if visited[other.triangle] {
+                    continue;
+                }
+                //flipped so the two run along the edge opposite ways
+                let runs = forward(ids, this) != flipped[triangle];
+                flipped[other.triangle] = forward(ids, other) == runs;
+                visited[other.triangle] = true;
+                component.push(other.triangle);
+                queue.push_back(other.triangle);
+            }
+        }
+        if !closed {
+            continue;
+        }
+        //divergence theorem, a closed surface facing in has a negative volume
+        let volume: f32 = component.iter().map(|triangle| {
+            let [a, mut b, mut c] = triangles[*triangle].map(|i| vertices.position(i));
+            if flipped[*triangle] {
+                std::mem::swap(&mut b, &mut c);
+            }
+            let cross = [
+                b[1] * c[2] - b[2] * c[1], b[2] * c[0] - b[0] * c[2], b[0] * c[1] - b[1] * c[0],
+            ];
+            a[0] * cross[0] + a[1] * cross[1] + a[2] * cross[2]
+        }).sum();
+        if volume < 0.0 {
+            for triangle in component {
+                flipped[triangle] = !flipped[triangle];
+            }
+        }
+    }
+    flipped
+}
+
+pub fn repair(model: &mut Model, settings: &ValidateSettings) -> RenderResult<RepairReport> {
+    if !model.lods.is_empty() || !model.meshlets.is_empty() || model.quantized.is_some() {
+        return Err(format!(
+            "model '{}' has levels of detail, meshlets or a quantized copy, repair before \
+            generating them",
+            model.name,
+        ));
+    }
+    let mut report = RepairReport::default();
+    let vertices = Vertices::new(model);
+    let ids = position_ids(&vertices, settings.weld_distance);
+
+    //weld, every index to the first vertex like it
+    let firsts = first_duplicates(&vertices, settings.weld_distance);
+    report.welded_vertices = firsts.iter().enumerate().filter(|(i, first)| i != *first).count();
+    let welded: Vec<u32> = model.indeces.iter()
+        .map(|i| firsts.get(*i as usize).map_or(*i, |first| *first as u32))
+        .collect();
+
+    //drop what can't be drawn, keeping each submesh's triangles together
+    let all = triangles(&welded);
+    let keep: Vec<bool> = all.iter()
+        .map(|t| broken(&vertices, &ids, t, settings).is_none())
+        .collect();
+    let mut kept_before = vec![0usize; all.len() + 1];
+    for (t, k) in keep.iter().enumerate() {
+        kept_before[t + 1] = kept_before[t] + *k as usize;
+    }
+    for submesh in model.submeshes.iter_mut() {
+        let first = (submesh.first_index / 3).min(all.len());
+        let end = ((submesh.first_index + submesh.index_count) / 3).min(all.len());
+        submesh.first_index = kept_before[first] * 3;
+        submesh.index_count = (kept_before[end] - kept_before[first]) * 3;
+    }
+    let mut kept: Vec<[u32; 3]> = all.iter().zip(keep.iter())
+        .filter(|(_, k)| **k)
+        .map(|(t, _)| *t)
+        .collect();
+    report.removed_triangles = all.len() - kept.len();
+
+    let flipped = unify_winding(&kept, &ids, &vertices);
+    for (t, flip) in kept.iter_mut().zip(flipped.iter()) {
+        if *flip {
+            t.swap(1, 2);
+            report.flipped_triangles += 1;
+        }
+    }
+
+    //compact, the used vertices keep their order
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut floats = vec![];
+    for i in kept.iter().flatten() {
+        if remap[*i as usize] == u32::MAX {
+            remap[*i as usize] = 0;
+        }
+    }
+    let mut next = 0;
+    for (i, new) in remap.iter_mut().enumerate().filter(|(_, new)| **new != u32::MAX) {
+        *new = next;
+        next += 1;
+        floats.extend_from_slice(vertices.get(i));
+    }
+    report.removed_vertices = vertices.len() - next as usize;
+    model.indeces = kept.into_iter().flatten().map(|i| remap[i as usize]).collect();
+    model.mesh = mesh_from_floats(mesh_layout(&model.mesh), &floats);
+    compute_bounds(model)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{
+        cooked::{mesh_floats, mesh_from_floats},
+        layout::mesh_layout,
+        primitives::{make_primitive, Primitive},
+        validate::{repair, validate, ValidateSettings},
+        Model, ModelView,
+    };
+
+    fn volume(model: &Model) -> f32 {
+        let floats = mesh_floats(&model.mesh);
+        let stride = floats.len() / model.mesh_slices().vertex_count();
+        let p = |i: u32| {
+            let v = &floats[i as usize * stride..];
+            [v[0], v[1], v[2]]
+        };
+        model.indeces.chunks_exact(3).map(|t| {
+            let (a, b, c) = (p(t[0]), p(t[1]), p(t[2]));
+            let cross = [
+                b[1] * c[2] - b[2] * c[1], b[2] * c[0] - b[0] * c[2], b[0] * c[1] - b[1] * c[0],
+            ];
+            (a[0] * cross[0] + a[1] * cross[1] + a[2] * cross[2]) / 6.0
+        }).sum()
+    }
+
+    //appends copies of vertices, moved when given a position, returning where the first went
+    fn push_vertices(model: &mut Model, copies: &[(u32, Option<[f32; 3]>)]) -> u32 {
+        let mut floats = mesh_floats(&model.mesh);
+        let stride = floats.len() / model.mesh_slices().vertex_count();
+        let first = (floats.len() / stride) as u32;
+        for (i, moved) in copies {
+            let mut v = floats[*i as usize * stride..][..stride].to_vec();
+            if let Some(p) = moved {
+                v[..3].copy_from_slice(p);
+            }
+            floats.extend(v);
+        }
+        model.mesh = mesh_from_floats(mesh_layout(&model.mesh), &floats);
+        first
+    }
+
+    #[test]
+    fn validate_primitives() {
+        for primitive in Primitive::DEFAULTS.iter() {
+            let model = make_primitive(primitive).unwrap();
+            let report = validate(&model, &ValidateSettings::default());
+            assert!(report.is_valid(), "{}: {report}", primitive.name());
+        }
+    }
+
+    #[test]
+    fn validate_and_repair() {
+        let mut model = make_primitive(&Primitive::Cube{ size: 2.0, subdivisions: 1 }).unwrap();
+        let (triangles, vertex_count) = (model.indeces.len() / 3, model.mesh_slices().vertex_count());
+        //a duplicate of vertex 0 used instead of it, an unused NaN vertex and an unused one
+        let copies = [(0, None), (1, Some([f32::NAN; 3])), (2, None)];
+        let extra = push_vertices(&mut model, &copies);
+        let used = model.indeces.iter().position(|i| *i == 0).unwrap();
+        model.indeces[used] = extra;
+        //the first and a later triangle face in, then a degenerate one
+        model.indeces.swap(1, 2);
+        model.indeces.swap(16, 17);
+        model.indeces.extend_from_slice(&[3, 4, 3]);
+        model.submeshes[0].index_count += 3;
+
+        let settings = ValidateSettings::default();
+        let report = validate(&model, &settings);
+        assert!(!report.is_valid());
+        assert!(report.out_of_range_indeces.is_empty());
+        assert_eq!(report.non_finite_vertices, vec![extra as usize + 1]);
+        assert_eq!(report.degenerate_triangles, vec![triangles]);
+        //the unused copy of vertex 2 is a duplicate too
+        let extra = extra as usize;
+        assert_eq!(report.duplicate_vertices, vec![(extra, 0), (extra + 2, 2)]);
+        assert_eq!(report.unreferenced_vertices, vec![extra + 1, extra + 2]);
+        //every edge of the two flipped triangles but the diagonal they share with no one
+        assert!(!report.inconsistent_edges.is_empty());
+        assert!(report.non_manifold_edges.is_empty());
+        assert!(report.to_string().contains("degenerate triangles: 1"), "{report}");
+
+        let repaired = repair(&mut model, &settings).unwrap();
+        assert_eq!(repaired.welded_vertices, 2);
+        assert_eq!(repaired.removed_triangles, 1);
+        assert_eq!(repaired.removed_vertices, 3);
+        //the rest follow the first triangle, then all of them turn to face out
+        assert_eq!(repaired.flipped_triangles, 2);
+        assert!(validate(&model, &settings).is_valid(), "{}", validate(&model, &settings));
+        assert_eq!(model.indeces.len() / 3, triangles);
+        assert_eq!(model.mesh_slices().vertex_count(), vertex_count);
+        assert_eq!(model.submeshes[0].index_count, model.indeces.len());
+        assert!((volume(&model) - 8.0).abs() < 1e-4, "{}", volume(&model));
+        assert!(!model.bounds.is_empty());
+
+        //a triangle out of range is dropped with the rest
+        model.indeces.extend_from_slice(&[0, 1, 1000]);
+        model.submeshes[0].index_count += 3;
+        let report = validate(&model, &settings);
+        assert_eq!(report.out_of_range_indeces, vec![model.indeces.len() - 1]);
+        assert_eq!(repair(&mut model, &settings).unwrap().removed_triangles, 1);
+    }
+
+    #[test]
+    fn validate_weld_distance() {
+        let mut model = make_primitive(&Primitive::Cube{ size: 2.0, subdivisions: 1 }).unwrap();
+        let p = mesh_floats(&model.mesh)[..3].to_vec();
+        let moved = |d: f32| Some([p[0] + d, p[1], p[2]]);
+        //either side of a 0.01 grid line from vertex 0, then too far from it
+        let copies = [(0, moved(0.006)), (0, moved(-0.004)), (0, moved(0.02))];
+        let extra = push_vertices(&mut model, &copies);
+        let settings = ValidateSettings{ weld_distance: 0.01, ..ValidateSettings::default() };
+        let extra = extra as usize;
+        let report = validate(&model, &settings);
+        assert_eq!(report.duplicate_vertices, vec![(extra, 0), (extra + 1, 0)]);
+    }
+
+    #[test]
+    fn validate_non_manifold() {
+        //three triangles sharing the first triangle's first edge, left as they are
+        let mut model = make_primitive(&Primitive::Plane{ size: 2.0, subdivisions: 1 }).unwrap();
+        let (a, b) = (model.indeces[0], model.indeces[1]);
+        let apexes = [(a, Some([0.3, 1.0, 0.7])), (a, Some([0.3, -1.0, 0.7]))];
+        let apex = push_vertices(&mut model, &apexes);
+        model.indeces.extend_from_slice(&[b, a, apex, b, a, apex + 1]);
+        model.submeshes[0].index_count += 6;
+        let settings = ValidateSettings::default();
+        let report = validate(&model, &settings);
+        assert_eq!(report.non_manifold_edges.len(), 1, "{report}");
+        let repaired = repair(&mut model, &settings).unwrap();
+        assert_eq!(repaired.removed_triangles, 0);
+        assert_eq!(validate(&model, &settings).non_manifold_edges.len(), 1);
+    }
+}
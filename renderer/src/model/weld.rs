@@ -0,0 +1,71 @@
+/* Finding positions within a distance of each other, for welding.
+ * A spatial hash with distance sized cells, a position can only be within the distance
+ * of something in its own cell or one of the 26 neighbors, so nothing just either side
+ * of a cell boundary gets missed the way rounding to a grid would. A distance of 0 is an
+ * exact match, -0.0 and 0.0 being the same.
+ * Used by the STL importer to index its triangle soup and by validate and repair.
+ */
+
+use std::collections::HashMap;
+
+//numbered in insertion order
+type Entry<T> = (usize, [f32; 3], T);
+
+pub struct WeldGrid<T> {
+    distance: f32,
+    cells: HashMap<[i64; 3], Vec<Entry<T>>>,
+    inserted: usize,
+}
+
+impl<T: Copy> WeldGrid<T> {
+    pub fn new(distance: f32) -> Self {
+        Self{
+            distance,
+            cells: HashMap::new(),
+            inserted: 0,
+        }
+    }
+
+    fn cell(&self, p: &[f32; 3]) -> [i64; 3] {
+        if self.distance > 0.0 {
+            p.map(|c| (c / self.distance).floor() as i64)
+        } else {
+            //+ 0.0 so -0.0 and 0.0 are the same
+            p.map(|c| (c + 0.0).to_bits() as i64)
+        }
+    }
+
+    //the first inserted value within the distance of p that accept takes
+    pub fn find(&self, p: &[f32; 3], accept: impl Fn(T) -> bool) -> Option<T> {
+        let key = self.cell(p);
+        let reach = if self.distance > 0.0 { 1 } else { 0 };
+        let mut found: Option<(usize, T)> = None;
+        for dx in -reach..=reach {
+            for dy in -reach..=reach {
+                for dz in -reach..=reach {
+                    let neighbor = [key[0] + dx, key[1] + dy, key[2] + dz];
+                    let Some(candidates) = self.cells.get(&neighbor) else {
+                        continue;
+                    };
+                    for (order, q, value) in candidates {
+                        let close = (0..3).all(|c| (p[c] - q[c]).abs() <= self.distance);
+                        if close && accept(*value) {
+                            //each cell is in order, keep the earliest across them
+                            if found.is_none_or(|(first, _)| *order < first) {
+                                found = Some((*order, *value));
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        found.map(|(_, value)| value)
+    }
+
+    pub fn insert(&mut self, p: [f32; 3], value: T) {
+        let key = self.cell(&p);
+        self.cells.entry(key).or_default().push((self.inserted, p, value));
+        self.inserted += 1;
+    }
+}
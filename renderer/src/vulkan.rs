@@ -1,6 +1,8 @@
 use std::{
-    borrow::Cow, default::Default, error::Error, 
+    borrow::Cow, cell::RefCell, collections::HashMap, default::Default, error::Error,
     ffi, mem,
+    rc::{self, Rc},
+    sync::{Arc, Weak},
     os::raw::{
         c_char, 
         c_void,
@@ -22,22 +24,23 @@ use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
 use crate::{
     model::{
-        model_manager::ModelManager, NormalVertex,
+        layout::{Semantic, VertexInput},
+        material::{SamplerSettings, Texture, TextureImage, TextureSlot, TextureSlotKind},
+        mipmap::{float_mip_chain, mip_chain, MipFilter},
+        texture_format::{EncodedTexture, TextureFormat},
+        texture_manager::TextureUsage,
+        model_manager::ModelManager,
+        quantize::Dequantize,
+        IndexSlice,
     }, 
     window::Window,
 };
 
-// Simple offset_of macro akin to C++ offsetof
-#[macro_export]
-macro_rules! offset_of {
-    ($base:path, $field:ident) => {{
-        #[allow(unused_unsafe)]
-        unsafe {
-            let b: $base = mem::zeroed();
-            std::ptr::addr_of!(b.$field) as isize - std::ptr::addr_of!(b) as isize
-        }
-    }};
-}
+//the inputs declared by shader/texture/texture.vert
+const TEXTURE_SHADER_INPUTS: &[Semantic] = &[Semantic::Position, Semantic::Uv, Semantic::Normal];
+//and by shader/quantized/quantized.vert, the normal octahedral encoded
+const QUANTIZED_SHADER_INPUTS: &[Semantic] =
+    &[Semantic::Position, Semantic::Uv, Semantic::Normal];
 
 //taken from ash
 /*
@@ -100,6 +103,46 @@ fn find_memorytype_index(
         .map(|(index, _memory_type)| index as _)
 }
 
+//a host visible buffer holding a copy of data, bound and ready to use
+unsafe fn create_host_buffer(
+    device: &Device,
+    memory_prop: &vk::PhysicalDeviceMemoryProperties,
+    data: &[u8],
+    usage: vk::BufferUsageFlags,
+) -> GpuBuffer {
+    let buffer_info = vk::BufferCreateInfo {
+        size: data.len() as u64,
+        usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        ..Default::default()
+    };
+    let buffer = device.create_buffer(&buffer_info, None).unwrap();
+    let memory_req = device.get_buffer_memory_requirements(buffer);
+    let memory_index = find_memorytype_index(
+        &memory_req,
+        memory_prop,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+    )
+    .expect("Unable to find suitable memorytype for the buffer.");
+    let allocate_info = vk::MemoryAllocateInfo {
+        allocation_size: memory_req.size,
+        memory_type_index: memory_index,
+        ..Default::default()
+    };
+    let memory = device.allocate_memory(&allocate_info, None).unwrap();
+    let ptr = device
+        .map_memory(memory, 0, memory_req.size, vk::MemoryMapFlags::empty())
+        .unwrap();
+    std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+    device.unmap_memory(memory);
+    device.bind_buffer_memory(buffer, memory, 0).unwrap();
+    GpuBuffer{
+        device: device.clone(),
+        buffer,
+        memory,
+    }
+}
+
 ///
 /// Helper function for submitting command buffers. Immediately waits for the fence before the command buffer
 /// is executed. That way we can delay the waiting for the fences by 1 frame which is good for performance.
@@ -156,6 +199,65 @@ fn record_submit_commandbuffer<F: FnOnce(&Device, vk::CommandBuffer)>(
     }
 }
 
+//a buffer and its memory, destroyed when it's dropped
+struct GpuBuffer {
+    device: Device,
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+}
+
+impl Drop for GpuBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_buffer(self.buffer, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+//a texture's image on the gpu, destroyed with the last Rc to it
+struct GpuTexture {
+    device: Device,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    usage: TextureUsage,
+}
+
+impl Drop for GpuTexture {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+struct GpuSampler {
+    device: Device,
+    sampler: vk::Sampler,
+}
+
+impl Drop for GpuSampler {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+//uploads by the SharedTexture they came from and whether its slot is srgb, the mips built
+//for it depend on that. A Weak keeps its allocation around so the pointer can't be reused
+//by another texture while it's here. Samplers by their settings
+type UploadKey = (Weak<Texture>, bool);
+
+#[derive(Default)]
+struct GpuTextures {
+    textures: Vec<(UploadKey, rc::Weak<GpuTexture>)>,
+    samplers: HashMap<SamplerSettings, rc::Weak<GpuSampler>>,
+}
+
 pub struct Vulkan {
     entry: Entry,
     instance: Instance,
@@ -191,6 +293,8 @@ pub struct Vulkan {
 
     draw_commands_reuse_fence: vk::Fence,
     setup_commands_reuse_fence: vk::Fence,
+
+    textures: RefCell<GpuTextures>,
 }
 
 impl Vulkan {
@@ -246,9 +350,20 @@ impl Vulkan {
             .dependencies(&dependencies);
 
 
-        //let model = primitives::hardcoded_square();
-        //let model = primitives::make_primitive(primitives::Primitive::Sphere);
-        let model = model_manager.get_model_by_id("sphere").unwrap();
+        //or a built in shape, model_manager.get_model_view_by_id("builtin:uv_sphere")
+        let model = model_manager.get_model_view_by_id("sphere").unwrap();
+        //a quantized copy is drawn instead when there is one, with its Dequantize pushed
+        let (mesh, shader_inputs, dequantize) = match model.quantized() {
+            Some(quantized) => {
+                (quantized.mesh, QUANTIZED_SHADER_INPUTS, Some(*quantized.dequantize))
+            },
+            None => (model.mesh_slices(), TEXTURE_SHADER_INPUTS, None),
+        };
+        let index_count = mesh.indeces.len() as u32;
+        let index_type = match mesh.indeces {
+            IndexSlice::U16(_) => vk::IndexType::UINT16,
+            IndexSlice::U32(_) => vk::IndexType::UINT32,
+        };
         let to_ret: Box<dyn FnMut()>;
         unsafe {
             let renderpass = self
@@ -277,11 +392,11 @@ impl Vulkan {
 
 
             //let index_buffer_data = [0u32, 1, 2, 2, 3, 0];
-            let index_buffer_data = model.indeces.clone();
+            let index_buffer_data = mesh.indeces.as_bytes();
 
             let index_buffer_info = vk::BufferCreateInfo {
                 //size: mem::size_of_val(&index_buffer_data) as u64,
-                size: mem::size_of_val(index_buffer_data.as_slice()) as u64,
+                size: index_buffer_data.len() as u64,
                 usage: vk::BufferUsageFlags::INDEX_BUFFER,
                 sharing_mode: vk::SharingMode::EXCLUSIVE,
                 ..Default::default()
@@ -321,19 +436,17 @@ impl Vulkan {
                 )
                 .unwrap();
 
-            let mut index_slice = Align::new(
-                index_ptr,
-                mem::align_of::<u32>() as u64,
-                index_buffer_memory_req.size,
+            //already laid out the way the buffer wants it, one copy
+            std::ptr::copy_nonoverlapping(
+                index_buffer_data.as_ptr(), index_ptr as *mut u8, index_buffer_data.len(),
             );
-            index_slice.copy_from_slice(&index_buffer_data);
 
             self.device.unmap_memory(index_buffer_memory);
             self.device
                 .bind_buffer_memory(index_buffer, index_buffer_memory, 0)
                 .unwrap();
             
-            let vertices = model.get_vertices();
+            let vertices = mesh.vertices;
             /*
             let vertices = [
                 NormalVertex {
@@ -423,19 +536,26 @@ impl Vulkan {
                     vk::MemoryMapFlags::empty(),
                 )
                 .unwrap();
-            let mut slice = Align::new(
-                vert_ptr,
-                //mem::align_of::<Vertex>() as u64,
-                mem::align_of::<NormalVertex>() as u64,
-                vertex_input_buffer_memory_req.size,
-            );
-            //slice.copy_from_slice(&vertices);
-            slice.copy_from_slice(vertices);
+            std::ptr::copy_nonoverlapping(vertices.as_ptr(), vert_ptr as *mut u8, vertices.len());
             self.device.unmap_memory(vertex_input_buffer_memory);
             self.device
                 .bind_buffer_memory(vertex_input_buffer, vertex_input_buffer_memory, 0)
                 .unwrap();
 
+            //whatever the shader reads that the mesh doesn't have
+            let vertex_input = VertexInput::new(mesh.layout, shader_inputs);
+            let mut vertex_buffers = vec![vertex_input_buffer];
+            let defaults_buffer = (!vertex_input.defaults.is_empty()).then(|| {
+                create_host_buffer(
+                    &self.device,
+                    &self.device_memory_properties,
+                    bytemuck::cast_slice(&vertex_input.defaults),
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                )
+            });
+            vertex_buffers.extend(defaults_buffer.as_ref().map(|defaults| defaults.buffer));
+            let vertex_buffer_offsets = vec![0; vertex_buffers.len()];
+
             let uniform_color_buffer_data = Vector3 {
                 x: 0.2,
                 y: 0.5,
@@ -492,205 +612,16 @@ impl Vulkan {
                 .bind_buffer_memory(uniform_color_buffer, uniform_color_buffer_memory, 0)
                 .unwrap();
 
-            //let tex_image = model.material.unwrap().texture;
-            let tex_image = &model.materials[0].texture;
-            //let mat = model.material.unwrap();
-            let (width, height) = tex_image.dimensions();
-            let image_extent = vk::Extent2D { width, height };
-            let image_data = tex_image.as_raw();
-            let image_buffer_info = vk::BufferCreateInfo {
-                size: (mem::size_of::<u8>() * image_data.len()) as u64,
-                usage: vk::BufferUsageFlags::TRANSFER_SRC,
-                sharing_mode: vk::SharingMode::EXCLUSIVE,
-                ..Default::default()
-            };
-            let image_buffer = self
-                .device.create_buffer(&image_buffer_info, None).unwrap();
-            let image_buffer_memory_req = self
-                .device.get_buffer_memory_requirements(image_buffer);
-            let image_buffer_memory_index = find_memorytype_index(
-                &image_buffer_memory_req,
-                &self.device_memory_properties,
-                vk::MemoryPropertyFlags::HOST_VISIBLE | 
-                    vk::MemoryPropertyFlags::HOST_COHERENT,
-            )
-            .expect("Unable to find suitable memorytype for the image buffer.");
-
-            let image_buffer_allocate_info = vk::MemoryAllocateInfo {
-                allocation_size: image_buffer_memory_req.size,
-                memory_type_index: image_buffer_memory_index,
-                ..Default::default()
-            };
-            let image_buffer_memory = self
-                .device
-                .allocate_memory(&image_buffer_allocate_info, None)
-                .unwrap();
-            let image_ptr = self
-                .device
-                .map_memory(
-                    image_buffer_memory,
-                    0,
-                    image_buffer_memory_req.size,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .unwrap();
-            let mut image_slice = Align::new(
-                image_ptr,
-                mem::align_of::<u8>() as u64,
-                image_buffer_memory_req.size,
-            );
-            image_slice.copy_from_slice(&image_data);
-            self.device.unmap_memory(image_buffer_memory);
-            self.device
-                .bind_buffer_memory(image_buffer, image_buffer_memory, 0)
-                .unwrap();
-
-            let texture_create_info = vk::ImageCreateInfo {
-                image_type: vk::ImageType::TYPE_2D,
-                format: vk::Format::R8G8B8A8_UNORM,
-                extent: image_extent.into(),
-                mip_levels: 1,
-                array_layers: 1,
-                samples: vk::SampleCountFlags::TYPE_1,
-                tiling: vk::ImageTiling::OPTIMAL,
-                usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-                sharing_mode: vk::SharingMode::EXCLUSIVE,
-                ..Default::default()
-            };
-            let texture_image = self
-                .device
-                .create_image(&texture_create_info, None)
-                .unwrap();
-            let texture_memory_req = self
-                .device.get_image_memory_requirements(texture_image);
-            let texture_memory_index = find_memorytype_index(
-                &texture_memory_req,
-                &self.device_memory_properties,
-                vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            )
-            .expect("Unable to find suitable memory index for depth image.");
-
-            let texture_allocate_info = vk::MemoryAllocateInfo {
-                allocation_size: texture_memory_req.size,
-                memory_type_index: texture_memory_index,
-                ..Default::default()
-            };
-            let texture_memory = self
-                .device
-                .allocate_memory(&texture_allocate_info, None)
-                .unwrap();
-            self.device
-                .bind_image_memory(texture_image, texture_memory, 0)
-                .expect("Unable to bind depth image memory");
-
-            record_submit_commandbuffer(
-                &self.device,
-                self.setup_command_buffer,
-                self.setup_commands_reuse_fence,
-                self.present_queue,
-                &[],
-                &[],
-                &[],
-                |device, texture_command_buffer| {
-                    let texture_barrier = vk::ImageMemoryBarrier {
-                        dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
-                        new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                        image: texture_image,
-                        subresource_range: vk::ImageSubresourceRange {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            level_count: 1,
-                            layer_count: 1,
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    };
-                    device.cmd_pipeline_barrier(
-                        texture_command_buffer,
-                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-                        vk::PipelineStageFlags::TRANSFER,
-                        vk::DependencyFlags::empty(),
-                        &[],
-                        &[],
-                        &[texture_barrier],
-                    );
-                    let buffer_copy_regions = vk::BufferImageCopy::default()
-                        .image_subresource(
-                            vk::ImageSubresourceLayers::default()
-                                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                                .layer_count(1),
-                        )
-                        .image_extent(image_extent.into());
-
-                    device.cmd_copy_buffer_to_image(
-                        texture_command_buffer,
-                        image_buffer,
-                        texture_image,
-                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                        &[buffer_copy_regions],
-                    );
-                    let texture_barrier_end = vk::ImageMemoryBarrier {
-                        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
-                        dst_access_mask: vk::AccessFlags::SHADER_READ,
-                        old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                        new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                        image: texture_image,
-                        subresource_range: vk::ImageSubresourceRange {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            level_count: 1,
-                            layer_count: 1,
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    };
-                    device.cmd_pipeline_barrier(
-                        texture_command_buffer,
-                        vk::PipelineStageFlags::TRANSFER,
-                        vk::PipelineStageFlags::FRAGMENT_SHADER,
-                        vk::DependencyFlags::empty(),
-                        &[],
-                        &[],
-                        &[texture_barrier_end],
-                    );
-                },
+            //untextured models sample a single white texel
+            let white = TextureSlot::new(
+                TextureImage::from_pixel(1, 1, image::Rgba([255; 4])),
+                None,
             );
-
-            let sampler_info = vk::SamplerCreateInfo {
-                mag_filter: vk::Filter::LINEAR,
-                min_filter: vk::Filter::LINEAR,
-                mipmap_mode: vk::SamplerMipmapMode::LINEAR,
-                address_mode_u: vk::SamplerAddressMode::MIRRORED_REPEAT,
-                address_mode_v: vk::SamplerAddressMode::MIRRORED_REPEAT,
-                address_mode_w: vk::SamplerAddressMode::MIRRORED_REPEAT,
-                max_anisotropy: 1.0,
-                border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
-                compare_op: vk::CompareOp::NEVER,
-                ..Default::default()
-            };
-
-            let sampler = self.device.create_sampler(&sampler_info, None).unwrap();
-
-            let tex_image_view_info = vk::ImageViewCreateInfo {
-                view_type: vk::ImageViewType::TYPE_2D,
-                format: texture_create_info.format,
-                components: vk::ComponentMapping {
-                    r: vk::ComponentSwizzle::R,
-                    g: vk::ComponentSwizzle::G,
-                    b: vk::ComponentSwizzle::B,
-                    a: vk::ComponentSwizzle::A,
-                },
-                subresource_range: vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    level_count: 1,
-                    layer_count: 1,
-                    ..Default::default()
-                },
-                image: texture_image,
-                ..Default::default()
-            };
-            let tex_image_view = self
-                .device
-                .create_image_view(&tex_image_view_info, None)
-                .unwrap();
+            let slot = model.materials().first()
+                .and_then(|material| material.texture(TextureSlotKind::BaseColor))
+                .unwrap_or(&white);
+            let texture = self.gpu_texture(slot, TextureSlotKind::BaseColor);
+            let sampler = self.gpu_sampler(&slot.sampler);
             let descriptor_sizes = [
                 vk::DescriptorPoolSize {
                     ty: vk::DescriptorType::UNIFORM_BUFFER,
@@ -749,8 +680,8 @@ impl Vulkan {
 
             let tex_descriptor = vk::DescriptorImageInfo {
                 image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                image_view: tex_image_view,
-                sampler,
+                image_view: texture.view,
+                sampler: sampler.sampler,
             };
 
             let write_desc_sets = [
@@ -773,9 +704,10 @@ impl Vulkan {
             self.device.update_descriptor_sets(&write_desc_sets, &[]);
 
             //TODO:(resources, shader spv)
-            let mut vertex_spv_file = Cursor::new(
-                &include_bytes!("../shader/texture/vert.spv")[..]
-            );
+            let mut vertex_spv_file = Cursor::new(match dequantize {
+                Some(_) => &include_bytes!("../shader/quantized/vert.spv")[..],
+                None => &include_bytes!("../shader/texture/vert.spv")[..],
+            });
             let mut frag_spv_file = Cursor::new(
                 &include_bytes!("../shader/texture/frag.spv")[..]
             );
@@ -800,8 +732,17 @@ impl Vulkan {
                 .create_shader_module(&frag_shader_info, None)
                 .expect("Fragment shader module error");
 
-            let layout_create_info =
-                vk::PipelineLayoutCreateInfo::default().set_layouts(&desc_set_layouts);
+            let push_constant_ranges = [vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: mem::size_of::<Dequantize>() as u32,
+            }];
+            let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&desc_set_layouts)
+                .push_constant_ranges(match dequantize {
+                    Some(_) => &push_constant_ranges[..],
+                    None => &[],
+                });
 
             let pipeline_layout = self
                 .device
@@ -823,42 +764,10 @@ impl Vulkan {
                     ..Default::default()
                 },
             ];
-            let vertex_input_binding_descriptions = [vk::VertexInputBindingDescription {
-                binding: 0,
-                //stride: mem::size_of::<Vertex>() as u32,
-                stride: mem::size_of::<NormalVertex>() as u32,
-                input_rate: vk::VertexInputRate::VERTEX,
-            }];
-            let vertex_input_attribute_descriptions = [
-                vk::VertexInputAttributeDescription {
-                    location: 0,
-                    binding: 0,
-                    format: vk::Format::R32G32B32A32_SFLOAT,
-                    //TODO:(investigate offset_of!)
-                    //offset: offset_of!(Vertex, pos) as u32,
-                    offset: offset_of!(NormalVertex, pos) as u32,
-                },
-                vk::VertexInputAttributeDescription {
-                    location: 1,
-                    binding: 0,
-                    format: vk::Format::R32G32_SFLOAT,
-                    //TODO:(investigate offset_of!)
-                    //offset: offset_of!(Vertex, uv) as u32,
-                    offset: offset_of!(NormalVertex, uv) as u32,
-                },
-                vk::VertexInputAttributeDescription {
-                    location: 2,
-                    binding: 0,
-                    format: vk::Format::R32G32B32A32_SFLOAT,
-                    //TODO:(investigate offset_of!)
-                    //offset: offset_of!(Vertex, pos) as u32,
-                    offset: offset_of!(NormalVertex, norm) as u32,
-                },
-            ];
             let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo
                 ::default()
-                    .vertex_attribute_descriptions(&vertex_input_attribute_descriptions)
-                    .vertex_binding_descriptions(&vertex_input_binding_descriptions);
+                    .vertex_attribute_descriptions(&vertex_input.attributes)
+                    .vertex_binding_descriptions(&vertex_input.bindings);
 
             let vertex_input_assembly_state_info = vk
                 ::PipelineInputAssemblyStateCreateInfo {
@@ -948,6 +857,9 @@ impl Vulkan {
             let graphic_pipeline = graphics_pipelines[0];
 
             to_ret = Box::new(move || {
+                //the texture, sampler and defaults stay alive for as long as this draws
+                //with them, and go with it
+                let _in_use = (&texture, &sampler, &defaults_buffer);
 
                 let (present_index, _) = self
                     .swapchain_loader
@@ -1007,21 +919,34 @@ impl Vulkan {
                         );
                         device.cmd_set_viewport(draw_command_buffer, 0, &viewports);
                         device.cmd_set_scissor(draw_command_buffer, 0, &scissors);
+                        if let Some(dequantize) = &dequantize {
+                            device.cmd_push_constants(
+                                draw_command_buffer,
+                                pipeline_layout,
+                                vk::ShaderStageFlags::VERTEX,
+                                0,
+                                bytemuck::cast_slice(&[
+                                    dequantize.position_offset,
+                                    dequantize.position_scale,
+                                    dequantize.uv_offset_scale,
+                                ]),
+                            );
+                        }
                         device.cmd_bind_vertex_buffers(
                             draw_command_buffer,
                             0,
-                            &[vertex_input_buffer],
-                            &[0],
+                            &vertex_buffers,
+                            &vertex_buffer_offsets,
                         );
                         device.cmd_bind_index_buffer(
                             draw_command_buffer,
                             index_buffer,
                             0,
-                            vk::IndexType::UINT32,
+                            index_type,
                         );
                         device.cmd_draw_indexed(
                             draw_command_buffer,
-                            index_buffer_data.len() as u32,
+                            index_count,
                             1,
                             0,
                             0,
@@ -1048,6 +973,333 @@ impl Vulkan {
         to_ret
     }
 
+    //the upload of slot's texture, shared by everything drawing the same SharedTexture
+    //from an srgb slot, or from a linear one, the mips built for each differ.
+    //textures without mips get a box filtered chain here, mipmap_mode has levels to blend.
+    //Encoded textures go up as they are when the gpu can sample their format, a single
+    //float level gets its chain too, otherwise their first level is uploaded like any
+    //other texture
+    unsafe fn gpu_texture(&self, slot: &TextureSlot, kind: TextureSlotKind) -> Rc<GpuTexture> {
+        let mut gpu_textures = self.textures.borrow_mut();
+        let (texture, srgb) = (Arc::downgrade(&slot.texture), kind.is_srgb());
+        gpu_textures.textures.retain(|(_, gpu)| gpu.strong_count() > 0);
+        let uploaded = gpu_textures.textures.iter()
+            .find(|((cpu, cpu_srgb), _)| cpu.ptr_eq(&texture) && *cpu_srgb == srgb)
+            .and_then(|(_, gpu)| gpu.upgrade());
+        if let Some(uploaded) = uploaded {
+            return uploaded;
+        }
+        let name = slot.location.as_deref().unwrap_or("unnamed");
+        let uploaded = match &slot.texture.encoded {
+            Some(encoded) if self.can_sample(encoded.format) => {
+                if encoded.format.is_float() && encoded.level_count() == 1 {
+                    let chain = float_mip_chain(encoded, MipFilter::Box);
+                    Rc::new(self.upload_texture(name, &chain))
+                } else {
+                    Rc::new(self.upload_texture(name, encoded))
+                }
+            },
+            _ if slot.texture.mips.is_empty() => {
+                let mips = mip_chain(&slot.texture, MipFilter::Box, srgb, None);
+                let texture = Texture{ mips, ..Texture::new(slot.texture.image.clone()) };
+                Rc::new(self.upload_texture(name, &EncodedTexture::from_texture(&texture, false)))
+            },
+            _ => {
+                let texture = EncodedTexture::from_texture(&slot.texture, false);
+                Rc::new(self.upload_texture(name, &texture))
+            },
+        };
+        gpu_textures.textures.push(((texture, srgb), Rc::downgrade(&uploaded)));
+        uploaded
+    }
+
+    unsafe fn gpu_sampler(&self, settings: &SamplerSettings) -> Rc<GpuSampler> {
+        let mut gpu_textures = self.textures.borrow_mut();
+        gpu_textures.samplers.retain(|_, gpu| gpu.strong_count() > 0);
+        if let Some(sampler) = gpu_textures.samplers.get(settings).and_then(|s| s.upgrade()) {
+            return sampler;
+        }
+        let sampler = Rc::new(self.create_sampler(settings));
+        gpu_textures.samplers.insert(*settings, Rc::downgrade(&sampler));
+        sampler
+    }
+
+    //what's on the gpu and how many draws hold it, largest first
+    pub fn texture_usage(&self) -> Vec<TextureUsage> {
+        let mut usage: Vec<TextureUsage> = self.textures.borrow().textures.iter()
+            .filter_map(|(_, gpu)| {
+                let references = gpu.strong_count();
+                gpu.upgrade().map(|gpu| TextureUsage{ references, ..gpu.usage.clone() })
+            })
+            .collect();
+        usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        usage
+    }
+
+    //the block compressed formats are optional, see the device's features in new
+    //with linear filtering, which 32 bit floats don't always have
+    unsafe fn can_sample(&self, format: TextureFormat) -> bool {
+        let features = vk::FormatFeatureFlags::SAMPLED_IMAGE
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        self.instance
+            .get_physical_device_format_properties(self.pdevice, format.vk_format())
+            .optimal_tiling_features
+            .contains(features)
+    }
+
+    //every level goes up through one staging buffer, one after the other, each level is
+    //every array layer and cubemap face in turn, the way vulkan numbers them.
+    //Everything is sampled as unorm, srgb formats included, the shader is given the
+    //same values whichever way the texture came
+    unsafe fn upload_texture(&self, name: &str, tex_image: &EncodedTexture) -> GpuTexture {
+        let (width, height) = (tex_image.width, tex_image.height);
+        let image_extent = vk::Extent2D { width, height };
+        let mip_levels = tex_image.level_count();
+        let array_layers = tex_image.layers * tex_image.faces;
+        let image_data: Vec<u8> = tex_image.levels.concat();
+        let image_buffer_info = vk::BufferCreateInfo {
+            size: (mem::size_of::<u8>() * image_data.len()) as u64,
+            usage: vk::BufferUsageFlags::TRANSFER_SRC,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+        let image_buffer = self
+            .device.create_buffer(&image_buffer_info, None).unwrap();
+        let image_buffer_memory_req = self
+            .device.get_buffer_memory_requirements(image_buffer);
+        let image_buffer_memory_index = find_memorytype_index(
+            &image_buffer_memory_req,
+            &self.device_memory_properties,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | 
+                vk::MemoryPropertyFlags::HOST_COHERENT,
+        )
+        .expect("Unable to find suitable memorytype for the image buffer.");
+
+        let image_buffer_allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: image_buffer_memory_req.size,
+            memory_type_index: image_buffer_memory_index,
+            ..Default::default()
+        };
+        let image_buffer_memory = self
+            .device
+            .allocate_memory(&image_buffer_allocate_info, None)
+            .unwrap();
+        let image_ptr = self
+            .device
+            .map_memory(
+                image_buffer_memory,
+                0,
+                image_buffer_memory_req.size,
+                vk::MemoryMapFlags::empty(),
+            )
+            .unwrap();
+        let mut image_slice = Align::new(
+            image_ptr,
+            mem::align_of::<u8>() as u64,
+            image_buffer_memory_req.size,
+        );
+        image_slice.copy_from_slice(&image_data);
+        self.device.unmap_memory(image_buffer_memory);
+        self.device
+            .bind_buffer_memory(image_buffer, image_buffer_memory, 0)
+            .unwrap();
+
+        let texture_create_info = vk::ImageCreateInfo {
+            flags: if tex_image.is_cubemap() {
+                vk::ImageCreateFlags::CUBE_COMPATIBLE
+            } else {
+                vk::ImageCreateFlags::empty()
+            },
+            image_type: vk::ImageType::TYPE_2D,
+            format: tex_image.format.with_srgb(false).vk_format(),
+            extent: image_extent.into(),
+            mip_levels,
+            array_layers,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+        let texture_image = self
+            .device
+            .create_image(&texture_create_info, None)
+            .unwrap();
+        let texture_memory_req = self
+            .device.get_image_memory_requirements(texture_image);
+        let texture_memory_index = find_memorytype_index(
+            &texture_memory_req,
+            &self.device_memory_properties,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+        .expect("Unable to find suitable memory index for depth image.");
+
+        let texture_allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: texture_memory_req.size,
+            memory_type_index: texture_memory_index,
+            ..Default::default()
+        };
+        let texture_memory = self
+            .device
+            .allocate_memory(&texture_allocate_info, None)
+            .unwrap();
+        self.device
+            .bind_image_memory(texture_image, texture_memory, 0)
+            .expect("Unable to bind depth image memory");
+
+        record_submit_commandbuffer(
+            &self.device,
+            self.setup_command_buffer,
+            self.setup_commands_reuse_fence,
+            self.present_queue,
+            &[],
+            &[],
+            &[],
+            |device, texture_command_buffer| {
+                let texture_barrier = vk::ImageMemoryBarrier {
+                    dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    image: texture_image,
+                    subresource_range: vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        level_count: mip_levels,
+                        layer_count: array_layers,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                device.cmd_pipeline_barrier(
+                    texture_command_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[texture_barrier],
+                );
+                let mut buffer_offset = 0;
+                let buffer_copy_regions: Vec<vk::BufferImageCopy> = tex_image.levels.iter()
+                    .enumerate()
+                    .map(|(mip_level, level)| {
+                        let (width, height) = tex_image.level_size(mip_level);
+                        let region = vk::BufferImageCopy::default()
+                            .buffer_offset(buffer_offset)
+                            .image_subresource(
+                                vk::ImageSubresourceLayers::default()
+                                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                    .mip_level(mip_level as u32)
+                                    .layer_count(array_layers),
+                            )
+                            .image_extent(vk::Extent3D {
+                                width,
+                                height,
+                                depth: 1,
+                            });
+                        buffer_offset += level.len() as u64;
+                        region
+                    })
+                    .collect();
+
+                device.cmd_copy_buffer_to_image(
+                    texture_command_buffer,
+                    image_buffer,
+                    texture_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &buffer_copy_regions,
+                );
+                let texture_barrier_end = vk::ImageMemoryBarrier {
+                    src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    dst_access_mask: vk::AccessFlags::SHADER_READ,
+                    old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    image: texture_image,
+                    subresource_range: vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        level_count: mip_levels,
+                        layer_count: array_layers,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                device.cmd_pipeline_barrier(
+                    texture_command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[texture_barrier_end],
+                );
+            },
+        );
+        //the copy has to finish before its staging buffer goes
+        self.device
+            .wait_for_fences(&[self.setup_commands_reuse_fence], true, u64::MAX)
+            .expect("Wait for fence failed.");
+        self.device.destroy_buffer(image_buffer, None);
+        self.device.free_memory(image_buffer_memory, None);
+
+        //samplerColor is a sampler2D, slot textures are single images, see slot_texture,
+        //anything else is seen through its first layer's first face
+        let tex_image_view_info = vk::ImageViewCreateInfo {
+            view_type: vk::ImageViewType::TYPE_2D,
+            format: texture_create_info.format,
+            components: vk::ComponentMapping {
+                r: vk::ComponentSwizzle::R,
+                g: vk::ComponentSwizzle::G,
+                b: vk::ComponentSwizzle::B,
+                a: vk::ComponentSwizzle::A,
+            },
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                level_count: mip_levels,
+                layer_count: 1,
+                ..Default::default()
+            },
+            image: texture_image,
+            ..Default::default()
+        };
+        let tex_image_view = self
+            .device
+            .create_image_view(&tex_image_view_info, None)
+            .unwrap();
+        GpuTexture{
+            device: self.device.clone(),
+            image: texture_image,
+            memory: texture_memory,
+            view: tex_image_view,
+            usage: TextureUsage{
+                name: name.to_string(),
+                width,
+                height,
+                bytes: texture_memory_req.size,
+                references: 0,
+            },
+        }
+    }
+
+    unsafe fn create_sampler(&self, settings: &SamplerSettings) -> GpuSampler {
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: settings.mag_filter.vk_filter(),
+            min_filter: settings.min_filter.vk_filter(),
+            mipmap_mode: settings.mipmap_filter.vk_mipmap_mode(),
+            address_mode_u: settings.wrap_u.vk_address_mode(),
+            address_mode_v: settings.wrap_v.vk_address_mode(),
+            address_mode_w: settings.wrap_u.vk_address_mode(),
+            max_anisotropy: 1.0,
+            //every level there is, textures sharing the sampler can have different counts
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            compare_op: vk::CompareOp::NEVER,
+            ..Default::default()
+        };
+
+        GpuSampler{
+            device: self.device.clone(),
+            sampler: self.device.create_sampler(&sampler_info, None).unwrap(),
+        }
+    }
+
     pub fn new(window: &Window) -> Result<Self, Box<dyn Error>> {
         unsafe {
             let entry = Entry::linked();
@@ -1158,8 +1410,11 @@ impl Vulkan {
                 #[cfg(any(target_os = "macos", target_os = "ios"))]
                 ash::khr::portability_subset::NAME.as_ptr(),
             ];
+            //block compressed textures are uploaded as they are where they can be
+            let supported = instance.get_physical_device_features(pdevice);
             let features = vk::PhysicalDeviceFeatures {
                 shader_clip_distance: 1,
+                texture_compression_bc: supported.texture_compression_bc,
                 ..Default::default()
             };
             let priorities = [1.0];
@@ -1411,6 +1666,7 @@ impl Vulkan {
                 debug_call_back,
                 debug_utils_loader,
                 depth_image_memory,
+                textures: RefCell::new(GpuTextures::default()),
             })
         }
     }